@@ -0,0 +1,275 @@
+use std::ops::Deref;
+
+use crate::chart::{Chart, Shape};
+use crate::enums::{Node, Position};
+use crate::utils::SourceFile;
+
+// Walks a body of statements, adding a box for each and joining it to those before
+struct Flow<'a> {
+    source: &'a SourceFile,
+    chart: Chart,
+    // Boxes whose arrow goes to the next box, with the label on the arrow
+    exits: Vec<(usize, String)>,
+    // The ends of loops the next box is kept below
+    below: Vec<usize>,
+    // RETURN boxes, joined to the end once there is one
+    returns: Vec<usize>,
+}
+
+// The flowchart of the main program, or of the PROCEDURE or FUNCTION named.
+// None when there is no such subroutine.
+pub(crate) fn flowchart(
+    source: &SourceFile,
+    ast: &[Box<Node>],
+    routine: Option<&str>,
+) -> Option<Chart> {
+    let children = match ast.first().map(|node| node.deref()) {
+        Some(Node::Main { children }) => children,
+        _ => return None,
+    };
+    let (title, start, end, body) = match routine {
+        None => (source.name.clone(), "START".to_string(), "STOP", children),
+        Some(routine) => {
+            let (at, pos, body) = children.iter().find_map(|node| match node.deref() {
+                Node::Function {
+                    name,
+                    children,
+                    pos,
+                    ..
+                }
+                | Node::Procedure {
+                    name,
+                    children,
+                    pos,
+                    ..
+                } => match name.deref() {
+                    Node::String { val, pos: at } if *val == routine.to_lowercase() => {
+                        Some((at, pos, children))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })?;
+            // The subroutine starts with its header, as it is written
+            let header = source.file[pos.line_start - 1]
+                .split("//")
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            (span(source, at), header, "END", body)
+        }
+    };
+
+    let mut flow = Flow {
+        source,
+        chart: Chart::new(&title),
+        exits: vec![],
+        below: vec![],
+        returns: vec![],
+    };
+    flow.add(&start, Shape::Terminator);
+    flow.stmts(body);
+    let end = flow.add(end, Shape::Terminator);
+    for r in std::mem::take(&mut flow.returns) {
+        flow.chart.join(r, end, "");
+    }
+    Some(flow.chart)
+}
+
+impl<'a> Flow<'a> {
+    // A box the arrows waiting for the next box point to
+    fn add(&mut self, label: &str, shape: Shape) -> usize {
+        let v = self.chart.add(label, shape);
+        for (from, label) in std::mem::take(&mut self.exits) {
+            self.chart.join(from, v, &label);
+        }
+        for from in std::mem::take(&mut self.below) {
+            self.chart.keep_below(from, v);
+        }
+        self.exits.push((v, String::new()));
+        v
+    }
+
+    fn stmts(&mut self, nodes: &[Box<Node>]) {
+        for node in nodes {
+            self.stmt(node);
+        }
+    }
+
+    fn stmt(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Input { pos, .. }
+            | Node::Output { pos, .. }
+            | Node::ReadFile { pos, .. }
+            | Node::WriteFile { pos, .. }
+            | Node::GetRecord { pos, .. }
+            | Node::PutRecord { pos, .. } => {
+                let label = span(self.source, pos);
+                self.add(&label, Shape::Data);
+            }
+            // The position of a call leaves out CALL
+            Node::FunctionCall { pos, .. } | Node::Composite { pos, .. } => {
+                let label = format!("CALL {}", span(self.source, pos));
+                self.add(&label, Shape::Process);
+            }
+            Node::Return { pos, .. } => {
+                let label = span(self.source, pos);
+                let v = self.add(&label, Shape::Process);
+                self.returns.push(v);
+                self.exits.clear();
+            }
+            Node::If {
+                cond,
+                true_body,
+                false_body,
+                ..
+            } => {
+                let decision = self.decision(&cond.pos());
+                self.exits = vec![(decision, "Yes".to_string())];
+                self.stmts(true_body);
+                let exits = std::mem::take(&mut self.exits);
+                let below = std::mem::take(&mut self.below);
+                self.exits = vec![(decision, "No".to_string())];
+                self.stmts(false_body);
+                self.exits.extend(exits);
+                self.below.extend(below);
+            }
+            Node::While { cond, body, .. } => {
+                let decision = self.decision(&cond.pos());
+                self.exits = vec![(decision, "Yes".to_string())];
+                self.stmts(body);
+                self.close(decision);
+                self.exits = vec![(decision, "No".to_string())];
+            }
+            Node::Repeat { cond, body, .. } => {
+                // The loop goes back to the first box of its body
+                let first = self.chart.len();
+                self.stmts(body);
+                let decision = self.decision(&cond.pos());
+                self.chart.join(decision, first, "No");
+                self.exits = vec![(decision, "Yes".to_string())];
+            }
+            Node::For {
+                iter,
+                range,
+                step,
+                body,
+                ..
+            } => {
+                let Node::Range { start, end, .. } = range.deref() else {
+                    return;
+                };
+                let counter = span(self.source, &iter.pos());
+                let label = format!("{} <- {}", counter, span(self.source, &start.pos()));
+                self.add(&label, Shape::Process);
+                // A negative STEP counts down to the end
+                let (compare, step) = match step.deref() {
+                    Node::Null => ("<=", "1".to_string()),
+                    Node::Int { val, .. } if *val < 0 => (">=", span(self.source, &step.pos())),
+                    Node::Unary { op, .. } if op.ends_with('-') => {
+                        (">=", span(self.source, &step.pos()))
+                    }
+                    _ => ("<=", span(self.source, &step.pos())),
+                };
+                let label = format!("{} {} {}?", counter, compare, span(self.source, &end.pos()));
+                let decision = self.add(&label, Shape::Decision);
+                self.exits = vec![(decision, "Yes".to_string())];
+                self.stmts(body);
+                let label = match step.strip_prefix('-') {
+                    Some(down) => format!("{} <- {} - {}", counter, counter, down),
+                    None => format!("{} <- {} + {}", counter, counter, step),
+                };
+                self.add(&label, Shape::Process);
+                self.close(decision);
+                self.exits = vec![(decision, "No".to_string())];
+            }
+            Node::Switch {
+                cmp,
+                cases,
+                otherwise,
+                ..
+            } => {
+                // One decision after another, each case taken on Yes
+                let subject = span(self.source, &cmp.pos());
+                let mut exits = vec![];
+                let mut below = vec![];
+                for case in cases {
+                    let Node::Case { expr, children, .. } = case.deref() else {
+                        continue;
+                    };
+                    let label = match expr.deref() {
+                        Node::Range { start, end, .. } => format!(
+                            "{} >= {} AND {} <= {}?",
+                            subject,
+                            span(self.source, &start.pos()),
+                            subject,
+                            span(self.source, &end.pos())
+                        ),
+                        _ => format!("{} = {}?", subject, span(self.source, &expr.pos())),
+                    };
+                    let decision = self.add(&label, Shape::Decision);
+                    self.exits = vec![(decision, "Yes".to_string())];
+                    self.stmts(children);
+                    exits.append(&mut self.exits);
+                    below.append(&mut self.below);
+                    self.exits = vec![(decision, "No".to_string())];
+                }
+                self.stmts(otherwise);
+                self.exits.extend(exits);
+                self.below.extend(below);
+            }
+            // Declarations and definitions are not steps of the flow
+            Node::Declare { .. }
+            | Node::Const { .. }
+            | Node::PointerDef { .. }
+            | Node::Enum { .. }
+            | Node::Record { .. }
+            | Node::Class { .. }
+            | Node::Function { .. }
+            | Node::Procedure { .. }
+            | Node::Null => {}
+            node => {
+                let label = span(self.source, &node.pos());
+                self.add(&label, Shape::Process);
+            }
+        }
+    }
+
+    fn decision(&mut self, cond: &Position) -> usize {
+        let label = format!("{}?", span(self.source, cond));
+        self.add(&label, Shape::Decision)
+    }
+
+    // The end of a loop body goes back to its decision, and what follows the loop
+    // is kept below the body
+    fn close(&mut self, decision: usize) {
+        for (from, label) in std::mem::take(&mut self.exits) {
+            self.chart.join(from, decision, &label);
+            self.below.push(from);
+        }
+    }
+}
+
+// The source a node was parsed from, on one line
+fn span(source: &SourceFile, pos: &Position) -> String {
+    let lines = &source.file;
+    let Some(first) = lines.get(pos.line_start.wrapping_sub(1)) else {
+        return String::new();
+    };
+    if pos.line_start == pos.line_end {
+        return first
+            .get(pos.pos_start..pos.pos_end)
+            .unwrap_or(first)
+            .trim()
+            .to_string();
+    }
+    let mut text = vec![first.get(pos.pos_start..).unwrap_or(first).trim()];
+    for line in pos.line_start..pos.line_end - 1 {
+        text.push(lines.get(line).map_or("", |line| line.trim()));
+    }
+    if let Some(last) = lines.get(pos.line_end - 1) {
+        text.push(last.get(..pos.pos_end).unwrap_or(last).trim());
+    }
+    text.join(" ")
+}
@@ -0,0 +1,150 @@
+mod flowchart;
+mod structure;
+mod svg;
+
+pub(crate) use flowchart::flowchart;
+pub(crate) use structure::call_graph;
+pub use structure::CallGraph;
+
+// How a chart is written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChartFormat {
+    // Graphviz source, to be laid out with dot
+    Dot,
+    // A picture laid out here, needing nothing else to view it
+    Svg,
+}
+
+// The standard flowchart shapes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Shape {
+    Terminator,
+    Process,
+    Decision,
+    // INPUT, OUTPUT and reading or writing files
+    Data,
+}
+
+pub(crate) struct Vertex {
+    pub label: String,
+    pub shape: Shape,
+}
+
+pub(crate) struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub label: String,
+    // Only keeps `to` below `from` when laid out, and is not drawn
+    pub hidden: bool,
+    // Drawn in red, as part of a cycle of calls
+    pub highlight: bool,
+}
+
+// Boxes joined by arrows, drawn top to bottom from the first box. Labels on
+// arrows may take several lines.
+pub struct Chart {
+    title: String,
+    vertices: Vec<Vertex>,
+    edges: Vec<Edge>,
+}
+
+impl Chart {
+    pub(crate) fn new(title: &str) -> Self {
+        Chart {
+            title: title.to_string(),
+            vertices: vec![],
+            edges: vec![],
+        }
+    }
+
+    pub(crate) fn add(&mut self, label: &str, shape: Shape) -> usize {
+        self.vertices.push(Vertex {
+            label: label.to_string(),
+            shape,
+        });
+        self.vertices.len() - 1
+    }
+
+    pub(crate) fn join(&mut self, from: usize, to: usize, label: &str) {
+        self.edges.push(Edge {
+            from,
+            to,
+            label: label.to_string(),
+            hidden: false,
+            highlight: false,
+        });
+    }
+
+    pub(crate) fn join_highlighted(&mut self, from: usize, to: usize, label: &str) {
+        self.join(from, to, label);
+        self.edges.last_mut().unwrap().highlight = true;
+    }
+
+    pub(crate) fn keep_below(&mut self, from: usize, to: usize) {
+        self.edges.push(Edge {
+            from,
+            to,
+            label: String::new(),
+            hidden: true,
+            highlight: false,
+        });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn render(&self, format: ChartFormat) -> String {
+        match format {
+            ChartFormat::Dot => self.dot(),
+            ChartFormat::Svg => svg::render(self),
+        }
+    }
+
+    fn dot(&self) -> String {
+        let mut out = vec![
+            format!("digraph \"{}\" {{", escape_dot(&self.title)),
+            "    node [fontname=\"Helvetica\", fontsize=12];".to_string(),
+            "    edge [fontname=\"Helvetica\", fontsize=10];".to_string(),
+        ];
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let shape = match vertex.shape {
+                Shape::Terminator => "box, style=rounded",
+                Shape::Process => "box",
+                Shape::Decision => "diamond",
+                Shape::Data => "parallelogram",
+            };
+            out.push(format!(
+                "    n{} [label=\"{}\", shape={}];",
+                i,
+                escape_dot(&vertex.label),
+                shape
+            ));
+        }
+        for edge in &self.edges {
+            let mut attributes = vec![];
+            if !edge.label.is_empty() {
+                attributes.push(format!("label=\"{}\"", escape_dot(&edge.label)));
+            }
+            if edge.hidden {
+                attributes.push("style=invis".to_string());
+            }
+            if edge.highlight {
+                attributes.push("color=red, fontcolor=red".to_string());
+            }
+            let attributes = match attributes.is_empty() {
+                true => String::new(),
+                false => format!(" [{}]", attributes.join(", ")),
+            };
+            out.push(format!("    n{} -> n{}{};", edge.from, edge.to, attributes));
+        }
+        out.push("}".to_string());
+        out.join("\n") + "\n"
+    }
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,300 @@
+use std::collections::{HashSet, VecDeque};
+use std::ops::Deref;
+
+use crate::chart::{Chart, Shape};
+use crate::enums::{Node, Position};
+use crate::transpile::lower::walk;
+use crate::utils::SourceFile;
+
+// The main program, or a PROCEDURE or FUNCTION
+struct Module {
+    // As it is written
+    name: String,
+    // The header after PROCEDURE or FUNCTION, as it is written
+    header: String,
+    params: Vec<Param>,
+    // The type a FUNCTION returns, as it is written
+    returns: Option<String>,
+    // The modules called, in the order they are first called
+    calls: Vec<usize>,
+}
+
+struct Param {
+    name: String,
+    byref: bool,
+}
+
+// Which modules call which, drawn as a structure chart or listed
+pub struct CallGraph {
+    title: String,
+    modules: Vec<Module>,
+    // Each group of modules calling round in a cycle, from its first module back
+    // to it again
+    cycles: Vec<Vec<usize>>,
+    // The cycle each module is in, if any
+    cycle: Vec<Option<usize>>,
+}
+
+// The calls between the main program and the PROCEDUREs and FUNCTIONs of a program
+pub(crate) fn call_graph(source: &SourceFile, ast: &[Box<Node>]) -> CallGraph {
+    let children: &[Box<Node>] = match ast.first().map(|node| node.deref()) {
+        Some(Node::Main { children }) => children,
+        _ => &[],
+    };
+    let mut modules = vec![Module {
+        name: "Main program".to_string(),
+        header: "Main program".to_string(),
+        params: vec![],
+        returns: None,
+        calls: vec![],
+    }];
+    let mut bodies = vec![children];
+    // The names calls are made by, in lowercase
+    let mut keys = vec![String::new()];
+    for node in children {
+        let (name, params, body, function) = match node.deref() {
+            Node::Function {
+                name,
+                params,
+                children,
+                ..
+            } => (name, params, children, true),
+            Node::Procedure {
+                name,
+                params,
+                children,
+                ..
+            } => (name, params, children, false),
+            _ => continue,
+        };
+        let Node::String { val, pos } = name.deref() else {
+            continue;
+        };
+        let header = source.file[pos.line_start - 1]
+            .get(pos.pos_start..)
+            .unwrap_or_default()
+            .split("//")
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let returns = match function {
+            true => header
+                .to_ascii_uppercase()
+                .rfind("RETURNS")
+                .map(|at| header[at + "RETURNS".len()..].trim().to_string()),
+            false => None,
+        };
+        let params = params
+            .iter()
+            .flat_map(|param| match param.deref() {
+                Node::Declare {
+                    children,
+                    byref,
+                    pos,
+                    ..
+                } => children
+                    .iter()
+                    .map(|name| Param {
+                        name: written(source, pos, name),
+                        byref: *byref,
+                    })
+                    .collect(),
+                _ => vec![],
+            })
+            .collect();
+        modules.push(Module {
+            name: written(source, pos, val),
+            header,
+            params,
+            returns,
+            calls: vec![],
+        });
+        bodies.push(body);
+        keys.push(val.clone());
+    }
+
+    for (module, body) in modules.iter_mut().zip(bodies) {
+        // Methods called on objects and classes made with NEW are not modules
+        let mut skip: HashSet<*const Node> = HashSet::new();
+        walk(body, &mut |node| match node {
+            Node::Composite { children, .. } => {
+                skip.extend(children.iter().skip(1).map(|c| c.deref() as *const Node));
+            }
+            Node::CreateObject { call, .. } => {
+                skip.insert(call.deref() as *const Node);
+            }
+            Node::FunctionCall { name, .. } if !skip.contains(&(node as *const Node)) => {
+                if let Some(callee) = keys.iter().skip(1).position(|key| key == name) {
+                    if !module.calls.contains(&(callee + 1)) {
+                        module.calls.push(callee + 1);
+                    }
+                }
+            }
+            _ => {}
+        });
+    }
+
+    let (cycles, cycle) = cycles(&modules);
+    CallGraph {
+        title: source.name.clone(),
+        modules,
+        cycles,
+        cycle,
+    }
+}
+
+impl CallGraph {
+    // A box for each module, with an arrow to each module it calls labelled with
+    // the parameters passed down, those passed BYREF coming back up, and what a
+    // FUNCTION returns
+    pub fn chart(&self) -> Chart {
+        let mut chart = Chart::new(&self.title);
+        for module in &self.modules {
+            chart.add(&module.name, Shape::Process);
+        }
+        for (from, module) in self.modules.iter().enumerate() {
+            for &to in &module.calls {
+                let callee = &self.modules[to];
+                let mut lines: Vec<String> = callee
+                    .params
+                    .iter()
+                    .map(|param| match param.byref {
+                        true => format!("↕ {}", param.name),
+                        false => format!("↓ {}", param.name),
+                    })
+                    .collect();
+                if let Some(returns) = &callee.returns {
+                    lines.push(format!("↑ {}", returns));
+                }
+                match self.recursive(from, to) {
+                    true => chart.join_highlighted(from, to, &lines.join("\n")),
+                    false => chart.join(from, to, &lines.join("\n")),
+                }
+            }
+        }
+        chart
+    }
+
+    // Each module and the modules it calls, then the cycles of calls
+    pub fn listing(&self) -> String {
+        let mut out = vec![];
+        for (from, module) in self.modules.iter().enumerate() {
+            out.push(module.header.clone());
+            for &to in &module.calls {
+                let callee = &self.modules[to];
+                let params: Vec<String> = callee
+                    .params
+                    .iter()
+                    .map(|param| match param.byref {
+                        true => format!("BYREF {}", param.name),
+                        false => param.name.clone(),
+                    })
+                    .collect();
+                let mut line = format!("    calls {}({})", callee.name, params.join(", "));
+                if let Some(returns) = &callee.returns {
+                    line += &format!(" RETURNS {}", returns);
+                }
+                if self.recursive(from, to) {
+                    line += "  [recursive]";
+                }
+                out.push(line);
+            }
+        }
+        out.push(String::new());
+        match self.cycles.is_empty() {
+            true => out.push("No recursion".to_string()),
+            false => {
+                out.push("Recursion".to_string());
+                for cycle in &self.cycles {
+                    let names: Vec<&str> = cycle
+                        .iter()
+                        .map(|m| self.modules[*m].name.as_str())
+                        .collect();
+                    out.push(format!("    {}", names.join(" -> ")));
+                }
+            }
+        }
+        out.join("\n") + "\n"
+    }
+
+    // Whether a call goes round a cycle back to the module making it
+    fn recursive(&self, from: usize, to: usize) -> bool {
+        self.cycle[from].is_some() && self.cycle[from] == self.cycle[to]
+    }
+}
+
+// Modules calling themselves, directly or through others, grouped by the modules
+// they can reach and be reached from. Each group is given as its shortest cycle
+// from its first module.
+fn cycles(modules: &[Module]) -> (Vec<Vec<usize>>, Vec<Option<usize>>) {
+    let n = modules.len();
+    let reach: Vec<Vec<bool>> = (0..n)
+        .map(|start| {
+            let mut seen = vec![false; n];
+            let mut stack = modules[start].calls.clone();
+            while let Some(m) = stack.pop() {
+                if !seen[m] {
+                    seen[m] = true;
+                    stack.extend(&modules[m].calls);
+                }
+            }
+            seen
+        })
+        .collect();
+    let mut cycles = vec![];
+    let mut cycle = vec![None; n];
+    for first in 0..n {
+        if !reach[first][first] || cycle[first].is_some() {
+            continue;
+        }
+        for m in 0..n {
+            if reach[first][m] && reach[m][first] {
+                cycle[m] = Some(cycles.len());
+            }
+        }
+        // Back to the first module the shortest way round
+        let mut previous = vec![None; n];
+        let mut queue = VecDeque::from([first]);
+        'search: while let Some(m) = queue.pop_front() {
+            for &next in &modules[m].calls {
+                if cycle[next] != cycle[first] || previous[next].is_some() {
+                    continue;
+                }
+                previous[next] = Some(m);
+                if next == first {
+                    break 'search;
+                }
+                queue.push_back(next);
+            }
+        }
+        let mut path = vec![first];
+        let mut m = previous[first].unwrap();
+        while m != first {
+            path.push(m);
+            m = previous[m].unwrap();
+        }
+        path.push(first);
+        path.reverse();
+        cycles.push(path);
+    }
+    (cycles, cycle)
+}
+
+// A name as it is written where it is declared, rather than in lowercase
+fn written(source: &SourceFile, pos: &Position, name: &str) -> String {
+    let Some(line) = source.file.get(pos.line_start.wrapping_sub(1)) else {
+        return name.to_string();
+    };
+    let lower = line.to_ascii_lowercase();
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    lower
+        .match_indices(name)
+        .filter(|(at, _)| *at >= pos.pos_start)
+        .find(|(at, _)| {
+            !lower[..*at].ends_with(word) && !lower[at + name.len()..].starts_with(word)
+        })
+        .map_or(name.to_string(), |(at, _)| {
+            line[at..at + name.len()].to_string()
+        })
+}
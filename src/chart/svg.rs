@@ -0,0 +1,701 @@
+use std::collections::VecDeque;
+
+use crate::chart::{Chart, Edge, Shape};
+
+const FONT_SIZE: f64 = 13.0;
+// Characters of a monospace font are about this wide
+const CHAR_WIDTH: f64 = FONT_SIZE * 0.6;
+const PADDING: f64 = 12.0;
+const HEIGHT: f64 = 36.0;
+const DECISION_HEIGHT: f64 = 60.0;
+// How far a parallelogram leans
+const SKEW: f64 = 12.0;
+// Between boxes side by side, and between rows of boxes
+const H_GAP: f64 = 30.0;
+const V_GAP: f64 = 40.0;
+// Between arrows looping back up the left hand side
+const LANE_GAP: f64 = 14.0;
+const MARGIN: f64 = 20.0;
+// Between the lines of a label on an arrow
+const LINE_HEIGHT: f64 = FONT_SIZE;
+
+// A box, or a point an arrow passes through on a row it crosses
+struct Item {
+    vertex: Option<usize>,
+    layer: usize,
+    width: f64,
+    height: f64,
+    x: f64,
+    y: f64,
+}
+
+// Boxes are put in rows so every arrow points down, except those closing a loop.
+// An arrow crossing rows passes through a point on each, so it can go around boxes.
+struct Layout {
+    items: Vec<Item>,
+    layers: Vec<Vec<usize>>,
+    // The items each drawn arrow passes through, from its start to its end, and
+    // the edge it is drawn for
+    paths: Vec<(Vec<usize>, usize)>,
+    // Boxes kept below others without an arrow
+    hidden: Vec<(usize, usize)>,
+    // Arrows pointing up or back to their own box
+    back: Vec<usize>,
+    // The most lines of a label above each row, for labels put at the ends of arrows
+    labels: Vec<usize>,
+    // The top and bottom of each row
+    rows: Vec<(f64, f64)>,
+}
+
+pub(crate) fn render(chart: &Chart) -> String {
+    let layout = Layout::new(chart);
+    layout.draw(chart)
+}
+
+impl Layout {
+    fn new(chart: &Chart) -> Self {
+        let n = chart.vertices.len();
+        let (back, order) = back_edges(chart);
+
+        // Each box goes on the row below the lowest box with an arrow into it
+        let forward: Vec<usize> = (0..chart.edges.len())
+            .filter(|&e| !back[e] && chart.edges[e].from != chart.edges[e].to)
+            .collect();
+        let mut incoming = vec![0; n];
+        for &e in &forward {
+            incoming[chart.edges[e].to] += 1;
+        }
+        let mut layer = vec![0; n];
+        let mut queue: VecDeque<usize> = (0..n).filter(|&v| incoming[v] == 0).collect();
+        while let Some(v) = queue.pop_front() {
+            for &e in &forward {
+                let edge = &chart.edges[e];
+                if edge.from == v {
+                    layer[edge.to] = layer[edge.to].max(layer[v] + 1);
+                    incoming[edge.to] -= 1;
+                    if incoming[edge.to] == 0 {
+                        queue.push_back(edge.to);
+                    }
+                }
+            }
+        }
+
+        let mut items: Vec<Item> = chart
+            .vertices
+            .iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                let text = vertex.label.chars().count() as f64 * CHAR_WIDTH;
+                let (width, height) = match vertex.shape {
+                    Shape::Decision => (text * 1.5 + 30.0, DECISION_HEIGHT),
+                    Shape::Data => (text + 2.0 * PADDING + SKEW, HEIGHT),
+                    _ => (text + 2.0 * PADDING, HEIGHT),
+                };
+                Item {
+                    vertex: Some(i),
+                    layer: layer[i],
+                    width,
+                    height,
+                    x: 0.0,
+                    y: 0.0,
+                }
+            })
+            .collect();
+        // Rows are first ordered the way the chart was walked through
+        let mut keys: Vec<f64> = (0..n).map(|v| order[v] as f64).collect();
+        let mut paths = vec![];
+        let mut hidden = vec![];
+        for &e in &forward {
+            let edge = &chart.edges[e];
+            if edge.hidden {
+                hidden.push((edge.from, edge.to));
+                continue;
+            }
+            let mut path = vec![edge.from];
+            for row in layer[edge.from] + 1..layer[edge.to] {
+                items.push(Item {
+                    vertex: None,
+                    layer: row,
+                    width: 0.0,
+                    height: 0.0,
+                    x: 0.0,
+                    y: 0.0,
+                });
+                // Beside the boxes the arrow passes, rather than before them
+                keys.push(order[edge.to] as f64 - 0.5);
+                path.push(items.len() - 1);
+            }
+            path.push(edge.to);
+            paths.push((path, e));
+        }
+        let back: Vec<usize> = (0..chart.edges.len())
+            .filter(|&e| !forward.contains(&e) && !chart.edges[e].hidden)
+            .collect();
+
+        let rows = items.iter().map(|item| item.layer + 1).max().unwrap_or(0);
+        let mut labels = vec![0; rows];
+        let ends = paths.iter().map(|(path, e)| (*path.last().unwrap(), *e));
+        for (to, e) in ends.chain(back.iter().map(|e| (chart.edges[*e].to, *e))) {
+            if at_end(chart, e) {
+                let row = items[to].layer;
+                labels[row] = labels[row].max(chart.edges[e].label.lines().count());
+            }
+        }
+        let mut layers: Vec<Vec<usize>> = vec![vec![]; rows];
+        for (i, item) in items.iter().enumerate() {
+            layers[item.layer].push(i);
+        }
+        for row in layers.iter_mut() {
+            row.sort_by(|a, b| keys[*a].total_cmp(&keys[*b]));
+        }
+        let mut layout = Layout {
+            items,
+            layers,
+            paths,
+            hidden,
+            back,
+            labels,
+            rows: vec![],
+        };
+        layout.order();
+        layout.position();
+        layout
+    }
+
+    // The items joined to each item on the rows above and below it, and with
+    // `hidden` the boxes kept below others too
+    fn neighbours(&self, hidden: bool) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+        let mut above = vec![vec![]; self.items.len()];
+        let mut below = vec![vec![]; self.items.len()];
+        let pairs = self.paths.iter().flat_map(|(path, _)| path.windows(2));
+        let pairs = pairs.map(|pair| (pair[0], pair[1]));
+        let kept = self.hidden.iter().copied().filter(|_| hidden);
+        for (from, to) in pairs.chain(kept) {
+            below[from].push(to);
+            above[to].push(from);
+        }
+        (above, below)
+    }
+
+    // Fewer arrows cross when each item sits near the middle of those it is joined to
+    fn order(&mut self) {
+        let (above, below) = self.neighbours(false);
+        for _ in 0..2 {
+            for row in 1..self.layers.len() {
+                self.reorder(row, row - 1, &above);
+            }
+            for row in (0..self.layers.len().saturating_sub(1)).rev() {
+                self.reorder(row, row + 1, &below);
+            }
+        }
+    }
+
+    fn reorder(&mut self, row: usize, next: usize, joined: &[Vec<usize>]) {
+        let place = |item: &usize, row: &Vec<usize>| row.iter().position(|i| i == item).unwrap();
+        let keys: Vec<f64> = self.layers[row]
+            .iter()
+            .enumerate()
+            .map(|(i, item)| match joined[*item].is_empty() {
+                true => i as f64,
+                false => {
+                    let sum: usize = joined[*item]
+                        .iter()
+                        .map(|other| place(other, &self.layers[next]))
+                        .sum();
+                    sum as f64 / joined[*item].len() as f64
+                }
+            })
+            .collect();
+        let mut sorted: Vec<(f64, usize)> =
+            keys.into_iter().zip(self.layers[row].clone()).collect();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.layers[row] = sorted.into_iter().map(|(_, item)| item).collect();
+    }
+
+    // Boxes line up with the box they follow on from, and the points arrows pass
+    // through make way for them
+    fn position(&mut self) {
+        let (above, below) = self.neighbours(true);
+        for row in 0..self.layers.len() {
+            self.place(row, &above);
+        }
+        for row in (0..self.layers.len()).rev() {
+            self.place(row, &below);
+        }
+        for row in 0..self.layers.len() {
+            self.place(row, &above);
+        }
+
+        self.straighten();
+
+        let left = self
+            .items
+            .iter()
+            .map(|item| item.x - item.width / 2.0)
+            .fold(f64::INFINITY, f64::min);
+        for item in self.items.iter_mut() {
+            item.x += MARGIN - left;
+        }
+        let mut top = MARGIN;
+        for (row, lines) in self.layers.iter().zip(&self.labels) {
+            top += *lines as f64 * LINE_HEIGHT;
+            let height = row
+                .iter()
+                .map(|i| self.items[*i].height)
+                .fold(HEIGHT, f64::max);
+            for i in row {
+                self.items[*i].y = top + height / 2.0;
+            }
+            self.rows.push((top, top + height));
+            top += height + V_GAP;
+        }
+    }
+
+    fn place(&mut self, row: usize, joined: &[Vec<usize>]) {
+        let items = self.layers[row].clone();
+        // Below or above the leftmost item joined to it
+        let wanted: Vec<f64> = items
+            .iter()
+            .map(|i| {
+                joined[*i]
+                    .iter()
+                    .map(|j| self.items[*j].x)
+                    .reduce(f64::min)
+                    .unwrap_or(self.items[*i].x)
+            })
+            .collect();
+        let apart = |a: usize, b: usize| {
+            let gap = match (self.items[a].vertex, self.items[b].vertex) {
+                (Some(_), Some(_)) => H_GAP,
+                _ => H_GAP / 2.0,
+            };
+            (self.items[a].width + self.items[b].width) / 2.0 + gap
+        };
+        // Boxes are placed first, left to right, then the points between them,
+        // each as near where it wants to be as those already placed allow
+        let mut first: Vec<usize> = (0..items.len()).collect();
+        first.sort_by_key(|i| self.items[items[*i]].vertex.is_none());
+        let mut placed: Vec<Option<f64>> = vec![None; items.len()];
+        for i in first {
+            let mut low = f64::NEG_INFINITY;
+            let mut high = f64::INFINITY;
+            let mut room = 0.0;
+            for j in (0..i).rev() {
+                room += apart(items[j], items[j + 1]);
+                if let Some(x) = placed[j] {
+                    low = x + room;
+                    break;
+                }
+            }
+            room = 0.0;
+            for j in i + 1..items.len() {
+                room += apart(items[j - 1], items[j]);
+                if let Some(x) = placed[j] {
+                    high = x - room;
+                    break;
+                }
+            }
+            placed[i] = Some(wanted[i].max(low).min(high.max(low)));
+        }
+        for (i, x) in placed.into_iter().enumerate() {
+            self.items[items[i]].x = x.unwrap();
+        }
+    }
+
+    // An arrow passing several rows goes straight down their right hand side
+    // where nothing is in the way
+    fn straighten(&mut self) {
+        for (path, _) in &self.paths {
+            let points = &path[1..path.len() - 1];
+            let Some(x) = points.iter().map(|i| self.items[*i].x).reduce(f64::max) else {
+                continue;
+            };
+            for point in points {
+                let row = &self.layers[self.items[*point].layer];
+                let place = row.iter().position(|i| i == point).unwrap();
+                let clear = row[place + 1..].iter().all(|i| {
+                    let item = &self.items[*i];
+                    item.x - item.width / 2.0 > x + H_GAP / 2.0
+                });
+                if clear {
+                    self.items[*point].x = x;
+                }
+            }
+        }
+    }
+
+    fn top(&self, item: usize) -> f64 {
+        match self.items[item].vertex {
+            Some(_) => self.items[item].y - self.items[item].height / 2.0,
+            None => self.rows[self.items[item].layer].0,
+        }
+    }
+
+    fn bottom(&self, item: usize) -> f64 {
+        match self.items[item].vertex {
+            Some(_) => self.items[item].y + self.items[item].height / 2.0,
+            None => self.rows[self.items[item].layer].1,
+        }
+    }
+
+    // Where an arrow leaves the left hand side of a box
+    fn left(&self, item: usize, chart: &Chart) -> f64 {
+        let item = &self.items[item];
+        let lean = match item.vertex.map(|v| chart.vertices[v].shape) {
+            Some(Shape::Data) => SKEW / 2.0,
+            _ => 0.0,
+        };
+        item.x - item.width / 2.0 + lean
+    }
+
+    fn draw(&self, chart: &Chart) -> String {
+        let mut shapes = vec![];
+        for item in &self.items {
+            let Some(v) = item.vertex else {
+                continue;
+            };
+            let (x, y, w, h) = (item.x, item.y, item.width, item.height);
+            let (left, top) = (x - w / 2.0, y - h / 2.0);
+            shapes.push(match chart.vertices[v].shape {
+                Shape::Terminator => format!(
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"{:.1}\" fill=\"white\" stroke=\"black\"/>",
+                    left, top, w, h, h / 2.0
+                ),
+                Shape::Process => format!(
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"white\" stroke=\"black\"/>",
+                    left, top, w, h
+                ),
+                Shape::Decision => polygon(&[
+                    (x, top),
+                    (left + w, y),
+                    (x, top + h),
+                    (left, y),
+                ]),
+                Shape::Data => polygon(&[
+                    (left + SKEW, top),
+                    (left + w, top),
+                    (left + w - SKEW, top + h),
+                    (left, top + h),
+                ]),
+            });
+            shapes.push(format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+                x,
+                y,
+                escape_xml(&chart.vertices[v].label)
+            ));
+        }
+
+        let mut arrows = vec![];
+        for (path, e) in &self.paths {
+            let (points, text) = self.route(path, *e, chart);
+            arrows.push(polyline(&points, chart.edges[*e].highlight));
+            arrows.extend(text);
+        }
+        // Arrows closing a loop go up the left hand side, inner loops nearest, out
+        // of the way of arrows leaving a loop, which go down on the right
+        let mut back = self.back.clone();
+        back.sort_by_key(|e| {
+            let edge = &chart.edges[*e];
+            self.items[edge.from].layer - self.items[edge.to].layer
+        });
+        let mut lanes: Vec<(usize, usize, f64)> = vec![];
+        let mut left = MARGIN;
+        // The furthest right a box going back to itself loops
+        let mut loops: f64 = 0.0;
+        for e in back {
+            let Edge {
+                from,
+                to,
+                label,
+                highlight,
+                ..
+            } = &chart.edges[e];
+            // Across above any labels on arrows into the row
+            let lines = self.labels[self.items[*to].layer] as f64;
+            let above = self.top(*to) - V_GAP / 2.0 - lines * LINE_HEIGHT;
+            let end = (self.items[*to].x, self.top(*to));
+            if at_end(chart, e) {
+                arrows.push(label_above(end, label, *highlight));
+            }
+
+            // A box going back to itself from beside others loops round its right
+            // hand side, rather than across them
+            let item = &self.items[*from];
+            if from == to && self.layers[item.layer].first() != Some(from) {
+                let side = item.x + item.width / 2.0;
+                let lane = side + LANE_GAP;
+                loops = loops.max(lane);
+                arrows.push(polyline(
+                    &[
+                        (side, item.y),
+                        (lane, item.y),
+                        (lane, above),
+                        (end.0, above),
+                        end,
+                    ],
+                    *highlight,
+                ));
+                continue;
+            }
+
+            let (low, high) = (self.items[*to].layer, self.items[*from].layer);
+            let mut lane = (low..=high)
+                .flat_map(|row| &self.layers[row])
+                .map(|i| self.items[*i].x - self.items[*i].width / 2.0)
+                .fold(f64::INFINITY, f64::min);
+            for (start, end, x) in &lanes {
+                if *start <= high && low <= *end {
+                    lane = lane.min(*x);
+                }
+            }
+            lane -= LANE_GAP;
+            lanes.push((low, high, lane));
+            left = left.min(lane);
+
+            let start = (self.left(*from, chart), item.y);
+            arrows.push(polyline(
+                &[start, (lane, start.1), (lane, above), (end.0, above), end],
+                *highlight,
+            ));
+            if !label.is_empty() && !at_end(chart, e) {
+                arrows.push(label_at(
+                    start.0 - 4.0,
+                    start.1 - 6.0,
+                    "end",
+                    label,
+                    *highlight,
+                ));
+            }
+        }
+
+        // Everything moves right to make room for the arrows on the left
+        let shift = MARGIN - left;
+        let right = self
+            .items
+            .iter()
+            .map(|item| item.x + item.width / 2.0)
+            .fold(loops, f64::max);
+        // Labels at the ends of arrows stick out to the right of them
+        let right = (0..chart.edges.len())
+            .filter(|e| at_end(chart, *e) && !chart.edges[*e].hidden)
+            .map(|e| {
+                let edge = &chart.edges[e];
+                let longest = edge.label.lines().map(|line| line.chars().count());
+                let text = longest.max().unwrap_or(0) as f64 * CHAR_WIDTH;
+                self.items[edge.to].x + 6.0 + text
+            })
+            .fold(right, f64::max);
+        let width = (right + shift + MARGIN).ceil();
+        let height = (self.rows.last().map_or(0.0, |row| row.1) + MARGIN).ceil();
+        let mut out = vec![
+            format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"monospace\" font-size=\"{}\">",
+                width, height, width, height, FONT_SIZE
+            ),
+            format!("<title>{}</title>", escape_xml(&chart.title)),
+            format!("<defs>{}{}</defs>", marker("arrow", "black"), marker("arrow-red", "red")),
+            "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>".to_string(),
+            format!("<g transform=\"translate({:.1} 0)\">", shift),
+        ];
+        out.extend(arrows);
+        out.extend(shapes);
+        out.push("</g>".to_string());
+        out.push("</svg>".to_string());
+        out.join("\n") + "\n"
+    }
+
+    // The corners of an arrow down through the items of a path, and its label
+    fn route(&self, path: &[usize], e: usize, chart: &Chart) -> (Vec<(f64, f64)>, Option<String>) {
+        let Edge {
+            label, highlight, ..
+        } = &chart.edges[e];
+        let first = &self.items[path[0]];
+        let next = &self.items[path[1]];
+        let decision = first
+            .vertex
+            .is_some_and(|v| chart.vertices[v].shape == Shape::Decision);
+        let mut points = vec![];
+        let mut text = None;
+        // A decision sends arrows off to either side from its corners
+        let side = match decision {
+            true if next.x < first.x - first.width / 4.0 => -1.0,
+            true if next.x > first.x + first.width / 4.0 => 1.0,
+            _ => 0.0,
+        };
+        if side != 0.0 {
+            let corner = (first.x + side * first.width / 2.0, first.y);
+            points.push(corner);
+            points.push((next.x, first.y));
+            if !label.is_empty() {
+                let anchor = if side < 0.0 { "end" } else { "start" };
+                text = Some(label_at(
+                    corner.0 + side * 4.0,
+                    corner.1 - 6.0,
+                    anchor,
+                    label,
+                    *highlight,
+                ));
+            }
+        } else {
+            let start = (first.x, self.bottom(path[0]));
+            points.push(start);
+            if !label.is_empty() && !at_end(chart, e) {
+                text = Some(label_at(
+                    start.0 + 6.0,
+                    start.1 + 14.0,
+                    "start",
+                    label,
+                    *highlight,
+                ));
+            }
+        }
+        for pair in path.windows(2) {
+            let (x, y) = *points.last().unwrap();
+            let target = &self.items[pair[1]];
+            if (x - target.x).abs() > 0.5 {
+                // Across in the gap below the row being left
+                let gap = self.rows[self.items[pair[0]].layer].1 + V_GAP / 2.0;
+                points.push((x, gap.max(y)));
+                points.push((target.x, gap.max(y)));
+            }
+            points.push((target.x, self.top(pair[1])));
+            if target.vertex.is_none() {
+                points.push((target.x, self.bottom(pair[1])));
+            }
+        }
+        // Beside the arrow where it goes into its box
+        if at_end(chart, e) {
+            text = Some(label_above(*points.last().unwrap(), label, *highlight));
+        }
+        (points, text)
+    }
+}
+
+// Which arrows close a loop, found walking the chart from its first box, and the
+// order each box was first reached in
+fn back_edges(chart: &Chart) -> (Vec<bool>, Vec<usize>) {
+    let n = chart.vertices.len();
+    let mut out: Vec<Vec<usize>> = vec![vec![]; n];
+    for (e, edge) in chart.edges.iter().enumerate() {
+        out[edge.from].push(e);
+    }
+    let mut back = vec![false; chart.edges.len()];
+    let mut order = vec![0; n];
+    // Not reached, being walked, or walked
+    let mut state = vec![0u8; n];
+    let mut count = 0;
+    for root in 0..n {
+        if state[root] != 0 {
+            continue;
+        }
+        // Boxes being walked, each with the next of its arrows to follow
+        let mut stack = vec![(root, 0)];
+        state[root] = 1;
+        order[root] = count;
+        count += 1;
+        while let Some((v, next)) = stack.pop() {
+            let Some(&e) = out[v].get(next) else {
+                state[v] = 2;
+                continue;
+            };
+            stack.push((v, next + 1));
+            let to = chart.edges[e].to;
+            match state[to] {
+                0 => {
+                    state[to] = 1;
+                    order[to] = count;
+                    count += 1;
+                    stack.push((to, 0));
+                }
+                1 => back[e] = true,
+                _ => {}
+            }
+        }
+    }
+    (back, order)
+}
+
+fn polygon(points: &[(f64, f64)]) -> String {
+    format!(
+        "<polygon points=\"{}\" fill=\"white\" stroke=\"black\"/>",
+        coordinates(points)
+    )
+}
+
+// Arrows in a cycle of calls are red
+fn polyline(points: &[(f64, f64)], highlight: bool) -> String {
+    let (colour, marker) = match highlight {
+        true => ("red", "arrow-red"),
+        false => ("black", "arrow"),
+    };
+    format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" marker-end=\"url(#{})\"/>",
+        coordinates(points),
+        colour,
+        marker
+    )
+}
+
+fn marker(id: &str, colour: &str) -> String {
+    format!(
+        "<marker id=\"{}\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto\"><path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"{}\"/></marker>",
+        id, colour
+    )
+}
+
+// Labels on arrows from a decision go where it is left, other labels where the
+// arrow ends, out of the way of the other arrows leaving the same box
+fn at_end(chart: &Chart, e: usize) -> bool {
+    let edge = &chart.edges[e];
+    !edge.label.is_empty() && chart.vertices[edge.from].shape != Shape::Decision
+}
+
+fn coordinates(points: &[(f64, f64)]) -> String {
+    points
+        .iter()
+        .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// A label just above and to the right of where an arrow ends
+fn label_above(end: (f64, f64), text: &str, highlight: bool) -> String {
+    let lines = text.lines().count() as f64;
+    let y = end.1 - 6.0 - (lines - 1.0) * LINE_HEIGHT;
+    label_at(end.0 + 6.0, y, "start", text, highlight)
+}
+
+// A label with its first line at (x, y) and any others below it
+fn label_at(x: f64, y: f64, anchor: &str, text: &str, highlight: bool) -> String {
+    let colour = if highlight { "red" } else { "black" };
+    let lines: Vec<String> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => escape_xml(line),
+            _ => format!(
+                "<tspan x=\"{:.1}\" dy=\"{:.1}\">{}</tspan>",
+                x,
+                LINE_HEIGHT,
+                escape_xml(line)
+            ),
+        })
+        .collect();
+    format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\" font-size=\"{}\" fill=\"{}\">{}</text>",
+        x,
+        y,
+        anchor,
+        FONT_SIZE - 2.0,
+        colour,
+        lines.concat()
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,544 @@
+use std::ops::Deref;
+
+use crate::checker::{assignable, is_text, Checker, Param, TypeDef};
+use crate::enums::{Node, Position, VariableType};
+
+// Type of an expression, None when it cannot be known without running the program
+pub fn check_expr(checker: &mut Checker, node: &Box<Node>) -> Option<VariableType> {
+    match node.deref() {
+        Node::Int { .. } => Some(VariableType::Integer),
+        Node::Real { .. } => Some(VariableType::Real),
+        Node::String { .. } => Some(VariableType::String),
+        Node::Char { .. } => Some(VariableType::Char),
+        Node::Boolean { .. } => Some(VariableType::Boolean),
+        Node::Date { .. } => Some(VariableType::Date),
+        Node::Var { name, pos } => match checker.get_var(name) {
+            Some(var) => Some(var.t.clone()),
+            None => {
+                checker.error(format!("'{}' is not declared", name).as_str(), pos);
+                None
+            }
+        },
+        Node::ArrayVar { name, indices, pos } => {
+            let t = check_expr(
+                checker,
+                &Box::new(Node::Var {
+                    name: name.clone(),
+                    pos: *pos,
+                }),
+            )?;
+            check_array_element(checker, name, t, indices, pos)
+        }
+        Node::FunctionCall { name, params, pos } => {
+            let returns = check_fn_call(checker, name, params, pos)?;
+            expect_value(checker, name, returns, pos)
+        }
+        Node::CreateObject { call, pos } => check_create_obj(checker, call, pos),
+        Node::Composite { children, .. } => check_composite(checker, children, true, false),
+        Node::Reference { expr, .. } => match expr.deref() {
+            Node::Var { .. }
+            | Node::ArrayVar { .. }
+            | Node::Composite { .. }
+            | Node::Dereference { .. } => {
+                let t = check_expr(checker, expr)?;
+                Some(VariableType::Pointer(Box::new(t)))
+            }
+            _ => {
+                checker.error(
+                    "Value is not a pointer, it cannot be referenced",
+                    &expr.pos(),
+                );
+                None
+            }
+        },
+        Node::Dereference { expr, .. } => check_dereference(checker, expr),
+        Node::Unary { op, expr, .. } => {
+            let t = check_expr(checker, expr)?;
+            if op == "!" {
+                if t != VariableType::Boolean {
+                    checker.error(
+                        format!("Logical operation {} can only be performed on BOOLEAN", op)
+                            .as_str(),
+                        &expr.pos(),
+                    );
+                }
+                Some(VariableType::Boolean)
+            } else {
+                check_numeric(checker, &t, &expr.pos());
+                Some(t)
+            }
+        }
+        Node::Binary { op, lhs, rhs, pos } => check_binary(checker, op, lhs, rhs, pos),
+        _ => None,
+    }
+}
+
+// Something that can be assigned to, INPUT into or passed BYREF
+pub fn check_target(
+    checker: &mut Checker,
+    node: &Box<Node>,
+    message: &str,
+) -> Option<VariableType> {
+    match node.deref() {
+        Node::Var { name, pos } | Node::ArrayVar { name, pos, .. } => {
+            check_mutable(checker, name, pos);
+            check_expr(checker, node)
+        }
+        Node::Composite { children, .. } => {
+            if let Node::Var { name, pos } | Node::ArrayVar { name, pos, .. } = children[0].deref()
+            {
+                check_mutable(checker, name, pos);
+            }
+            check_composite(checker, children, true, true)
+        }
+        Node::Dereference { expr, .. } => check_dereference(checker, expr),
+        _ => {
+            checker.error(message, &node.pos());
+            None
+        }
+    }
+}
+
+// A call made as a statement may be to a procedure
+pub fn check_call_stmt(checker: &mut Checker, node: &Box<Node>) {
+    match node.deref() {
+        Node::FunctionCall { name, params, pos } => {
+            check_fn_call(checker, name, params, pos);
+        }
+        Node::Composite { children, .. } => {
+            check_composite(checker, children, false, false);
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn check_mutable(checker: &mut Checker, name: &String, pos: &Position) {
+    if let Some(var) = checker.get_var(name) {
+        if !var.mutable {
+            checker.error(
+                format!("'{}' is a constant, it's value cannot be modified", name).as_str(),
+                pos,
+            );
+        }
+    }
+}
+
+fn check_binary(
+    checker: &mut Checker,
+    op: &String,
+    lhs: &Box<Node>,
+    rhs: &Box<Node>,
+    pos: &Position,
+) -> Option<VariableType> {
+    let lhs_type = check_expr(checker, lhs);
+    let rhs_type = check_expr(checker, rhs);
+    match op.as_str() {
+        "+" | "-" | "*" | "/" | "%" | "//" => {
+            let lhs_type = lhs_type?;
+            let rhs_type = rhs_type?;
+            let lhs_ok = check_numeric(checker, &lhs_type, &lhs.pos());
+            let rhs_ok = check_numeric(checker, &rhs_type, &rhs.pos());
+            if !lhs_ok || !rhs_ok {
+                return None;
+            }
+            if lhs_type == VariableType::Real || rhs_type == VariableType::Real {
+                Some(VariableType::Real)
+            } else {
+                Some(VariableType::Integer)
+            }
+        }
+        "<" | ">" | "<=" | ">=" => {
+            // CHAR values are ordered among themselves
+            let is_char = |t: &Option<VariableType>| t == &Some(VariableType::Char);
+            if is_char(&lhs_type) || is_char(&rhs_type) {
+                if let (Some(lhs_type), Some(rhs_type)) = (lhs_type, rhs_type) {
+                    if lhs_type != rhs_type {
+                        checker.error(
+                            format!(
+                                "Cannot compare types {} AND {}",
+                                lhs_type.str(),
+                                rhs_type.str()
+                            )
+                            .as_str(),
+                            pos,
+                        );
+                    }
+                }
+                return Some(VariableType::Boolean);
+            }
+            if let Some(t) = lhs_type {
+                check_numeric(checker, &t, &lhs.pos());
+            }
+            if let Some(t) = rhs_type {
+                check_numeric(checker, &t, &rhs.pos());
+            }
+            Some(VariableType::Boolean)
+        }
+        "=" | "!=" => {
+            if let (Some(lhs_type), Some(rhs_type)) = (lhs_type, rhs_type) {
+                // INTEGER and REAL values compare as numbers, CHAR and STRING as text
+                let number =
+                    |t: &VariableType| matches!(t, VariableType::Integer | VariableType::Real);
+                let text = is_text(&lhs_type) && is_text(&rhs_type);
+                if lhs_type != rhs_type && !(number(&lhs_type) && number(&rhs_type)) && !text {
+                    checker.error(
+                        format!(
+                            "Cannot compare types {} AND {}",
+                            lhs_type.str(),
+                            rhs_type.str()
+                        )
+                        .as_str(),
+                        pos,
+                    );
+                } else if !comparable(checker, &lhs_type) {
+                    checker.error(
+                        format!("Cannot compare type {}", lhs_type.str()).as_str(),
+                        pos,
+                    );
+                }
+            }
+            Some(VariableType::Boolean)
+        }
+        "&&" | "||" => {
+            for (t, node) in [(lhs_type, lhs), (rhs_type, rhs)] {
+                if t.is_some_and(|t| t != VariableType::Boolean) {
+                    checker.error(
+                        format!("Logical operation {} can only be performed on BOOLEAN", op)
+                            .as_str(),
+                        &node.pos(),
+                    );
+                }
+            }
+            Some(VariableType::Boolean)
+        }
+        "&" => {
+            for (t, node) in [(lhs_type, lhs), (rhs_type, rhs)] {
+                if t.is_some_and(|t| t != VariableType::String && t != VariableType::Char) {
+                    checker.error("'&' can only be performed on STRING or CHAR", &node.pos());
+                }
+            }
+            Some(VariableType::String)
+        }
+        _ => None,
+    }
+}
+
+fn check_numeric(checker: &mut Checker, t: &VariableType, pos: &Position) -> bool {
+    match t {
+        VariableType::Integer | VariableType::Real => true,
+        _ => {
+            checker.error(
+                "Arithmetic operation can only be performed on INTEGER or REAL",
+                pos,
+            );
+            false
+        }
+    }
+}
+
+// Arrays, objects and pointers cannot be compared with = and <>
+fn comparable(checker: &Checker, t: &VariableType) -> bool {
+    match t {
+        VariableType::Array { .. } | VariableType::Pointer(_) => false,
+        VariableType::Custom(name) => !matches!(
+            checker.types.get(name),
+            Some(TypeDef::Record { .. }) | Some(TypeDef::Class { .. })
+        ),
+        _ => true,
+    }
+}
+
+fn check_dereference(checker: &mut Checker, expr: &Box<Node>) -> Option<VariableType> {
+    match check_expr(checker, expr)? {
+        VariableType::Pointer(t) => Some(*t),
+        _ => {
+            checker.error(
+                "Value is not a pointer, it cannot be dereferenced",
+                &expr.pos(),
+            );
+            None
+        }
+    }
+}
+
+fn check_array_element(
+    checker: &mut Checker,
+    name: &String,
+    t: VariableType,
+    indices: &Vec<Box<Node>>,
+    pos: &Position,
+) -> Option<VariableType> {
+    for index in indices {
+        match check_expr(checker, index) {
+            Some(VariableType::Integer) | None => {}
+            Some(_) => checker.error("Expected INTEGER", &index.pos()),
+        }
+    }
+    match t {
+        VariableType::Array { shape, t } => {
+            if indices.len() != shape.len() {
+                checker.error("Number of indices doesnt match array shape", pos);
+                return None;
+            }
+            for (index, bounds) in indices.iter().zip(shape.iter()) {
+                if let Node::Int { val, pos } = index.deref() {
+                    if *val < bounds.lower || *val > bounds.upper {
+                        checker.error(
+                            format!(
+                                "Index out of bounds: {} is not in range of {}..{}",
+                                val, bounds.lower, bounds.upper
+                            )
+                            .as_str(),
+                            pos,
+                        );
+                    }
+                }
+            }
+            Some(*t)
+        }
+        _ => {
+            checker.error(format!("{} is not an array", name).as_str(), pos);
+            None
+        }
+    }
+}
+
+// Returns None for an unknown callee, Some(None) for a procedure
+fn check_fn_call(
+    checker: &mut Checker,
+    name: &String,
+    args: &Vec<Box<Node>>,
+    pos: &Position,
+) -> Option<Option<VariableType>> {
+    if let Some((params, returns)) = builtin(name) {
+        if params.len() != args.len() {
+            checker.error("Invalid number of arguments", pos);
+        }
+        for (arg, allowed) in args.iter().zip(params.iter()) {
+            if allowed.contains(&VariableType::Char) {
+                checker.check_char(&VariableType::Char, arg);
+            }
+            if let Some(t) = check_expr(checker, arg) {
+                if !allowed.iter().any(|a| assignable(a, &t)) {
+                    checker.error(
+                        format!(
+                            "Cannot assign type {:?} to parameter of type {:?}",
+                            t.str(),
+                            allowed[0].str()
+                        )
+                        .as_str(),
+                        &arg.pos(),
+                    );
+                }
+            }
+        }
+        return returns.map(Some);
+    }
+    let signature = match checker.methods.get(name) {
+        Some(signature) => Some(signature.clone()),
+        None => checker.routines.get(name).cloned(),
+    };
+    match signature {
+        Some(signature) => {
+            check_args(checker, &signature.params, args, pos);
+            Some(signature.returns.map(|t| checker.resolve(&t)))
+        }
+        None => {
+            checker.error(format!("'{}' is not declared", name).as_str(), pos);
+            for arg in args {
+                check_expr(checker, arg);
+            }
+            None
+        }
+    }
+}
+
+fn check_args(checker: &mut Checker, params: &[Param], args: &[Box<Node>], pos: &Position) {
+    if params.len() != args.len() {
+        checker.error("Number of parameters doesnt match function definition", pos);
+    }
+    for (i, arg) in args.iter().enumerate() {
+        let param = match params.get(i) {
+            Some(param) => param,
+            None => {
+                check_expr(checker, arg);
+                continue;
+            }
+        };
+        let t = if param.byref {
+            check_target(checker, arg, "Cannot pass this value byref")
+        } else {
+            check_expr(checker, arg)
+        };
+        let param_type = checker.resolve(&param.t);
+        if !param.byref {
+            checker.check_char(&param_type, arg);
+        }
+        if let Some(t) = t {
+            // A BYREF parameter shares the argument, so the types must match exactly
+            let fits = match param.byref {
+                true => t == param_type,
+                false => assignable(&param_type, &t),
+            };
+            if !fits {
+                checker.error(
+                    format!(
+                        "Cannot assign type {:?} to parameter of type {:?}",
+                        t.str(),
+                        param_type.str()
+                    )
+                    .as_str(),
+                    &arg.pos(),
+                );
+            }
+        }
+    }
+}
+
+fn expect_value(
+    checker: &mut Checker,
+    name: &String,
+    returns: Option<VariableType>,
+    pos: &Position,
+) -> Option<VariableType> {
+    if returns.is_none() {
+        checker.error(
+            format!("'{}' is a procedure, it does not return a value", name).as_str(),
+            pos,
+        );
+    }
+    returns
+}
+
+fn check_create_obj(
+    checker: &mut Checker,
+    call: &Box<Node>,
+    pos: &Position,
+) -> Option<VariableType> {
+    if let Node::FunctionCall { name, params, .. } = call.deref() {
+        match checker.types.get(name) {
+            Some(TypeDef::Class { .. }) => {
+                if let Some(constructor) = checker.get_method(name, &"new".to_string()) {
+                    check_args(checker, &constructor.params, params, pos);
+                }
+                return Some(VariableType::Custom(name.clone()));
+            }
+            Some(_) => checker.error(format!("{} is not a class", name).as_str(), pos),
+            None => checker.error(format!("'{}' is not declared", name).as_str(), pos),
+        }
+        for param in params {
+            check_expr(checker, param);
+        }
+    }
+    None
+}
+
+// obj.prop, obj.arr[i] and obj.method() chains. A method call at the end of a
+// statement may be a procedure, anywhere else it has to return a value.
+fn check_composite(
+    checker: &mut Checker,
+    children: &[Box<Node>],
+    value: bool,
+    target: bool,
+) -> Option<VariableType> {
+    let mut t = match children[0].deref() {
+        Node::FunctionCall { .. } if target => {
+            checker.error("Cannot assign to this value", &children[0].pos());
+            return None;
+        }
+        _ => check_expr(checker, &children[0])?,
+    };
+    for (i, child) in children.iter().enumerate().skip(1) {
+        let last = i == children.len() - 1;
+        let class = match &t {
+            VariableType::Custom(name)
+                if matches!(
+                    checker.types.get(name),
+                    Some(TypeDef::Record { .. }) | Some(TypeDef::Class { .. })
+                ) =>
+            {
+                name.clone()
+            }
+            _ => {
+                checker.error("Value is not an object", &child.pos());
+                return None;
+            }
+        };
+        t = match child.deref() {
+            Node::Var { name, pos } | Node::ArrayVar { name, pos, .. } => {
+                let field = match checker.get_field(&class, name) {
+                    Some(field) => field,
+                    None => {
+                        checker.error(format!("Property '{}' not found", name).as_str(), pos);
+                        return None;
+                    }
+                };
+                if field.private {
+                    checker.error("Cannot access private property", pos);
+                }
+                let field_type = checker.resolve(&field.t);
+                match child.deref() {
+                    Node::ArrayVar { indices, .. } => {
+                        check_array_element(checker, name, field_type, indices, pos)?
+                    }
+                    _ => field_type,
+                }
+            }
+            Node::FunctionCall { name, params, pos } => {
+                if target && last {
+                    checker.error("Cannot assign to this value", pos);
+                    return None;
+                }
+                let method = match checker.get_method(&class, name) {
+                    Some(method) => method,
+                    None => {
+                        checker.error(format!("Method '{}' not found", name).as_str(), pos);
+                        return None;
+                    }
+                };
+                if method.private {
+                    checker.error("Cannot call private method", pos);
+                }
+                check_args(checker, &method.params, params, pos);
+                let returns = method.returns.map(|t| checker.resolve(&t));
+                if last && !value {
+                    return returns;
+                }
+                expect_value(checker, name, returns, pos)?
+            }
+            _ => return None,
+        };
+    }
+    Some(t)
+}
+
+// Parameter types accepted by each builtin, and what it returns when that is fixed
+fn builtin(name: &str) -> Option<(Vec<Vec<VariableType>>, Option<VariableType>)> {
+    use VariableType::*;
+    let number = vec![Integer, Real];
+    Some(match name.to_uppercase().as_str() {
+        "LEFT" | "RIGHT" => (vec![vec![String], vec![Integer]], Some(String)),
+        "MID" => (
+            vec![vec![String], vec![Integer], vec![Integer]],
+            Some(String),
+        ),
+        "LENGTH" => (vec![vec![String]], Some(Integer)),
+        "TO_UPPER" | "TO_LOWER" => (vec![vec![String]], Some(String)),
+        "NUM_TO_STR" => (vec![number], Some(String)),
+        // INTEGER or REAL depending on the text
+        "STR_TO_NUM" => (vec![vec![String]], None),
+        "IS_NUM" => (vec![vec![String]], Some(Boolean)),
+        "ASC" => (vec![vec![Char]], Some(Integer)),
+        "CHR" => (vec![vec![Integer]], Some(Char)),
+        "INT" => (vec![number], Some(Integer)),
+        "RAND" => (vec![vec![Integer]], Some(Real)),
+        "DAY" | "MONTH" | "YEAR" | "DAYINDEX" => (vec![vec![Date]], Some(Integer)),
+        "SETDATE" => (
+            vec![vec![Integer], vec![Integer], vec![Integer]],
+            Some(Date),
+        ),
+        "TODAY" => (vec![], Some(Date)),
+        "EOF" => (vec![vec![String]], Some(Boolean)),
+        _ => return None,
+    })
+}
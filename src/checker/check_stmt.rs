@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::checker::check_expr::{check_call_stmt, check_expr, check_target};
+use crate::checker::{assignable, Checker, Signature, TypeDef};
+use crate::enums::{Node, Position, VariableType};
+
+pub fn check_stmts(checker: &mut Checker, nodes: &Vec<Box<Node>>) {
+    for node in nodes {
+        check_stmt(checker, node);
+    }
+}
+
+pub fn check_stmt(checker: &mut Checker, node: &Box<Node>) {
+    match node.deref() {
+        Node::Declare {
+            t, children, pos, ..
+        } => {
+            checker.check_type(t, pos);
+            let t = checker.resolve(t);
+            for name in children {
+                checker.declare_var(name, t.clone(), true, pos);
+            }
+        }
+        Node::Const { name, val, pos } => {
+            if let Some(t) = check_expr(checker, val) {
+                checker.declare_var(name, t, false, pos);
+            }
+        }
+        Node::Enum { name, variants, .. } => {
+            for variant in variants {
+                if let Node::String { val, pos } = variant.deref() {
+                    checker.declare_var(val, VariableType::Custom(name.clone()), false, pos);
+                }
+            }
+        }
+        // Definitions are collected before the program is walked
+        Node::PointerDef { .. }
+        | Node::Record { .. }
+        | Node::Class { .. }
+        | Node::Function { .. }
+        | Node::Procedure { .. }
+        | Node::Null => {}
+        Node::Output { children, .. } => {
+            for child in children {
+                if let Some(t) = check_expr(checker, child) {
+                    if !printable(checker, &t) {
+                        checker.error("Value cannot be printed", &child.pos());
+                    }
+                }
+            }
+        }
+        Node::Input { child, .. } => {
+            if let Some(t) = check_target(checker, child, "Input cannot be stored in this value") {
+                match t {
+                    VariableType::Integer
+                    | VariableType::Real
+                    | VariableType::String
+                    | VariableType::Char
+                    | VariableType::Boolean => {}
+                    _ => checker.error(
+                        format!("Input type {} is not allowed", t.str()).as_str(),
+                        &child.pos(),
+                    ),
+                }
+            }
+        }
+        Node::Assignment { lhs, rhs, pos } => {
+            let lhs_type = check_target(checker, lhs, "Cannot assign to this value");
+            let rhs_type = check_expr(checker, rhs);
+            if let Some(t) = &lhs_type {
+                checker.check_char(t, rhs);
+            }
+            check_assign(checker, lhs_type, rhs_type, pos);
+        }
+        Node::If {
+            cond,
+            true_body,
+            false_body,
+            ..
+        } => {
+            check_condition(checker, cond);
+            check_stmts(checker, true_body);
+            check_stmts(checker, false_body);
+        }
+        Node::While { cond, body, .. } => {
+            check_condition(checker, cond);
+            check_stmts(checker, body);
+        }
+        Node::Repeat { cond, body, .. } => {
+            check_stmts(checker, body);
+            check_condition(checker, cond);
+        }
+        Node::For {
+            iter,
+            range,
+            step,
+            body,
+            ..
+        } => {
+            if let Node::Range { start, end, .. } = range.deref() {
+                check_integer(checker, start);
+                check_integer(checker, end);
+            }
+            if Node::Null != *step.deref() {
+                check_integer(checker, step);
+            }
+            if let Node::Var { name, pos } = iter.deref() {
+                // The counter is declared by the loop when it does not exist yet
+                match checker.get_var(name).cloned() {
+                    None => checker.declare_var(name, VariableType::Integer, true, pos),
+                    Some(var) => {
+                        if !var.mutable {
+                            checker.error(
+                                format!("'{}' is a constant, it's value cannot be modified", name)
+                                    .as_str(),
+                                pos,
+                            );
+                        } else {
+                            check_assign(checker, Some(var.t), Some(VariableType::Integer), pos);
+                        }
+                    }
+                }
+            }
+            check_stmts(checker, body);
+        }
+        Node::Switch {
+            cmp,
+            cases,
+            otherwise,
+            ..
+        } => {
+            let cmp_type = check_expr(checker, cmp);
+            for case in cases {
+                if let Node::Case { expr, children, .. } = case.deref() {
+                    if let Node::Range { start, end, .. } = expr.deref() {
+                        // Ranges run over numbers, or over characters when the value is a CHAR
+                        if cmp_type == Some(VariableType::Char) {
+                            check_char_bound(checker, start);
+                            check_char_bound(checker, end);
+                        } else {
+                            check_number(checker, start);
+                            check_number(checker, end);
+                        }
+                    } else {
+                        check_expr(checker, expr);
+                    }
+                    check_stmts(checker, children);
+                }
+            }
+            check_stmts(checker, otherwise);
+        }
+        Node::Return { expr, pos } => {
+            let t = check_expr(checker, expr);
+            match checker.routine.clone() {
+                None => checker.error("Cannot return outside of function or procedure", pos),
+                Some(Signature { returns: None, .. }) => {
+                    checker.error("Cannot return within procedure", pos)
+                }
+                Some(Signature {
+                    returns: Some(returns),
+                    ..
+                }) => {
+                    let returns = checker.resolve(&returns);
+                    checker.check_char(&returns, expr);
+                    if let Some(t) = t {
+                        if !assignable(&returns, &t) {
+                            checker.error(
+                                format!(
+                                    "Cannot return type {} from a function returning {}",
+                                    t.str(),
+                                    returns.str()
+                                )
+                                .as_str(),
+                                &expr.pos(),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Node::OpenFile { filename, .. } | Node::CloseFile { filename, .. } => {
+            check_string(checker, filename);
+        }
+        Node::ReadFile { filename, var, pos } => {
+            check_string(checker, filename);
+            let t = check_target(checker, var, "Cannot assign to this value");
+            check_assign(checker, t, Some(VariableType::String), pos);
+        }
+        Node::WriteFile { filename, expr, .. } => {
+            check_string(checker, filename);
+            if let Some(t) = check_expr(checker, expr) {
+                if !printable(checker, &t) {
+                    checker.error("Value cannot be written to a file", &expr.pos());
+                }
+            }
+        }
+        Node::SeekFile { filename, expr, .. } => {
+            check_string(checker, filename);
+            check_number(checker, expr);
+        }
+        Node::PutRecord { filename, var, .. } => {
+            check_string(checker, filename);
+            let t = check_expr(checker, var);
+            check_record(checker, t, &var.pos());
+        }
+        Node::GetRecord { filename, var, .. } => {
+            check_string(checker, filename);
+            let t = check_target(checker, var, "Cannot assign to this value");
+            check_record(checker, t, &var.pos());
+        }
+        Node::FunctionCall { .. } | Node::Composite { .. } => check_call_stmt(checker, node),
+        _ => {
+            check_expr(checker, node);
+        }
+    }
+}
+
+// Check the body of a function, procedure or method against its own scope
+pub fn check_routine(checker: &mut Checker, node: &Box<Node>, class: Option<&String>) {
+    let signature = enter_routine(checker, node, class);
+    let (name, children) = match node.deref() {
+        Node::Function { name, children, .. } | Node::Procedure { name, children, .. } => {
+            (name, children)
+        }
+        _ => unreachable!(),
+    };
+    check_stmts(checker, children);
+    if signature.returns.is_some() && !always_returns(children) {
+        checker.error("Missing return statement", &name.pos());
+    }
+    leave_routine(checker);
+}
+
+// Open the scope of a routine with its parameters, and the members of its class
+// for a method, returning its signature
+pub fn enter_routine(checker: &mut Checker, node: &Box<Node>, class: Option<&String>) -> Signature {
+    let signature = checker.signature(node);
+    let (name, params) = match node.deref() {
+        Node::Function { name, params, .. } | Node::Procedure { name, params, .. } => {
+            (name, params)
+        }
+        _ => unreachable!(),
+    };
+
+    checker.scopes.push(HashMap::new());
+    if let Some(class) = class {
+        // Methods see the class's own properties and methods by name, and the parent as super
+        if let Some(TypeDef::Class {
+            base,
+            fields,
+            methods,
+        }) = checker.types.get(class).cloned()
+        {
+            for (field, prop) in fields {
+                let t = checker.resolve(&prop.t);
+                checker.declare_var(&field, t, true, &name.pos());
+            }
+            if let Some(base) = base {
+                checker.declare_var(
+                    &"super".to_string(),
+                    VariableType::Custom(base),
+                    true,
+                    &name.pos(),
+                );
+            }
+            checker.methods = methods;
+        }
+    }
+    let mut names: Vec<&String> = Vec::new();
+    for (param, node) in signature.params.iter().zip(params) {
+        if names.contains(&&param.name) {
+            checker.error(
+                format!("Duplicate parameter {}", param.name).as_str(),
+                &node.pos(),
+            );
+            continue;
+        }
+        names.push(&param.name);
+        checker.check_type(&param.t, &node.pos());
+        let t = checker.resolve(&param.t);
+        checker.declare_var(&param.name, t, true, &node.pos());
+    }
+
+    checker.routine = Some(signature.clone());
+    signature
+}
+
+pub fn leave_routine(checker: &mut Checker) {
+    checker.routine = None;
+    checker.methods.clear();
+    checker.scopes.pop();
+}
+
+// Whether every path through the statements ends in a RETURN
+fn always_returns(nodes: &[Box<Node>]) -> bool {
+    nodes.iter().any(|node| match node.deref() {
+        Node::Return { .. } => true,
+        Node::If {
+            cond,
+            true_body,
+            false_body,
+            ..
+        } => match constant(cond) {
+            Some(true) => always_returns(true_body),
+            Some(false) => always_returns(false_body),
+            None => always_returns(true_body) && always_returns(false_body),
+        },
+        Node::While { cond, body, .. } => constant(cond) == Some(true) && always_returns(body),
+        Node::Switch {
+            cases, otherwise, ..
+        } => {
+            always_returns(otherwise)
+                && cases.iter().all(|case| match case.deref() {
+                    Node::Case { children, .. } => always_returns(children),
+                    _ => false,
+                })
+        }
+        // The body of a REPEAT always runs at least once
+        Node::Repeat { body, .. } => always_returns(body),
+        _ => false,
+    })
+}
+
+// Value of a condition made only of literals, such as TRUE = TRUE
+fn constant(node: &Box<Node>) -> Option<bool> {
+    match node.deref() {
+        Node::Boolean { val, .. } => Some(*val),
+        Node::Unary { op, expr, .. } if op == "!" => constant(expr).map(|val| !val),
+        Node::Binary { op, lhs, rhs, .. } => match (op.as_str(), lhs.deref(), rhs.deref()) {
+            ("&&", _, _) => Some(constant(lhs)? && constant(rhs)?),
+            ("||", _, _) => Some(constant(lhs)? || constant(rhs)?),
+            ("=" | "!=", Node::Boolean { .. }, Node::Boolean { .. })
+            | ("=" | "!=", Node::Int { .. }, Node::Int { .. })
+            | ("=" | "!=", Node::String { .. }, Node::String { .. }) => {
+                Some((lhs.val_as_str() == rhs.val_as_str()) == (op == "="))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn check_assign(
+    checker: &mut Checker,
+    lhs: Option<VariableType>,
+    rhs: Option<VariableType>,
+    pos: &Position,
+) {
+    if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+        if !assignable(&lhs, &rhs) {
+            checker.error(
+                format!("Cannot assign type {:?} to type {:?}", rhs.str(), lhs.str()).as_str(),
+                pos,
+            );
+        }
+    }
+}
+
+fn check_condition(checker: &mut Checker, node: &Box<Node>) {
+    match check_expr(checker, node) {
+        Some(VariableType::Boolean) | None => {}
+        Some(_) => checker.error("Expected BOOLEAN", &node.pos()),
+    }
+}
+
+fn check_integer(checker: &mut Checker, node: &Box<Node>) {
+    match check_expr(checker, node) {
+        Some(VariableType::Integer) | None => {}
+        Some(_) => checker.error("Expected INTEGER", &node.pos()),
+    }
+}
+
+fn check_char_bound(checker: &mut Checker, node: &Box<Node>) {
+    match check_expr(checker, node) {
+        Some(VariableType::Char) | None => {}
+        Some(_) => checker.error("Expected CHAR", &node.pos()),
+    }
+}
+
+fn check_number(checker: &mut Checker, node: &Box<Node>) {
+    match check_expr(checker, node) {
+        Some(VariableType::Integer) | Some(VariableType::Real) | None => {}
+        Some(_) => checker.error(
+            "Arithmetic operation can only be performed on INTEGER or REAL",
+            &node.pos(),
+        ),
+    }
+}
+
+fn check_string(checker: &mut Checker, node: &Box<Node>) {
+    match check_expr(checker, node) {
+        Some(VariableType::String) | None => {}
+        Some(_) => checker.error("Expected STRING", &node.pos()),
+    }
+}
+
+fn check_record(checker: &mut Checker, t: Option<VariableType>, pos: &Position) {
+    if let Some(t) = t {
+        if let VariableType::Custom(name) = &t {
+            if let Some(TypeDef::Record { .. }) = checker.types.get(name) {
+                return;
+            }
+        }
+        checker.error("Invalid data type. RECORD data type expected", pos);
+    }
+}
+
+fn printable(checker: &Checker, t: &VariableType) -> bool {
+    match t {
+        VariableType::Integer
+        | VariableType::Real
+        | VariableType::String
+        | VariableType::Char
+        | VariableType::Boolean
+        | VariableType::Date => true,
+        VariableType::Custom(name) => matches!(checker.types.get(name), Some(TypeDef::Enum)),
+        _ => false,
+    }
+}
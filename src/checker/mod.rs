@@ -0,0 +1,384 @@
+pub(crate) mod check_expr;
+pub(crate) mod check_stmt;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::checker::check_stmt::{check_routine, check_stmts};
+use crate::enums::{Node, Position, VariableType};
+use crate::utils::{ErrorKind, PseudoError};
+
+// Walk the program without running it and report every problem found.
+// Types the checker cannot work out are left as None and never reported on,
+// so anything flagged here would also fail when the line runs.
+pub fn check(nodes: &Vec<Box<Node>>) -> Vec<PseudoError> {
+    let mut checker = Checker::new();
+    for node in nodes {
+        if let Node::Main { children } = node.deref() {
+            checker.check_main(children);
+        }
+    }
+    let mut errors = checker.errors;
+    errors.sort_by_key(|e| (e.pos.line_start, e.pos.pos_start));
+    errors
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub t: VariableType,
+    pub mutable: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub t: VariableType,
+    pub byref: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub params: Vec<Param>,
+    // None for procedures
+    pub returns: Option<VariableType>,
+    pub private: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub t: VariableType,
+    pub private: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeDef {
+    Record {
+        fields: HashMap<String, Field>,
+    },
+    Class {
+        base: Option<String>,
+        fields: HashMap<String, Field>,
+        methods: HashMap<String, Signature>,
+    },
+    Enum,
+    Pointer(VariableType),
+}
+
+// Whether a value of type from can be stored where type to is expected
+pub fn assignable(to: &VariableType, from: &VariableType) -> bool {
+    to == from || (is_text(to) && is_text(from))
+}
+
+// A CHAR is stored as a STRING of one character and the other way round
+pub fn is_text(t: &VariableType) -> bool {
+    matches!(t, VariableType::Char | VariableType::String)
+}
+
+pub struct Checker {
+    // Innermost scope last, the first one holds the globals
+    pub scopes: Vec<HashMap<String, Symbol>>,
+    pub types: HashMap<String, TypeDef>,
+    pub routines: HashMap<String, Signature>,
+    // Methods callable by name while checking a method body
+    pub methods: HashMap<String, Signature>,
+    // Signature of the routine being checked, None at the top level
+    pub routine: Option<Signature>,
+    pub errors: Vec<PseudoError>,
+}
+
+impl Checker {
+    pub(crate) fn new() -> Self {
+        Checker {
+            scopes: vec![HashMap::new()],
+            types: HashMap::new(),
+            routines: HashMap::new(),
+            methods: HashMap::new(),
+            routine: None,
+            errors: Vec::new(),
+        }
+    }
+
+    fn check_main(&mut self, children: &Vec<Box<Node>>) {
+        self.collect(children);
+        check_stmts(self, children);
+        for node in children {
+            match node.deref() {
+                Node::Function { .. } | Node::Procedure { .. } => check_routine(self, node, None),
+                Node::Class { name, children, .. } => {
+                    if let Node::String { val, .. } = name.deref() {
+                        for child in children {
+                            if let Node::Function { .. } | Node::Procedure { .. } = child.deref() {
+                                check_routine(self, child, Some(val));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Types and subroutines can be used anywhere in the program, so collect them first
+    pub(crate) fn collect(&mut self, children: &Vec<Box<Node>>) {
+        for node in children {
+            self.collect_def(node);
+        }
+        for node in children {
+            self.collect_members(node);
+        }
+    }
+
+    // Register the names of user defined types and subroutines
+    fn collect_def(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Record { name, .. } | Node::Class { name, .. } => {
+                if let Node::String { val, .. } = name.deref() {
+                    let def = match node.deref() {
+                        Node::Record { .. } => TypeDef::Record {
+                            fields: HashMap::new(),
+                        },
+                        _ => TypeDef::Class {
+                            base: None,
+                            fields: HashMap::new(),
+                            methods: HashMap::new(),
+                        },
+                    };
+                    self.declare_type(val, def, &name.pos());
+                }
+            }
+            Node::Enum { name, pos, .. } => self.declare_type(name, TypeDef::Enum, pos),
+            Node::PointerDef { name, ref_to, pos } => {
+                self.declare_type(name, TypeDef::Pointer(*ref_to.clone()), pos)
+            }
+            Node::Function { name, .. } | Node::Procedure { name, .. } => {
+                if let Node::String { val, pos } = name.deref() {
+                    if self.routines.contains_key(val) {
+                        self.error(&format!("'{}' is already declared", val), pos);
+                    }
+                    let signature = self.signature(node);
+                    self.routines.insert(val.clone(), signature);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Fill in record fields and class members once every type name is known
+    fn collect_members(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Record { name, children, .. } => {
+                let mut fields = HashMap::new();
+                for child in children {
+                    if let Node::Declare { t, children, .. } = child.deref() {
+                        self.check_type(t, &child.pos());
+                        for field in children {
+                            fields.insert(
+                                field.clone(),
+                                Field {
+                                    t: *t.clone(),
+                                    private: false,
+                                },
+                            );
+                        }
+                    }
+                }
+                if let Node::String { val, .. } = name.deref() {
+                    self.types.insert(val.clone(), TypeDef::Record { fields });
+                }
+            }
+            Node::Class {
+                name,
+                base,
+                children,
+                ..
+            } => {
+                let mut fields = HashMap::new();
+                let mut methods = HashMap::new();
+                for child in children {
+                    match child.deref() {
+                        Node::Declare {
+                            t,
+                            children,
+                            private,
+                            ..
+                        } => {
+                            self.check_type(t, &child.pos());
+                            for field in children {
+                                if fields.contains_key(field) {
+                                    self.error(
+                                        &format!("Property {} already exists", field),
+                                        &child.pos(),
+                                    );
+                                }
+                                fields.insert(
+                                    field.clone(),
+                                    Field {
+                                        t: *t.clone(),
+                                        private: *private,
+                                    },
+                                );
+                            }
+                        }
+                        Node::Function { name, .. } | Node::Procedure { name, .. } => {
+                            if let Node::String { val, pos } = name.deref() {
+                                if methods.contains_key(val) {
+                                    self.error(&format!("Method {} already exists", val), pos);
+                                }
+                                methods.insert(val.clone(), self.signature(child));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                let base = match base.deref() {
+                    Node::String { val, pos } => {
+                        if let Some(TypeDef::Class { .. }) = self.types.get(val) {
+                        } else {
+                            self.error(&format!("Class '{}' is not defined", val), pos);
+                        }
+                        Some(val.clone())
+                    }
+                    _ => None,
+                };
+                if let Node::String { val, pos } = name.deref() {
+                    match methods.get("new") {
+                        Some(Signature { returns: None, .. }) => {}
+                        _ => self.error("Class must have a constructor", pos),
+                    }
+                    self.types.insert(
+                        val.clone(),
+                        TypeDef::Class {
+                            base,
+                            fields,
+                            methods,
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn signature(&mut self, node: &Box<Node>) -> Signature {
+        let (params, returns, private) = match node.deref() {
+            Node::Function {
+                params,
+                return_type,
+                private,
+                ..
+            } => (params, Some(*return_type.clone()), *private),
+            Node::Procedure {
+                params, private, ..
+            } => (params, None, *private),
+            _ => unreachable!(),
+        };
+        let params = params
+            .iter()
+            .filter_map(|param| match param.deref() {
+                Node::Declare {
+                    t, children, byref, ..
+                } => Some(Param {
+                    name: children[0].clone(),
+                    t: *t.clone(),
+                    byref: *byref,
+                }),
+                _ => None,
+            })
+            .collect();
+        Signature {
+            params,
+            returns,
+            private,
+        }
+    }
+
+    fn declare_type(&mut self, name: &String, def: TypeDef, pos: &Position) {
+        if self.types.contains_key(name) {
+            self.error(&format!("'{}' is already declared", name), pos);
+        }
+        self.types.insert(name.clone(), def);
+    }
+
+    pub fn declare_var(&mut self, name: &String, t: VariableType, mutable: bool, pos: &Position) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(name) {
+            self.error(&format!("'{}' is already initialized", name), pos);
+            return;
+        }
+        scope.insert(name.clone(), Symbol { t, mutable });
+    }
+
+    pub fn get_var(&self, name: &String) -> Option<&Symbol> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // Report a type name that was never defined
+    pub fn check_type(&mut self, t: &VariableType, pos: &Position) {
+        match t {
+            VariableType::Custom(name) if !self.types.contains_key(name) => {
+                self.error(&format!("Type '{}' is not defined", name), pos);
+            }
+            VariableType::Array { t, .. } | VariableType::Pointer(t) => self.check_type(t, pos),
+            _ => {}
+        }
+    }
+
+    // Variables declared with a pointer TYPE hold pointers to the referenced type
+    pub fn resolve(&self, t: &VariableType) -> VariableType {
+        match t {
+            VariableType::Custom(name) => match self.types.get(name) {
+                Some(TypeDef::Pointer(ref_to)) => {
+                    VariableType::Pointer(Box::new(self.resolve(ref_to)))
+                }
+                _ => t.clone(),
+            },
+            VariableType::Array { shape, t } => VariableType::Array {
+                shape: shape.clone(),
+                t: Box::new(self.resolve(t)),
+            },
+            VariableType::Pointer(t) => VariableType::Pointer(Box::new(self.resolve(t))),
+            _ => t.clone(),
+        }
+    }
+
+    // Look a property up on a record or class, following the INHERITS chain
+    pub fn get_field(&self, type_name: &String, name: &String) -> Option<Field> {
+        match self.types.get(type_name)? {
+            TypeDef::Record { fields } => fields.get(name).cloned(),
+            TypeDef::Class { base, fields, .. } => match fields.get(name) {
+                Some(field) => Some(field.clone()),
+                None => self.get_field(base.as_ref()?, name),
+            },
+            _ => None,
+        }
+    }
+
+    pub fn get_method(&self, type_name: &String, name: &String) -> Option<Signature> {
+        match self.types.get(type_name)? {
+            TypeDef::Class { base, methods, .. } => match methods.get(name) {
+                Some(method) => Some(method.clone()),
+                None => self.get_method(base.as_ref()?, name),
+            },
+            _ => None,
+        }
+    }
+
+    // A STRING stored in a CHAR is checked for length when the program runs,
+    // literals can be checked straight away
+    pub fn check_char(&mut self, t: &VariableType, value: &Box<Node>) {
+        if let (VariableType::Char, Node::String { val, pos }) = (t, value.deref()) {
+            let length = val.chars().count();
+            if length != 1 {
+                self.error(
+                    format!("Cannot assign a STRING of {} characters to a CHAR", length).as_str(),
+                    pos,
+                );
+            }
+        }
+    }
+
+    pub fn error(&mut self, message: &str, pos: &Position) {
+        self.errors
+            .push(PseudoError::new(ErrorKind::Semantic, message, pos));
+    }
+}
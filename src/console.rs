@@ -0,0 +1,63 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use pseudoengine::{DiskFileSystem, FileSystem, IoBackend};
+
+// Input and output shared by a prompt, such as the debugger's or the repl's, and
+// the program's INPUT and OUTPUT
+#[derive(Clone)]
+pub struct Console {
+    input: Rc<RefCell<Box<dyn BufRead>>>,
+    output: Rc<RefCell<Box<dyn Write>>>,
+}
+
+impl Console {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Console {
+            input: Rc::new(RefCell::new(Box::new(input))),
+            output: Rc::new(RefCell::new(Box::new(output))),
+        }
+    }
+
+    pub fn read_line(&self) -> Option<String> {
+        let mut line = String::new();
+        match self.input.borrow_mut().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+
+    pub fn print(&self, text: &str) {
+        let mut output = self.output.borrow_mut();
+        let _ = output.write_all(text.as_bytes());
+        let _ = output.flush();
+    }
+}
+
+impl IoBackend for Console {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Ok(Console::read_line(self))
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.print(text);
+        Ok(())
+    }
+
+    fn file_exists(&self, path: &str) -> bool {
+        DiskFileSystem.exists(path)
+    }
+
+    fn read_file(&mut self, path: &str) -> io::Result<String> {
+        DiskFileSystem.read(path)
+    }
+
+    fn write_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        DiskFileSystem.write(path, content)
+    }
+
+    fn append_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        DiskFileSystem.append(path, content)
+    }
+}
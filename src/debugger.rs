@@ -0,0 +1,267 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::ops::Deref;
+
+use pseudoengine::enums::{Node, Position};
+use pseudoengine::executor::hook::Hook;
+use pseudoengine::executor::{self, Executor, Property, Scope, Value};
+use pseudoengine::parser::parse_expr::parse_expression;
+use pseudoengine::{lexer, parser, PseudoError, SourceFile};
+
+use crate::console::Console;
+
+const PROMPT: &str = "(debug) ";
+const HELP: &str = "Commands:
+  step, s            run to the next statement
+  next, n            run to the next statement, stepping over calls
+  continue, c        run to the next breakpoint
+  break, b <line>    pause before the statement on a line
+  delete, d <line>   remove a breakpoint
+  print, p <expr>    show the value of an expression
+  locals             show the variables in the current scope
+  backtrace, bt      show the calls leading to the current statement
+  quit, q            stop the program";
+
+// Run the program, pausing before statements to take commands from input.
+// Commands and the program's INPUT are read from the same input, one line each.
+pub fn debug(
+    name: &str,
+    text: &str,
+    input: impl BufRead + 'static,
+    output: impl Write + 'static,
+) -> Result<(), Vec<PseudoError>> {
+    let source = SourceFile::new(name, text);
+    let console = Console::new(input, output);
+
+    let mut buf = text.to_string();
+    buf.push('\n');
+    let tokens = lexer::lexer(&mut buf.chars()).map_err(|e| vec![e.with_source(name)])?;
+    let nodes = parser::parse_program(&mut tokens.into_iter().peekable()).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|e| e.with_source(name))
+            .collect::<Vec<_>>()
+    })?;
+
+    let mut executor = Executor::with_io(Box::new(console.clone()));
+    executor.hook = Some(Box::new(Debugger {
+        source,
+        console: console.clone(),
+        breakpoints: BTreeSet::new(),
+        mode: Mode::Step,
+        frames: Vec::new(),
+        line: 0,
+    }));
+    let result = executor::run_with(&mut executor, nodes);
+    match &result {
+        Ok(()) => console.print("Program finished\n"),
+        Err(_) => console.print("Program stopped on an error\n"),
+    }
+    result.map_err(|e| vec![e.with_source(name)])
+}
+
+enum Mode {
+    Step,
+    // Step over calls made deeper than this many frames
+    Next(usize),
+    Continue,
+}
+
+struct Frame {
+    name: String,
+    // Line the call was made from
+    line: usize,
+}
+
+struct Debugger {
+    source: SourceFile,
+    console: Console,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    frames: Vec<Frame>,
+    // Line of the statement about to run
+    line: usize,
+}
+
+impl Hook for Debugger {
+    fn before_stmt(
+        &mut self,
+        executor: &mut Executor,
+        node: &Box<Node>,
+    ) -> Result<(), PseudoError> {
+        // Definitions are registered before the program starts, there is nothing to step through
+        if let Node::Function { .. }
+        | Node::Procedure { .. }
+        | Node::Class { .. }
+        | Node::Record { .. }
+        | Node::PointerDef { .. }
+        | Node::Enum { .. } = node.deref()
+        {
+            return Ok(());
+        }
+        self.line = node.pos().line_start;
+        let pause = match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) => self.frames.len() <= depth,
+            Mode::Continue => false,
+        } || self.breakpoints.contains(&self.line);
+        if pause {
+            self.show_line();
+            self.prompt(executor);
+        }
+        Ok(())
+    }
+
+    fn enter_call(&mut self, name: &str, pos: &Position) {
+        self.frames.push(Frame {
+            name: name.to_string(),
+            line: pos.line_start,
+        });
+    }
+
+    fn exit_call(&mut self) {
+        self.frames.pop();
+    }
+}
+
+impl Debugger {
+    fn show_line(&self) {
+        let text = self
+            .source
+            .file
+            .get(self.line.wrapping_sub(1))
+            .map_or("", |line| line.as_str());
+        self.console
+            .print(&format!("{:>4} | {}\n", self.line, text));
+    }
+
+    // Take commands until one of them resumes the program
+    fn prompt(&mut self, executor: &mut Executor) {
+        loop {
+            self.console.print(PROMPT);
+            let Some(line) = self.console.read_line() else {
+                // Nobody left to give commands, so let the program finish
+                self.mode = Mode::Continue;
+                self.breakpoints.clear();
+                return;
+            };
+            let line = line.trim();
+            let (command, arg) = match line.split_once(char::is_whitespace) {
+                Some((command, arg)) => (command, arg.trim()),
+                None => (line, ""),
+            };
+            match command {
+                "step" | "s" => {
+                    self.mode = Mode::Step;
+                    return;
+                }
+                "next" | "n" => {
+                    self.mode = Mode::Next(self.frames.len());
+                    return;
+                }
+                "continue" | "c" => {
+                    self.mode = Mode::Continue;
+                    return;
+                }
+                "break" | "b" => match arg.parse::<usize>() {
+                    Ok(line) if line >= 1 && line <= self.source.file.len() => {
+                        self.breakpoints.insert(line);
+                        self.console
+                            .print(&format!("Breakpoint set at line {}\n", line));
+                    }
+                    _ => self
+                        .console
+                        .print("Expected a line number in the program\n"),
+                },
+                "delete" | "d" => match arg.parse::<usize>() {
+                    Ok(line) if self.breakpoints.remove(&line) => self
+                        .console
+                        .print(&format!("Breakpoint at line {} removed\n", line)),
+                    _ => self.console.print("No breakpoint at that line\n"),
+                },
+                "print" | "p" => {
+                    let text = match evaluate(executor, arg) {
+                        Ok(value) => display(&value),
+                        Err(e) => format!("error: {}", e.message),
+                    };
+                    self.console.print(&format!("{}\n", text));
+                }
+                "locals" => self.locals(executor),
+                "backtrace" | "bt" => self.backtrace(),
+                "quit" | "q" => std::process::exit(0),
+                "" => {}
+                _ => self.console.print(&format!("{}\n", HELP)),
+            }
+        }
+    }
+
+    fn locals(&self, executor: &Executor) {
+        let variables = match executor.scopes.last() {
+            Some(Scope::Global(state)) | Some(Scope::Local(state)) => &state.variables,
+            None => return,
+        };
+        let mut names: Vec<&String> = variables.keys().collect();
+        names.sort();
+        if names.is_empty() {
+            self.console.print("No variables\n");
+        }
+        for name in names {
+            let var = &variables[name];
+            self.console.print(&format!(
+                "{} : {} = {}\n",
+                name,
+                var.t.str(),
+                display(&var.value.borrow())
+            ));
+        }
+    }
+
+    fn backtrace(&self) {
+        let mut line = self.line;
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            self.console
+                .print(&format!("#{} {} at line {}\n", i, frame.name, line));
+            line = frame.line;
+        }
+        self.console
+            .print(&format!("#{} main at line {}\n", self.frames.len(), line));
+    }
+}
+
+fn evaluate(executor: &mut Executor, text: &str) -> Result<Value, PseudoError> {
+    let mut buf = text.to_string();
+    buf.push('\n');
+    let mut lex = lexer::lexer(&mut buf.chars())?.into_iter().peekable();
+    let expr = parse_expression(&mut lex)?;
+    executor::evaluate(executor, &expr)
+}
+
+// Values are shown the way OUTPUT prints them, with strings quoted and
+// arrays, records and objects written out in full
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(val) => format!("{:?}", val),
+        Value::Char(val) => format!("'{}'", val),
+        Value::Array(array) => {
+            let values: Vec<String> = array.values.iter().map(|v| display(&v.borrow())).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Value::Record(_) | Value::Object(_) => {
+            let props = value.props().unwrap();
+            let mut names: Vec<&String> = props.keys().collect();
+            names.sort();
+            let props: Vec<String> = names
+                .into_iter()
+                .filter_map(|prop| match &props[prop] {
+                    Property::Var { value, .. } => {
+                        Some(format!("{}: {}", prop, display(&value.borrow())))
+                    }
+                    Property::Method { .. } => None,
+                })
+                .collect();
+            format!("{} {{{}}}", value.type_of().str(), props.join(", "))
+        }
+        // Pointers can form cycles, so only where they point is shown
+        _ => value.to_string(),
+    }
+}
@@ -1,12 +1,5 @@
-use crate::{executor::Property, tokens::TToken};
+use crate::tokens::TToken;
 use chrono::NaiveDate;
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    fmt::{format, Display},
-    ops::Deref,
-    rc::Rc,
-};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
@@ -75,8 +68,6 @@ pub struct Token {
     pub pos: Position,
 }
 
-pub type NodeRef = Rc<RefCell<Box<Node>>>;
-
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Node {
@@ -116,6 +107,10 @@ pub enum Node {
         val: String,
         pos: Position,
     },
+    Char {
+        val: char,
+        pos: Position,
+    },
     Boolean {
         val: bool,
         pos: Position,
@@ -287,26 +282,7 @@ pub enum Node {
         children: Vec<Box<Node>>,
         pos: Position,
     },
-
-    // Interpreter only
-    Array {
-        t: Box<VariableType>,
-        values: Vec<NodeRef>,
-        shape: Vec<Index>,
-    },
-    EnumVal {
-        family: String,
-        val: String,
-    },
-    Object {
-        name: String,
-        base: Box<Node>,
-        props: HashMap<String, Property>,
-    },
-    Pointer(NodeRef),
-    RefVar(NodeRef),
     Null,
-    NullObject(VariableType),
 }
 
 impl Node {
@@ -314,58 +290,57 @@ impl Node {
         match self {
             Node::Int { val, .. } => val.to_string(),
             Node::String { val, .. } => val.clone(),
+            Node::Char { val, .. } => val.to_string(),
             Node::Boolean { val, .. } => val.to_string(),
             Node::Real { val, .. } => val.to_string(),
             Node::Date { val, .. } => val.to_string(),
-            Node::EnumVal { val, .. } => val.to_string(),
             _ => unimplemented!(),
         }
     }
 
     pub fn pos(&self) -> Position {
         match &self {
-            Node::Unary { pos, .. } => pos.clone(),
-            Node::Binary { pos, .. } => pos.clone(),
-            Node::Class { pos, .. } => pos.clone(),
-            Node::Object { base, .. } => base.pos(),
-            Node::Pointer(node_ref) => node_ref.borrow().pos(),
-            Node::RefVar(node_ref) => node_ref.borrow().pos(),
-            Node::Int { pos, .. } => pos.clone(),
-            Node::String { pos, .. } => pos.clone(),
-            Node::Boolean { pos, .. } => pos.clone(),
-            Node::Real { pos, .. } => pos.clone(),
-            Node::Date { pos, .. } => pos.clone(),
-            Node::Unary { pos, .. } => pos.clone(),
-            Node::Binary { pos, .. } => pos.clone(),
-            Node::Function { pos, .. } => pos.clone(),
-            Node::Procedure { pos, .. } => pos.clone(),
-            Node::CreateObject { pos, .. } => pos.clone(),
-            Node::Var { pos, .. } => pos.clone(),
-            Node::Const { pos, .. } => pos.clone(),
-            Node::PointerDef { pos, .. } => pos.clone(),
-            Node::Declare { pos, .. } => pos.clone(),
-            Node::Range { pos, .. } => pos.clone(),
-            Node::If { pos, .. } => pos.clone(),
-            Node::While { pos, .. } => pos.clone(),
-            Node::Repeat { pos, .. } => pos.clone(),
-            Node::For { pos, .. } => pos.clone(),
-            Node::Switch { pos, .. } => pos.clone(),
-            Node::Case { pos, .. } => pos.clone(),
-            Node::Output { pos, .. } => pos.clone(),
-            Node::Input { pos, .. } => pos.clone(),
-            Node::OpenFile { pos, .. } => pos.clone(),
-            Node::CloseFile { pos, .. } => pos.clone(),
-            Node::ReadFile { pos, .. } => pos.clone(),
-            Node::WriteFile { pos, .. } => pos.clone(),
-            Node::PutRecord { pos, .. } => pos.clone(),
-            Node::GetRecord { pos, .. } => pos.clone(),
-            Node::Reference { pos, .. } => pos.clone(),
-            Node::Dereference { pos, .. } => pos.clone(),
-            Node::SeekFile { pos, .. } => pos.clone(),
-            Node::Return { pos, .. } => pos.clone(),
-            Node::Composite { pos, .. } => pos.clone(),
-            Node::ArrayVar { pos, .. } => pos.clone(),
-            Node::FunctionCall { pos, .. } => pos.clone(),
+            Node::Unary { pos, .. } => *pos,
+            Node::Binary { pos, .. } => *pos,
+            Node::Class { pos, .. } => *pos,
+            Node::Int { pos, .. } => *pos,
+            Node::String { pos, .. } => *pos,
+            Node::Char { pos, .. } => *pos,
+            Node::Boolean { pos, .. } => *pos,
+            Node::Real { pos, .. } => *pos,
+            Node::Date { pos, .. } => *pos,
+            Node::Function { pos, .. } => *pos,
+            Node::Procedure { pos, .. } => *pos,
+            Node::CreateObject { pos, .. } => *pos,
+            Node::Var { pos, .. } => *pos,
+            Node::Const { pos, .. } => *pos,
+            Node::PointerDef { pos, .. } => *pos,
+            Node::Declare { pos, .. } => *pos,
+            Node::Range { pos, .. } => *pos,
+            Node::If { pos, .. } => *pos,
+            Node::While { pos, .. } => *pos,
+            Node::Repeat { pos, .. } => *pos,
+            Node::For { pos, .. } => *pos,
+            Node::Switch { pos, .. } => *pos,
+            Node::Case { pos, .. } => *pos,
+            Node::Output { pos, .. } => *pos,
+            Node::Input { pos, .. } => *pos,
+            Node::OpenFile { pos, .. } => *pos,
+            Node::CloseFile { pos, .. } => *pos,
+            Node::ReadFile { pos, .. } => *pos,
+            Node::WriteFile { pos, .. } => *pos,
+            Node::PutRecord { pos, .. } => *pos,
+            Node::GetRecord { pos, .. } => *pos,
+            Node::Reference { pos, .. } => *pos,
+            Node::Dereference { pos, .. } => *pos,
+            Node::SeekFile { pos, .. } => *pos,
+            Node::Return { pos, .. } => *pos,
+            Node::Composite { pos, .. } => *pos,
+            Node::ArrayVar { pos, .. } => *pos,
+            Node::FunctionCall { pos, .. } => *pos,
+            Node::Assignment { pos, .. } => *pos,
+            Node::Record { pos, .. } => *pos,
+            Node::Enum { pos, .. } => *pos,
             _ => unimplemented!("{:?}", self),
         }
     }
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Storage used by OPENFILE, READFILE, WRITEFILE and the record statements
+pub trait FileSystem {
+    fn exists(&self, path: &str) -> bool;
+    fn read(&mut self, path: &str) -> io::Result<String>;
+    // Replace the content of the file, creating it if needed
+    fn write(&mut self, path: &str, content: &str) -> io::Result<()>;
+    // Add to the end of an existing file
+    fn append(&mut self, path: &str, content: &str) -> io::Result<()>;
+
+    // Why the program may not use path, if there is a reason
+    fn check(&self, _path: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// Files on the disk, relative to the working directory
+pub struct DiskFileSystem;
+
+impl FileSystem for DiskFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        Path::new(path).is_file()
+    }
+
+    fn read(&mut self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&mut self, path: &str, content: &str) -> io::Result<()> {
+        fs::write(path, content)
+    }
+
+    fn append(&mut self, path: &str, content: &str) -> io::Result<()> {
+        OpenOptions::new()
+            .append(true)
+            .open(path)?
+            .write_all(content.as_bytes())
+    }
+}
+
+// Files kept in memory, keyed by the name given to OPENFILE.
+// Clones share the same files.
+#[derive(Clone, Default)]
+pub struct MemoryFileSystem {
+    files: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        MemoryFileSystem::default()
+    }
+
+    pub fn insert(&self, path: &str, content: &str) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), content.to_string());
+    }
+
+    pub fn get(&self, path: &str) -> Option<String> {
+        self.files.lock().unwrap().get(path).cloned()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        self.files.lock().unwrap().contains_key(path)
+    }
+
+    fn read(&mut self, path: &str) -> io::Result<String> {
+        self.get(path).ok_or(io::ErrorKind::NotFound.into())
+    }
+
+    fn write(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.insert(path, content);
+        Ok(())
+    }
+
+    fn append(&mut self, path: &str, content: &str) -> io::Result<()> {
+        match self.files.lock().unwrap().get_mut(path) {
+            Some(file) => {
+                file.push_str(content);
+                Ok(())
+            }
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+}
+
+// Files on the disk inside root, given paths relative to it. A path leading out of
+// root through a link is refused, and folders inside it are made as needed.
+pub struct RootedFileSystem {
+    root: PathBuf,
+}
+
+impl RootedFileSystem {
+    pub fn new(root: &Path) -> io::Result<Self> {
+        Ok(RootedFileSystem {
+            root: root.canonicalize()?,
+        })
+    }
+
+    fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let path = self.root.join(path);
+        // A file not made yet is as safe as the folder it would be made in
+        let existing = path
+            .ancestors()
+            .find(|ancestor| ancestor.symlink_metadata().is_ok())
+            .unwrap();
+        if existing.canonicalize()?.starts_with(&self.root) {
+            Ok(path)
+        } else {
+            Err(io::ErrorKind::PermissionDenied.into())
+        }
+    }
+}
+
+impl FileSystem for RootedFileSystem {
+    fn exists(&self, path: &str) -> bool {
+        self.resolve(path).is_ok_and(|path| path.is_file())
+    }
+
+    fn read(&mut self, path: &str) -> io::Result<String> {
+        fs::read_to_string(self.resolve(path)?)
+    }
+
+    fn write(&mut self, path: &str, content: &str) -> io::Result<()> {
+        let path = self.resolve(path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)
+    }
+
+    fn append(&mut self, path: &str, content: &str) -> io::Result<()> {
+        OpenOptions::new()
+            .append(true)
+            .open(self.resolve(path)?)?
+            .write_all(content.as_bytes())
+    }
+
+    fn check(&self, path: &str) -> Result<(), String> {
+        match self.resolve(path) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                Err("the path leads outside the file system root".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Keeps a program to the files of another file system it is allowed, by refusing
+// absolute paths and paths climbing out with '..'. The rest are passed on in one
+// form, so 'data/../a.txt' and './a.txt' are both the file 'a.txt'.
+pub struct Jail<F> {
+    inner: F,
+}
+
+impl<F: FileSystem> Jail<F> {
+    pub fn new(inner: F) -> Self {
+        Jail { inner }
+    }
+}
+
+// The path relative to the jail, with '/' between its parts
+pub fn jail_path(path: &str) -> Result<String, String> {
+    let mut chars = path.chars();
+    let drive = chars.next().is_some_and(|c| c.is_ascii_alphabetic()) && chars.next() == Some(':');
+    if path.starts_with(['/', '\\']) || drive {
+        return Err("absolute paths are not allowed".to_string());
+    }
+    let mut parts = vec![];
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err("the path leads outside the file system root".to_string());
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+    if parts.is_empty() {
+        return Err("the path does not name a file".to_string());
+    }
+    Ok(parts.join("/"))
+}
+
+fn refused(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, reason)
+}
+
+impl<F: FileSystem> FileSystem for Jail<F> {
+    fn exists(&self, path: &str) -> bool {
+        jail_path(path).is_ok_and(|path| self.inner.exists(&path))
+    }
+
+    fn read(&mut self, path: &str) -> io::Result<String> {
+        self.inner.read(&jail_path(path).map_err(refused)?)
+    }
+
+    fn write(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.inner
+            .write(&jail_path(path).map_err(refused)?, content)
+    }
+
+    fn append(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.inner
+            .append(&jail_path(path).map_err(refused)?, content)
+    }
+
+    fn check(&self, path: &str) -> Result<(), String> {
+        self.inner.check(&jail_path(path)?)
+    }
+}
+
+// Copies every file under dir into fs at the same path relative to dir, returning
+// how many there were
+pub fn seed(fs: &mut dyn FileSystem, dir: &Path) -> io::Result<usize> {
+    let mut count = 0;
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                let parts: Vec<String> = path
+                    .components()
+                    .map(|part| part.as_os_str().to_string_lossy().to_string())
+                    .collect();
+                fs.write(&parts.join("/"), &fs::read_to_string(entry.path())?)?;
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
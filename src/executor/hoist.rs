@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+
+use crate::enums::{Node, Position, VariableType};
+use crate::utils::{err, PseudoError};
+
+// The definitions of the main program in the order they are made before its first
+// statement runs, so a subroutine or type can be used above where it is written.
+// Enums, pointers and subroutines come first, then records and classes once the
+// types their properties use are defined. Defining a name twice is an error.
+pub(crate) fn hoisted(children: &[Box<Node>]) -> Result<Vec<&Box<Node>>, PseudoError> {
+    let mut names = HashSet::new();
+    for node in children {
+        if let Some((name, pos)) = definition(node) {
+            if !names.insert(name.clone()) {
+                err(format!("'{}' is already declared", name).as_str(), &pos)?
+            }
+        }
+    }
+
+    let (mut order, mut types): (Vec<&Box<Node>>, Vec<&Box<Node>>) = children
+        .iter()
+        .filter(|node| is_definition(node))
+        .partition(|node| uses(node).is_none());
+    while !types.is_empty() {
+        let pending: Vec<String> = types
+            .iter()
+            .map(|node| definition(node).unwrap().0)
+            .collect();
+        // A record or class using a type that can never be defined is left to fail
+        // when it is reached, after the others
+        let ready = types
+            .iter()
+            .position(|node| {
+                let uses = uses(node).unwrap_or_default();
+                uses.iter().all(|name| !pending.contains(name))
+            })
+            .unwrap_or(0);
+        order.push(types.remove(ready));
+    }
+    Ok(order)
+}
+
+pub(crate) fn is_definition(node: &Box<Node>) -> bool {
+    definition(node).is_some()
+}
+
+// The name a definition gives, and where an error about it points
+fn definition(node: &Box<Node>) -> Option<(String, Position)> {
+    match node.deref() {
+        Node::Function { name, .. }
+        | Node::Procedure { name, .. }
+        | Node::Class { name, .. }
+        | Node::Record { name, .. } => match name.deref() {
+            Node::String { val, pos } => Some((val.clone(), *pos)),
+            _ => None,
+        },
+        Node::Enum { name, pos, .. } | Node::PointerDef { name, pos, .. } => {
+            Some((name.clone(), *pos))
+        }
+        _ => None,
+    }
+}
+
+// Types that must be defined before a record or class can be, None for the
+// definitions that need no others
+fn uses(node: &Box<Node>) -> Option<Vec<String>> {
+    let mut names = vec![];
+    let children = match node.deref() {
+        Node::Record { children, .. } => children,
+        Node::Class { base, children, .. } => {
+            if let Node::String { val, .. } = base.deref() {
+                names.push(val.clone());
+            }
+            children
+        }
+        _ => return None,
+    };
+    for child in children {
+        if let Node::Declare { t, .. } = child.deref() {
+            type_names(t, &mut names);
+        }
+    }
+    Some(names)
+}
+
+// A pointer starts out pointing nowhere, so the type it points to is not needed
+fn type_names(t: &VariableType, names: &mut Vec<String>) {
+    match t {
+        VariableType::Custom(name) => names.push(name.clone()),
+        VariableType::Array { t, .. } => type_names(t, names),
+        _ => {}
+    }
+}
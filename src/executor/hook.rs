@@ -0,0 +1,31 @@
+use crate::enums::{Node, Position};
+use crate::executor::{Executor, Value};
+use crate::utils::{PseudoError, SourceFile};
+
+// Lets a tool such as the debugger watch the program as it runs
+pub trait Hook {
+    // Called once with the program before it runs
+    fn program(&mut self, _source: &SourceFile, _ast: &Vec<Box<Node>>) {}
+
+    // Called before each statement runs, an error stops the program
+    fn before_stmt(
+        &mut self,
+        _executor: &mut Executor,
+        _node: &Box<Node>,
+    ) -> Result<(), PseudoError> {
+        Ok(())
+    }
+
+    // Called when a function, procedure or method is entered from pos
+    fn enter_call(&mut self, _name: &str, _pos: &Position) {}
+
+    // Called when the innermost call returns
+    fn exit_call(&mut self) {}
+
+    // Called when an assignment, INPUT or FOR loop gives a variable a new value.
+    // target names what changed, such as count, nums[2] or p.x
+    fn assigned(&mut self, _target: &str, _value: &Value, _pos: &Position) {}
+
+    // Called with each line OUTPUT prints, without the newline
+    fn output(&mut self, _line: &str, _pos: &Position) {}
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::executor::file_system::{DiskFileSystem, FileSystem, MemoryFileSystem};
+
+// Everything a program can do to the outside world goes through here
+pub trait IoBackend {
+    // Next line of console input without its line ending, None once input runs out
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+    fn write(&mut self, text: &str) -> io::Result<()>;
+
+    fn file_exists(&self, path: &str) -> bool;
+    fn read_file(&mut self, path: &str) -> io::Result<String>;
+    fn write_file(&mut self, path: &str, content: &str) -> io::Result<()>;
+    fn append_file(&mut self, path: &str, content: &str) -> io::Result<()>;
+
+    // Why the program may not open path, if there is a reason
+    fn check_path(&self, _path: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// Console streams paired with a filesystem, stdin/stdout and the disk by default
+pub struct StreamIo {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    fs: Box<dyn FileSystem>,
+}
+
+impl StreamIo {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>, fs: Box<dyn FileSystem>) -> Self {
+        StreamIo { input, output, fs }
+    }
+
+    pub fn std() -> Self {
+        StreamIo::new(
+            Box::new(BufReader::new(io::stdin())),
+            Box::new(io::stdout()),
+            Box::new(DiskFileSystem),
+        )
+    }
+}
+
+impl IoBackend for StreamIo {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.write_all(text.as_bytes())
+    }
+
+    fn file_exists(&self, path: &str) -> bool {
+        self.fs.exists(path)
+    }
+
+    fn read_file(&mut self, path: &str) -> io::Result<String> {
+        self.fs.read(path)
+    }
+
+    fn write_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.fs.write(path, content)
+    }
+
+    fn append_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.fs.append(path, content)
+    }
+
+    fn check_path(&self, path: &str) -> Result<(), String> {
+        self.fs.check(path)
+    }
+}
+
+// Scripted input, captured output and files that never touch the disk.
+// Clones share their state so a copy can be inspected after the run.
+#[derive(Clone, Default)]
+pub struct MemoryIo {
+    input: Arc<Mutex<VecDeque<String>>>,
+    output: Arc<Mutex<String>>,
+    pub files: MemoryFileSystem,
+}
+
+impl MemoryIo {
+    pub fn new(input: &str) -> Self {
+        let io = MemoryIo::default();
+        io.input
+            .lock()
+            .unwrap()
+            .extend(input.lines().map(|line| line.to_string()));
+        io
+    }
+
+    pub fn with_files(mut self, files: MemoryFileSystem) -> Self {
+        self.files = files;
+        self
+    }
+
+    pub fn output(&self) -> String {
+        self.output.lock().unwrap().clone()
+    }
+}
+
+impl IoBackend for MemoryIo {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Ok(self.input.lock().unwrap().pop_front())
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.lock().unwrap().push_str(text);
+        Ok(())
+    }
+
+    fn file_exists(&self, path: &str) -> bool {
+        self.files.exists(path)
+    }
+
+    fn read_file(&mut self, path: &str) -> io::Result<String> {
+        self.files.read(path)
+    }
+
+    fn write_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.files.write(path, content)
+    }
+
+    fn append_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.files.append(path, content)
+    }
+}
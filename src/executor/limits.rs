@@ -0,0 +1,181 @@
+use std::time::{Duration, Instant};
+
+use crate::enums::{Index, Position};
+use crate::executor::variable::{Executor, MAX_CALL_DEPTH};
+use crate::utils::{err, limit_err, Limit, PseudoError};
+
+// The clock is only read every so many steps, reading it is slow next to a step
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Room on the stack for each nested call the tree-walker makes, most of it taken
+// by unoptimised builds, and for everything beneath the first call
+const STACK_PER_CALL: usize = 96 * 1024;
+const STACK_BASE: usize = 8 * 1024 * 1024;
+
+// How much a program may do before it is stopped, for running programs that cannot
+// be trusted to finish. Only the call depth is limited unless asked for.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    // Statements run and loops gone round
+    pub max_steps: Option<u64>,
+    pub max_time: Option<Duration>,
+    pub max_call_depth: usize,
+    // Bytes printed by OUTPUT
+    pub max_output_bytes: Option<usize>,
+    // Elements of any one array
+    pub max_array_elements: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_time: None,
+            max_call_depth: MAX_CALL_DEPTH,
+            max_output_bytes: None,
+            max_array_elements: None,
+        }
+    }
+}
+
+impl Limits {
+    // The stack a thread needs to reach max_call_depth without overflowing
+    pub fn stack_size(&self) -> usize {
+        STACK_PER_CALL * (self.max_call_depth + 1) + STACK_BASE
+    }
+}
+
+// What the program has used so far
+#[derive(Debug, Clone)]
+pub struct Usage {
+    pub steps: u64,
+    // The step at which the limits are next looked at
+    pub next_check: u64,
+    pub output_bytes: usize,
+    pub started: Instant,
+}
+
+impl Usage {
+    pub fn new() -> Self {
+        Usage {
+            steps: 0,
+            next_check: 0,
+            output_bytes: 0,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Executor {
+    // Counts a statement about to run at pos, or a loop about to go round again
+    #[inline]
+    pub fn step(&mut self, pos: &Position) -> Result<(), PseudoError> {
+        self.usage.steps += 1;
+        if self.usage.steps < self.usage.next_check {
+            return Ok(());
+        }
+        self.check_steps(pos)
+    }
+
+    // The limits on steps and time are only looked at once enough steps have been run
+    fn check_steps(&mut self, pos: &Position) -> Result<(), PseudoError> {
+        let steps = self.usage.steps;
+        if let Some(max) = self.limits.max_steps {
+            if steps > max {
+                limit_err(
+                    Limit::Steps,
+                    format!(
+                        "Step limit of {} reached before the program finished. Check for a loop that never ends",
+                        max
+                    )
+                    .as_str(),
+                    pos,
+                )?
+            }
+        }
+        if let Some(max) = self.limits.max_time {
+            if self.usage.started.elapsed() > max {
+                limit_err(
+                    Limit::Time,
+                    format!(
+                        "Time limit reached, the program ran for more than {:?}",
+                        max
+                    )
+                    .as_str(),
+                    pos,
+                )?
+            }
+        }
+        let time_check = match self.limits.max_time {
+            Some(_) => steps + TIME_CHECK_INTERVAL,
+            None => u64::MAX,
+        };
+        let step_check = self
+            .limits
+            .max_steps
+            .map_or(u64::MAX, |max| max.saturating_add(1));
+        self.usage.next_check = time_check.min(step_check);
+        Ok(())
+    }
+
+    // Checks there is room for another call on top of the calls running
+    pub fn check_call_depth(&self, depth: usize, pos: &Position) -> Result<(), PseudoError> {
+        if depth >= self.limits.max_call_depth {
+            limit_err(
+                Limit::CallDepth,
+                format!(
+                    "Stack overflow, calls were nested {} deep. Check that the recursion reaches a base case",
+                    depth
+                )
+                .as_str(),
+                pos,
+            )?
+        }
+        Ok(())
+    }
+
+    // Prints a line of OUTPUT, if it fits within the limit
+    pub fn write_output(&mut self, line: &str, pos: &Position) -> Result<(), PseudoError> {
+        if let Some(max) = self.limits.max_output_bytes {
+            if self.usage.output_bytes + line.len() > max {
+                limit_err(
+                    Limit::Output,
+                    format!(
+                        "Output limit reached, the program printed more than {} bytes",
+                        max
+                    )
+                    .as_str(),
+                    pos,
+                )?
+            }
+        }
+        self.usage.output_bytes += line.len();
+        if self.io.write(line).is_err() {
+            err("System error, failed to write output", &Position::invalid())?
+        }
+        Ok(())
+    }
+
+    // The number of elements an array of this shape has, if it is within the limit
+    pub fn array_capacity(&self, shape: &[Index], pos: &Position) -> Result<usize, PseudoError> {
+        // Index bounds are inclusive, and an array with bounds the wrong way round is empty
+        let capacity = shape.iter().fold(1i64, |capacity, index| {
+            let length = index.upper.saturating_sub(index.lower).saturating_add(1);
+            capacity.saturating_mul(length.max(0))
+        });
+        if let Some(max) = self.limits.max_array_elements {
+            if capacity as u64 > max as u64 {
+                limit_err(
+                    Limit::ArrayElements,
+                    format!(
+                        "ARRAY of {} elements is over the limit of {}",
+                        capacity, max
+                    )
+                    .as_str(),
+                    pos,
+                )?
+            }
+        }
+        Ok(capacity as usize)
+    }
+}
@@ -1,119 +1,177 @@
-mod run_builtins;
+pub mod file_system;
+pub(crate) mod hoist;
+pub mod hook;
+pub mod io;
+mod limits;
+pub(crate) mod run_builtins;
 mod run_class;
-mod run_expr;
-mod run_file;
+pub(crate) mod run_expr;
+pub(crate) mod run_file;
 mod run_io;
 mod run_stmt;
+pub mod value;
 mod variable;
 
-use crate::enums::{Index, Node, NodeRef, Position, VariableType};
+use crate::enums::{Node, Position, VariableType};
+use crate::executor::hoist::{hoisted, is_definition};
+pub use crate::executor::limits::Limits;
+use crate::executor::run_expr::run_expr;
 use crate::executor::run_stmt::run_stmt;
+use crate::executor::value::{ArrayValue, ObjectValue, RecordValue};
+pub use crate::executor::value::{Value, ValueRef};
+use crate::executor::variable::Definition;
 pub use crate::executor::variable::Property;
-use crate::executor::variable::{Definition, Executor, NodeDeref};
-use crate::utils::err;
+pub use crate::executor::variable::Scope;
+pub use crate::executor::variable::{Executor, MAX_CALL_DEPTH};
+use crate::utils::{err, PseudoError};
 use chrono::NaiveDate;
 use std::collections::HashMap;
 use std::ops::Deref;
+use std::rc::Rc;
 
-pub fn run(nodes: Vec<Box<Node>>) {
+pub fn run(nodes: Vec<Box<Node>>) -> Result<(), PseudoError> {
     let mut executor = Executor::new();
+    run_with(&mut executor, nodes)
+}
 
+// Run the program against an existing executor so state can outlive a single program
+pub fn run_with(executor: &mut Executor, nodes: Vec<Box<Node>>) -> Result<(), PseudoError> {
     for node in nodes {
         match *node {
-            Node::Main { mut children } => {
+            Node::Main { children } => {
+                for node in hoisted(&children)? {
+                    run_stmt(executor, node)?;
+                }
                 for node in children {
                     if let Node::Return { pos, .. } = *node {
-                        err("Cannot return outside of function or procedure", &pos)
+                        err("Cannot return outside of function or procedure", &pos)?
+                    }
+                    if !is_definition(&node) {
+                        run_stmt(executor, &node)?;
                     }
-                    run_stmt(&mut executor, &node);
                 }
             }
             _ => unimplemented!(),
         };
     }
+    Ok(())
 }
 
-// Get the VariableType of primitive node
-pub fn var_type_of(node: &Box<Node>) -> VariableType {
-    match node.deref().clone() {
-        Node::Boolean { .. } => VariableType::Boolean,
-        Node::Int { .. } => VariableType::Integer,
-        Node::Real { .. } => VariableType::Real,
-        Node::String { .. } => VariableType::String,
-        Node::Date { .. } => VariableType::Date,
-        Node::EnumVal { family, .. } => VariableType::Custom(family.clone()),
-        Node::Pointer(value) => {
-            let inner_type = var_type_of(value.borrow().deref());
-            VariableType::Pointer(Box::new(inner_type))
-        }
-        Node::RefVar(value) => var_type_of(value.borrow().deref()),
-        Node::Object { name, .. } => VariableType::Custom(name.clone()),
-        Node::Array { t, shape, .. } => VariableType::Array { shape, t },
-        Node::NullObject(var_type) => var_type.clone(),
-        _ => unimplemented!("{:?}", node),
-    }
+// Evaluate an expression against the current state of the program
+pub fn evaluate(executor: &mut Executor, expr: &Box<Node>) -> Result<Value, PseudoError> {
+    run_expr(executor, expr)
 }
 
-pub fn default_var(executor: &mut Executor, t: &Box<VariableType>, pos: &Position) -> Box<Node> {
-    Box::new(match t.deref() {
-        VariableType::Integer => Node::Int {
-            val: 0,
-            pos: Position::invalid(),
-        },
-        VariableType::Real => Node::Real {
-            val: 0.0,
-            pos: Position::invalid(),
-        },
-        VariableType::String => Node::String {
-            val: String::new(),
-            pos: Position::invalid(),
-        },
-        VariableType::Boolean => Node::Boolean {
-            val: false,
-            pos: Position::invalid(),
-        },
-        VariableType::Date => Node::Date {
-            val: NaiveDate::default(),
-            pos: Position::invalid(),
-        },
+pub fn default_var(
+    executor: &mut Executor,
+    t: &Box<VariableType>,
+    pos: &Position,
+) -> Result<Value, PseudoError> {
+    Ok(match t.deref() {
+        VariableType::Integer => Value::Integer(0),
+        VariableType::Real => Value::Real(0.0),
+        VariableType::String => Value::string(""),
+        VariableType::Char => Value::Char(' '),
+        VariableType::Boolean => Value::Boolean(false),
+        VariableType::Date => Value::Date(NaiveDate::default()),
         VariableType::Array { shape, t } => {
-            let mut capacity = 1;
-            for index in shape {
-                // index bounds are inclusive
-                capacity = capacity * (index.upper - index.lower + 1);
+            let capacity = executor.array_capacity(shape, pos)?;
+            // Every element needs a cell of its own, cloning one would share it
+            let mut values = Vec::with_capacity(capacity);
+            for _ in 0..capacity {
+                values.push(default_var(executor, t, pos)?.into_ref());
             }
-            Node::Array {
-                values: vec![NodeRef::new_ref(default_var(executor, &t, pos)); capacity as usize],
+            Value::Array(Rc::new(ArrayValue {
+                t: *t.clone(),
                 shape: shape.clone(),
-                t: t.clone(),
-            }
+                values,
+            }))
         }
-        VariableType::Custom(name) => match executor.get_def(name, pos) {
-            Definition::Class { props, base, name } => def_base_class(props, base, name),
-            Definition::Record { props, name } => {
-                let base = Box::new(Node::Null);
-                return Box::new(Node::Object { props, base, name });
+        VariableType::Custom(name) => match executor.get_def(name, pos)? {
+            Definition::Class { props, base, name } => {
+                Value::Object(def_base_class(props, *base, name))
             }
-            Definition::Enum { name } => {
-                return Box::from(Node::NullObject(VariableType::Custom((name))))
-            }
-            Definition::Pointer { ref_to, .. } => {
-                return Box::from(Node::NullObject(VariableType::Pointer(ref_to.clone())))
+            Definition::Record { props, name } => {
+                copy_value(Value::Record(Rc::new(RecordValue { name, props })))
             }
+            Definition::Enum { name } => Value::Nothing(VariableType::Custom(name)),
+            Definition::Pointer { ref_to, .. } => Value::Nothing(VariableType::Pointer(ref_to)),
             _ => unreachable!(),
         },
         _ => unimplemented!(),
     })
 }
 
+// A STRING of one character can be stored wherever a CHAR is expected, and a CHAR
+// wherever a STRING is
+pub(crate) fn promote(
+    value: Value,
+    t: &VariableType,
+    pos: &Position,
+) -> Result<Value, PseudoError> {
+    match (t, &value) {
+        (VariableType::Char, Value::String(val)) => {
+            let mut chars = val.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Value::Char(c)),
+                _ => err(
+                    format!(
+                        "Cannot assign a STRING of {} characters to a CHAR",
+                        val.chars().count()
+                    )
+                    .as_str(),
+                    pos,
+                ),
+            }
+        }
+        (VariableType::String, Value::Char(c)) => Ok(Value::string(&c.to_string())),
+        _ => Ok(value),
+    }
+}
+
+// Arrays and records are values, storing one copies its elements rather than
+// sharing them with the variable it came from. Objects of a class are shared.
+pub(crate) fn copy_value(value: Value) -> Value {
+    let copy = |value: &ValueRef| copy_value(value.borrow().clone()).into_ref();
+    match value {
+        Value::Array(array) => Value::Array(Rc::new(ArrayValue {
+            t: array.t.clone(),
+            shape: array.shape.clone(),
+            values: array.values.iter().map(copy).collect(),
+        })),
+        Value::Record(record) => {
+            let props = record
+                .props
+                .iter()
+                .map(|(name, prop)| match prop {
+                    Property::Var { value, t, private } => (
+                        name.clone(),
+                        Property::Var {
+                            value: copy(value),
+                            t: t.clone(),
+                            private: *private,
+                        },
+                    ),
+                    method => (name.clone(), method.clone()),
+                })
+                .collect();
+            Value::Record(Rc::new(RecordValue {
+                name: record.name.clone(),
+                props,
+            }))
+        }
+        value => value,
+    }
+}
+
 pub fn def_base_class(
     props: HashMap<String, Property>,
-    base: Box<Definition>,
+    base: Definition,
     name: String,
-) -> Node {
-    let base = Box::new(match base.deref().clone() {
-        Definition::Class { props, base, name } => def_base_class(props, base, name),
-        _ => Node::Null,
-    });
-    Node::Object { props, base, name }
+) -> Rc<ObjectValue> {
+    let base = match base {
+        Definition::Class { props, base, name } => Some(def_base_class(props, *base, name)),
+        _ => None,
+    };
+    Rc::new(ObjectValue { name, base, props })
 }
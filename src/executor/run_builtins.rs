@@ -1,547 +1,381 @@
 use crate::enums::{Node, Position, VariableType};
+use crate::executor::promote;
 use crate::executor::run_expr::run_expr;
+use crate::executor::value::Value;
 use crate::executor::variable::Executor;
-use crate::executor::{err, var_type_of};
+use crate::utils::{err, PseudoError};
 use chrono::{Datelike, NaiveDate};
 
-pub fn match_builtin(
-    executor: &mut Executor,
-    name: &String,
-    call_params: &Vec<Box<Node>>,
-    pos: &Position,
-) -> Option<Box<Node>> {
+// A builtin is given its arguments, already checked against the types it accepts,
+// along with where each was written
+pub(crate) type BuiltinFn = fn(&mut Executor, &[Value], &[Position]) -> Result<Value, PseudoError>;
+
+// The types each parameter of a builtin accepts and the function working out its result
+pub(crate) fn builtin(name: &str) -> Option<(Vec<Vec<VariableType>>, BuiltinFn)> {
+    use VariableType::*;
+    let number = vec![Integer, Real];
     Some(match name.to_uppercase().as_str() {
-        "LEFT" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String, VariableType::Integer],
-            &builtin_func_left,
-            pos,
-        ),
-        "RIGHT" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String, VariableType::Integer],
-            &builtin_func_right,
-            pos,
-        ),
-        "MID" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![
-                VariableType::String,
-                VariableType::Integer,
-                VariableType::Integer,
-            ],
-            &builtin_func_mid,
-            pos,
-        ),
-        "LENGTH" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String],
-            &builtin_func_length,
-            pos,
-        ),
-        "TO_UPPER" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String],
-            &builtin_func_to_upper,
-            pos,
-        ),
-        "TO_LOWER" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String],
-            &builtin_func_to_lower,
-            pos,
-        ),
-        "NUM_TO_STR" => {
-            let t = var_type_of(&run_expr(executor, &call_params[0].clone()));
-            if t == VariableType::Integer {
-                run_fn_call_builtin(
-                    executor,
-                    call_params,
-                    &vec![VariableType::Integer],
-                    &builtin_func_num_to_str,
-                    pos,
-                )
-            } else {
-                run_fn_call_builtin(
-                    executor,
-                    call_params,
-                    &vec![VariableType::Real],
-                    &builtin_func_num_to_str,
-                    pos,
-                )
-            }
-        }
-        "STR_TO_NUM" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String],
-            &builtin_func_str_to_num,
-            pos,
-        ),
-        "IS_NUM" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String],
-            &builtin_func_is_num,
-            pos,
-        ),
-        "ASC" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String],
-            &builtin_func_asc,
-            pos,
-        ),
-        "CHR" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::Integer],
-            &builtin_func_chr,
-            pos,
-        ),
-        "INT" => {
-            let t = var_type_of(&run_expr(executor, &call_params[0].clone()));
-            if t == VariableType::Integer {
-                run_fn_call_builtin(
-                    executor,
-                    call_params,
-                    &vec![VariableType::Integer],
-                    &builtin_func_int,
-                    pos,
-                )
-            } else {
-                run_fn_call_builtin(
-                    executor,
-                    call_params,
-                    &vec![VariableType::Real],
-                    &builtin_func_int,
-                    pos,
-                )
-            }
-        }
-        "RAND" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::Integer],
-            &builtin_func_rand,
-            pos,
-        ),
-        "DAY" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::Date],
-            &builtin_func_day,
-            pos,
+        "LEFT" => (vec![vec![String], vec![Integer]], builtin_func_left),
+        "RIGHT" => (vec![vec![String], vec![Integer]], builtin_func_right),
+        "MID" => (
+            vec![vec![String], vec![Integer], vec![Integer]],
+            builtin_func_mid,
         ),
-        "MONTH" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::Date],
-            &builtin_func_month,
-            pos,
-        ),
-        "YEAR" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::Date],
-            &builtin_func_year,
-            pos,
-        ),
-        "DAYINDEX" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::Date],
-            &builtin_func_day_index,
-            pos,
-        ),
-        "SETDATE" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![
-                VariableType::Integer,
-                VariableType::Integer,
-                VariableType::Integer,
-            ],
-            &builtin_func_set_date,
-            pos,
-        ),
-        "TODAY" => run_fn_call_builtin(executor, call_params, &vec![], &builtin_func_today, pos),
-        "EOF" => run_fn_call_builtin(
-            executor,
-            call_params,
-            &vec![VariableType::String],
-            &builtin_func_eof,
-            pos,
+        "LENGTH" => (vec![vec![String]], builtin_func_length),
+        "TO_UPPER" => (vec![vec![String]], builtin_func_to_upper),
+        "TO_LOWER" => (vec![vec![String]], builtin_func_to_lower),
+        "NUM_TO_STR" => (vec![number], builtin_func_num_to_str),
+        "STR_TO_NUM" => (vec![vec![String]], builtin_func_str_to_num),
+        "IS_NUM" => (vec![vec![String]], builtin_func_is_num),
+        "ASC" => (vec![vec![Char]], builtin_func_asc),
+        "CHR" => (vec![vec![Integer]], builtin_func_chr),
+        "INT" => (vec![number], builtin_func_int),
+        "RAND" => (vec![vec![Integer]], builtin_func_rand),
+        "DAY" => (vec![vec![Date]], builtin_func_day),
+        "MONTH" => (vec![vec![Date]], builtin_func_month),
+        "YEAR" => (vec![vec![Date]], builtin_func_year),
+        "DAYINDEX" => (vec![vec![Date]], builtin_func_day_index),
+        "SETDATE" => (
+            vec![vec![Integer], vec![Integer], vec![Integer]],
+            builtin_func_set_date,
         ),
+        "TODAY" => (vec![], builtin_func_today),
+        "EOF" => (vec![vec![String]], builtin_func_eof),
         _ => return None,
     })
 }
 
-fn run_fn_call_builtin(
+pub fn match_builtin(
     executor: &mut Executor,
-    call_params: &Vec<Box<Node>>,
-    fn_params: &Vec<VariableType>,
-    func: &dyn Fn(&mut Executor, &Vec<String>, &Vec<Box<Node>>) -> Box<Node>,
+    name: &str,
+    call_params: &[Box<Node>],
     pos: &Position,
-) -> Box<Node> {
-    let mut values = Vec::<String>::new();
+) -> Result<Option<Value>, PseudoError> {
+    let Some((fn_params, func)) = builtin(name) else {
+        return Ok(None);
+    };
     if call_params.len() != fn_params.len() {
-        err("Invalid number of arguments", pos)
+        err("Invalid number of arguments", pos)?
     }
-    for (call_param, fn_param) in call_params.iter().zip(fn_params.iter()) {
-        let expr = run_expr(executor, call_param);
-        if var_type_of(&expr) == *fn_param {
-            values.push(expr.val_as_str());
+    let mut values = Vec::new();
+    for (call_param, allowed) in call_params.iter().zip(fn_params.iter()) {
+        let expr = run_expr(executor, call_param)?;
+        // Parameters taking one type are shown by it, the last type otherwise
+        let fn_param = allowed.last().unwrap();
+        let expr = promote(expr, fn_param, &call_param.pos())?;
+        if allowed.contains(&expr.type_of()) {
+            values.push(expr);
         } else {
             let msg = format!(
                 "Cannot assign type {:?} to parameter of type {:?}",
-                var_type_of(&expr).str(),
+                expr.type_of().str(),
                 fn_param.str()
             );
-            err(msg.as_str(), &call_param.pos())
+            err(msg.as_str(), &call_param.pos())?
         }
     }
+    let positions: Vec<Position> = call_params.iter().map(|param| param.pos()).collect();
+    func(executor, &values, &positions).map(Some)
+}
+
+// The arguments have been checked, so each is known to be of its parameter's type
+fn string(value: &Value) -> &str {
+    match value {
+        Value::String(val) => val,
+        _ => unreachable!(),
+    }
+}
 
-    func(executor, &values, call_params)
+// Lengths and positions count characters, not the bytes they are stored in
+fn substring(text: &str, start: usize, length: usize) -> String {
+    text.chars().skip(start).take(length).collect()
+}
+
+fn integer(value: &Value) -> i64 {
+    match value {
+        Value::Integer(val) => *val,
+        _ => unreachable!(),
+    }
+}
+
+fn date(value: &Value) -> NaiveDate {
+    match value {
+        Value::Date(val) => *val,
+        _ => unreachable!(),
+    }
 }
 
 pub fn builtin_func_left(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let operand = params[0].clone();
-    let length = match params[1].clone().parse::<usize>() {
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let operand = string(&params[0]);
+    let length = match usize::try_from(integer(&params[1])) {
         Ok(length) => length,
         Err(_) => err(
             "Length for 'LEFT' function cannot be less than 0",
-            &nodes[1].pos(),
-        ),
+            &positions[1],
+        )?,
     };
 
-    if length > operand.len() {
+    if length > operand.chars().count() {
         err(
             "Length for 'LEFT' function cannot exceed string length",
-            &nodes[1].pos(),
-        )
+            &positions[1],
+        )?;
     }
 
-    Box::new(Node::String {
-        val: operand[..length].to_string(),
-        pos: Position::invalid(),
-    })
+    Ok(Value::string(&substring(operand, 0, length)))
 }
 
 pub fn builtin_func_right(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let operand = params[0].clone();
-    let length = match params[1].clone().parse::<usize>() {
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let operand = string(&params[0]);
+    let length = match usize::try_from(integer(&params[1])) {
         Ok(length) => length,
         Err(_) => err(
             "Length for 'RIGHT' function cannot be less than 0",
-            &nodes[1].pos(),
-        ),
+            &positions[1],
+        )?,
     };
 
-    if length > operand.len() {
+    let count = operand.chars().count();
+    if length > count {
         err(
             "Length for 'RIGHT' function cannot exceed string length",
-            &nodes[1].pos(),
-        )
+            &positions[1],
+        )?;
     }
 
-    Box::new(Node::String {
-        val: operand[operand.len() - length..].to_string(),
-        pos: Position::invalid(),
-    })
+    Ok(Value::string(&substring(operand, count - length, length)))
 }
 
 pub fn builtin_func_mid(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let operand = params[0].clone();
-    let start = match params[1].clone().parse::<usize>() {
-        Ok(length) => {
-            if length > 0 {
-                length
-            } else {
-                err(
-                    "Starting position for 'MID' function cannot be less than 1",
-                    &nodes[1].pos(),
-                )
-            }
-        }
-        Err(_) => err(
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let operand = string(&params[0]);
+    let start = match usize::try_from(integer(&params[1])) {
+        Ok(start) if start > 0 => start,
+        _ => err(
             "Starting position for 'MID' function cannot be less than 1",
-            &nodes[1].pos(),
-        ),
+            &positions[1],
+        )?,
     };
-    let length = match params[2].clone().parse::<usize>() {
+    let length = match usize::try_from(integer(&params[2])) {
         Ok(length) => length,
         Err(_) => err(
             "Length for 'MID' function cannot be less than 0",
-            &nodes[2].pos(),
-        ),
+            &positions[2],
+        )?,
     };
 
-    if start + length > operand.len() + 1 {
+    if start + length > operand.chars().count() + 1 {
         err(
             "Substring length for 'MID' function cannot exceed string length",
-            &nodes[2].pos(),
-        )
+            &positions[2],
+        )?;
     }
 
-    Box::new(Node::String {
-        val: operand[start - 1..start + length - 1].to_string(),
-        pos: Position::invalid(),
-    })
+    Ok(Value::string(&substring(operand, start - 1, length)))
 }
 
 pub fn builtin_func_length(
     _: &mut Executor,
-    params: &Vec<String>,
-    _: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let operand = params[0].clone();
-    Box::new(Node::Int {
-        val: operand.len() as i64,
-        pos: Position::invalid(),
-    })
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::Integer(string(&params[0]).chars().count() as i64))
 }
 
 pub fn builtin_func_to_upper(
     _: &mut Executor,
-    params: &Vec<String>,
-    _: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let operand = params[0].clone();
-    Box::new(Node::String {
-        val: operand.to_uppercase(),
-        pos: Position::invalid(),
-    })
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::string(&string(&params[0]).to_uppercase()))
 }
 
 pub fn builtin_func_to_lower(
     _: &mut Executor,
-    params: &Vec<String>,
-    _: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let operand = params[0].clone();
-    Box::new(Node::String {
-        val: operand.to_lowercase(),
-        pos: Position::invalid(),
-    })
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::string(&string(&params[0]).to_lowercase()))
 }
 
 pub fn builtin_func_num_to_str(
     _: &mut Executor,
-    params: &Vec<String>,
-    _: &Vec<Box<Node>>,
-) -> Box<Node> {
-    Box::new(Node::String {
-        val: params[0].clone(),
-        pos: Position::invalid(),
-    })
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::string(&params[0].to_string()))
 }
 
 pub fn builtin_func_str_to_num(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    match params[0].clone().parse::<i64>() {
-        Ok(number) => Box::new(Node::Int {
-            val: number,
-            pos: Position::invalid(),
-        }),
-        Err(_) => match params[0].clone().parse::<f64>() {
-            Ok(number) => Box::new(Node::Real {
-                val: number,
-                pos: Position::invalid(),
-            }),
-            Err(_) => err("String is not a valid number", &nodes[0].pos()),
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let operand = string(&params[0]);
+    match operand.parse::<i64>() {
+        Ok(number) => Ok(Value::Integer(number)),
+        Err(_) => match operand.parse::<f64>() {
+            Ok(number) => Ok(Value::Real(number)),
+            Err(_) => err("String is not a valid number", &positions[0]),
         },
     }
 }
 
 pub fn builtin_func_is_num(
     _: &mut Executor,
-    params: &Vec<String>,
-    _: &Vec<Box<Node>>,
-) -> Box<Node> {
-    Box::new(match params[0].clone().parse::<f64>() {
-        Ok(_) => Node::Boolean {
-            val: true,
-            pos: Position::invalid(),
-        },
-        Err(_) => Node::Boolean {
-            val: false,
-            pos: Position::invalid(),
-        },
-    })
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::Boolean(string(&params[0]).parse::<f64>().is_ok()))
 }
 
 pub fn builtin_func_asc(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let operand = params[0].clone();
-    if operand.len() != 1 {
-        err(
-            "String length for 'ASC' function must be 1",
-            &nodes[0].pos(),
-        )
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    match params[0] {
+        Value::Char(val) => Ok(Value::Integer(val as i64)),
+        _ => unreachable!(),
     }
-    Box::new(Node::Int {
-        val: operand.chars().last().unwrap() as i64,
-        pos: Position::invalid(),
-    })
 }
 
 pub fn builtin_func_chr(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let ascii = match params[0].parse::<u8>() {
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let ascii = match u8::try_from(integer(&params[0])) {
         Ok(ascii) => ascii,
         Err(_) => err(
             "Ascii value for 'CHR' function must be between 0-255 inclusive",
-            &nodes[0].pos(),
-        ),
+            &positions[0],
+        )?,
     };
-    Box::new(Node::String {
-        val: (ascii as char).to_string(),
-        pos: Position::invalid(),
-    })
+    Ok(Value::Char(ascii as char))
 }
 
-pub fn builtin_func_int(_: &mut Executor, params: &Vec<String>, _: &Vec<Box<Node>>) -> Box<Node> {
-    let operand = params[0].parse::<f64>().unwrap();
-    Box::new(Node::Int {
-        val: operand.trunc() as i64,
-        pos: Position::invalid(),
-    })
+pub fn builtin_func_int(
+    _: &mut Executor,
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    let (operand, _) = params[0].as_number().unwrap();
+    Ok(Value::Integer(operand.trunc() as i64))
 }
 
 pub fn builtin_func_rand(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let upper = params[0].parse::<i64>().unwrap();
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let upper = integer(&params[0]);
 
     if upper < 1 {
         err(
             "Number for 'RAND' function cannot be less than 1",
-            &nodes[0].pos(),
-        );
+            &positions[0],
+        )?;
     }
 
-    Box::new(Node::Real {
-        val: rand::random_range(0.0..(upper as f64)),
-        pos: Position::invalid(),
-    })
+    Ok(Value::Real(rand::random_range(0.0..(upper as f64))))
 }
 
-pub fn builtin_func_day(_: &mut Executor, params: &Vec<String>, _: &Vec<Box<Node>>) -> Box<Node> {
-    let date = NaiveDate::parse_from_str(params[0].as_str(), "%Y-%m-%d").unwrap();
-    Box::new(Node::Int {
-        val: date.day() as i64,
-        pos: Position::invalid(),
-    })
+pub fn builtin_func_day(
+    _: &mut Executor,
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::Integer(date(&params[0]).day() as i64))
 }
 
-pub fn builtin_func_month(_: &mut Executor, params: &Vec<String>, _: &Vec<Box<Node>>) -> Box<Node> {
-    let date = NaiveDate::parse_from_str(params[0].as_str(), "%Y-%m-%d").unwrap();
-    Box::new(Node::Int {
-        val: date.month() as i64,
-        pos: Position::invalid(),
-    })
+pub fn builtin_func_month(
+    _: &mut Executor,
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::Integer(date(&params[0]).month() as i64))
 }
 
-pub fn builtin_func_year(_: &mut Executor, params: &Vec<String>, _: &Vec<Box<Node>>) -> Box<Node> {
-    let date = NaiveDate::parse_from_str(params[0].as_str(), "%Y-%m-%d").unwrap();
-    Box::new(Node::Int {
-        val: date.year() as i64,
-        pos: Position::invalid(),
-    })
+pub fn builtin_func_year(
+    _: &mut Executor,
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::Integer(date(&params[0]).year() as i64))
 }
 
 pub fn builtin_func_day_index(
     _: &mut Executor,
-    params: &Vec<String>,
-    _: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let date = NaiveDate::parse_from_str(params[0].as_str(), "%Y-%m-%d").unwrap();
+    params: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
     // Sunday is 1 for CIE
-    Box::new(Node::Int {
-        val: (date.weekday().num_days_from_sunday() + 1) as i64,
-        pos: Position::invalid(),
-    })
+    let weekday = date(&params[0]).weekday();
+    Ok(Value::Integer((weekday.num_days_from_sunday() + 1) as i64))
 }
+
 pub fn builtin_func_set_date(
     _: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let day = params[0].clone();
-    let month = params[1].clone();
-    let year = params[2].clone();
-
-    match NaiveDate::parse_from_str(&format!("{}/{}/{}", year, month, day), "%Y/%m/%d") {
-        Ok(date) => Box::new(Node::Date {
-            val: date,
-            pos: Position::invalid(),
-        }),
-        Err(_) => err(
-            "Date given is not valid",
-            &Position::range(nodes[0].pos(), nodes[2].pos()),
-        ),
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let day = u32::try_from(integer(&params[0]));
+    let month = u32::try_from(integer(&params[1]));
+    let year = i32::try_from(integer(&params[2]));
+
+    match (day, month, year) {
+        (Ok(day), Ok(month), Ok(year)) if year >= 0 => {
+            if let Some(date) = NaiveDate::from_ymd_opt(year, month, day) {
+                return Ok(Value::Date(date));
+            }
+        }
+        _ => {}
     }
+    err(
+        "Date given is not valid",
+        &Position::range(positions[0], positions[2]),
+    )
 }
 
-pub fn builtin_func_today(_: &mut Executor, _: &Vec<String>, _: &Vec<Box<Node>>) -> Box<Node> {
-    Box::new(Node::Date {
-        val: chrono::offset::Local::now().date_naive(),
-        pos: Position::invalid(),
-    })
+pub fn builtin_func_today(
+    _: &mut Executor,
+    _: &[Value],
+    _: &[Position],
+) -> Result<Value, PseudoError> {
+    Ok(Value::Date(chrono::offset::Local::now().date_naive()))
 }
 
 pub fn builtin_func_eof(
     executor: &mut Executor,
-    params: &Vec<String>,
-    nodes: &Vec<Box<Node>>,
-) -> Box<Node> {
-    let filename = params[0].clone();
-    match executor.file_handles.get_mut(&filename) {
+    params: &[Value],
+    positions: &[Position],
+) -> Result<Value, PseudoError> {
+    let filename = string(&params[0]);
+    match executor.file_handles.get_mut(filename) {
         None => err(
             format!("File {} is not opened", filename).as_str(),
-            &nodes[0].pos(),
+            &positions[0],
         ),
         Some(file) => {
             if file.mode != "READ" {
                 err(
                     "Function EOF only works on file opened for READ",
-                    &nodes[0].pos(),
-                );
+                    &positions[0],
+                )?;
             }
-            Box::from(Node::Boolean {
-                val: file.cursor > file.content.len(),
-                pos: Position::invalid(),
-            })
+            Ok(Value::Boolean(file.cursor > file.content.len()))
         }
     }
 }
@@ -1,38 +1,37 @@
 use std::collections::HashMap;
-use std::ops::{Deref, DerefMut};
+use std::ops::Deref;
 
-use crate::enums::{Index, Node, NodeRef, Position, VariableType};
-use crate::executor::run_expr::{get_array_index, run_expr, run_fn_call, run_fn_call_inner};
-use crate::executor::run_stmt::{as_number_expr, run_stmt};
-use crate::executor::variable::{Definition, Executor, NodeDeref, Property};
+use crate::enums::{Node, Position};
+use crate::executor::run_expr::{get_array_index, run_fn_call, run_fn_call_inner};
+use crate::executor::run_stmt::as_number_expr;
+use crate::executor::value::{Value, ValueRef};
+use crate::executor::variable::{Definition, Executor, Property, State, Variable};
 use crate::executor::{def_base_class, default_var};
-use crate::utils::err;
-
-use super::var_type_of;
+use crate::utils::{err, PseudoError};
 
 pub fn run_class(
     executor: &mut Executor,
     name: &Box<Node>,
     base: &Box<Node>,
-    children: &Vec<Box<Node>>,
-) {
+    children: &[Box<Node>],
+) -> Result<(), PseudoError> {
     let mut class_props = HashMap::new();
-    for node in children.clone() {
+    for node in children {
         match node.deref() {
             Node::Null => (),
             _ => {
-                for (name, prop) in run_prop_decl(executor, &node).into_iter() {
+                for (name, prop) in run_prop_decl(executor, node)?.into_iter() {
                     if class_props.contains_key(&name) {
                         if let Property::Var { .. } = prop {
                             err(
                                 format!("Property {} already exists", name).as_str(),
                                 &node.pos(),
-                            );
+                            )?;
                         } else {
                             err(
                                 format!("Method {} already exists", name).as_str(),
                                 &node.pos(),
-                            );
+                            )?;
                         }
                     };
                     class_props.insert(name, prop);
@@ -42,7 +41,7 @@ pub fn run_class(
     }
     if let Node::String { val, pos } = name.deref() {
         let base = match base.deref() {
-            Node::String { val, pos } => executor.get_def(val, pos),
+            Node::String { val, pos } => executor.get_def(val, pos)?,
             _ => Definition::Null,
         };
         if let Some(Property::Method { .. }) = class_props.get("new") {
@@ -56,18 +55,22 @@ pub fn run_class(
                 pos,
             );
         }
-        err("Class must have a constructor", pos);
+        return err("Class must have a constructor", pos);
     }
     unreachable!()
 }
 
-pub fn run_record(executor: &mut Executor, name: &Box<Node>, children: &Vec<Box<Node>>) {
+pub fn run_record(
+    executor: &mut Executor,
+    name: &Box<Node>,
+    children: &[Box<Node>],
+) -> Result<(), PseudoError> {
     let mut props = HashMap::new();
-    for node in children.clone() {
+    for node in children {
         match node.deref() {
             Node::Null => (),
             _ => {
-                for (name, prop) in run_prop_decl(executor, &node).into_iter() {
+                for (name, prop) in run_prop_decl(executor, node)?.into_iter() {
                     props.insert(name, prop);
                 }
             }
@@ -86,7 +89,10 @@ pub fn run_record(executor: &mut Executor, name: &Box<Node>, children: &Vec<Box<
     unreachable!()
 }
 
-fn run_prop_decl(executor: &mut Executor, prop: &Box<Node>) -> Vec<(String, Property)> {
+fn run_prop_decl(
+    executor: &mut Executor,
+    prop: &Box<Node>,
+) -> Result<Vec<(String, Property)>, PseudoError> {
     match prop.deref() {
         Node::Procedure {
             name,
@@ -111,14 +117,14 @@ fn run_prop_decl(executor: &mut Executor, prop: &Box<Node>) -> Vec<(String, Prop
         } => children
             .iter()
             .map(|var_name| {
-                (
+                Ok((
                     var_name.clone(),
                     Property::Var {
                         private: *private,
-                        value: NodeRef::new_ref(default_var(executor, t, pos)),
+                        value: default_var(executor, t, pos)?.into_ref(),
                         t: t.clone(),
                     },
-                )
+                ))
             })
             .collect(),
         _ => err("Statement not allowed within class", &prop.pos()),
@@ -128,10 +134,10 @@ fn run_prop_decl(executor: &mut Executor, prop: &Box<Node>) -> Vec<(String, Prop
 fn run_method_decl(
     name: &Box<Node>,
     params: &Vec<Box<Node>>,
-    children: &Vec<Box<Node>>,
+    children: &[Box<Node>],
     private: bool,
     returns: bool,
-) -> Vec<(String, Property)> {
+) -> Result<Vec<(String, Property)>, PseudoError> {
     let mut names = Vec::new();
     for param in params {
         if let Node::Var { name, .. } = param.deref() {
@@ -139,95 +145,105 @@ fn run_method_decl(
                 err(
                     format!("Duplicate parameter {}", name).as_str(),
                     &param.pos(),
-                );
+                )?;
             }
             names.push(name.clone());
         }
     }
     if let Node::String { val, .. } = name.deref() {
         if val == "new" && private {
-            err("Constructor cannot be private", &name.pos())
+            err("Constructor cannot be private", &name.pos())?
         }
         if val == "new" && returns {
-            err("Constructor must be a procedure", &name.pos())
+            err("Constructor must be a procedure", &name.pos())?
         }
-        return vec![(
+        return Ok(vec![(
             val.clone(),
             Property::Method {
                 private,
                 params: params.clone(),
-                children: children.clone(),
+                children: children.to_vec(),
                 returns,
             },
-        )];
+        )]);
     }
     unreachable!()
 }
 
-pub fn run_access_mut(executor: &mut Executor, node: &Box<Node>) -> NodeRef {
+pub fn run_access_mut(executor: &mut Executor, node: &Box<Node>) -> Result<ValueRef, PseudoError> {
     match node.deref() {
         Node::Var { name, pos } => run_var_access(executor, name, pos),
         Node::ArrayVar { name, indices, pos } => run_array_access(executor, name, indices, pos),
         Node::Dereference { expr, .. } => run_pointer_access(executor, expr),
         Node::Composite { children, .. } => {
             let mut base = match children[0].deref() {
-                Node::Var { name, pos } => run_var_access(executor, name, pos),
+                Node::Var { name, pos } => run_var_access(executor, name, pos)?,
                 Node::ArrayVar { name, indices, pos } => {
-                    run_array_access(executor, name, indices, pos)
+                    run_array_access(executor, name, indices, pos)?
                 }
                 _ => unreachable!(),
             };
             for child in children.iter().skip(1) {
                 base = match child.deref() {
-                    Node::Var { name, pos } => run_prop_access(base, name, pos),
+                    Node::Var { name, pos } => run_prop_access(base, name, pos)?,
                     Node::ArrayVar { name, indices, pos } => {
-                        run_array_prop_access(executor, base, name, indices, pos)
+                        run_array_prop_access(executor, base, name, indices, pos)?
                     }
                     _ => unreachable!(),
                 };
             }
-            base
+            Ok(base)
         }
         _ => unreachable!(),
     }
 }
 
-pub fn run_access(executor: &mut Executor, node: &Box<Node>) -> Box<Node> {
+pub fn run_access(executor: &mut Executor, node: &Box<Node>) -> Result<Value, PseudoError> {
     match node.deref() {
-        Node::Var { name, pos } => run_var_access(executor, name, pos).clone_node(),
+        Node::Var { name, pos } => Ok(run_var_access(executor, name, pos)?.borrow().clone()),
         Node::ArrayVar { name, indices, pos } => {
-            run_array_access(executor, name, indices, pos).clone_node()
+            Ok(run_array_access(executor, name, indices, pos)?
+                .borrow()
+                .clone())
         }
         Node::Composite { children, .. } => run_composite_access(executor, children),
         _ => unreachable!(),
     }
 }
 
-pub fn run_composite_access(executor: &mut Executor, children: &Vec<Box<Node>>) -> Box<Node> {
+pub fn run_composite_access(
+    executor: &mut Executor,
+    children: &[Box<Node>],
+) -> Result<Value, PseudoError> {
     let mut base = match children[0].deref() {
-        Node::Var { name, pos } => run_var_access(executor, name, pos),
-        Node::ArrayVar { name, indices, pos } => run_array_access(executor, name, indices, pos),
+        Node::Var { name, pos } => run_var_access(executor, name, pos)?,
+        Node::ArrayVar { name, indices, pos } => run_array_access(executor, name, indices, pos)?,
         Node::FunctionCall { name, params, pos } => {
-            NodeRef::new_ref(run_fn_call(executor, name, params, pos))
+            run_fn_call(executor, name, params, pos)?.into_ref()
         }
         _ => unreachable!(),
     };
     for child in children.iter().skip(1) {
         base = match child.deref() {
-            Node::Var { name, pos } => run_prop_access(base, name, pos),
+            Node::Var { name, pos } => run_prop_access(base, name, pos)?,
             Node::ArrayVar { name, indices, pos } => {
-                run_array_prop_access(executor, base, name, indices, pos)
+                run_array_prop_access(executor, base, name, indices, pos)?
             }
             Node::FunctionCall { name, params, pos } => {
-                return run_method_call(executor, base, name, params, pos);
+                let object = base.borrow().clone();
+                return run_method_call(executor, &object, name, params, pos, false);
             }
             _ => unreachable!(),
         };
     }
-    base.clone_node()
+    let value = base.borrow().clone();
+    Ok(value)
 }
 
-pub fn run_pointer_access(executor: &mut Executor, node: &Box<Node>) -> NodeRef {
+pub fn run_pointer_access(
+    executor: &mut Executor,
+    node: &Box<Node>,
+) -> Result<ValueRef, PseudoError> {
     match node.deref() {
         Node::Var { .. }
         | Node::ArrayVar { .. }
@@ -236,192 +252,179 @@ pub fn run_pointer_access(executor: &mut Executor, node: &Box<Node>) -> NodeRef
         _ => err(
             "Value is not a pointer, it cannot be dereferenced",
             &node.pos(),
-        ),
+        )?,
     };
-    let pointer = run_access_mut(executor, node);
-    if let Node::Pointer(value) = pointer.borrow().deref().deref() {
-        return value.clone();
+    let pointer = run_access_mut(executor, node)?;
+    if let Value::Pointer(value) = pointer.borrow().deref() {
+        return Ok(value.clone());
     }
     err(
         "Value is not a pointer, it cannot be dereferenced",
         &node.pos(),
-    );
+    )
 }
 
-fn run_var_access(executor: &mut Executor, name: &String, pos: &Position) -> NodeRef {
-    let var = &executor.get_var_mut(name, pos);
+fn run_var_access(
+    executor: &mut Executor,
+    name: &String,
+    pos: &Position,
+) -> Result<ValueRef, PseudoError> {
+    let var = &executor.get_var_mut(name, pos)?;
     if !var.mutable {
         err(
             format!("{} is a constant, it's value cannot be modified", name).as_str(),
             pos,
-        )
+        )?
     }
-    if let Node::RefVar(reference) = var.value.borrow().deref().deref() {
-        return reference.clone();
-    }
-    var.value.clone()
+    Ok(var.value.clone())
 }
 
 fn run_array_access(
     executor: &mut Executor,
     name: &String,
-    indices: &Vec<Box<Node>>,
+    indices: &[Box<Node>],
     pos: &Position,
-) -> NodeRef {
-    let nodes = indices.clone();
+) -> Result<ValueRef, PseudoError> {
+    let nodes = indices;
     let indices = indices
         .iter()
-        .map(|index| match run_expr(executor, index).deref() {
-            Node::Int { val, .. } => val.clone(),
-            _ => unreachable!(),
-        })
-        .collect::<Vec<i64>>();
-    let var = &executor.get_var_mut(name, pos);
+        .map(|index| as_number_expr(executor, index))
+        .collect::<Result<Vec<i64>, PseudoError>>()?;
+    let var = &executor.get_var_mut(name, pos)?;
     if !var.mutable {
         err(
             format!("{} is a constant, it's value cannot be modified", name).as_str(),
             pos,
-        )
+        )?
     }
-    let value = match var.value.borrow().deref().deref() {
-        Node::RefVar(reference) => reference.clone(),
-        _ => var.value.clone(),
-    };
-    if let Node::Array { values, shape, .. } = value.borrow().deref().deref() {
-        return values[get_array_index(indices, shape, &nodes)].clone();
+    if let Value::Array(array) = var.value.borrow().deref() {
+        return Ok(array.values[get_array_index(indices, &array.shape, nodes)?].clone());
     };
     err(format!("{} is not an array", name).as_str(), pos)
 }
 
-fn run_base_prop_access(
-    name: &String,
-    base: &Box<Node>,
-    props: &HashMap<String, Property>,
-) -> Option<Property> {
-    if let Some(value) = props.get(name) {
-        return Some(value.clone());
-    } else if let Node::Object { base, props, .. } = base.deref() {
-        return run_base_prop_access(name, base, props);
-    } else {
-        None
-    }
-}
-
 fn run_array_prop_access(
     executor: &mut Executor,
-    base: NodeRef,
+    base: ValueRef,
     name: &String,
-    indices: &Vec<Box<Node>>,
+    indices: &[Box<Node>],
     pos: &Position,
-) -> NodeRef {
-    if let Node::Object { props, base, .. } = base.borrow().deref().deref() {
-        let prop = run_base_prop_access(name, base, props);
-        if let Some(Property::Var { value, private, .. }) = prop {
-            if private {
-                err("Cannot access private property", pos)
-            }
-            if let Node::Array { values, shape, .. } = value.borrow().deref().deref() {
-                let nodes = indices.clone();
-                let indices = indices
-                    .iter()
-                    .map(|index| match run_expr(executor, index).deref() {
-                        Node::Int { val, .. } => val.clone(),
-                        _ => unreachable!(),
-                    })
-                    .collect::<Vec<i64>>();
-                return values[get_array_index(indices, shape, &nodes)].clone();
-            };
-            err(format!("{} is not an array", name).as_str(), pos)
-        }
-        err(format!("Property '{}' not found", name).as_str(), pos)
-    }
-    err("Value is not an object", pos)
+) -> Result<ValueRef, PseudoError> {
+    let value = run_prop_access(base, name, pos)?;
+    if let Value::Array(array) = value.borrow().deref() {
+        let nodes = indices;
+        let indices = indices
+            .iter()
+            .map(|index| as_number_expr(executor, index))
+            .collect::<Result<Vec<i64>, PseudoError>>()?;
+        return Ok(array.values[get_array_index(indices, &array.shape, nodes)?].clone());
+    };
+    err(format!("{} is not an array", name).as_str(), pos)
 }
 
-fn run_prop_access(base: NodeRef, name: &String, pos: &Position) -> NodeRef {
-    if let Node::Object { props, base, .. } = base.borrow().deref().deref() {
-        let prop = run_base_prop_access(name, base, props);
-        if let Some(Property::Var { value, private, .. }) = prop {
-            if private {
-                err("Cannot access private property", pos)
+fn run_prop_access(base: ValueRef, name: &String, pos: &Position) -> Result<ValueRef, PseudoError> {
+    let base = base.borrow();
+    if base.props().is_none() {
+        return err("Value is not an object", pos);
+    }
+    match base.prop(name) {
+        Some(Property::Var { value, private, .. }) => {
+            if *private {
+                err("Cannot access private property", pos)?
             }
-            return value;
+            Ok(value.clone())
         }
-        err(format!("Property '{}' not found", name).as_str(), pos)
+        _ => err(format!("Property '{}' not found", name).as_str(), pos),
     }
-    err("Value is not an object", pos)
 }
 
-fn run_method_call(
+// inside is set when a method calls another of its object by name, which may be private
+pub(crate) fn run_method_call(
     executor: &mut Executor,
-    base: NodeRef,
+    object: &Value,
     name: &String,
-    call_params: &Vec<Box<Node>>,
+    call_params: &[Box<Node>],
     pos: &Position,
-) -> Box<Node> {
-    if let Node::Object { props, base, .. } = base.borrow().deref().deref() {
-        let prop = run_base_prop_access(name, base, props);
-        if let Some(Property::Method {
-            params: fn_params,
-            children,
-            private,
-            returns,
-        }) = prop
-        {
-            executor.enter_scope();
-            for (name, prop) in props.iter() {
-                if let Property::Var { value, t, .. } = prop {
-                    let value = Box::new(Node::RefVar(value.clone()));
-                    executor.declare_var(name, value, &t, true, pos);
-                } else if let Property::Method {
+    inside: bool,
+) -> Result<Value, PseudoError> {
+    let Some(props) = object.props() else {
+        unreachable!()
+    };
+    if let Some(Property::Method {
+        params: fn_params,
+        children,
+        private,
+        returns,
+    }) = object.prop(name).cloned()
+    {
+        // The method sees the members of its object as variables
+        let mut members = State::local(name);
+        members.object = Some(object.clone());
+        for (name, prop) in props.iter() {
+            if let Property::Var { value, t, .. } = prop {
+                let var = Variable {
+                    value: value.clone(),
+                    t: *t.clone(),
+                    mutable: true,
+                };
+                members.variables.insert(name.clone(), var);
+            } else if let Property::Method {
+                params,
+                children,
+                returns,
+                ..
+            } = prop.clone()
+            {
+                let def = Definition::Function {
                     params,
                     children,
                     returns,
-                    ..
-                } = prop.clone()
-                {
-                    executor.declare_def(
-                        name,
-                        Definition::Function {
-                            params,
-                            children,
-                            returns,
-                        },
-                        pos,
-                    );
-                }
-            }
-            if Node::Null != *base.deref() {
-                executor.declare_var(
-                    &"super".to_string(),
-                    Box::new(Node::RefVar(NodeRef::new_ref(base.clone()))),
-                    &Box::new(var_type_of(base)),
-                    true,
-                    pos,
-                );
+                };
+                members.defs.insert(name.clone(), def);
             }
-            if private {
-                err("Cannot call private method", pos)
+        }
+        if let Value::Object(object) = object {
+            if let Some(base) = &object.base {
+                let base = Value::Object(base.clone());
+                let var = Variable {
+                    t: base.type_of(),
+                    value: base.into_ref(),
+                    mutable: true,
+                };
+                members.variables.insert("super".to_string(), var);
             }
-            let result =
-                run_fn_call_inner(executor, call_params, &fn_params, &children, returns, pos);
-            executor.exit_scope();
-            return result;
         }
-        err(format!("Method '{}' not found", name).as_str(), pos)
+        if private && !inside {
+            err("Cannot call private method", pos)?
+        }
+        executor.enter_call(name, pos);
+        let result = run_fn_call_inner(
+            executor,
+            call_params,
+            &fn_params,
+            &children,
+            returns,
+            pos,
+            members,
+        )?;
+        executor.exit_call();
+        return Ok(result);
     }
-    unreachable!()
+    err(format!("Method '{}' not found", name).as_str(), pos)
 }
 
-pub fn run_create_obj(executor: &mut Executor, node: &Box<Node>, pos: &Position) -> Box<Node> {
+pub fn run_create_obj(
+    executor: &mut Executor,
+    node: &Box<Node>,
+    pos: &Position,
+) -> Result<Value, PseudoError> {
     if let Node::FunctionCall { params, name, .. } = node.deref() {
-        if let Definition::Class { props, base, name } = executor.get_def(name, pos) {
-            let base = def_base_class(props, base, name.clone());
-            let base_ref = NodeRef::new_ref(Box::new(base));
-            run_method_call(executor, base_ref.clone(), &"new".to_string(), params, pos);
-            return base_ref.clone_node();
+        if let Definition::Class { props, base, name } = executor.get_def(name, pos)? {
+            let object = Value::Object(def_base_class(props, *base, name.clone()));
+            run_method_call(executor, &object, &"new".to_string(), params, pos, false)?;
+            return Ok(object);
         }
-        err(format!("{} is not a class", name).as_str(), pos)
+        return err(format!("{} is not a class", name).as_str(), pos);
     }
     unreachable!()
 }
@@ -1,22 +1,23 @@
-use std::cell::RefCell;
-use std::ops::{Deref, DerefMut};
-use std::rc::Rc;
+use std::ops::Deref;
 
-use crate::enums::{Index, Node, Position, VariableType};
+use crate::enums::{Index, Node, Position};
 use crate::executor::run_builtins::match_builtin;
-use crate::executor::run_class::{run_access_mut, run_composite_access, run_create_obj};
-use crate::executor::run_stmt::{as_number_expr, run_stmt};
-use crate::executor::var_type_of;
-use crate::executor::variable::{Definition, Executor, NodeDeref, Property};
-use crate::utils::err;
+use crate::executor::run_class::{
+    run_access_mut, run_composite_access, run_create_obj, run_method_call,
+};
+use crate::executor::run_stmt::as_number_expr;
+use crate::executor::value::{Value, ValueRef};
+use crate::executor::variable::{Call, Definition, Executor, State};
+use crate::executor::{copy_value, promote};
+use crate::utils::{err, PseudoError};
 
 use super::run_class::run_access;
 use super::run_stmt::run_stmts;
 
-pub fn run_expr(executor: &mut Executor, node: &Box<Node>) -> Box<Node> {
+pub fn run_expr(executor: &mut Executor, node: &Box<Node>) -> Result<Value, PseudoError> {
     match node.deref() {
         Node::Binary { op, rhs, lhs, pos } => run_binary(executor, op, rhs, lhs, pos),
-        Node::Unary { op, expr, pos } => run_unary(executor, op, expr, pos),
+        Node::Unary { op, expr, .. } => run_unary(executor, op, expr),
         Node::Var { name, pos } => run_var(executor, name, pos),
         Node::FunctionCall { name, params, pos } => run_fn_call(executor, name, params, pos),
         Node::ArrayVar { name, indices, pos } => run_array_var(executor, name, indices, pos),
@@ -24,38 +25,53 @@ pub fn run_expr(executor: &mut Executor, node: &Box<Node>) -> Box<Node> {
         Node::Composite { children, .. } => run_composite_access(executor, children),
         Node::Reference { expr, .. } => run_reference(executor, expr),
         Node::Dereference { expr, .. } => run_dereference(executor, expr),
-        _ => node.clone(),
+        _ => Ok(Value::from_node(node)),
     }
 }
 
 fn run_array_var(
     executor: &mut Executor,
     name: &String,
-    indices: &Vec<Box<Node>>,
+    indices: &[Box<Node>],
     pos: &Position,
-) -> Box<Node> {
-    let nodes = indices.clone();
+) -> Result<Value, PseudoError> {
+    let nodes = indices;
     let indices = indices
         .iter()
         .map(|index| as_number_expr(executor, index))
-        .collect::<Vec<i64>>();
-    let node = executor.get_var(name, pos).value.borrow();
-    if let Node::Array { values, shape, .. } = node.deref().deref() {
-        return values[get_array_index(indices, shape, &nodes)]
+        .collect::<Result<Vec<i64>, PseudoError>>()?;
+    let value = &executor.get_var(name, pos)?.value;
+    if let Value::Array(array) = value.borrow().deref() {
+        return Ok(array.values[get_array_index(indices, &array.shape, nodes)?]
             .borrow()
-            .clone();
+            .clone());
     };
     err(format!("{} is not an array", name).as_str(), pos)
 }
 
-pub fn get_array_index(indices: Vec<i64>, shape: &Vec<Index>, nodes: &Vec<Box<Node>>) -> usize {
+pub fn get_array_index(
+    indices: Vec<i64>,
+    shape: &[Index],
+    nodes: &[Box<Node>],
+) -> Result<usize, PseudoError> {
+    let positions: Vec<Position> = nodes.iter().map(|node| node.pos()).collect();
+    array_offset(&indices, shape, &positions)
+}
+
+// Where an element is kept in the flattened array, given where each index was written
+pub(crate) fn array_offset(
+    indices: &[i64],
+    shape: &[Index],
+    positions: &[Position],
+) -> Result<usize, PseudoError> {
     let mut size = 1;
     let mut total_index = 0;
-    let pos = Position::range(nodes[0].pos(), nodes[indices.len() - 1].pos());
+    let pos = Position::range(positions[0], positions[indices.len() - 1]);
     if indices.len() != shape.len() {
-        err("Number of indices doesnt match array shape", &pos)
+        err("Number of indices doesnt match array shape", &pos)?
     }
     for (i, (shape, index)) in shape.iter().zip(indices).rev().enumerate() {
+        let index = *index;
         // bound check
         if index < shape.lower || index > shape.upper {
             err(
@@ -64,32 +80,27 @@ pub fn get_array_index(indices: Vec<i64>, shape: &Vec<Index>, nodes: &Vec<Box<No
                     index, shape.lower, shape.upper
                 )
                 .as_str(),
-                &nodes[i].pos(),
-            );
+                &positions[i],
+            )?;
         }
         // 1D index calculation, bounds are inclusive hence +1
         total_index += (index - shape.lower) * size;
-        size = size * (shape.upper - shape.lower + 1);
+        size *= shape.upper - shape.lower + 1;
     }
-    total_index as usize
+    Ok(total_index as usize)
 }
 
-fn run_var(executor: &mut Executor, name: &String, pos: &Position) -> Box<Node> {
-    let value = &executor.get_var(name, pos).value;
-    if let Node::RefVar(reference) = value.borrow().deref().deref() {
-        return (*reference).clone_node();
-    }
-    value.clone_node()
+fn run_var(executor: &mut Executor, name: &String, pos: &Position) -> Result<Value, PseudoError> {
+    Ok(executor.get_var(name, pos)?.value.borrow().clone())
 }
 
 fn run_binary(
     executor: &mut Executor,
-    op: &String,
+    op: &str,
     rhs: &Box<Node>,
     lhs: &Box<Node>,
     pos: &Position,
-) -> Box<Node> {
-    let op = op.as_str();
+) -> Result<Value, PseudoError> {
     match op {
         "+" | "-" | "*" | "/" | "%" | "//" => run_arithmetic_op(executor, op, rhs, lhs),
         "<" | ">" | "<=" | ">=" => run_comparison_op(executor, op, rhs, lhs),
@@ -100,21 +111,23 @@ fn run_binary(
     }
 }
 
-fn run_concat_op(executor: &mut Executor, rhs: &Box<Node>, lhs: &Box<Node>) -> Box<Node> {
+fn run_concat_op(
+    executor: &mut Executor,
+    rhs: &Box<Node>,
+    lhs: &Box<Node>,
+) -> Result<Value, PseudoError> {
     let rhs_pos = rhs.pos();
     let lhs_pos = lhs.pos();
-    let rhs = run_expr(executor, rhs);
-    let lhs = run_expr(executor, lhs);
-    if var_type_of(&rhs) != VariableType::String {
-        err("'&' can only be performed on STRING", &rhs_pos)
+    let rhs = run_expr(executor, rhs)?;
+    let lhs = run_expr(executor, lhs)?;
+    // A CHAR joins as a STRING of one character
+    if !rhs.is_text() {
+        err("'&' can only be performed on STRING or CHAR", &rhs_pos)?
     }
-    if var_type_of(&lhs) != VariableType::String {
-        err("'&' can only be performed on STRING", &lhs_pos)
+    if !lhs.is_text() {
+        err("'&' can only be performed on STRING or CHAR", &lhs_pos)?
     }
-    Box::from(Node::String {
-        val: format!("{}{}", lhs.val_as_str(), rhs.val_as_str()),
-        pos: Position::invalid(),
-    })
+    Ok(Value::string(&format!("{}{}", lhs, rhs)))
 }
 
 // function for equality op
@@ -124,49 +137,45 @@ fn run_eq_op(
     rhs: &Box<Node>,
     lhs: &Box<Node>,
     pos: &Position,
-) -> Box<Node> {
-    let rhs = run_expr(executor, rhs);
-    let lhs = run_expr(executor, lhs);
+) -> Result<Value, PseudoError> {
+    let rhs = run_expr(executor, rhs)?;
+    let lhs = run_expr(executor, lhs)?;
+    check_eq_types(&lhs, &rhs, pos)?;
+    Ok(Value::Boolean(match op {
+        "=" => lhs == rhs,
+        "!=" => lhs != rhs,
+        _ => unreachable!(),
+    }))
+}
 
-    if var_type_of(&rhs) != var_type_of(&lhs) {
+// INTEGER and REAL can be compared with each other, other values only with their own type
+pub(crate) fn check_eq_types(lhs: &Value, rhs: &Value, pos: &Position) -> Result<(), PseudoError> {
+    let numbers = lhs.as_number().is_some() && rhs.as_number().is_some();
+    // A CHAR compares as a STRING of one character
+    let text = lhs.is_text() && rhs.is_text();
+    if !numbers && !text && lhs.type_of() != rhs.type_of() {
         err(
             format!(
                 "Cannot compare types {} AND {}",
-                var_type_of(&rhs).str(),
-                var_type_of(&rhs).str()
+                lhs.type_of().str(),
+                rhs.type_of().str()
             )
             .as_str(),
             pos,
-        )
+        )?
     }
-
-    match lhs.deref() {
-        Node::Int { .. }
-        | Node::Real { .. }
-        | Node::Boolean { .. }
-        | Node::String { .. }
-        | Node::Date { .. }
-        | Node::EnumVal { .. } => {}
-        Node::Array { .. } | Node::Object { .. } | Node::Pointer { .. } => err(
+    if !lhs.comparable() {
+        err(
             format!(
                 "Cannot compare type {}. {}",
-                var_type_of(&lhs).str(),
+                lhs.type_of().str(),
                 crate::utils::SUPPORT_MESSAGE
             )
             .as_str(),
             pos,
-        ),
-        _ => unimplemented!("{:?}", lhs),
+        )?
     }
-
-    Box::from(Node::Boolean {
-        val: match op {
-            "=" => rhs.val_as_str() == lhs.val_as_str(),
-            "!=" => rhs.val_as_str() != lhs.val_as_str(),
-            _ => unreachable!(),
-        },
-        pos: Position::invalid(),
-    })
+    Ok(())
 }
 
 fn run_logical_op(
@@ -174,57 +183,43 @@ fn run_logical_op(
     op: &str,
     rhs: &Box<Node>,
     lhs: &Box<Node>,
-) -> Box<Node> {
+) -> Result<Value, PseudoError> {
     let rhs_pos = rhs.pos();
     let lhs_pos = lhs.pos();
-    let rhs = run_expr(executor, rhs);
-    let lhs = run_expr(executor, lhs);
-    let (rhs_val, is_bool) = assert_boolean(&rhs);
-    if !is_bool {
-        err(
+    let rhs = run_expr(executor, rhs)?;
+    let lhs = run_expr(executor, lhs)?;
+    let Value::Boolean(rhs_val) = rhs else {
+        return err(
             format!("Logical operation {} can only be performed on BOOLEAN", op).as_str(),
             &rhs_pos,
-        )
-    }
-
-    let (lhs_val, is_bool) = assert_boolean(&lhs);
-    if !is_bool {
-        err(
+        );
+    };
+    let Value::Boolean(lhs_val) = lhs else {
+        return err(
             format!("Logical operation {} can only be performed on BOOLEAN", op).as_str(),
             &lhs_pos,
-        )
-    }
-    Box::from(match op {
-        "&&" => Node::Boolean {
-            val: lhs_val && rhs_val,
-            pos: Position::invalid(),
-        },
-        "||" => Node::Boolean {
-            val: lhs_val || rhs_val,
-            pos: Position::invalid(),
-        },
+        );
+    };
+    Ok(Value::Boolean(match op {
+        "&&" => lhs_val && rhs_val,
+        "||" => lhs_val || rhs_val,
         _ => unreachable!(),
-    })
+    }))
 }
 
-fn run_unary(executor: &mut Executor, op: &str, expr: &Box<Node>, pos: &Position) -> Box<Node> {
+fn run_unary(executor: &mut Executor, op: &str, expr: &Box<Node>) -> Result<Value, PseudoError> {
     let expr_pos = expr.pos();
-    let expr = run_expr(executor, expr);
+    let expr = run_expr(executor, expr)?;
     if op == "!" {
-        let (rhs_val, is_bool) = assert_boolean(&expr);
-        if !is_bool {
-            err(
+        let Value::Boolean(val) = expr else {
+            return err(
                 format!("Logical operation {} can only be performed on BOOLEAN", op).as_str(),
                 &expr_pos,
-            )
-        }
-
-        Box::from(Node::Boolean {
-            val: !rhs_val,
-            pos: Position::invalid(),
-        })
+            );
+        };
+        Ok(Value::Boolean(!val))
     } else {
-        let (val, is_real) = assert_number(&expr);
+        let (val, is_real) = assert_number_at(&expr, &expr_pos)?;
 
         let res = match op {
             "_-" => -val,
@@ -233,15 +228,9 @@ fn run_unary(executor: &mut Executor, op: &str, expr: &Box<Node>, pos: &Position
         };
 
         if is_real {
-            Box::from(Node::Real {
-                val: res,
-                pos: Position::invalid(),
-            })
+            Ok(Value::Real(res))
         } else {
-            Box::from(Node::Int {
-                val: res.trunc() as i64,
-                pos: Position::invalid(),
-            })
+            Ok(Value::Integer(res.trunc() as i64))
         }
     }
 }
@@ -251,11 +240,13 @@ fn run_arithmetic_op(
     op: &str,
     rhs: &Box<Node>,
     lhs: &Box<Node>,
-) -> Box<Node> {
-    let rhs = run_expr(executor, rhs);
-    let lhs = run_expr(executor, lhs);
-    let (lhs_val, lhs_real) = assert_number(&lhs);
-    let (rhs_val, rhs_real) = assert_number(&rhs);
+) -> Result<Value, PseudoError> {
+    let rhs_pos = rhs.pos();
+    let lhs_pos = lhs.pos();
+    let rhs = run_expr(executor, rhs)?;
+    let lhs = run_expr(executor, lhs)?;
+    let (lhs_val, lhs_real) = assert_number_at(&lhs, &lhs_pos)?;
+    let (rhs_val, rhs_real) = assert_number_at(&rhs, &rhs_pos)?;
 
     let res = match op {
         "+" => lhs_val + rhs_val,
@@ -268,15 +259,9 @@ fn run_arithmetic_op(
     };
 
     if lhs_real || rhs_real {
-        Box::from(Node::Real {
-            val: res,
-            pos: Position::invalid(),
-        })
+        Ok(Value::Real(res))
     } else {
-        Box::from(Node::Int {
-            val: res.trunc() as i64,
-            pos: Position::invalid(),
-        })
+        Ok(Value::Integer(res.trunc() as i64))
     }
 }
 
@@ -285,81 +270,105 @@ fn run_comparison_op(
     op: &str,
     rhs: &Box<Node>,
     lhs: &Box<Node>,
-) -> Box<Node> {
-    let rhs = run_expr(executor, rhs);
-    let lhs = run_expr(executor, lhs);
-    let (lhs_val, _) = assert_number(&lhs);
-    let (rhs_val, _) = assert_number(&rhs);
+) -> Result<Value, PseudoError> {
+    let rhs_pos = rhs.pos();
+    let lhs_pos = lhs.pos();
+    let rhs = run_expr(executor, rhs)?;
+    let lhs = run_expr(executor, lhs)?;
+    // CHAR values are ordered among themselves
+    let is_char = |value: &Value| matches!(value, Value::Char(_));
+    if is_char(&lhs) != is_char(&rhs) {
+        err(
+            format!(
+                "Cannot compare types {} AND {}",
+                lhs.type_of().str(),
+                rhs.type_of().str()
+            )
+            .as_str(),
+            &Position::range(lhs_pos, rhs_pos),
+        )?
+    }
+    if !is_char(&lhs) {
+        assert_number_at(&lhs, &lhs_pos)?;
+        assert_number_at(&rhs, &rhs_pos)?;
+    }
 
-    let res = match op {
-        ">" => lhs_val > rhs_val,
-        "<" => lhs_val < rhs_val,
-        ">=" => lhs_val >= rhs_val,
-        "<=" => lhs_val <= rhs_val,
+    Ok(Value::Boolean(match op {
+        ">" => lhs > rhs,
+        "<" => lhs < rhs,
+        ">=" => lhs >= rhs,
+        "<=" => lhs <= rhs,
         _ => unreachable!(),
-    };
-
-    Box::from(Node::Boolean {
-        val: res,
-        pos: Position::invalid(),
-    })
+    }))
 }
 
-pub fn assert_number(node: &Box<Node>) -> (f64, bool) {
-    match *node.deref() {
-        Node::Int { val, .. } => (val as f64, false),
-        Node::Real { val, .. } => (val, true),
-        _ => err(
+// Evaluated values carry no position, so errors point at the expression they came from
+pub fn assert_number_at(value: &Value, pos: &Position) -> Result<(f64, bool), PseudoError> {
+    match value.as_number() {
+        Some(number) => Ok(number),
+        None => err(
             "Arithmetic operation can only be performed on INTEGER or REAL",
-            &node.pos(),
+            pos,
         ),
     }
 }
 
-fn assert_boolean(node: &Box<Node>) -> (bool, bool) {
-    match *node.deref() {
-        Node::Boolean { val, .. } => (val, true),
-        _ => (false, false),
-    }
-}
-
 pub fn run_fn_call(
     executor: &mut Executor,
     name: &String,
-    call_params: &Vec<Box<Node>>,
+    call_params: &[Box<Node>],
     pos: &Position,
-) -> Box<Node> {
-    match match_builtin(executor, name, call_params, pos) {
-        Some(result) => return result,
-        None => {}
+) -> Result<Value, PseudoError> {
+    if let Some(result) = match_builtin(executor, name, call_params, pos)? {
+        return Ok(result);
     };
 
+    // A method calling another method of its object by name
+    let frame = executor.scopes.last().unwrap().state();
+    if let Some(object) = &frame.object {
+        if frame.defs.contains_key(name) {
+            let object = object.clone();
+            return run_method_call(executor, &object, name, call_params, pos, true);
+        }
+    }
+
     if let Definition::Function {
         params,
-        mut children,
+        children,
         returns,
-    } = executor.get_def(name, pos)
+    } = executor.get_def(name, pos)?
     {
-        return run_fn_call_inner(executor, call_params, &params, &mut children, returns, pos);
+        executor.enter_call(name, pos);
+        let result = run_fn_call_inner(
+            executor,
+            call_params,
+            &params,
+            &children,
+            returns,
+            pos,
+            State::local(name),
+        )?;
+        executor.exit_call();
+        return Ok(result);
     }
     err("Value is not a function, it cannot be called", pos)
 }
 
+// Arguments are worked out in the caller's scope, then bound in scope, which
+// already holds the members of the object when a method is called
 pub fn run_fn_call_inner(
     executor: &mut Executor,
-    call_params: &Vec<Box<Node>>,
+    call_params: &[Box<Node>],
     fn_params: &Vec<Box<Node>>,
     children: &Vec<Box<Node>>,
     returns: bool,
     pos: &Position,
-) -> Box<Node> {
-    executor.enter_scope();
+    scope: State,
+) -> Result<Value, PseudoError> {
     if fn_params.len() != call_params.len() {
-        err(
-            "Number of parameters doesnt match function definition",
-            &pos,
-        )
+        err("Number of parameters doesnt match function definition", pos)?
     }
+    let mut args = Vec::new();
     for (call_param, fn_param) in call_params.iter().zip(fn_params) {
         if let Node::Declare {
             t,
@@ -369,48 +378,89 @@ pub fn run_fn_call_inner(
             ..
         } = fn_param.deref()
         {
-            let param_name = &children[0];
-            let value = if *byref {
+            // A BYREF parameter shares the cell of the variable it was given
+            let (cell, value_type) = if *byref {
                 match call_param.deref() {
                     Node::Var { .. }
                     | Node::ArrayVar { .. }
                     | Node::Composite { .. }
                     | Node::Dereference { .. } => {}
-                    _ => err("Cannot pass this value byref", &call_param.pos()),
+                    _ => err("Cannot pass this value byref", &call_param.pos())?,
                 };
-                let node = run_access_mut(executor, &call_param);
-                Box::new(Node::RefVar(node.clone()))
+                let cell = run_access_mut(executor, call_param)?;
+                let value_type = cell.borrow().type_of();
+                (cell, value_type)
             } else {
-                run_expr(executor, call_param)
+                let value = run_expr(executor, call_param)?;
+                let value = copy_value(promote(value, t, &call_param.pos())?);
+                let value_type = value.type_of();
+                (value.into_ref(), value_type)
             };
-            if var_type_of(&value) == *t.deref() {
-                executor.declare_var(param_name, value, t, true, pos);
-            } else {
+            if value_type != *t.deref() {
                 let msg = format!(
                     "Cannot assign type {:?} to parameter of type {:?}",
-                    var_type_of(&value).str(),
+                    value_type.str(),
                     t.str()
                 );
-                err(msg.as_str(), &call_param.pos())
+                err(msg.as_str(), &call_param.pos())?
             }
+            args.push((&children[0], cell, t, pos));
+        }
+    }
+    let cells: Vec<ValueRef> = args.iter().map(|(_, cell, ..)| cell.clone()).collect();
+    executor.enter_scope(scope.routine.as_deref().unwrap_or_default());
+    for (param_name, cell, t, pos) in args {
+        executor.declare_ref(param_name, cell, t, true, pos)?;
+    }
+    // Parameters hide members of the same name
+    let state = executor.scopes.last_mut().unwrap().state_mut();
+    state.object = scope.object;
+    for (name, var) in scope.variables {
+        state.variables.entry(name).or_insert(var);
+    }
+    for (name, def) in scope.defs {
+        state.defs.entry(name).or_insert(def);
+    }
+
+    executor.check_call_depth(executor.calls.len(), pos)?;
+    executor.calls.push(Call {
+        name: scope.routine.unwrap_or_default(),
+        pos: *pos,
+        args: cells,
+    });
+    let mut result = run_body(executor, children, returns, pos);
+    // The innermost call the error passes through knows every call still running
+    if let Err(e) = &mut result {
+        if e.backtrace.is_empty() {
+            e.backtrace = executor.backtrace();
         }
     }
-    if let Some(expr) = run_stmts(executor, children) {
+    executor.calls.pop();
+    result
+}
+
+fn run_body(
+    executor: &mut Executor,
+    children: &Vec<Box<Node>>,
+    returns: bool,
+    pos: &Position,
+) -> Result<Value, PseudoError> {
+    if let Some(expr) = run_stmts(executor, children)? {
         if !returns {
-            err("Cannot return within procedure", pos)
+            err("Cannot return within procedure", pos)?
         }
         executor.exit_scope();
-        return expr;
+        return Ok(expr);
     }
     if returns {
         err("Missing return statement", pos)
     } else {
         executor.exit_scope();
-        Box::new(Node::Null)
+        Ok(Value::Null)
     }
 }
 
-pub fn run_reference(executor: &mut Executor, value: &Box<Node>) -> Box<Node> {
+pub fn run_reference(executor: &mut Executor, value: &Box<Node>) -> Result<Value, PseudoError> {
     match value.deref() {
         Node::Var { .. }
         | Node::ArrayVar { .. }
@@ -419,13 +469,13 @@ pub fn run_reference(executor: &mut Executor, value: &Box<Node>) -> Box<Node> {
         _ => err(
             "Value is not a pointer, it cannot be referenced",
             &value.pos(),
-        ),
+        )?,
     };
-    let pointer = run_access_mut(executor, value);
-    return Box::new(Node::Pointer(pointer));
+    let pointer = run_access_mut(executor, value)?;
+    Ok(Value::Pointer(pointer))
 }
 
-pub fn run_dereference(executor: &mut Executor, value: &Box<Node>) -> Box<Node> {
+pub fn run_dereference(executor: &mut Executor, value: &Box<Node>) -> Result<Value, PseudoError> {
     match value.deref() {
         Node::Var { .. }
         | Node::ArrayVar { .. }
@@ -434,14 +484,14 @@ pub fn run_dereference(executor: &mut Executor, value: &Box<Node>) -> Box<Node>
         _ => err(
             "Value is not a pointer, it cannot be dereferenced",
             &value.pos(),
-        ),
+        )?,
     };
-    let pointer = run_access(executor, value);
-    if let Node::Pointer(pointer) = pointer.deref() {
-        return pointer.clone_node();
+    let pointer = run_access(executor, value)?;
+    if let Value::Pointer(pointer) = pointer {
+        return Ok(pointer.borrow().clone());
     }
     err(
         "Value is not a pointer, it cannot be dereferenced",
         &value.pos(),
-    );
+    )
 }
@@ -1,79 +1,124 @@
-use crate::enums::{Array, Index, Node, NodeRef, Position, VariableType};
-use crate::executor::run_expr::{assert_number, run_expr};
-use crate::executor::run_stmt::run_assign;
+use crate::enums::{Index, Node, Position, VariableType};
+use crate::executor::run_expr::{assert_number_at, run_expr};
+use crate::executor::run_stmt::assign_value;
+use crate::executor::value::{ArrayValue, Value};
 use crate::executor::variable::{Executor, XFile};
 use crate::executor::Property;
 use crate::tokens::TToken;
-use crate::utils::err;
+use crate::utils::{err, PseudoError};
 use chrono::NaiveDate;
-use serde_json::{Map, Number, Value};
+use serde_json::{Map, Number, Value as Json};
 use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::ErrorKind;
 use std::mem::discriminant;
-use std::ops::{Deref, DerefMut};
+use std::ops::Deref;
+use std::rc::Rc;
 
-use super::variable::NodeDeref;
-
-pub fn run_open_file(executor: &mut Executor, filename: &Box<Node>, mode: &TToken, pos: &Position) {
-    let filename = run_expr(executor, filename).val_as_str();
+pub fn run_open_file(
+    executor: &mut Executor,
+    filename: &Box<Node>,
+    mode: &TToken,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let filename = run_expr(executor, filename)?.text();
     if let TToken::FileMode(mode) = mode {
-        match executor.file_handles.get(&filename) {
-            Some(_) => err(format!("File {} is already open", filename).as_str(), pos),
-            None => {
-                let mut content = vec![];
-                executor.file_handles.insert(
-                    filename.clone(),
-                    XFile {
-                        handle: match mode.as_str() {
-                            "APPEND" => OpenOptions::new().append(true).open(filename).expect(""),
-                            "WRITE" => OpenOptions::new()
-                                .write(true)
-                                .create(true)
-                                .truncate(true)
-                                .open(filename)
-                                .expect(""),
-                            "READ" | "RANDOM" => {
-                                let mut file = OpenOptions::new()
-                                    .write(true)
-                                    .read(true)
-                                    .create(true)
-                                    .append(false)
-                                    .open(filename.clone())
-                                    .expect("");
-                                let mut buf = String::new();
-                                file.read_to_string(&mut buf)
-                                    .expect(format!("{} contains invalid data", filename).as_str());
+        open_file(executor, filename, mode, pos)
+    } else {
+        unreachable!()
+    }
+}
+
+// The file statements below are split so the filename and data can be worked out
+// by whichever backend is running the program
+pub(crate) fn open_file(
+    executor: &mut Executor,
+    filename: String,
+    mode: &str,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    match executor.file_handles.get(&filename) {
+        Some(_) => err(format!("File {} is already open", filename).as_str(), pos)?,
+        None => {
+            if let Err(reason) = executor.io.check_path(&filename) {
+                err(
+                    format!("File {} cannot be opened, {}", filename, reason).as_str(),
+                    pos,
+                )?
+            }
+            let mut content = vec![];
+            let opened = match mode {
+                "APPEND" => {
+                    if executor.io.file_exists(&filename) {
+                        Ok(())
+                    } else {
+                        Err(ErrorKind::NotFound.into())
+                    }
+                }
+                "WRITE" => executor.io.write_file(&filename, ""),
+                "READ" | "RANDOM" => {
+                    if !executor.io.file_exists(&filename) {
+                        executor.io.write_file(&filename, "")
+                    } else {
+                        match executor.io.read_file(&filename) {
+                            Ok(buf) => {
                                 content = buf.lines().map(|s| s.to_string()).collect();
-                                file
+                                Ok(())
                             }
-                            _ => unreachable!(),
-                        },
-                        mode: mode.to_string(),
-                        content,
-                        cursor: 1,
-                    },
-                );
+                            Err(_) => {
+                                err(format!("{} contains invalid data", filename).as_str(), pos)?
+                            }
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            };
+            if opened.is_err() {
+                err(format!("File {} cannot be opened", filename).as_str(), pos)?
             }
+            executor.file_handles.insert(
+                filename.clone(),
+                XFile {
+                    mode: mode.to_string(),
+                    content,
+                    cursor: 1,
+                },
+            );
         }
-    } else {
-        unreachable!()
     }
+    Ok(())
 }
 
-pub fn run_close_file(executor: &mut Executor, filename: &Box<Node>, pos: &Position) {
-    let filename = run_expr(executor, filename).val_as_str();
-    let mut file = match executor.file_handles.remove(&filename) {
-        None => err(format!("File {} is already closed", filename).as_str(), pos),
+pub fn run_close_file(
+    executor: &mut Executor,
+    filename: &Box<Node>,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let filename = run_expr(executor, filename)?.text();
+    close_file(executor, filename, pos)
+}
+
+pub(crate) fn close_file(
+    executor: &mut Executor,
+    filename: String,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let file = match executor.file_handles.remove(&filename) {
+        None => err(format!("File {} is already closed", filename).as_str(), pos)?,
         Some(file) => file,
     };
     // commit changes as PUTRECORD does not write to file immediately
-    if file.mode == "RANDOM" {
-        file.handle.seek(SeekFrom::Start(0)).expect("");
-        file.handle
-            .write_all(file.content.join("\n").as_bytes())
-            .expect("");
+    if file.mode == "RANDOM"
+        && executor
+            .io
+            .write_file(&filename, &file.content.join("\n"))
+            .is_err()
+    {
+        err(
+            format!("File {} cannot be written to", filename).as_str(),
+            pos,
+        )?
     }
+    Ok(())
 }
 
 pub fn run_write_file(
@@ -81,19 +126,35 @@ pub fn run_write_file(
     filename: &Box<Node>,
     data: &Box<Node>,
     pos: &Position,
-) {
-    let filename = run_expr(executor, filename).val_as_str();
-    let data = run_expr(executor, data).val_as_str() + "\n";
-    match executor.file_handles.get_mut(&filename) {
+) -> Result<(), PseudoError> {
+    let filename = run_expr(executor, filename)?.text();
+    let data = run_expr(executor, data)?.text();
+    write_file(executor, filename, data, pos)
+}
+
+pub(crate) fn write_file(
+    executor: &mut Executor,
+    filename: String,
+    data: String,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let data = data + "\n";
+    match executor.file_handles.get(&filename) {
         None => err(format!("File {} is not opened", filename).as_str(), pos),
         Some(file) => {
             if file.mode != "APPEND" && file.mode != "WRITE" {
                 err(
                     format!("File {} is not opened for APPEND or WRITE", filename).as_str(),
                     pos,
-                )
+                )?
             }
-            file.handle.write_all(data.as_bytes()).expect("");
+            if executor.io.append_file(&filename, &data).is_err() {
+                err(
+                    format!("File {} cannot be written to", filename).as_str(),
+                    pos,
+                )?
+            }
+            Ok(())
         }
     }
 }
@@ -103,9 +164,18 @@ pub fn run_read_file(
     filename: &Box<Node>,
     destination: &Box<Node>,
     pos: &Position,
-) {
-    let filename = run_expr(executor, filename).val_as_str();
+) -> Result<(), PseudoError> {
+    let filename = run_expr(executor, filename)?.text();
+    let content = Value::string(&read_line(executor, filename, pos)?);
+    assign_value(executor, destination, content, &destination.pos())
+}
 
+// Next line of a file opened for READ, empty once the file runs out
+pub(crate) fn read_line(
+    executor: &mut Executor,
+    filename: String,
+    pos: &Position,
+) -> Result<String, PseudoError> {
     match executor.file_handles.get_mut(&filename) {
         None => err(format!("File {} is not opened", filename).as_str(), pos),
         Some(file) => {
@@ -113,19 +183,15 @@ pub fn run_read_file(
                 err(
                     format!("File {} is not opened for READ", filename).as_str(),
                     pos,
-                )
+                )?
             }
-
-            let content = Box::from(Node::String {
-                val: if file.cursor <= file.content.len() {
-                    file.content[file.cursor - 1].clone()
-                } else {
-                    String::new()
-                },
-                pos: Position::invalid(),
-            });
+            let line = if file.cursor <= file.content.len() {
+                file.content[file.cursor - 1].clone()
+            } else {
+                String::new()
+            };
             file.cursor += 1;
-            run_assign(executor, destination, &content, &destination.pos());
+            Ok(line)
         }
     }
 }
@@ -135,40 +201,43 @@ pub fn run_get_record(
     filename: &Box<Node>,
     destination: &Box<Node>,
     pos: &Position,
-) {
-    let filename = run_expr(executor, filename).val_as_str();
-    let dest_content = run_expr(executor, destination);
+) -> Result<(), PseudoError> {
+    let filename = run_expr(executor, filename)?.text();
+    let dest_content = run_expr(executor, destination)?;
     let file = match executor.file_handles.get_mut(&filename) {
-        None => err(format!("File {} is not opened", filename).as_str(), pos),
+        None => err(format!("File {} is not opened", filename).as_str(), pos)?,
         Some(file) => {
             if file.mode != "RANDOM" {
-                err("GETRECORD only works for file opened for RANDOM", pos)
+                err("GETRECORD only works for file opened for RANDOM", pos)?
             }
             file
         }
     };
 
-    let mut data: Map<String, Value> = match file.content.get(file.cursor - 1) {
-        Some(str) => serde_json::from_str(str).unwrap(),
+    let data: Map<String, Json> = match file.content.get(file.cursor - 1) {
+        Some(str) => match serde_json::from_str(str) {
+            Ok(data) => data,
+            Err(_) => err(
+                format!("Line {} of file {} is not a record", file.cursor, filename).as_str(),
+                pos,
+            )?,
+        },
         None => err(
             format!("Line {} of file {} is empty", file.cursor, filename).as_str(),
             pos,
-        ),
+        )?,
     };
 
-    if let Node::Object { name, base, props } = dest_content.deref() {
-        deserialise_record(&props, &data, pos);
-        run_assign(
+    if let Value::Record(record) = &dest_content {
+        deserialise_record(&record.props, &data, pos)?;
+        assign_value(
             executor,
-            &destination,
-            &Box::from(Node::Object {
-                name: name.clone(),
-                props: props.clone(),
-                base: base.clone(),
-            }),
+            destination,
+            dest_content.clone(),
             &destination.pos(),
-        );
+        )?;
     }
+    Ok(())
 }
 
 pub fn run_seek(
@@ -176,25 +245,26 @@ pub fn run_seek(
     filename: &Box<Node>,
     position: &Box<Node>,
     pos: &Position,
-) {
-    let filename = run_expr(executor, filename).val_as_str();
-    let (position, _) = assert_number(&run_expr(executor, position));
+) -> Result<(), PseudoError> {
+    let filename = run_expr(executor, filename)?.text();
+    let (position, _) = assert_number_at(&run_expr(executor, position)?, &position.pos())?;
 
     let file = match executor.file_handles.get_mut(&filename) {
-        None => err(format!("File {} is not opened", filename).as_str(), pos),
+        None => err(format!("File {} is not opened", filename).as_str(), pos)?,
         Some(file) => {
             if file.mode != "RANDOM" {
-                err("SEEK only works for file opened for RANDOM", pos)
+                err("SEEK only works for file opened for RANDOM", pos)?
             }
             file
         }
     };
 
     if position <= 0.0 {
-        err("Invalid position. Line number must be greater than 0", pos)
+        err("Invalid position. Line number must be greater than 0", pos)?
     }
 
     file.cursor = position as usize;
+    Ok(())
 }
 
 pub fn run_put_record(
@@ -202,24 +272,24 @@ pub fn run_put_record(
     filename: &Box<Node>,
     data: &Box<Node>,
     pos: &Position,
-) {
-    let filename = run_expr(executor, filename).val_as_str();
-    let data = run_expr(executor, data);
+) -> Result<(), PseudoError> {
+    let filename = run_expr(executor, filename)?.text();
+    let data = run_expr(executor, data)?;
 
     let file = match executor.file_handles.get_mut(&filename) {
-        None => err(format!("File {} is not opened", filename).as_str(), pos),
+        None => err(format!("File {} is not opened", filename).as_str(), pos)?,
         Some(file) => {
             if file.mode != "RANDOM" {
-                err("PUTRECORD only works for file opened for RANDOM", pos)
+                err("PUTRECORD only works for file opened for RANDOM", pos)?
             }
             file
         }
     };
     let json_string;
-    if let Node::Object { props, .. } = data.deref() {
-        json_string = serde_json::to_string(&serialise_record(props, pos)).unwrap();
+    if let Value::Record(record) = &data {
+        json_string = serde_json::to_string(&serialise_record(&record.props, pos)?).unwrap();
     } else {
-        err("Invalid data type. RECORD data type expected", pos)
+        return err("Invalid data type. RECORD data type expected", pos);
     };
 
     // fill skipped lines
@@ -227,99 +297,100 @@ pub fn run_put_record(
         file.content.push("\n".to_string());
     }
     file.content[file.cursor - 1] = json_string;
+    Ok(())
 }
 
 fn deserialise_record(
     props: &HashMap<String, Property>,
-    data: &Map<String, Value>,
+    data: &Map<String, Json>,
     pos: &Position,
-) {
-    props.iter().for_each(|(k, property)| {
+) -> Result<(), PseudoError> {
+    for (k, property) in props.iter() {
         match data.get(k) {
             Some(dv) => {
                 if let Property::Var { value, ref t, .. } = property {
-                    value.replace(Box::from(
-                        if let Node::Object { name, props, base } = &value.clone_node().deref() {
-                            // handle nested record type
-                            if let Value::Object(map) = data.get(name).unwrap() {
-                                deserialise_record(props, map, pos);
-                                Node::Object {
-                                    name: name.clone(),
-                                    base: base.clone(),
-                                    props: props.clone(),
-                                }
-                            } else {
-                                unreachable!()
-                            }
+                    let old = value.borrow().clone();
+                    let new = if let Value::Record(record) = &old {
+                        // handle nested record type
+                        if let Json::Object(map) = data.get(&record.name).unwrap() {
+                            deserialise_record(&record.props, map, pos)?;
+                            old.clone()
                         } else {
-                            // convert Value to VariableType equivalent
-                            let dv_type = match dv {
-                                Value::Number(val) => {
-                                    if val.is_i64() {
-                                        VariableType::Integer
-                                    } else {
-                                        VariableType::Real
-                                    }
-                                }
-                                Value::String(val) => {
-                                    match NaiveDate::parse_from_str(val, "%Y-%m-%d") {
-                                        Ok(..) => VariableType::Date,
-                                        Err(_) => VariableType::String,
-                                    }
+                            unreachable!()
+                        }
+                    } else {
+                        // convert Value to VariableType equivalent
+                        let dv_type = match dv {
+                            Json::Number(val) => {
+                                if val.is_i64() {
+                                    VariableType::Integer
+                                } else {
+                                    VariableType::Real
                                 }
-                                Value::Bool(..) => VariableType::Boolean,
-                                // Content of Array does not matter
-                                Value::Array(..) => VariableType::Array {
-                                    t: Box::new(VariableType::Boolean),
-                                    shape: vec![Index { lower: 0, upper: 0 }],
-                                },
-                                _ => unreachable!(),
-                            };
-                            if discriminant(&dv_type) != discriminant(&t) {
-                                err("Property type mismatch while getting record", pos)
                             }
-                            value_to_node(value.borrow().deref(), dv, t.clone(), pos)
-                        },
-                    ));
+                            Json::String(val) => match NaiveDate::parse_from_str(val, "%Y-%m-%d") {
+                                Ok(..) => VariableType::Date,
+                                Err(_) => VariableType::String,
+                            },
+                            Json::Bool(..) => VariableType::Boolean,
+                            // Content of Array does not matter
+                            Json::Array(..) => VariableType::Array {
+                                t: Box::new(VariableType::Boolean),
+                                shape: vec![Index { lower: 0, upper: 0 }],
+                            },
+                            _ => unreachable!(),
+                        };
+                        if discriminant(&dv_type) != discriminant(t) {
+                            err("Property type mismatch while getting record", pos)?
+                        }
+                        json_to_value(&old, dv, t.clone(), pos)?
+                    };
+                    value.replace(new);
                 }
             }
-            None => err(format!("Property {} is missing", k).as_str(), pos),
+            None => err(format!("Property {} is missing", k).as_str(), pos)?,
         }
-    });
+    }
+    Ok(())
 }
-fn serialise_record(props: &HashMap<String, Property>, pos: &Position) -> Map<String, Value> {
+fn serialise_record(
+    props: &HashMap<String, Property>,
+    pos: &Position,
+) -> Result<Map<String, Json>, PseudoError> {
     props
         .iter()
         .map(|field| {
-            if let Property::Var { value, t, private } = field.1 {
+            if let Property::Var { value, t, .. } = field.1 {
                 match t.deref() {
                     VariableType::Custom(..) => {
-                        if let Node::Object { props, .. } = value.borrow().deref().deref() {
-                            (field.0.clone(), Value::from(serialise_record(props, pos)))
+                        if let Value::Record(record) = value.borrow().deref() {
+                            Ok((
+                                field.0.clone(),
+                                Json::from(serialise_record(&record.props, pos)?),
+                            ))
                         } else {
                             err("Invalid data type. RECORD data type expected", pos)
                         }
                     }
-                    _ => (
-                        field.0.clone(),
-                        Value::from(serialise_data(value.borrow().deref())),
-                    ),
+                    _ => Ok((field.0.clone(), serialise_data(value.borrow().deref()))),
                 }
             } else {
                 err("Object is not a record", pos)
             }
         })
-        .collect::<Map<String, Value>>()
+        .collect::<Result<Map<String, Json>, PseudoError>>()
 }
 
-fn serialise_data(node: &Box<Node>) -> Value {
-    match node.deref() {
-        Node::String { val, .. } => Value::String(val.clone()),
-        Node::Date { val, .. } => Value::String(val.to_string()),
-        Node::Real { val, .. } => Value::Number(Number::from_f64(*val).unwrap()),
-        Node::Int { val, .. } => Value::Number(Number::from(*val)),
-        Node::Boolean { val, .. } => Value::Bool(*val),
-        Node::Array { values, .. } => values
+fn serialise_data(value: &Value) -> Json {
+    match value {
+        Value::String(val) => Json::String(val.to_string()),
+        Value::Char(val) => Json::String(val.to_string()),
+        Value::Date(val) => Json::String(val.to_string()),
+        Value::Real(val) => Json::Number(Number::from_f64(*val).unwrap()),
+        Value::Integer(val) => Json::Number(Number::from(*val)),
+        Value::Boolean(val) => Json::Bool(*val),
+        Value::Array(array) => array
+            .values
             .iter()
             .map(|v| serialise_data(v.borrow().deref()))
             .collect(),
@@ -327,72 +398,52 @@ fn serialise_data(node: &Box<Node>) -> Value {
     }
 }
 
-fn value_to_node(
-    old: &Box<Node>,
-    new: &Value,
+fn json_to_value(
+    old: &Value,
+    new: &Json,
     new_type: Box<VariableType>,
     pos: &Position,
-) -> Node {
+) -> Result<Value, PseudoError> {
     let new_type = new_type.deref().clone();
-    match new {
-        Value::String(val) => {
+    Ok(match new {
+        Json::String(val) => {
             if new_type == VariableType::String {
-                Node::String {
-                    val: val.clone(),
-                    pos: Position::invalid(),
-                }
+                Value::string(val)
+            } else if new_type == VariableType::Char {
+                Value::Char(val.chars().next().unwrap_or(' '))
             } else {
-                Node::Date {
-                    val: NaiveDate::parse_from_str(val.as_str(), "%Y-%m-%d").unwrap(),
-                    pos: Position::invalid(),
-                }
+                Value::Date(NaiveDate::parse_from_str(val.as_str(), "%Y-%m-%d").unwrap())
             }
         }
-        Value::Number(val) => {
+        Json::Number(val) => {
             if new_type == VariableType::Integer {
-                Node::Int {
-                    val: val.as_i64().unwrap(),
-                    pos: Position::invalid(),
-                }
+                Value::Integer(val.as_i64().unwrap())
             } else {
-                Node::Real {
-                    val: val.as_f64().unwrap(),
-                    pos: Position::invalid(),
-                }
+                Value::Real(val.as_f64().unwrap())
             }
         }
-        Value::Bool(val) => Node::Boolean {
-            val: val.clone(),
-            pos: Position::invalid(),
-        },
-        Value::Array(values) => {
-            if let Node::Array { shape, .. } = old.clone().deref().deref() {
+        Json::Bool(val) => Value::Boolean(*val),
+        Json::Array(values) => {
+            if let Value::Array(array) = old {
                 if let VariableType::Array { t, .. } = new_type {
                     let mut len_of_narray = 0;
-                    shape.iter().for_each(|index| {
+                    array.shape.iter().for_each(|index| {
                         len_of_narray += (index.upper - index.lower + 1) as usize
                     });
                     if values.len() != len_of_narray {
                         err(
                             "Number of indices doesnt match array shape while getting record",
                             pos,
-                        )
+                        )?
                     }
-                    Node::Array {
+                    Value::Array(Rc::new(ArrayValue {
                         values: values
                             .iter()
-                            .map(|v| {
-                                NodeRef::new_ref(Box::from(value_to_node(
-                                    old,
-                                    v,
-                                    Box::from(t.clone()),
-                                    pos,
-                                )))
-                            })
-                            .collect(),
-                        shape: shape.clone(),
-                        t: t.clone(),
-                    }
+                            .map(|v| Ok(json_to_value(old, v, t.clone(), pos)?.into_ref()))
+                            .collect::<Result<Vec<_>, PseudoError>>()?,
+                        shape: array.shape.clone(),
+                        t: *t.clone(),
+                    }))
                 } else {
                     unreachable!()
                 }
@@ -401,5 +452,5 @@ fn value_to_node(
             }
         }
         _ => unreachable!(),
-    }
+    })
 }
@@ -3,74 +3,79 @@ use std::ops::Deref;
 use crate::enums::{Node, Position, VariableType};
 
 use crate::executor::run_expr::run_expr;
+use crate::executor::run_stmt::target_name;
+use crate::executor::value::Value;
 use crate::executor::variable::Executor;
-use crate::utils::err;
+use crate::utils::{err, PseudoError};
 
+use super::promote;
 use super::run_class::run_access_mut;
-use super::var_type_of;
 
-pub fn run_output(executor: &mut Executor, exprs: &Vec<Box<Node>>) {
+pub fn run_output(
+    executor: &mut Executor,
+    exprs: &Vec<Box<Node>>,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let mut line = String::new();
     for expr in exprs {
         let pos = expr.pos();
-        let res = run_expr(executor, expr);
-        match *res {
-            Node::Int { val, .. } => print!("{}", val.to_string()),
-            Node::Real { val, .. } => print!("{}", val.to_string()),
-            Node::String { val, .. } => print!("{}", val),
-            Node::Boolean { val, .. } => print!("{}", val.to_string().to_uppercase()),
-            Node::Date { val, .. } => print!("{}", val.format("%d-%m-%Y").to_string()),
-            Node::Null => print!("null"),
-            Node::EnumVal { val, .. } => print!("{}", val),
-            _ => err("Value cannot be printed", &pos),
+        match run_expr(executor, expr)? {
+            Value::Array(_)
+            | Value::Record(_)
+            | Value::Object(_)
+            | Value::Pointer(_)
+            | Value::Nothing(_) => err("Value cannot be printed", &pos)?,
+            value => line.push_str(&value.to_string()),
         }
     }
-    print!("\n")
+    executor.output(&line, pos);
+    line.push('\n');
+    executor.write_output(&line, pos)
 }
 
-pub fn run_input(executor: &mut Executor, child: &Box<Node>, pos: &Position) {
+pub fn run_input(
+    executor: &mut Executor,
+    child: &Box<Node>,
+    pos: &Position,
+) -> Result<(), PseudoError> {
     match child.deref() {
         Node::Var { .. }
         | Node::ArrayVar { .. }
         | Node::Composite { .. }
         | Node::Reference { .. } => {}
-        _ => err("Input cannot be stored in this value", &child.pos()),
+        _ => err("Input cannot be stored in this value", &child.pos())?,
     };
-    let node = run_access_mut(executor, child);
-    let mut temp = String::new();
-    std::io::stdin()
-        .read_line(&mut temp)
-        .unwrap_or_else(|_| err("System error, failed to read input", pos));
-    temp = temp
-        .strip_suffix("\r\n")
-        .or(temp.strip_suffix("\n"))
-        .unwrap()
-        .parse()
-        .unwrap();
-    let var_type = var_type_of(node.borrow().deref());
-    let node = match var_type {
-        VariableType::Integer => Box::new(Node::Int {
-            val: temp
-                .parse::<i64>()
-                .unwrap_or_else(|_| err("Input value is not INTEGER", pos)),
-            pos: Position::invalid(),
-        }),
-        VariableType::String => Box::new(Node::String {
-            val: temp,
-            pos: Position::invalid(),
-        }),
-        VariableType::Real => Box::new(Node::Real {
-            val: temp
-                .parse::<f64>()
-                .unwrap_or_else(|_| err("Input value is not REAL", pos)),
-            pos: Position::invalid(),
-        }),
-        VariableType::Boolean => Box::new(Node::Boolean {
-            val: temp.to_lowercase() == "TRUE",
-            pos: Position::invalid(),
-        }),
+    let target = executor
+        .hook
+        .is_some()
+        .then(|| target_name(executor, child));
+    let node = run_access_mut(executor, child)?;
+    let temp = match executor.io.read_line() {
+        Ok(Some(line)) => line,
+        Ok(None) => err("No more input to read", pos)?,
+        Err(_) => err("System error, failed to read input", pos)?,
+    };
+    let var_type = node.borrow().type_of();
+    let value = match var_type {
+        VariableType::Integer => match temp.parse::<i64>() {
+            Ok(val) => Value::Integer(val),
+            Err(_) => err("Input value is not INTEGER", pos)?,
+        },
+        VariableType::String => Value::string(&temp),
+        VariableType::Char => promote(Value::string(&temp), &var_type, pos)?,
+        VariableType::Real => match temp.parse::<f64>() {
+            Ok(val) => Value::Real(val),
+            Err(_) => err("Input value is not REAL", pos)?,
+        },
+        VariableType::Boolean => Value::Boolean(temp.to_uppercase() == "TRUE"),
         _ => err(
             format!("Input type {} is not allowed", var_type.str()).as_str(),
             &child.pos(),
-        ),
+        )?,
     };
+    if let Some(target) = target {
+        executor.assigned(&target, &value, pos);
+    }
+    node.replace(value);
+    Ok(())
 }
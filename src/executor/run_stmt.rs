@@ -1,99 +1,110 @@
 use std::ops::Deref;
+use std::rc::Rc;
 
-use crate::enums::Node::EnumVal;
 use crate::enums::{Node, Position, VariableType};
 use crate::executor::run_class::{run_access_mut, run_class, run_record};
-use crate::executor::run_expr::{assert_number, run_expr};
+use crate::executor::run_expr::{assert_number_at, run_expr};
 use crate::executor::run_file::{
     run_close_file, run_get_record, run_open_file, run_put_record, run_read_file, run_seek,
     run_write_file,
 };
 use crate::executor::run_io::{run_input, run_output};
+use crate::executor::value::{EnumValue, Value, ValueRef};
 use crate::executor::variable::{Definition, Executor};
-use crate::executor::{default_var, var_type_of};
-use crate::utils::err;
+use crate::executor::{copy_value, default_var, promote};
+use crate::utils::{err, PseudoError};
 
-pub fn run_stmts(executor: &mut Executor, nodes: &Vec<Box<Node>>) -> Option<Box<Node>> {
+pub fn run_stmts(
+    executor: &mut Executor,
+    nodes: &Vec<Box<Node>>,
+) -> Result<Option<Value>, PseudoError> {
     for node in nodes {
-        if let Some(expr) = run_stmt(executor, node) {
-            return Some(expr);
+        if let Some(expr) = run_stmt(executor, node)? {
+            return Ok(Some(expr));
         }
     }
-    None
+    Ok(None)
 }
 
-pub fn run_stmt(executor: &mut Executor, node: &Box<Node>) -> Option<Box<Node>> {
+pub fn run_stmt(executor: &mut Executor, node: &Box<Node>) -> Result<Option<Value>, PseudoError> {
+    if Node::Null != *node.deref() {
+        executor.step(&node.pos())?;
+        executor.before_stmt(node)?;
+    }
     match node.deref() {
         Node::Declare {
             t, children, pos, ..
-        } => run_declare(executor, children, t, pos),
-        Node::Const { name, val, pos } => run_const(executor, name, val, pos),
-        Node::PointerDef { name, ref_to, pos } => run_pointer(executor, name, ref_to, pos),
+        } => run_declare(executor, children, t, pos)?,
+        Node::Const { name, val, pos } => run_const(executor, name, val, pos)?,
+        Node::PointerDef { name, ref_to, pos } => run_pointer(executor, name, ref_to, pos)?,
         Node::Enum {
             name,
             variants,
             pos,
-        } => run_enum(executor, name, variants, pos),
-        Node::Output { children, .. } => run_output(executor, children),
-        Node::Input { child, pos } => run_input(executor, child, pos),
+        } => run_enum(executor, name, variants, pos)?,
+        Node::Output { children, pos } => run_output(executor, children, pos)?,
+        Node::Input { child, pos } => run_input(executor, child, pos)?,
         Node::Function {
             name,
             params,
             children,
             ..
-        } => run_function(executor, name, params, children, true),
+        } => run_function(executor, name, params, children, true)?,
         Node::Procedure {
             name,
             params,
             children,
             ..
-        } => run_function(executor, name, params, children, false),
+        } => run_function(executor, name, params, children, false)?,
         Node::Class {
             name,
             base,
             children,
             ..
-        } => run_class(executor, name, base, children),
-        Node::Record { name, children, .. } => run_record(executor, name, children),
-        Node::Assignment { lhs, rhs, pos } => run_assign(executor, lhs, rhs, pos),
+        } => run_class(executor, name, base, children)?,
+        Node::Record { name, children, .. } => run_record(executor, name, children)?,
+        Node::Assignment { lhs, rhs, pos } => run_assign(executor, lhs, rhs, pos)?,
         Node::Null => (),
         Node::OpenFile {
             filename,
             mode,
             pos,
-        } => run_open_file(executor, filename, mode, pos),
-        Node::ReadFile { filename, var, pos } => run_read_file(executor, filename, var, pos),
+        } => run_open_file(executor, filename, mode, pos)?,
+        Node::ReadFile { filename, var, pos } => run_read_file(executor, filename, var, pos)?,
         Node::WriteFile {
             filename,
             expr,
             pos,
-        } => run_write_file(executor, filename, expr, pos),
-        Node::PutRecord { filename, var, pos } => run_put_record(executor, filename, var, pos),
-        Node::GetRecord { filename, var, pos } => run_get_record(executor, filename, var, pos),
+        } => run_write_file(executor, filename, expr, pos)?,
+        Node::PutRecord { filename, var, pos } => run_put_record(executor, filename, var, pos)?,
+        Node::GetRecord { filename, var, pos } => run_get_record(executor, filename, var, pos)?,
         Node::SeekFile {
             filename,
             expr,
             pos,
-        } => run_seek(executor, filename, expr, pos),
-        Node::CloseFile { filename, pos } => run_close_file(executor, filename, pos),
+        } => run_seek(executor, filename, expr, pos)?,
+        Node::CloseFile { filename, pos } => run_close_file(executor, filename, pos)?,
         Node::If { .. }
         | Node::While { .. }
         | Node::For { .. }
         | Node::Switch { .. }
         | Node::Repeat { .. }
         | Node::Return { .. } => {
-            if let Some(expr) = run_control_flow(executor, node) {
-                return Some(expr);
+            if let Some(expr) = run_control_flow(executor, node)? {
+                return Ok(Some(expr));
             }
         }
         _ => {
-            run_expr(executor, node);
+            run_expr(executor, node)?;
         }
     }
-    None
+    Ok(None)
 }
 
-pub fn run_control_flow(executor: &mut Executor, node: &Box<Node>) -> Option<Box<Node>> {
+pub fn run_control_flow(
+    executor: &mut Executor,
+    node: &Box<Node>,
+) -> Result<Option<Value>, PseudoError> {
     match node.deref() {
         Node::If {
             cond,
@@ -116,7 +127,7 @@ pub fn run_control_flow(executor: &mut Executor, node: &Box<Node>) -> Option<Box
             otherwise,
             ..
         } => run_switch(executor, cmp, cases, otherwise),
-        Node::Return { expr, .. } => Some(run_expr(executor, expr)),
+        Node::Return { expr, .. } => Ok(Some(run_expr(executor, expr)?)),
         _ => unreachable!(),
     }
 }
@@ -125,9 +136,9 @@ fn run_function(
     executor: &mut Executor,
     identifier: &Box<Node>,
     params: &Vec<Box<Node>>,
-    children: &Vec<Box<Node>>,
+    children: &[Box<Node>],
     returns: bool,
-) {
+) -> Result<(), PseudoError> {
     let mut names = Vec::new();
     for param in params {
         if let Node::Var { name, .. } = param.deref() {
@@ -135,7 +146,7 @@ fn run_function(
                 err(
                     format!("Duplicate parameter {}", name).as_str(),
                     &param.pos(),
-                );
+                )?;
             }
             names.push(name.clone());
         }
@@ -145,7 +156,7 @@ fn run_function(
             val,
             Definition::Function {
                 params: params.clone(),
-                children: children.clone(),
+                children: children.to_vec(),
                 returns,
             },
             &identifier.pos(),
@@ -159,7 +170,7 @@ fn run_pointer(
     name: &String,
     pointer: &Box<VariableType>,
     pos: &Position,
-) {
+) -> Result<(), PseudoError> {
     executor.declare_def(
         name,
         Definition::Pointer {
@@ -167,36 +178,43 @@ fn run_pointer(
             ref_to: pointer.clone(),
         },
         pos,
-    );
+    )
 }
 
-fn run_const(executor: &mut Executor, identifier: &String, val: &Box<Node>, pos: &Position) {
-    executor.declare_var(
-        identifier,
-        val.clone(),
-        &Box::from(var_type_of(val)),
-        false,
-        pos,
-    );
+fn run_const(
+    executor: &mut Executor,
+    identifier: &String,
+    val: &Box<Node>,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let val = Value::from_node(val);
+    let t = Box::new(val.type_of());
+    executor.declare_var(identifier, val, &t, false, pos)
 }
 
-fn run_enum(executor: &mut Executor, name: &String, variants: &[Box<Node>], pos: &Position) {
-    executor.declare_def(name, Definition::Enum { name: name.clone() }, pos);
+fn run_enum(
+    executor: &mut Executor,
+    name: &String,
+    variants: &[Box<Node>],
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    executor.declare_def(name, Definition::Enum { name: name.clone() }, pos)?;
 
     for variant in variants {
         if let Node::String { val, pos } = variant.deref() {
             executor.declare_var(
                 val,
-                Box::from(EnumVal {
+                Value::Enum(Rc::new(EnumValue {
                     family: name.clone(),
                     val: val.clone(),
-                }),
+                })),
                 &Box::from(VariableType::Custom(name.clone())),
                 false,
                 pos,
-            );
+            )?;
         }
     }
+    Ok(())
 }
 
 fn run_declare(
@@ -204,11 +222,12 @@ fn run_declare(
     identifiers: &[String],
     t: &Box<VariableType>,
     pos: &Position,
-) {
+) -> Result<(), PseudoError> {
     for identifier in identifiers {
-        let value = default_var(executor, t, pos);
-        executor.declare_var(identifier, value, t, true, pos);
+        let value = default_var(executor, t, pos)?;
+        executor.declare_var(identifier, value, t, true, pos)?;
     }
+    Ok(())
 }
 
 fn run_if(
@@ -216,8 +235,8 @@ fn run_if(
     cond: &Box<Node>,
     true_body: &Vec<Box<Node>>,
     false_body: &Vec<Box<Node>>,
-) -> Option<Box<Node>> {
-    if as_boolean_expr(executor, cond) {
+) -> Result<Option<Value>, PseudoError> {
+    if as_boolean_expr(executor, cond)? {
         run_stmts(executor, true_body)
     } else {
         run_stmts(executor, false_body)
@@ -228,33 +247,35 @@ fn run_while(
     executor: &mut Executor,
     cond: &Box<Node>,
     body: &Vec<Box<Node>>,
-) -> Option<Box<Node>> {
+) -> Result<Option<Value>, PseudoError> {
     loop {
-        if as_boolean_expr(executor, cond) {
-            if let Some(expr) = run_stmts(executor, body) {
-                return Some(expr);
+        if as_boolean_expr(executor, cond)? {
+            executor.step(&cond.pos())?;
+            if let Some(expr) = run_stmts(executor, body)? {
+                return Ok(Some(expr));
             }
         } else {
             break;
         }
     }
-    None
+    Ok(None)
 }
 
 fn run_repeat(
     executor: &mut Executor,
     cond: &Box<Node>,
     body: &Vec<Box<Node>>,
-) -> Option<Box<Node>> {
+) -> Result<Option<Value>, PseudoError> {
     loop {
-        if let Some(expr) = run_stmts(executor, body) {
-            return Some(expr);
+        executor.step(&cond.pos())?;
+        if let Some(expr) = run_stmts(executor, body)? {
+            return Ok(Some(expr));
         }
-        if as_boolean_expr(executor, cond) {
+        if as_boolean_expr(executor, cond)? {
             break;
         }
     }
-    None
+    Ok(None)
 }
 
 fn run_switch(
@@ -262,22 +283,31 @@ fn run_switch(
     cmp: &Box<Node>,
     cases: &Vec<Box<Node>>,
     otherwise: &Vec<Box<Node>>,
-) -> Option<Box<Node>> {
-    let cmp = run_expr(executor, cmp);
+) -> Result<Option<Value>, PseudoError> {
+    let cmp_pos = cmp.pos();
+    let cmp = run_expr(executor, cmp)?;
     for case in cases {
         if let Node::Case { expr, children, .. } = case.deref() {
             match expr.deref() {
+                // Both ends of a range are included
                 Node::Range { start, end, .. } => {
-                    let (cmp_val, _) = assert_number(&cmp);
-                    let (start_val, _) = assert_number(start);
-                    let (end_val, _) = assert_number(end);
-                    let range = start_val..end_val;
-                    if range.contains(&cmp_val) {
+                    let start_pos = start.pos();
+                    let end_pos = end.pos();
+                    let (start, end) = (Value::from_node(start), Value::from_node(end));
+                    if !matches!(
+                        (&cmp, &start, &end),
+                        (Value::Char(_), Value::Char(_), Value::Char(_))
+                    ) {
+                        assert_number_at(&cmp, &cmp_pos)?;
+                        assert_number_at(&start, &start_pos)?;
+                        assert_number_at(&end, &end_pos)?;
+                    }
+                    if start <= cmp && cmp <= end {
                         return run_stmts(executor, children);
                     }
                 }
                 _ => {
-                    if cmp.deref().val_as_str() == expr.val_as_str() {
+                    if cmp == Value::from_node(expr) {
                         return run_stmts(executor, children);
                     }
                 }
@@ -294,18 +324,48 @@ pub(crate) fn run_assign(
     lhs: &Box<Node>,
     rhs: &Box<Node>,
     pos: &Position,
-) {
+) -> Result<(), PseudoError> {
+    let (target, cell) = assign_target(executor, lhs)?;
+    let rhs = run_expr(executor, rhs)?;
+    store(executor, target, cell, rhs, pos)
+}
+
+// Assigns a value worked out by the statement itself, such as the line READFILE reads
+pub(crate) fn assign_value(
+    executor: &mut Executor,
+    lhs: &Box<Node>,
+    rhs: Value,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let (target, cell) = assign_target(executor, lhs)?;
+    store(executor, target, cell, rhs, pos)
+}
+
+fn assign_target(
+    executor: &mut Executor,
+    lhs: &Box<Node>,
+) -> Result<(Option<String>, ValueRef), PseudoError> {
     match lhs.deref() {
         Node::Var { .. }
         | Node::ArrayVar { .. }
         | Node::Composite { .. }
         | Node::Dereference { .. } => {}
-        _ => err("Cannot assign to this value", &lhs.pos()),
+        _ => err("Cannot assign to this value", &lhs.pos())?,
     };
-    let lhs = run_access_mut(executor, lhs);
-    let rhs = run_expr(executor, rhs);
-    let lhs_type = var_type_of(lhs.borrow().deref());
-    let rhs_type = var_type_of(&rhs);
+    let target = executor.hook.is_some().then(|| target_name(executor, lhs));
+    Ok((target, run_access_mut(executor, lhs)?))
+}
+
+fn store(
+    executor: &mut Executor,
+    target: Option<String>,
+    lhs: ValueRef,
+    rhs: Value,
+    pos: &Position,
+) -> Result<(), PseudoError> {
+    let lhs_type = lhs.borrow().type_of();
+    let rhs = copy_value(promote(rhs, &lhs_type, pos)?);
+    let rhs_type = rhs.type_of();
     if lhs_type != rhs_type {
         err(
             format!(
@@ -315,9 +375,54 @@ pub(crate) fn run_assign(
             )
             .as_str(),
             pos,
-        )
+        )?
+    }
+    if let Some(target) = target {
+        executor.assigned(&target, &rhs, pos);
     }
     lhs.replace(rhs);
+    Ok(())
+}
+
+// Name of what an assignment changes, as shown in a trace table. Indices are
+// only worked out when that cannot call a function, so nothing runs twice.
+pub(crate) fn target_name(executor: &mut Executor, node: &Box<Node>) -> String {
+    match node.deref() {
+        Node::Var { name, .. } => name.clone(),
+        Node::ArrayVar { name, indices, .. } => {
+            let indices: Vec<String> = indices
+                .iter()
+                .map(|index| {
+                    if !pure(index) {
+                        return "?".to_string();
+                    }
+                    match run_expr(executor, index) {
+                        Ok(Value::Integer(val)) => val.to_string(),
+                        _ => "?".to_string(),
+                    }
+                })
+                .collect();
+            format!("{}[{}]", name, indices.join(", "))
+        }
+        Node::Composite { children, .. } => {
+            let names: Vec<String> = children
+                .iter()
+                .map(|child| target_name(executor, child))
+                .collect();
+            names.join(".")
+        }
+        Node::Dereference { expr, .. } => format!("{}^", target_name(executor, expr)),
+        _ => "?".to_string(),
+    }
+}
+
+fn pure(node: &Box<Node>) -> bool {
+    match node.deref() {
+        Node::Int { .. } | Node::Var { .. } => true,
+        Node::Unary { expr, .. } => pure(expr),
+        Node::Binary { lhs, rhs, .. } => pure(lhs) && pure(rhs),
+        _ => false,
+    }
 }
 
 fn run_for(
@@ -326,57 +431,52 @@ fn run_for(
     range: &Box<Node>,
     step: &Box<Node>,
     body: &Vec<Box<Node>>,
-) -> Option<Box<Node>> {
+) -> Result<Option<Value>, PseudoError> {
     if let Node::Var { name, pos } = iter.deref() {
         if let Node::Range { start, end, .. } = range.deref() {
-            let mut start = as_number_expr(executor, start);
-            let end = as_number_expr(executor, end);
+            let mut start = as_number_expr(executor, start)?;
+            let end = as_number_expr(executor, end)?;
             let step = if let Node::Null = *step.deref() {
                 1
             } else {
-                as_number_expr(executor, step)
+                as_number_expr(executor, step)?
             };
             if !executor.var_exist(name) {
                 executor.declare_var(
                     name,
-                    Box::new(Node::Int {
-                        val: start,
-                        pos: Position::invalid(),
-                    }),
+                    Value::Integer(start),
                     &Box::new(VariableType::Integer),
                     true,
                     pos,
-                );
+                )?;
             }
             while start <= end {
-                let var = &executor.get_var_mut(name, pos).value;
-                var.replace(Box::new(Node::Int {
-                    val: start,
-                    pos: Position::invalid(),
-                }));
+                let value = Value::Integer(start);
+                executor.assigned(name, &value, pos);
+                let var = &executor.get_var_mut(name, pos)?.value;
+                var.replace(value);
                 start += step;
-                if let Some(expr) = run_stmts(executor, body) {
-                    return Some(expr);
+                executor.step(pos)?;
+                if let Some(expr) = run_stmts(executor, body)? {
+                    return Ok(Some(expr));
                 }
             }
-            return None;
+            return Ok(None);
         };
     }
     unreachable!()
 }
 
-pub fn as_number_expr(executor: &mut Executor, node: &Box<Node>) -> i64 {
-    let expr = run_expr(executor, node);
-    return match *expr.deref() {
-        Node::Int { val, .. } => val,
-        _ => err("Expected INTEGER", &expr.pos()),
-    };
+pub fn as_number_expr(executor: &mut Executor, node: &Box<Node>) -> Result<i64, PseudoError> {
+    match run_expr(executor, node)? {
+        Value::Integer(val) => Ok(val),
+        _ => err("Expected INTEGER", &node.pos()),
+    }
 }
 
-pub fn as_boolean_expr(executor: &mut Executor, node: &Box<Node>) -> bool {
-    let expr = run_expr(executor, node);
-    if let Node::Boolean { val, .. } = expr.deref() {
-        return val.clone();
-    };
-    err("Expected BOOLEAN", &expr.pos())
+pub fn as_boolean_expr(executor: &mut Executor, node: &Box<Node>) -> Result<bool, PseudoError> {
+    match run_expr(executor, node)? {
+        Value::Boolean(val) => Ok(val),
+        _ => err("Expected BOOLEAN", &node.pos()),
+    }
 }
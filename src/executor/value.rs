@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+
+use crate::enums::{Index, Node, VariableType};
+use crate::executor::variable::Property;
+
+// A variable, array element or property. Passing one BYREF shares the cell.
+pub type ValueRef = Rc<RefCell<Value>>;
+
+// What an expression evaluates to while the program runs. Strings and the
+// contents of arrays, records and objects are shared, so cloning is cheap;
+// arrays and records are copied with copy_value when they are stored.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Integer(i64),
+    Real(f64),
+    Boolean(bool),
+    Char(char),
+    String(Rc<str>),
+    Date(NaiveDate),
+    Array(Rc<ArrayValue>),
+    Record(Rc<RecordValue>),
+    Object(Rc<ObjectValue>),
+    Pointer(ValueRef),
+    Enum(Rc<EnumValue>),
+    // What a procedure gives back
+    Null,
+    // A variable of an enum or pointer type that has not been given a value yet
+    Nothing(VariableType),
+}
+
+#[derive(Debug)]
+pub struct ArrayValue {
+    pub t: VariableType,
+    pub shape: Vec<Index>,
+    pub values: Vec<ValueRef>,
+}
+
+#[derive(Debug)]
+pub struct RecordValue {
+    pub name: String,
+    pub props: HashMap<String, Property>,
+}
+
+#[derive(Debug)]
+pub struct ObjectValue {
+    pub name: String,
+    // The object of the class it inherits from
+    pub base: Option<Rc<ObjectValue>>,
+    pub props: HashMap<String, Property>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EnumValue {
+    pub family: String,
+    pub val: String,
+}
+
+impl Value {
+    // The value of a literal written in the program
+    pub fn from_node(node: &Node) -> Value {
+        match node {
+            Node::Int { val, .. } => Value::Integer(*val),
+            Node::Real { val, .. } => Value::Real(*val),
+            Node::Boolean { val, .. } => Value::Boolean(*val),
+            Node::Char { val, .. } => Value::Char(*val),
+            Node::String { val, .. } => Value::String(Rc::from(val.as_str())),
+            Node::Date { val, .. } => Value::Date(*val),
+            Node::Null => Value::Null,
+            _ => unimplemented!("{:?}", node),
+        }
+    }
+
+    pub fn string(val: &str) -> Value {
+        Value::String(Rc::from(val))
+    }
+
+    pub fn into_ref(self) -> ValueRef {
+        Rc::new(RefCell::new(self))
+    }
+
+    pub fn type_of(&self) -> VariableType {
+        match self {
+            Value::Integer(_) => VariableType::Integer,
+            Value::Real(_) => VariableType::Real,
+            Value::Boolean(_) => VariableType::Boolean,
+            Value::Char(_) => VariableType::Char,
+            Value::String(_) => VariableType::String,
+            Value::Date(_) => VariableType::Date,
+            Value::Array(array) => VariableType::Array {
+                shape: array.shape.clone(),
+                t: Box::new(array.t.clone()),
+            },
+            Value::Record(record) => VariableType::Custom(record.name.clone()),
+            Value::Object(object) => VariableType::Custom(object.name.clone()),
+            Value::Pointer(value) => VariableType::Pointer(Box::new(value.borrow().type_of())),
+            Value::Enum(value) => VariableType::Custom(value.family.clone()),
+            Value::Null => VariableType::Custom("NULL".to_string()),
+            Value::Nothing(t) => t.clone(),
+        }
+    }
+
+    // The number and whether it is REAL
+    pub fn as_number(&self) -> Option<(f64, bool)> {
+        match self {
+            Value::Integer(val) => Some((*val as f64, false)),
+            Value::Real(val) => Some((*val, true)),
+            _ => None,
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, Value::String(_) | Value::Char(_))
+    }
+
+    // Whether = and <> can be used on it
+    pub fn comparable(&self) -> bool {
+        !matches!(
+            self,
+            Value::Array(_) | Value::Record(_) | Value::Object(_) | Value::Pointer(_)
+        )
+    }
+
+    // The value as it is written to a file or used as a filename
+    pub fn text(&self) -> String {
+        match self {
+            Value::Boolean(val) => val.to_string(),
+            Value::Date(val) => val.to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    // The value as it would be written in a program, as the arguments of a call are
+    // shown when an error is reported
+    pub fn quoted(&self) -> String {
+        match self {
+            Value::String(val) => format!("{:?}", val),
+            Value::Char(val) => format!("'{}'", val),
+            _ => self.to_string(),
+        }
+    }
+
+    // The properties of a record or object, then those of the objects it inherits from
+    pub fn prop(&self, name: &str) -> Option<&Property> {
+        match self {
+            Value::Record(record) => record.props.get(name),
+            Value::Object(object) => object.prop(name),
+            _ => None,
+        }
+    }
+
+    pub fn props(&self) -> Option<&HashMap<String, Property>> {
+        match self {
+            Value::Record(record) => Some(&record.props),
+            Value::Object(object) => Some(&object.props),
+            _ => None,
+        }
+    }
+}
+
+impl ObjectValue {
+    pub fn prop(&self, name: &str) -> Option<&Property> {
+        match (self.props.get(name), &self.base) {
+            (Some(prop), _) => Some(prop),
+            (None, Some(base)) => base.prop(name),
+            (None, None) => None,
+        }
+    }
+}
+
+// Numbers are equal whether INTEGER or REAL, other values only to their own type.
+// Two INTEGERs are compared exactly, as they may be too big for a REAL to hold.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Integer(lhs), Value::Integer(rhs)) => lhs == rhs,
+            (Value::Boolean(lhs), Value::Boolean(rhs)) => lhs == rhs,
+            (Value::Char(lhs), Value::Char(rhs)) => lhs == rhs,
+            (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+            (Value::Char(_), Value::String(_)) | (Value::String(_), Value::Char(_)) => {
+                self.text() == other.text()
+            }
+            (Value::Date(lhs), Value::Date(rhs)) => lhs == rhs,
+            (Value::Enum(lhs), Value::Enum(rhs)) => lhs == rhs,
+            (Value::Null, Value::Null) | (Value::Nothing(_), Value::Nothing(_)) => true,
+            (Value::Integer(int), Value::Real(real)) | (Value::Real(real), Value::Integer(int)) => {
+                compare_mixed(*int, *real) == Some(Ordering::Equal)
+            }
+            _ => match (self.as_number(), other.as_number()) {
+                (Some((lhs, _)), Some((rhs, _))) => lhs == rhs,
+                _ => false,
+            },
+        }
+    }
+}
+
+// An INTEGER against a REAL, exactly when the REAL is a whole number an INTEGER can
+// hold, as an INTEGER past 2^53 has no REAL of its own
+fn compare_mixed(int: i64, real: f64) -> Option<Ordering> {
+    if real.fract() == 0.0 && real >= i64::MIN as f64 && real < i64::MAX as f64 {
+        Some(int.cmp(&(real as i64)))
+    } else {
+        (int as f64).partial_cmp(&real)
+    }
+}
+
+// Numbers are ordered by value and CHAR values by their character codes
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Integer(lhs), Value::Integer(rhs)) => lhs.partial_cmp(rhs),
+            (Value::Char(lhs), Value::Char(rhs)) => lhs.partial_cmp(rhs),
+            (Value::Integer(int), Value::Real(real)) => compare_mixed(*int, *real),
+            (Value::Real(real), Value::Integer(int)) => {
+                compare_mixed(*int, *real).map(Ordering::reverse)
+            }
+            _ => {
+                let (lhs, _) = self.as_number()?;
+                let (rhs, _) = other.as_number()?;
+                lhs.partial_cmp(&rhs)
+            }
+        }
+    }
+}
+
+// The value as OUTPUT prints it. Values that cannot be printed show their type.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Integer(val) => write!(f, "{}", val),
+            Value::Real(val) => write!(f, "{}", val),
+            Value::Boolean(val) => write!(f, "{}", val.to_string().to_uppercase()),
+            Value::Char(val) => write!(f, "{}", val),
+            Value::String(val) => write!(f, "{}", val),
+            Value::Date(val) => write!(f, "{}", val.format("%d-%m-%Y")),
+            Value::Enum(value) => write!(f, "{}", value.val),
+            Value::Null | Value::Nothing(_) => write!(f, "null"),
+            _ => write!(f, "{}", self.type_of().str()),
+        }
+    }
+}
@@ -1,20 +1,37 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fs::File;
-use std::rc::Rc;
 
 use crate::{
-    enums::{Node, NodeRef, Position, VariableType},
-    utils::err,
+    enums::{Node, Position, VariableType},
+    executor::hook::Hook,
+    executor::io::{IoBackend, StreamIo},
+    executor::limits::{Limits, Usage},
+    executor::value::{Value, ValueRef},
+    utils::{err, Frame, PseudoError},
 };
 
+// Calls nested deeper than this are taken to be unbounded recursion
+pub const MAX_CALL_DEPTH: usize = 1000;
+
 pub struct Executor {
     pub scopes: Vec<Scope>,
+    // The procedures, functions and methods running, for reporting errors
+    pub calls: Vec<Call>,
+    pub limits: Limits,
+    pub usage: Usage,
     pub file_handles: HashMap<String, XFile>,
+    // Console and files used by INPUT, OUTPUT and the file statements
+    pub io: Box<dyn IoBackend>,
+    pub hook: Option<Box<dyn Hook>>,
+}
+
+pub struct Call {
+    pub name: String,
+    pub pos: Position,
+    // The cells of its parameters, shown as they are when an error is raised
+    pub args: Vec<ValueRef>,
 }
 
 pub struct XFile {
-    pub handle: File,
     pub mode: String,
     pub content: Vec<String>,
     pub cursor: usize,
@@ -24,6 +41,10 @@ pub struct XFile {
 pub struct State {
     pub variables: HashMap<String, Variable>,
     pub defs: HashMap<String, Definition>,
+    // The procedure, function or method the scope belongs to
+    pub routine: Option<String>,
+    // The object whose method is running
+    pub object: Option<Value>,
 }
 
 impl State {
@@ -31,6 +52,16 @@ impl State {
         Self {
             variables: HashMap::new(),
             defs: HashMap::new(),
+            routine: None,
+            object: None,
+        }
+    }
+
+    // The scope of a call to routine
+    pub fn local(routine: &str) -> Self {
+        Self {
+            routine: Some(routine.to_string()),
+            ..Self::new()
         }
     }
 }
@@ -41,9 +72,23 @@ pub enum Scope {
     Local(State),
 }
 
+impl Scope {
+    pub fn state(&self) -> &State {
+        match self {
+            Scope::Global(state) | Scope::Local(state) => state,
+        }
+    }
+
+    pub fn state_mut(&mut self) -> &mut State {
+        match self {
+            Scope::Global(state) | Scope::Local(state) => state,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Variable {
-    pub value: NodeRef,
+    pub value: ValueRef,
     pub t: VariableType,
     pub mutable: bool,
 }
@@ -51,7 +96,7 @@ pub struct Variable {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Property {
     Var {
-        value: NodeRef,
+        value: ValueRef,
         t: Box<VariableType>,
         private: bool,
     },
@@ -89,16 +134,77 @@ pub enum Definition {
     Null,
 }
 
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Executor {
     pub fn new() -> Executor {
+        Executor::with_io(Box::new(StreamIo::std()))
+    }
+
+    pub fn with_io(io: Box<dyn IoBackend>) -> Executor {
         Executor {
             scopes: vec![Scope::Global(State::new())],
+            calls: vec![],
+            limits: Limits::default(),
+            usage: Usage::new(),
             file_handles: HashMap::new(),
+            io,
+            hook: None,
         }
     }
 
-    pub fn enter_scope(&mut self) {
-        self.scopes.push(Scope::Local(State::new()))
+    // The hook is taken out while it runs so it can be given the executor
+    pub fn before_stmt(&mut self, node: &Box<Node>) -> Result<(), PseudoError> {
+        if let Some(mut hook) = self.hook.take() {
+            let result = hook.before_stmt(self, node);
+            self.hook = Some(hook);
+            result?;
+        }
+        Ok(())
+    }
+
+    pub fn enter_call(&mut self, name: &str, pos: &Position) {
+        if let Some(hook) = self.hook.as_mut() {
+            hook.enter_call(name, pos);
+        }
+    }
+
+    pub fn exit_call(&mut self) {
+        if let Some(hook) = self.hook.as_mut() {
+            hook.exit_call();
+        }
+    }
+
+    pub fn assigned(&mut self, target: &str, value: &Value, pos: &Position) {
+        if let Some(hook) = self.hook.as_mut() {
+            hook.assigned(target, value, pos);
+        }
+    }
+
+    pub fn output(&mut self, line: &str, pos: &Position) {
+        if let Some(hook) = self.hook.as_mut() {
+            hook.output(line, pos);
+        }
+    }
+
+    pub fn backtrace(&self) -> Vec<Frame> {
+        self.calls
+            .iter()
+            .rev()
+            .map(|call| Frame {
+                name: call.name.clone(),
+                pos: call.pos,
+                args: call.args.iter().map(|arg| arg.borrow().quoted()).collect(),
+            })
+            .collect()
+    }
+
+    pub fn enter_scope(&mut self, routine: &str) {
+        self.scopes.push(Scope::Local(State::local(routine)))
     }
 
     pub fn exit_scope(&mut self) -> Scope {
@@ -108,11 +214,23 @@ impl Executor {
     pub fn declare_var(
         &mut self,
         identifier: &String,
-        value: Box<Node>,
+        value: Value,
+        t: &Box<VariableType>,
+        mutable: bool,
+        pos: &Position,
+    ) -> Result<(), PseudoError> {
+        self.declare_ref(identifier, value.into_ref(), t, mutable, pos)
+    }
+
+    // Declare a variable kept in an existing cell, as a BYREF parameter is
+    pub fn declare_ref(
+        &mut self,
+        identifier: &String,
+        value: ValueRef,
         t: &Box<VariableType>,
         mutable: bool,
         pos: &Position,
-    ) {
+    ) -> Result<(), PseudoError> {
         let scope = self.scopes.last_mut().unwrap();
         match scope {
             Scope::Global(ref mut state) | Scope::Local(ref mut state) => {
@@ -120,7 +238,7 @@ impl Executor {
                     state.variables.insert(
                         identifier.clone(),
                         Variable {
-                            value: Rc::new(RefCell::new(value)),
+                            value,
                             t: *t.clone(),
                             mutable,
                         },
@@ -129,65 +247,92 @@ impl Executor {
                     err(
                         format!("'{}' is already initialized", identifier).as_str(),
                         pos,
-                    )
+                    )?
                 }
             }
         }
+        Ok(())
     }
 
-    pub fn var_exist(&self, identifier: &String) -> bool {
-        for scope in self.scopes.iter().rev() {
-            match scope {
-                Scope::Global(state) | Scope::Local(state) => {
-                    if let Some(_) = state.variables.get(identifier) {
-                        return true;
-                    }
-                }
-            }
-        }
-        false
+    // Names are looked up lexically: a subroutine sees its own locals and parameters
+    // and the globals, never the locals of whichever subroutine called it
+    fn visible(&self, identifier: &String) -> Option<usize> {
+        let current = self.scopes.len() - 1;
+        [current, 0]
+            .into_iter()
+            .find(|i| self.scopes[*i].state().variables.contains_key(identifier))
     }
 
-    pub fn get_var(&self, identifier: &String, pos: &Position) -> &Variable {
-        for scope in self.scopes.iter().rev() {
-            match scope {
-                Scope::Global(state) | Scope::Local(state) => {
-                    if let Some(var) = state.variables.get(identifier) {
-                        return var;
-                    }
-                }
-            }
+    fn not_visible<T>(&self, identifier: &String, pos: &Position) -> Result<T, PseudoError> {
+        let current = self.scopes.len() - 1;
+        let owner = self.scopes[..current]
+            .iter()
+            .skip(1)
+            .rev()
+            .map(Scope::state)
+            .find(|state| state.variables.contains_key(identifier));
+        match (owner, &self.scopes[current].state().routine) {
+            (
+                Some(State {
+                    routine: Some(owner),
+                    ..
+                }),
+                Some(routine),
+            ) => err(
+                format!(
+                    "'{}' is local to {}, pass it as a parameter to use it in {}",
+                    identifier, owner, routine
+                )
+                .as_str(),
+                pos,
+            ),
+            _ => err(format!("'{}' is not declared", identifier).as_str(), pos),
         }
+    }
 
-        err(format!("'{}' is not declared", identifier).as_str(), pos)
+    pub fn var_exist(&self, identifier: &String) -> bool {
+        self.visible(identifier).is_some()
     }
 
-    pub fn get_var_mut(&mut self, identifier: &String, pos: &Position) -> &mut Variable {
-        for scope in self.scopes.iter_mut().rev() {
-            match scope {
-                Scope::Global(state) | Scope::Local(state) => {
-                    if let Some(var) = state.variables.get_mut(identifier) {
-                        if var.mutable {
-                            return var;
-                        } else {
-                            err(
-                                format!(
-                                    "'{}' is a constant, it's value cannot be modified",
-                                    identifier
-                                )
-                                .as_str(),
-                                pos,
-                            )
-                        }
-                    }
-                }
-            }
+    pub fn get_var(&self, identifier: &String, pos: &Position) -> Result<&Variable, PseudoError> {
+        match self.visible(identifier) {
+            Some(i) => Ok(&self.scopes[i].state().variables[identifier]),
+            None => self.not_visible(identifier, pos),
         }
+    }
 
-        err(format!("'{}' is not declared", identifier).as_str(), pos)
+    pub fn get_var_mut(
+        &mut self,
+        identifier: &String,
+        pos: &Position,
+    ) -> Result<&mut Variable, PseudoError> {
+        let Some(i) = self.visible(identifier) else {
+            return self.not_visible(identifier, pos);
+        };
+        let var = self.scopes[i]
+            .state_mut()
+            .variables
+            .get_mut(identifier)
+            .unwrap();
+        if !var.mutable {
+            err(
+                format!(
+                    "'{}' is a constant, it's value cannot be modified",
+                    identifier
+                )
+                .as_str(),
+                pos,
+            )?
+        }
+        Ok(var)
     }
 
-    pub fn declare_def(&mut self, identifier: &String, def: Definition, pos: &Position) {
+    pub fn declare_def(
+        &mut self,
+        identifier: &String,
+        def: Definition,
+        pos: &Position,
+    ) -> Result<(), PseudoError> {
         let scope = self.scopes.last_mut().unwrap();
         match scope {
             Scope::Global(ref mut state) | Scope::Local(ref mut state) => {
@@ -197,42 +342,24 @@ impl Executor {
                     err(
                         format!("'{}' is already declared", identifier).as_str(),
                         pos,
-                    )
+                    )?
                 }
             }
         }
+        Ok(())
     }
 
-    pub fn get_def(&mut self, identifier: &String, pos: &Position) -> Definition {
-        for scope in self.scopes.iter().rev() {
-            match scope {
-                Scope::Global(state) | Scope::Local(state) => {
-                    if let Some(def) = state.defs.get(identifier) {
-                        return def.clone();
-                    }
-                }
+    pub fn get_def(
+        &mut self,
+        identifier: &String,
+        pos: &Position,
+    ) -> Result<Definition, PseudoError> {
+        let current = self.scopes.len() - 1;
+        for i in [current, 0] {
+            if let Some(def) = self.scopes[i].state().defs.get(identifier) {
+                return Ok(def.clone());
             }
         }
         err(format!("'{}' is not declared", identifier).as_str(), pos)
     }
 }
-
-pub trait NodeDeref {
-    fn new_ref(node: Box<Node>) -> NodeRef;
-    fn clone_node(&self) -> Box<Node>;
-    fn clone_ref(&self) -> NodeRef;
-}
-
-impl NodeDeref for NodeRef {
-    fn new_ref(node: Box<Node>) -> NodeRef {
-        Rc::new(RefCell::new(node))
-    }
-
-    fn clone_node(&self) -> Box<Node> {
-        self.borrow().clone()
-    }
-
-    fn clone_ref(&self) -> NodeRef {
-        Rc::new(RefCell::new(self.borrow().clone()))
-    }
-}
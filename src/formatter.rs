@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use crate::enums::Token;
+use crate::lexer::{self, match_word, KEYWORDS};
+use crate::parser;
+use crate::tokens::TToken;
+use crate::utils::PseudoError;
+
+const INDENT: &str = "    ";
+
+// Lay a program out the way Cambridge mark schemes do: blocks indented by four
+// spaces, keywords in uppercase, single spaces around operators and CASE branches
+// lined up. Comments and single blank lines are kept where they were written.
+// A program with syntax errors is left alone and its errors are returned.
+pub fn format(text: &str) -> Result<String, Vec<PseudoError>> {
+    let mut buf = text.to_string();
+    buf.push('\n');
+    let mut tokens = lexer::lexer_with_comments(&mut buf.chars()).map_err(|e| vec![e])?;
+    let source: Vec<&str> = text.lines().collect();
+    let errors = match syntax(text) {
+        Ok(()) => return Ok(layout(&tokens, &source)),
+        Err(errors) => errors,
+    };
+    // Keywords written in lowercase are identifiers to the lexer, so a program
+    // using them only parses once they are uppercased
+    for token in &mut tokens {
+        if let TToken::Identifier(name) = &token.t {
+            let upper = name.to_uppercase();
+            if KEYWORDS.contains(&upper.as_str()) {
+                token.t = match_word(upper);
+            }
+        }
+    }
+    let formatted = layout(&tokens, &source);
+    match syntax(&formatted) {
+        Ok(()) => Ok(formatted),
+        Err(_) => Err(errors),
+    }
+}
+
+fn syntax(text: &str) -> Result<(), Vec<PseudoError>> {
+    let mut buf = text.to_string();
+    buf.push('\n');
+    let tokens = lexer::lexer(&mut buf.chars()).map_err(|e| vec![e])?;
+    parser::parse_program(&mut tokens.into_iter().peekable()).map(|_| ())
+}
+
+// An open block and how far it indents the lines inside it
+struct Block {
+    // CASE blocks are numbered so their branches can be lined up together
+    case: Option<usize>,
+    // Lines after a CASE branch label are indented once more
+    in_branch: bool,
+}
+
+struct Line {
+    indent: usize,
+    blank_before: bool,
+    text: String,
+    // CASE branch label with the CASE it belongs to, written before the text
+    label: Option<(usize, String)>,
+    comment: Option<String>,
+}
+
+fn layout(tokens: &[Token], source: &[&str]) -> String {
+    let mut lines = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut cases = 0;
+    let mut last_line = None;
+
+    for line in tokens.split(|token| matches!(token.t, TToken::Newline | TToken::EOF)) {
+        let (code, comment) = match line.last() {
+            Some(Token {
+                t: TToken::Comment(text),
+                ..
+            }) => (&line[..line.len() - 1], Some(format!("//{}", text))),
+            _ => (line, None),
+        };
+        let Some(first) = line.first() else {
+            continue;
+        };
+        let blank_before = last_line.is_some_and(|last| first.pos.line_start > last + 1);
+        last_line = line.last().map(|token| token.pos.line_end);
+
+        let level = |blocks: &Vec<Block>| -> usize {
+            blocks
+                .iter()
+                .map(|block| 1 + block.in_branch as usize)
+                .sum()
+        };
+        let mut indent = level(&blocks);
+        let mut stmt = code;
+        let mut label = None;
+        match code.first().map(|token| &token.t) {
+            Some(
+                TToken::EndIf
+                | TToken::EndWhile
+                | TToken::Until
+                | TToken::Next
+                | TToken::EndFunction
+                | TToken::EndProcedure
+                | TToken::EndClass
+                | TToken::EndType
+                | TToken::EndCase,
+            ) => {
+                blocks.pop();
+                indent = level(&blocks);
+            }
+            Some(TToken::Else) => indent = indent.saturating_sub(1),
+            Some(t) if is_branch(t) && blocks.last().is_some_and(|b| b.case.is_some()) => {
+                let block = blocks.last_mut().unwrap();
+                block.in_branch = false;
+                indent = level(&blocks);
+                let block = blocks.last_mut().unwrap();
+                block.in_branch = true;
+                let colon = code
+                    .iter()
+                    .position(|token| token.t == TToken::Colon)
+                    .unwrap_or(code.len());
+                let text = join(&code[..colon], source);
+                // OTHERWISE is left out of the alignment, it would push every label far right
+                label = match t {
+                    TToken::Otherwise => Some((usize::MAX, text)),
+                    _ => Some((block.case.unwrap(), text)),
+                };
+                stmt = &code[(colon + 1).min(code.len())..];
+            }
+            _ => {}
+        }
+        if opens(stmt) {
+            let case = match stmt[0].t {
+                TToken::Case => {
+                    cases += 1;
+                    Some(cases)
+                }
+                _ => None,
+            };
+            blocks.push(Block {
+                case,
+                in_branch: false,
+            });
+        }
+        lines.push(Line {
+            indent,
+            blank_before,
+            text: join(stmt, source),
+            label,
+            comment,
+        });
+    }
+
+    let mut widths: HashMap<usize, usize> = HashMap::new();
+    for line in &lines {
+        if let Some((case, label)) = &line.label {
+            let width = widths.entry(*case).or_default();
+            *width = (*width).max(label.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.blank_before && i > 0 {
+            out.push('\n');
+        }
+        let mut text = INDENT.repeat(line.indent);
+        if let Some((case, label)) = &line.label {
+            let width = if *case == usize::MAX { 0 } else { widths[case] };
+            text += &format!("{:<width$} :", label, width = width);
+            if !line.text.is_empty() {
+                text.push(' ');
+            }
+        }
+        text += &line.text;
+        if let Some(comment) = &line.comment {
+            if !text.trim().is_empty() {
+                text.push(' ');
+            }
+            text += comment;
+        }
+        out += text.trim_end();
+        out.push('\n');
+    }
+    out
+}
+
+fn is_branch(t: &TToken) -> bool {
+    matches!(
+        t,
+        TToken::IntegerLit(_)
+            | TToken::RealLit(_)
+            | TToken::StringLit(_)
+            | TToken::CharLit(_)
+            | TToken::BoolLit(_)
+            | TToken::DateLit(_)
+            | TToken::Otherwise
+    )
+}
+
+// Whether a statement starts a block that ends on a later line
+fn opens(stmt: &[Token]) -> bool {
+    match stmt.first().map(|token| &token.t) {
+        Some(
+            TToken::If
+            | TToken::While
+            | TToken::Repeat
+            | TToken::For
+            | TToken::Function
+            | TToken::Procedure
+            | TToken::Class
+            | TToken::Case,
+        ) => true,
+        // TYPE on its own starts a record, enums and pointers fit on one line
+        Some(TToken::Type) => stmt.len() == 2,
+        Some(TToken::Public | TToken::Private) => matches!(
+            stmt.get(1).map(|token| &token.t),
+            Some(TToken::Function | TToken::Procedure)
+        ),
+        _ => false,
+    }
+}
+
+// Write out the tokens of one line with canonical spacing
+fn join(tokens: &[Token], source: &[&str]) -> String {
+    let mut out = String::new();
+    // Inside square brackets ':' separates bounds and takes no spaces
+    let mut brackets = 0;
+    // No space goes before the next token
+    let mut tight = true;
+    let mut prev: Option<&TToken> = None;
+    for token in tokens {
+        let t = &token.t;
+        let space = !tight
+            && match t {
+                TToken::Comma | TToken::RParen | TToken::RSqrBracket | TToken::Period => false,
+                TToken::Colon => brackets == 0,
+                TToken::LParen | TToken::LSqrBracket => {
+                    !ends_value(prev) && !matches!(prev, Some(TToken::Array | TToken::New))
+                }
+                // A caret after a value dereferences it
+                TToken::Caret => !ends_value(prev),
+                _ => true,
+            };
+        if space {
+            out.push(' ');
+        }
+        out += &text(token, source);
+        tight = match t {
+            TToken::LParen | TToken::LSqrBracket | TToken::Period => true,
+            TToken::Colon => brackets > 0,
+            TToken::Caret => !ends_value(prev),
+            // Unary minus and plus stay next to their operand
+            TToken::Operator(op) if op == "-" || op == "+" => !ends_value(prev),
+            _ => false,
+        };
+        match t {
+            TToken::LSqrBracket => brackets += 1,
+            TToken::RSqrBracket => brackets -= 1,
+            _ => {}
+        }
+        prev = Some(t);
+    }
+    out
+}
+
+// Whether a token can end an operand, which makes a following '-' binary
+fn ends_value(t: Option<&TToken>) -> bool {
+    matches!(
+        t,
+        Some(
+            TToken::Identifier(_)
+                | TToken::IntegerLit(_)
+                | TToken::RealLit(_)
+                | TToken::StringLit(_)
+                | TToken::CharLit(_)
+                | TToken::BoolLit(_)
+                | TToken::DateLit(_)
+                | TToken::RParen
+                | TToken::RSqrBracket
+                | TToken::Caret
+        )
+    )
+}
+
+fn text(token: &Token, source: &[&str]) -> String {
+    let symbol = match &token.t {
+        TToken::StringLit(val) => return format!("\"{}\"", val),
+        TToken::CharLit(val) => return format!("'{}'", val),
+        TToken::Identifier(_) => return written(token, source),
+        TToken::Assignment => "<-",
+        TToken::LParen => "(",
+        TToken::RParen => ")",
+        TToken::LSqrBracket => "[",
+        TToken::RSqrBracket => "]",
+        TToken::Colon => ":",
+        TToken::Comma => ",",
+        TToken::Caret => "^",
+        TToken::Period => ".",
+        TToken::Operator(op) => match op.as_str() {
+            "!=" => "<>",
+            "//" => "DIV",
+            "%" => "MOD",
+            "&&" => "AND",
+            "||" => "OR",
+            "!" => "NOT",
+            op => op,
+        },
+        // Keywords, types and numbers as written, keywords in uppercase
+        _ => return written(token, source).to_uppercase(),
+    };
+    symbol.to_string()
+}
+
+// The token as it appears in the source
+fn written(token: &Token, source: &[&str]) -> String {
+    let pos = &token.pos;
+    source
+        .get(pos.line_start.wrapping_sub(1))
+        .and_then(|line| line.get(pos.pos_start..pos.pos_end))
+        .unwrap_or_default()
+        .to_string()
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::enums::{Node, Position, VariableType};
+use crate::table::{self, TableFormat};
+use crate::utils::SourceFile;
+
+// Every name a program declares, with its type, where it can be used and the
+// comment it was declared with, as an identifier table
+pub struct IdentifierTable {
+    rows: Vec<Identifier>,
+}
+
+struct Identifier {
+    name: String,
+    kind: String,
+    t: String,
+    scope: String,
+    description: String,
+}
+
+// Walks the declarations of a body, remembering what it is the body of
+struct Collector<'a> {
+    source: &'a SourceFile,
+    // Type names as written, by their name in lowercase
+    types: HashMap<String, String>,
+    rows: Vec<Identifier>,
+}
+
+impl IdentifierTable {
+    pub(crate) fn new(source: &SourceFile, ast: &[Box<Node>]) -> Self {
+        let children: &[Box<Node>] = match ast.first().map(|node| node.deref()) {
+            Some(Node::Main { children }) => children,
+            _ => &[],
+        };
+        let mut collector = Collector {
+            source,
+            types: HashMap::new(),
+            rows: vec![],
+        };
+        // Types can be used before they are declared
+        for node in children {
+            let (name, pos) = match node.deref() {
+                Node::Record { name, pos, .. } | Node::Class { name, pos, .. } => {
+                    match name.deref() {
+                        Node::String { val, .. } => (val, pos),
+                        _ => continue,
+                    }
+                }
+                Node::Enum { name, pos, .. } | Node::PointerDef { name, pos, .. } => (name, pos),
+                _ => continue,
+            };
+            let written = collector.written(pos, name);
+            collector.types.insert(name.clone(), written);
+        }
+        collector.stmts(children, "Global");
+        IdentifierTable {
+            rows: collector.rows,
+        }
+    }
+
+    // The names declared, as they are written
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.rows.iter().map(|row| row.name.as_str())
+    }
+
+    pub fn render(&self, format: TableFormat) -> String {
+        let mut table = vec![["Identifier", "Kind", "Data type", "Scope", "Description"]
+            .map(String::from)
+            .to_vec()];
+        for row in &self.rows {
+            table.push(vec![
+                row.name.clone(),
+                row.kind.clone(),
+                row.t.clone(),
+                row.scope.clone(),
+                row.description.clone(),
+            ]);
+        }
+        table::render(&table, format)
+    }
+}
+
+impl<'a> Collector<'a> {
+    fn stmts(&mut self, nodes: &[Box<Node>], scope: &str) {
+        for node in nodes {
+            match node.deref() {
+                Node::Declare {
+                    t, children, pos, ..
+                } => {
+                    let t = self.type_name(t);
+                    for name in children {
+                        self.push(name, "Variable", &t, scope, pos);
+                    }
+                }
+                Node::Const { name, val, pos } => {
+                    let t = constant_type(val);
+                    self.push(name, "Constant", &t, scope, pos);
+                }
+                Node::Function { .. } | Node::Procedure { .. } => self.routine(node, None),
+                Node::Record {
+                    name,
+                    children,
+                    pos,
+                } => {
+                    let Node::String { val, .. } = name.deref() else {
+                        continue;
+                    };
+                    self.push(val, "Type", "Record", scope, pos);
+                    let inside = format!("TYPE {}", self.written(pos, val));
+                    for field in children {
+                        if let Node::Declare {
+                            t, children, pos, ..
+                        } = field.deref()
+                        {
+                            let t = self.type_name(t);
+                            for name in children {
+                                self.push(name, "Field", &t, &inside, pos);
+                            }
+                        }
+                    }
+                }
+                Node::Class {
+                    name,
+                    children,
+                    pos,
+                    ..
+                } => {
+                    let Node::String { val, .. } = name.deref() else {
+                        continue;
+                    };
+                    let inside = format!("CLASS {}", self.written(pos, val));
+                    for member in children {
+                        match member.deref() {
+                            Node::Declare {
+                                t,
+                                children,
+                                private,
+                                pos,
+                                ..
+                            } => {
+                                let kind = match private {
+                                    true => "Private property",
+                                    false => "Public property",
+                                };
+                                let t = self.type_name(t);
+                                for name in children {
+                                    self.push(name, kind, &t, &inside, pos);
+                                }
+                            }
+                            Node::Function { .. } | Node::Procedure { .. } => {
+                                self.routine(member, Some(&inside))
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Node::Enum {
+                    name,
+                    variants,
+                    pos,
+                } => {
+                    self.push(name, "Type", "Enumerated", scope, pos);
+                    let t = self.written(pos, name);
+                    for variant in variants {
+                        let Node::String { val, .. } = variant.deref() else {
+                            continue;
+                        };
+                        // Values have no position of their own, and only have a
+                        // comment of their own on a line of their own
+                        let at = (pos.line_start..=pos.line_end)
+                            .find(|line| find(self.source, *line, val, 0).is_some())
+                            .unwrap_or(pos.line_start);
+                        let description = match at == pos.line_start {
+                            true => "",
+                            false => comment(self.source, at),
+                        };
+                        self.rows.push(Identifier {
+                            name: self.written(&Position::from(at, 0, 0), val),
+                            kind: "Enumerated value".to_string(),
+                            t: t.clone(),
+                            scope: scope.to_string(),
+                            description: description.to_string(),
+                        });
+                    }
+                }
+                Node::PointerDef { name, ref_to, pos } => {
+                    let t = format!("^{}", self.type_name(ref_to));
+                    self.push(name, "Type", &t, scope, pos);
+                }
+                Node::If {
+                    true_body,
+                    false_body,
+                    ..
+                } => {
+                    self.stmts(true_body, scope);
+                    self.stmts(false_body, scope);
+                }
+                Node::While { body, .. } | Node::Repeat { body, .. } | Node::For { body, .. } => {
+                    self.stmts(body, scope)
+                }
+                Node::Switch {
+                    cases, otherwise, ..
+                } => {
+                    for case in cases {
+                        if let Node::Case { children, .. } = case.deref() {
+                            self.stmts(children, scope);
+                        }
+                    }
+                    self.stmts(otherwise, scope);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // The parameters and locals of a PROCEDURE or FUNCTION, which is a method when
+    // it is declared inside a CLASS
+    fn routine(&mut self, node: &Node, class: Option<&str>) {
+        let (keyword, name, params, children, pos) = match node {
+            Node::Function {
+                name,
+                params,
+                children,
+                pos,
+                ..
+            } => ("FUNCTION", name, params, children, pos),
+            Node::Procedure {
+                name,
+                params,
+                children,
+                pos,
+                ..
+            } => ("PROCEDURE", name, params, children, pos),
+            _ => return,
+        };
+        let Node::String { val, .. } = name.deref() else {
+            return;
+        };
+        let mut scope = format!("{} {}", keyword, self.written(pos, val));
+        if let Some(class) = class {
+            scope = format!("{} in {}", scope, class);
+        }
+        for param in params {
+            if let Node::Declare {
+                t,
+                children,
+                byref,
+                pos: at,
+                ..
+            } = param.deref()
+            {
+                let kind = match byref {
+                    true => "BYREF parameter",
+                    false => "Parameter",
+                };
+                let t = self.type_name(t);
+                for name in children {
+                    self.push(name, kind, &t, &scope, at);
+                    // The comment on the header is about the routine, not the parameter
+                    if at.line_start == pos.line_start {
+                        self.rows.last_mut().unwrap().description.clear();
+                    }
+                }
+            }
+        }
+        self.stmts(children, &scope);
+    }
+
+    fn push(&mut self, name: &str, kind: &str, t: &str, scope: &str, pos: &Position) {
+        self.rows.push(Identifier {
+            name: self.written(pos, name),
+            kind: kind.to_string(),
+            t: t.to_string(),
+            scope: scope.to_string(),
+            description: comment(self.source, pos.line_start).to_string(),
+        });
+    }
+
+    // A name as it is written on the first line of its declaration, rather than
+    // in lowercase
+    fn written(&self, pos: &Position, name: &str) -> String {
+        let line = pos.line_start;
+        match find(self.source, line, name, pos.pos_start) {
+            Some(at) => self.source.file[line - 1][at..at + name.len()].to_string(),
+            None => name.to_string(),
+        }
+    }
+
+    fn type_name(&self, t: &VariableType) -> String {
+        match t {
+            VariableType::Array { shape, t } => {
+                let bounds: Vec<String> = shape
+                    .iter()
+                    .map(|index| format!("{}:{}", index.lower, index.upper))
+                    .collect();
+                format!("ARRAY[{}] OF {}", bounds.join(", "), self.type_name(t))
+            }
+            VariableType::Pointer(t) => format!("^{}", self.type_name(t)),
+            VariableType::Custom(name) => self.types.get(name).unwrap_or(name).clone(),
+            _ => t.str(),
+        }
+    }
+}
+
+// The type of the value a CONSTANT is given
+fn constant_type(val: &Node) -> String {
+    match val {
+        Node::Int { .. } => "INTEGER".to_string(),
+        Node::Real { .. } => "REAL".to_string(),
+        Node::String { .. } => "STRING".to_string(),
+        Node::Char { .. } => "CHAR".to_string(),
+        Node::Boolean { .. } => "BOOLEAN".to_string(),
+        Node::Date { .. } => "DATE".to_string(),
+        Node::Unary { expr, .. } => constant_type(expr),
+        _ => String::new(),
+    }
+}
+
+// Where a name is written as a whole word on a line, from a column on
+fn find(source: &SourceFile, line: usize, name: &str, from: usize) -> Option<usize> {
+    let text = source.file.get(line.wrapping_sub(1))?.to_ascii_lowercase();
+    let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(name)
+        .map(|(at, _)| at)
+        .filter(|at| *at >= from)
+        .find(|at| !text[..*at].ends_with(word) && !text[at + name.len()..].starts_with(word))
+}
+
+// The comment at the end of a line, outside any string or character
+fn comment(source: &SourceFile, line: usize) -> &str {
+    let Some(text) = source.file.get(line.wrapping_sub(1)) else {
+        return "";
+    };
+    let mut quote = None;
+    for (at, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if text[at..].starts_with("//") => return text[at + 2..].trim(),
+            None => {}
+        }
+    }
+    ""
+}
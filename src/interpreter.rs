@@ -0,0 +1,313 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use crate::chart::{self, CallGraph, Chart};
+use crate::enums::{Node, Position};
+use crate::executor::file_system::{DiskFileSystem, FileSystem};
+use crate::executor::hook::Hook;
+use crate::executor::io::{IoBackend, StreamIo};
+use crate::executor::{self, Executor, Limits};
+use crate::identifiers::IdentifierTable;
+use crate::transpile::{self, Language};
+use crate::utils::{ErrorKind, PseudoError, SourceFile};
+use crate::vm::CompileError;
+use crate::{checker, lexer, parser, vm};
+
+// How a program is run. The VM leaves programs using classes, pointers or random
+// files to the tree-walker, so either gives the same output and errors.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    Tree,
+    Vm,
+}
+
+// Runs a program in-process. Input defaults to empty, output is always captured
+// and files are read from the working directory unless replaced.
+pub struct Interpreter {
+    source: SourceFile,
+    text: String,
+    // Sent to the thread the program runs on
+    input: Box<dyn BufRead + Send>,
+    output: Option<Box<dyn Write + Send>>,
+    fs: Box<dyn FileSystem + Send>,
+    // Replaces input, output and fs altogether when set
+    io: Option<Box<dyn IoBackend + Send>>,
+    hook: Option<Box<dyn Hook + Send>>,
+    backend: Backend,
+    limits: Limits,
+}
+
+pub struct Outcome {
+    // Everything the program printed with OUTPUT
+    pub output: String,
+    pub status: i32,
+    // The first of errors
+    pub error: Option<PseudoError>,
+    // Every syntax error in the program, or the error that stopped it running
+    pub errors: Vec<PseudoError>,
+    pub source: SourceFile,
+}
+
+impl Interpreter {
+    pub fn new(name: &str, text: &str) -> Self {
+        Interpreter {
+            source: SourceFile::new(name, text),
+            text: text.to_string(),
+            input: Box::new(io::empty()),
+            output: None,
+            fs: Box::new(DiskFileSystem),
+            io: None,
+            hook: None,
+            backend: Backend::Tree,
+            limits: Limits::default(),
+        }
+    }
+
+    pub fn stdin(mut self, input: impl BufRead + Send + 'static) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
+    // Output is still captured in the outcome, this only adds a place to stream it to
+    pub fn stdout(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    pub fn filesystem(mut self, fs: impl FileSystem + Send + 'static) -> Self {
+        self.fs = Box::new(fs);
+        self
+    }
+
+    pub fn io(mut self, io: impl IoBackend + Send + 'static) -> Self {
+        self.io = Some(Box::new(io));
+        self
+    }
+
+    // Watch the program as it runs, for example with a TraceTable
+    pub fn hook(mut self, hook: impl Hook + Send + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    // Hooks watch the tree-walker, so a program with one always runs on it
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    // Stop the program with an error of kind Limit once it does more than allowed
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn source(&self) -> &SourceFile {
+        &self.source
+    }
+
+    // Every problem the checker can find without running the program.
+    // A syntax error stops the program from being checked, so it is reported alone.
+    pub fn check(&self) -> Vec<PseudoError> {
+        match self.parse_with_source() {
+            Ok(ast) => self.with_source(checker::check(&ast)),
+            Err(errors) => errors,
+        }
+    }
+
+    // The program written in another language, once it checks without problems
+    pub fn transpile(&self, to: Language) -> Result<String, Vec<PseudoError>> {
+        let ast = self.parse_with_source()?;
+        let errors = checker::check(&ast);
+        if !errors.is_empty() {
+            return Err(self.with_source(errors));
+        }
+        Ok(transpile::transpile(
+            &self.source.name,
+            &self.text,
+            &ast,
+            to,
+        ))
+    }
+
+    // The flowchart of the main program, or of the named PROCEDURE or FUNCTION.
+    // Only the syntax has to be right, the program is not checked.
+    pub fn flowchart(&self, routine: Option<&str>) -> Result<Chart, Vec<PseudoError>> {
+        let ast = self.parse_with_source()?;
+        chart::flowchart(&self.source, &ast, routine).ok_or_else(|| {
+            let message = format!(
+                "No PROCEDURE or FUNCTION named {}",
+                routine.unwrap_or_default()
+            );
+            self.with_source(vec![PseudoError::new(
+                ErrorKind::Semantic,
+                &message,
+                &Position::invalid(),
+            )])
+        })
+    }
+
+    // The calls between the main program and its PROCEDUREs and FUNCTIONs
+    pub fn call_graph(&self) -> Result<CallGraph, Vec<PseudoError>> {
+        let ast = self.parse_with_source()?;
+        Ok(chart::call_graph(&self.source, &ast))
+    }
+
+    // Every name the program declares, for an identifier table
+    pub fn identifiers(&self) -> Result<IdentifierTable, Vec<PseudoError>> {
+        let ast = self.parse_with_source()?;
+        Ok(IdentifierTable::new(&self.source, &ast))
+    }
+
+    // The syntax tree of the program, or every syntax error in it
+    fn parse_with_source(&self) -> Result<Vec<Box<Node>>, Vec<PseudoError>> {
+        parse(&self.text).map_err(|errors| self.with_source(errors))
+    }
+
+    // Errors marked as coming from this program
+    fn with_source(&self, errors: Vec<PseudoError>) -> Vec<PseudoError> {
+        errors
+            .into_iter()
+            .map(|e| e.with_source(&self.source.name))
+            .collect()
+    }
+
+    // Runs the program on a thread of its own, with room on its stack for the
+    // deepest nesting of calls the limits allow, so unbounded recursion is stopped
+    // with an error whatever the stack of the calling thread
+    pub fn run(self) -> Outcome {
+        let stack = self.limits.stack_size();
+        std::thread::scope(|scope| {
+            let program = std::thread::Builder::new()
+                .stack_size(stack)
+                .spawn_scoped(scope, move || self.run_here())
+                .expect("Could not start a thread to run the program on");
+            match program.join() {
+                Ok(outcome) => outcome,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+
+    fn run_here(self) -> Outcome {
+        let inner: Box<dyn IoBackend> = match self.io {
+            Some(io) => io,
+            None => Box::new(StreamIo::new(
+                self.input,
+                self.output.unwrap_or(Box::new(io::sink())),
+                self.fs,
+            )),
+        };
+        let captured = Rc::new(RefCell::new(String::new()));
+        let io = Capture {
+            buffer: captured.clone(),
+            inner,
+        };
+        let mut executor = Executor::with_io(Box::new(io));
+        let backend = match self.hook {
+            Some(_) => Backend::Tree,
+            None => self.backend,
+        };
+        executor.hook = self.hook.map(|hook| hook as Box<dyn Hook>);
+        executor.limits = self.limits;
+        let result = execute(&mut executor, &self.source, &self.text, backend);
+
+        let output = captured.borrow().clone();
+        let errors: Vec<PseudoError> = result
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| e.with_source(&self.source.name))
+            .collect();
+        Outcome {
+            output,
+            status: errors.first().map_or(0, |e| e.status()),
+            error: errors.first().cloned(),
+            errors,
+            source: self.source,
+        }
+    }
+}
+
+impl Outcome {
+    pub fn success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    // The errors as annotated snippets of the program, if there were any
+    pub fn render_error(&self) -> Option<String> {
+        if self.errors.is_empty() {
+            return None;
+        }
+        let rendered: Vec<String> = self.errors.iter().map(|e| e.render(&self.source)).collect();
+        Some(rendered.join("\n"))
+    }
+}
+
+fn execute(
+    executor: &mut Executor,
+    source: &SourceFile,
+    text: &str,
+    backend: Backend,
+) -> Result<(), Vec<PseudoError>> {
+    let ast = parse(text)?;
+    if let Some(hook) = executor.hook.as_mut() {
+        hook.program(source, &ast);
+    }
+    if backend == Backend::Vm {
+        match vm::compile(&ast) {
+            Ok(program) => return program.run(executor).map_err(|e| vec![e]),
+            // Left to the tree-walker
+            Err(CompileError::Unsupported { .. }) => {}
+            Err(CompileError::Invalid(e)) => return Err(vec![e]),
+        }
+    }
+    executor::run_with(executor, ast).map_err(|e| vec![e])
+}
+
+fn parse(text: &str) -> Result<Vec<Box<Node>>, Vec<PseudoError>> {
+    // End with a newline for better error reporting
+    let mut buf = text.to_string();
+    buf.push('\n');
+    let tokens = lexer::lexer(&mut buf.chars()).map_err(|e| vec![e])?;
+    parser::parse_program(&mut tokens.into_iter().peekable())
+}
+
+// Keeps a copy of everything written to the console while passing it through
+struct Capture {
+    buffer: Rc<RefCell<String>>,
+    inner: Box<dyn IoBackend>,
+}
+
+impl IoBackend for Capture {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        self.inner.read_line()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.buffer.borrow_mut().push_str(text);
+        self.inner.write(text)
+    }
+
+    fn file_exists(&self, path: &str) -> bool {
+        self.inner.file_exists(path)
+    }
+
+    fn read_file(&mut self, path: &str) -> io::Result<String> {
+        self.inner.read_file(path)
+    }
+
+    fn write_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.inner.write_file(path, content)
+    }
+
+    fn append_file(&mut self, path: &str, content: &str) -> io::Result<()> {
+        self.inner.append_file(path, content)
+    }
+
+    fn check_path(&self, path: &str) -> Result<(), String> {
+        self.inner.check_path(path)
+    }
+}
@@ -1,6 +1,6 @@
 use crate::enums::{Position, Token, VariableType};
 use crate::tokens::TToken;
-use crate::utils::err;
+use crate::utils::{err, ErrorKind, PseudoError};
 use chrono::NaiveDate;
 use std::iter::Peekable;
 use std::str::Chars;
@@ -8,19 +8,32 @@ use std::vec::IntoIter;
 
 pub type Lexer = Peekable<IntoIter<Token>>;
 
-// TODO: incorrect pos start reporting
+// Columns in positions are byte offsets into the line, so characters like '←'
+// move the column on by more than one
 
-pub fn lexer(buf: &mut Chars) -> Vec<Token> {
-    let mut c_line = 1;
+pub fn lexer(buf: &mut Chars) -> Result<Vec<Token>, PseudoError> {
+    lexer_from_line(buf, 1)
+}
+
+// Lex a chunk of source that starts on the given line of a larger file
+pub fn lexer_from_line(buf: &mut Chars, first_line: usize) -> Result<Vec<Token>, PseudoError> {
+    lex(buf, first_line, false).map_err(|e| e.with_kind(ErrorKind::Syntax))
+}
+
+// Lex keeping comments as tokens, for tools that write the source back out.
+// The parser does not accept comment tokens.
+pub fn lexer_with_comments(buf: &mut Chars) -> Result<Vec<Token>, PseudoError> {
+    lex(buf, 1, true).map_err(|e| e.with_kind(ErrorKind::Syntax))
+}
+
+fn lex(buf: &mut Chars, first_line: usize, comments: bool) -> Result<Vec<Token>, PseudoError> {
+    let mut c_line = first_line;
     let mut c_pos = 0;
     let mut tokens = Vec::new();
     let mut buf = buf.peekable();
 
     while let Some(ch) = buf.next() {
         match ch {
-            ' ' | '\t' | '\r' => {
-                c_pos += 1;
-            }
             '\n' => {
                 // Minus 1 because newline is invisible to user
                 tokens.push(Token {
@@ -30,6 +43,10 @@ pub fn lexer(buf: &mut Chars) -> Vec<Token> {
                 c_line += 1;
                 c_pos = 0;
             }
+            // Includes the non-breaking spaces left behind by copying from PDFs
+            _ if ch.is_whitespace() => {
+                c_pos += ch.len_utf8();
+            }
             '0'..='9' => {
                 let mut number = String::new();
                 let mut dot_count = 0;
@@ -56,22 +73,26 @@ pub fn lexer(buf: &mut Chars) -> Vec<Token> {
 
                 if possible_date {
                     let pos = Position::from(c_line, c_pos, number.len());
-                    if let Ok(date) = NaiveDate::parse_from_str(&*number, "%d/%m/%Y") {
+                    if let Ok(date) = NaiveDate::parse_from_str(&number, "%d/%m/%Y") {
                         tokens.push(Token {
                             t: TToken::DateLit(date),
-                            pos: pos,
+                            pos,
                         })
                     } else {
-                        err("Invalid DATE format", &pos)
+                        err("Invalid DATE format", &pos)?
                     }
                     c_pos += number.len();
                 } else {
-                    for temp in number.split('/').into_iter() {
+                    for temp in number.split('/') {
                         let pos = Position::from(c_line, c_pos, temp.len());
                         match temp.chars().filter(|c| *c == '.').count() {
                             0 => {
+                                let val = match temp.parse::<i64>() {
+                                    Ok(val) => val,
+                                    Err(_) => err("INTEGER literal is too large", &pos)?,
+                                };
                                 tokens.push(Token {
-                                    t: TToken::IntegerLit(temp.parse::<i64>().unwrap()),
+                                    t: TToken::IntegerLit(val),
                                     pos,
                                 });
                             }
@@ -81,7 +102,7 @@ pub fn lexer(buf: &mut Chars) -> Vec<Token> {
                                     pos,
                                 });
                             }
-                            _ => err("Multiple decimal points are not allowed", &pos),
+                            _ => err("Multiple decimal points are not allowed", &pos)?,
                         }
                         c_pos += temp.len();
                         tokens.push(Token {
@@ -90,9 +111,10 @@ pub fn lexer(buf: &mut Chars) -> Vec<Token> {
                         });
                         c_pos += 1;
                     }
-                    if number.chars().last().unwrap() != '/' {
+                    if !number.ends_with('/') {
                         // remove the extra '/' operator
                         tokens.pop();
+                        c_pos -= 1;
                     }
                 }
             }
@@ -116,8 +138,17 @@ pub fn lexer(buf: &mut Chars) -> Vec<Token> {
             '/' => {
                 if let Some('/') = buf.peek() {
                     // Comment detected, ignore until \n
+                    buf.next();
+                    let mut text = String::new();
                     while buf.peek() != Some(&'\n') && buf.peek().is_some() {
-                        buf.next();
+                        text.push(buf.next().unwrap());
+                    }
+                    if comments {
+                        let text = text.trim_end().to_string();
+                        tokens.push(Token {
+                            pos: Position::from(c_line, c_pos, text.len() + 2),
+                            t: TToken::Comment(text),
+                        });
                     }
                 } else {
                     tokens.push(Token {
@@ -127,29 +158,60 @@ pub fn lexer(buf: &mut Chars) -> Vec<Token> {
                     c_pos += 1;
                 }
             }
-            '"' => {
-                // String literals
+            '"' | '“' | '”' => {
+                // String literals, which may be in the curly quotes word processors write
                 let line_start = c_line;
                 let pos_start = c_pos;
                 let mut lit = String::new();
-                c_pos += 1;
-                while let Some(c) = buf.next() {
+                let close = closing_quote(ch);
+                c_pos += ch.len_utf8();
+                for c in buf.by_ref() {
                     match c {
-                        '"' => break,
+                        _ if c == close => {
+                            c_pos += c.len_utf8();
+                            break;
+                        }
                         '\n' => {
                             c_pos = 0;
                             c_line += 1
                         }
-                        _ => c_pos += 1,
+                        _ => c_pos += c.len_utf8(),
                     }
                     lit.push(c);
                 }
-                c_pos += 1;
                 tokens.push(Token {
                     t: TToken::StringLit(lit.clone()),
                     pos: Position::new(line_start, c_line, pos_start, c_pos),
                 });
             }
+            '\'' | '‘' | '’' => {
+                // CHAR literals, which hold exactly one character
+                let pos_start = c_pos;
+                let mut lit = String::new();
+                let close = closing_quote(ch);
+                c_pos += ch.len_utf8();
+                while let Some(c) = buf.next_if(|c| *c != close && *c != '\n') {
+                    c_pos += c.len_utf8();
+                    lit.push(c);
+                }
+                let closed = buf.next_if(|c| *c != '\n');
+                if let Some(c) = closed {
+                    c_pos += c.len_utf8();
+                }
+                let pos = Position::from(c_line, pos_start, c_pos - pos_start);
+                let mut chars = lit.chars();
+                match (chars.next(), chars.next(), closed) {
+                    (_, _, None) => err("Closing ' expected", &pos)?,
+                    (Some(val), None, _) => tokens.push(Token {
+                        t: TToken::CharLit(val),
+                        pos,
+                    }),
+                    _ => err(
+                        "CHAR literal must be exactly one character, use \" for a STRING",
+                        &pos,
+                    )?,
+                }
+            }
             _ => {
                 // Symbols
                 let mut sym = String::new();
@@ -179,38 +241,37 @@ pub fn lexer(buf: &mut Chars) -> Vec<Token> {
         t: TToken::EOF,
         pos: Position::from(c_line, c_pos, 1),
     });
-    c_pos += 1;
 
     // Second pass to reduce multiple newline and identify array
     let mut temp_tokens = tokens.into_iter().peekable();
     tokens = vec![];
     while temp_tokens.peek().is_some() {
         tokens.push(temp_tokens.next().unwrap());
-        match tokens.last().unwrap().clone().t {
-            TToken::Newline => {
-                while temp_tokens.peek().is_some()
-                    && temp_tokens.peek().unwrap().t == TToken::Newline
-                {
-                    temp_tokens.next();
-                }
+        if tokens.last().unwrap().clone().t == TToken::Newline {
+            while temp_tokens.peek().is_some() && temp_tokens.peek().unwrap().t == TToken::Newline {
+                temp_tokens.next();
             }
-            _ => (),
         }
     }
 
-    tokens
+    Ok(tokens)
 }
 
 fn match_symbol(sym: String) -> TToken {
     match sym.as_str() {
         "+" | "-" | "*" | "/" => TToken::Operator(sym.clone()),
-        "<>" => TToken::Operator("!=".to_string()),
+        "<>" | "≠" => TToken::Operator("!=".to_string()),
+        // Symbols from the syntax guide and exam papers
+        "≤" => TToken::Operator("<=".to_string()),
+        "≥" => TToken::Operator(">=".to_string()),
+        "×" => TToken::Operator("*".to_string()),
+        "÷" => TToken::Operator("/".to_string()),
         "=" | ">=" | ">" | "<" | "<=" | "&" => TToken::Operator(sym.clone()),
         "(" => TToken::LParen,
         ")" => TToken::RParen,
         "[" => TToken::LSqrBracket,
         "]" => TToken::RSqrBracket,
-        "<-" => TToken::Assignment,
+        "<-" | "←" => TToken::Assignment,
         ":" => TToken::Colon,
         "," => TToken::Comma,
         "^" => TToken::Caret,
@@ -219,7 +280,87 @@ fn match_symbol(sym: String) -> TToken {
     }
 }
 
-fn match_word(word: String) -> TToken {
+// A curly quote is closed by its partner, any other by itself, so the other
+// kinds can be written inside a literal
+fn closing_quote(open: char) -> char {
+    match open {
+        '“' => '”',
+        '‘' => '’',
+        _ => open,
+    }
+}
+
+// Every reserved word, match_word reads any other word as an identifier
+pub const KEYWORDS: &[&str] = &[
+    "APPEND",
+    "WRITE",
+    "READ",
+    "RANDOM",
+    "BYREF",
+    "BYVAL",
+    "ARRAY",
+    "CALL",
+    "CASE",
+    "CLASS",
+    "CLOSEFILE",
+    "CONSTANT",
+    "DECLARE",
+    "DEFINE",
+    "ELSE",
+    "ENDCASE",
+    "ENDCLASS",
+    "ENDFUNCTION",
+    "ENDIF",
+    "ENDPROCEDURE",
+    "ENDTYPE",
+    "ENDWHILE",
+    "FOR",
+    "FUNCTION",
+    "GETRECORD",
+    "IF",
+    "INHERITS",
+    "INPUT",
+    "NEXT",
+    "NEW",
+    "OPENFILE",
+    "OTHERWISE",
+    "OUTPUT",
+    "OF",
+    "PROCEDURE",
+    "PRIVATE",
+    "PUBLIC",
+    "PUTRECORD",
+    "READFILE",
+    "REPEAT",
+    "RETURN",
+    "RETURNS",
+    "TO",
+    "SEEK",
+    "STEP",
+    "THEN",
+    "TYPE",
+    "UNTIL",
+    "WHILE",
+    "WRITEFILE",
+    "TRUE",
+    "FALSE",
+    "DIV",
+    "MOD",
+    "AND",
+    "OR",
+    "NOT",
+    "INTEGER",
+    "REAL",
+    "STRING",
+    "CHAR",
+    "BOOLEAN",
+    "DATE",
+];
+
+pub(crate) fn match_word(word: String) -> TToken {
+    if !KEYWORDS.contains(&word.as_str()) {
+        return TToken::Identifier(word.to_lowercase());
+    }
     match word.as_str() {
         "APPEND" | "WRITE" | "READ" | "RANDOM" => TToken::FileMode(word),
         "BYREF" | "BYVAL" => TToken::PassBy(word),
@@ -277,8 +418,9 @@ fn match_word(word: String) -> TToken {
         "INTEGER" => TToken::VarType(VariableType::Integer),
         "REAL" => TToken::VarType(VariableType::Real),
         "STRING" => TToken::VarType(VariableType::String),
+        "CHAR" => TToken::VarType(VariableType::Char),
         "BOOLEAN" => TToken::VarType(VariableType::Boolean),
         "DATE" => TToken::VarType(VariableType::Date),
-        _ => TToken::Identifier(word.to_lowercase()),
+        _ => unreachable!("{} is in KEYWORDS without a token", word),
     }
 }
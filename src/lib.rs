@@ -0,0 +1,28 @@
+pub mod chart;
+pub mod checker;
+pub mod enums;
+pub mod executor;
+pub mod formatter;
+pub mod identifiers;
+mod interpreter;
+pub mod lexer;
+pub mod parser;
+pub mod table;
+pub mod tokens;
+pub mod trace;
+pub mod transpile;
+pub mod utils;
+pub mod vm;
+
+pub use crate::chart::{CallGraph, Chart, ChartFormat};
+pub use crate::executor::file_system::{
+    DiskFileSystem, FileSystem, Jail, MemoryFileSystem, RootedFileSystem,
+};
+pub use crate::executor::io::{IoBackend, MemoryIo, StreamIo};
+pub use crate::executor::Limits;
+pub use crate::identifiers::IdentifierTable;
+pub use crate::interpreter::{Backend, Interpreter, Outcome};
+pub use crate::table::TableFormat;
+pub use crate::trace::TraceTable;
+pub use crate::transpile::Language;
+pub use crate::utils::{ErrorKind, Frame, Limit, PseudoError, SourceFile};
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use pseudoengine::enums::Position;
+use pseudoengine::{checker, lexer, parser, PseudoError, SourceFile};
+
+use symbols::{Kind, Symbol};
+
+mod symbols;
+
+// Open documents and what was found in them the last time they changed
+struct Document {
+    text: String,
+    symbols: Vec<Symbol>,
+}
+
+// Serve the language server protocol over a pair of streams until the client exits
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    let mut shutdown = false;
+    while let Some(message) = read_message(&mut input)? {
+        let id = message.get("id").cloned();
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": {},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "pseudoengine" },
+            })),
+            "shutdown" => {
+                shutdown = true;
+                Some(Value::Null)
+            }
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                publish(&mut output, &mut documents, uri, text)?;
+                None
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                // Only whole documents are synchronised, so the last change holds all of the text
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    publish(&mut output, &mut documents, uri, text)?;
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                documents.remove(uri);
+                None
+            }
+            "textDocument/completion" => Some(completion(&documents, params)),
+            "textDocument/hover" => Some(hover(&documents, params)),
+            "textDocument/definition" => Some(definition(&documents, params)),
+            "textDocument/documentSymbol" => Some(document_symbols(&documents, params)),
+            _ => None,
+        };
+        // Notifications carry no id and are never answered
+        let Some(id) = id else {
+            continue;
+        };
+        let response = match result {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": format!("Method not found: {}", method) },
+            }),
+        };
+        write_message(&mut output, &response)?;
+    }
+    if !shutdown {
+        return Err(io::Error::other(
+            "Client exited without shutting down the server",
+        ));
+    }
+    Ok(())
+}
+
+// Messages are JSON bodies preceded by a Content-Length header
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Message without a Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+// Look at the new text of a document and send the problems found in it
+fn publish(
+    output: &mut impl Write,
+    documents: &mut HashMap<String, Document>,
+    uri: &str,
+    text: &str,
+) -> io::Result<()> {
+    let (symbols, errors) = analyse(uri, text);
+    documents.insert(
+        uri.to_string(),
+        Document {
+            text: text.to_string(),
+            symbols,
+        },
+    );
+    let diagnostics: Vec<Value> = errors
+        .iter()
+        .map(|e| {
+            json!({
+                "range": range(&e.pos, text),
+                "severity": 1,
+                "source": "pseudoengine",
+                "message": e.message,
+            })
+        })
+        .collect();
+    write_message(
+        output,
+        &json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }),
+    )
+}
+
+// The symbols declared in a program and its problems. Like the check subcommand,
+// the checker only runs once the program is free of syntax errors.
+fn analyse(uri: &str, text: &str) -> (Vec<Symbol>, Vec<PseudoError>) {
+    let source = SourceFile::new(uri, text);
+    let mut buf = text.to_string();
+    buf.push('\n');
+    let tokens = match lexer::lexer(&mut buf.chars()) {
+        Ok(tokens) => tokens,
+        Err(e) => return (Vec::new(), vec![e]),
+    };
+    let (nodes, errors) = parser::parse_partial(&mut tokens.into_iter().peekable());
+    let symbols = symbols::collect(&nodes, &source);
+    if !errors.is_empty() {
+        return (symbols, errors);
+    }
+    (symbols, checker::check(&nodes))
+}
+
+fn completion(documents: &HashMap<String, Document>, params: &Value) -> Value {
+    let mut items: Vec<Value> = lexer::KEYWORDS
+        .iter()
+        .map(|keyword| json!({ "label": keyword, "kind": 14 }))
+        .collect();
+    if let Some(document) = document(documents, params) {
+        let mut seen = Vec::new();
+        for symbol in symbols::flatten(&document.symbols) {
+            let name = symbol.name.to_lowercase();
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name);
+            items.push(json!({
+                "label": symbol.name,
+                "kind": completion_kind(symbol.kind),
+                "detail": symbol.detail,
+            }));
+        }
+    }
+    json!(items)
+}
+
+fn hover(documents: &HashMap<String, Document>, params: &Value) -> Value {
+    match symbol_at(documents, params) {
+        Some(symbol) => json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```\n{}\n```", symbol.detail),
+            },
+        }),
+        None => Value::Null,
+    }
+}
+
+fn definition(documents: &HashMap<String, Document>, params: &Value) -> Value {
+    let Some(document) = document(documents, params) else {
+        return Value::Null;
+    };
+    match symbol_at(documents, params) {
+        Some(symbol) => json!({
+            "uri": params["textDocument"]["uri"],
+            "range": range(&symbol.pos, &document.text),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn document_symbols(documents: &HashMap<String, Document>, params: &Value) -> Value {
+    fn to_json(symbol: &Symbol, text: &str) -> Value {
+        json!({
+            "name": symbol.name,
+            "detail": symbol.detail,
+            "kind": symbol_kind(symbol.kind),
+            "range": range(&symbol.range, text),
+            "selectionRange": range(&symbol.pos, text),
+            "children": symbol
+                .children
+                .iter()
+                .map(|child| to_json(child, text))
+                .collect::<Vec<Value>>(),
+        })
+    }
+    match document(documents, params) {
+        Some(document) => json!(document
+            .symbols
+            .iter()
+            .map(|symbol| to_json(symbol, &document.text))
+            .collect::<Vec<Value>>()),
+        None => json!([]),
+    }
+}
+
+fn document<'a>(documents: &'a HashMap<String, Document>, params: &Value) -> Option<&'a Document> {
+    documents.get(params["textDocument"]["uri"].as_str()?)
+}
+
+// The symbol named by the word under the cursor
+fn symbol_at<'a>(documents: &'a HashMap<String, Document>, params: &Value) -> Option<&'a Symbol> {
+    let document = document(documents, params)?;
+    let line = params["position"]["line"].as_u64()? as usize;
+    let character = params["position"]["character"].as_u64()? as usize;
+    let text: Vec<char> = document.text.lines().nth(line)?.chars().collect();
+    // The protocol counts columns in UTF-16 code units
+    let mut units = 0;
+    let column = text
+        .iter()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= character
+        })
+        .count();
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+    let mut start = column.min(text.len());
+    while start > 0 && is_word(&text[start - 1]) {
+        start -= 1;
+    }
+    let mut end = column.min(text.len());
+    while end < text.len() && is_word(&text[end]) {
+        end += 1;
+    }
+    if start == end {
+        return None;
+    }
+    let word: String = text[start..end].iter().collect();
+    symbols::lookup(&document.symbols, &word, line + 1)
+}
+
+// Positions count lines from 1 and columns in bytes, the protocol counts
+// lines from 0 and columns in UTF-16 code units
+fn range(pos: &Position, text: &str) -> Value {
+    let column = |line: usize, byte: usize| {
+        let line = text.lines().nth(line).unwrap_or("");
+        match line.get(..byte) {
+            Some(before) => before.encode_utf16().count(),
+            None => byte,
+        }
+    };
+    let start = pos.line_start.saturating_sub(1);
+    let end = pos.line_end.saturating_sub(1);
+    json!({
+        "start": { "line": start, "character": column(start, pos.pos_start) },
+        "end": { "line": end, "character": column(end, pos.pos_end) },
+    })
+}
+
+fn symbol_kind(kind: Kind) -> u8 {
+    match kind {
+        Kind::Variable | Kind::Parameter => 13,
+        Kind::Constant => 14,
+        Kind::Function | Kind::Procedure => 12,
+        Kind::Record => 23,
+        Kind::Field => 8,
+        Kind::Class => 5,
+        Kind::Enum => 10,
+        Kind::EnumMember => 22,
+        Kind::Pointer => 26,
+    }
+}
+
+fn completion_kind(kind: Kind) -> u8 {
+    match kind {
+        Kind::Variable | Kind::Parameter => 6,
+        Kind::Constant => 21,
+        Kind::Function | Kind::Procedure => 3,
+        Kind::Record => 22,
+        Kind::Field => 5,
+        Kind::Class => 7,
+        Kind::Enum => 13,
+        Kind::EnumMember => 20,
+        Kind::Pointer => 25,
+    }
+}
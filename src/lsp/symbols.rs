@@ -0,0 +1,427 @@
+use std::ops::Deref;
+
+use pseudoengine::enums::{Node, Position, VariableType};
+use pseudoengine::SourceFile;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Variable,
+    Parameter,
+    Constant,
+    Function,
+    Procedure,
+    Record,
+    Field,
+    Class,
+    Enum,
+    EnumMember,
+    Pointer,
+}
+
+// Something the program declares, found by walking the parsed program
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    // As written in the source, the parser only keeps names in lowercase
+    pub name: String,
+    pub kind: Kind,
+    // The declaration as it would be written, shown on hover
+    pub detail: String,
+    // Where the name itself is written
+    pub pos: Position,
+    // The whole declaration
+    pub range: Position,
+    // The routine the symbol is local to, None for globals
+    pub scope: Option<Position>,
+    pub children: Vec<Symbol>,
+}
+
+impl Symbol {
+    // Whether the symbol can be seen from a line
+    fn visible_from(&self, line: usize) -> bool {
+        match self.scope {
+            Some(scope) => scope.line_start <= line && line <= scope.line_end,
+            None => true,
+        }
+    }
+}
+
+pub fn collect(nodes: &Vec<Box<Node>>, source: &SourceFile) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    for node in nodes {
+        if let Node::Main { children } = node.deref() {
+            collect_stmts(children, source, None, &mut symbols);
+        }
+    }
+    symbols
+}
+
+// The symbol a name on a line refers to, preferring the innermost declaration
+pub fn lookup<'a>(symbols: &'a Vec<Symbol>, name: &str, line: usize) -> Option<&'a Symbol> {
+    let name = name.to_lowercase();
+    let mut found: Option<&Symbol> = None;
+    for symbol in flatten(symbols) {
+        if symbol.name.to_lowercase() != name || !symbol.visible_from(line) {
+            continue;
+        }
+        // A local declaration hides a global one
+        if found.is_none() || (found.unwrap().scope.is_none() && symbol.scope.is_some()) {
+            found = Some(symbol);
+        }
+    }
+    found
+}
+
+pub fn flatten(symbols: &Vec<Symbol>) -> Vec<&Symbol> {
+    let mut all = Vec::new();
+    for symbol in symbols {
+        all.push(symbol);
+        all.extend(flatten(&symbol.children));
+    }
+    all
+}
+
+fn collect_stmts(
+    nodes: &Vec<Box<Node>>,
+    source: &SourceFile,
+    scope: Option<Position>,
+    symbols: &mut Vec<Symbol>,
+) {
+    for node in nodes {
+        match node.deref() {
+            Node::Declare {
+                t, children, pos, ..
+            } => {
+                for name in children {
+                    symbols.push(symbol(
+                        source,
+                        name,
+                        Kind::Variable,
+                        format!("DECLARE {} : {}", name, type_name(t)),
+                        pos,
+                        scope,
+                    ));
+                }
+            }
+            Node::Const { name, val, pos } => {
+                let val = match val.deref() {
+                    Node::String { val, .. } => format!("{:?}", val),
+                    Node::Char { val, .. } => format!("'{}'", val),
+                    Node::Boolean { val, .. } => val.to_string().to_uppercase(),
+                    _ => val.val_as_str(),
+                };
+                symbols.push(symbol(
+                    source,
+                    name,
+                    Kind::Constant,
+                    format!("CONSTANT {} = {}", name, val),
+                    pos,
+                    scope,
+                ));
+            }
+            Node::Function { .. } | Node::Procedure { .. } => {
+                if let Some(routine) = routine(node, source, scope) {
+                    symbols.push(routine);
+                }
+            }
+            Node::Record {
+                name,
+                children,
+                pos,
+            } => {
+                if let Node::String { val, .. } = name.deref() {
+                    let mut record = symbol(
+                        source,
+                        val,
+                        Kind::Record,
+                        format!("TYPE {}", val),
+                        pos,
+                        scope,
+                    );
+                    for field in children {
+                        if let Node::Declare {
+                            t, children, pos, ..
+                        } = field.deref()
+                        {
+                            for name in children {
+                                record.children.push(symbol(
+                                    source,
+                                    name,
+                                    Kind::Field,
+                                    format!("DECLARE {} : {}", name, type_name(t)),
+                                    pos,
+                                    Some(record.range),
+                                ));
+                            }
+                        }
+                    }
+                    symbols.push(record);
+                }
+            }
+            Node::Class {
+                name,
+                base,
+                children,
+                pos,
+            } => {
+                if let Node::String { val, .. } = name.deref() {
+                    let detail = match base.deref() {
+                        Node::String { val: base, .. } => {
+                            format!("CLASS {} INHERITS {}", val, base)
+                        }
+                        _ => format!("CLASS {}", val),
+                    };
+                    let mut class = symbol(source, val, Kind::Class, detail, pos, scope);
+                    for member in children {
+                        match member.deref() {
+                            Node::Declare {
+                                t,
+                                children,
+                                private,
+                                pos,
+                                ..
+                            } => {
+                                for name in children {
+                                    let access = if *private { "PRIVATE" } else { "PUBLIC" };
+                                    class.children.push(symbol(
+                                        source,
+                                        name,
+                                        Kind::Field,
+                                        format!("{} {} : {}", access, name, type_name(t)),
+                                        pos,
+                                        Some(class.range),
+                                    ));
+                                }
+                            }
+                            Node::Function { .. } | Node::Procedure { .. } => {
+                                if let Some(method) = routine(member, source, Some(class.range)) {
+                                    class.children.push(method);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    symbols.push(class);
+                }
+            }
+            Node::Enum {
+                name,
+                variants,
+                pos,
+            } => {
+                let names: Vec<String> = variants
+                    .iter()
+                    .filter_map(|variant| match variant.deref() {
+                        Node::String { val, .. } => Some(val.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                let mut family = symbol(
+                    source,
+                    name,
+                    Kind::Enum,
+                    format!("TYPE {} = ({})", name, names.join(", ")),
+                    pos,
+                    scope,
+                );
+                // Variants are global constants but carry no position of their own
+                for variant in names {
+                    let mut member = symbol(
+                        source,
+                        &variant,
+                        Kind::EnumMember,
+                        format!("{} : {}", variant, name),
+                        pos,
+                        scope,
+                    );
+                    member.pos = find_name(source, pos, &variant, pos.pos_start);
+                    family.children.push(member);
+                }
+                symbols.push(family);
+            }
+            Node::PointerDef { name, ref_to, pos } => symbols.push(symbol(
+                source,
+                name,
+                Kind::Pointer,
+                format!("TYPE {} = ^{}", name, type_name(ref_to)),
+                pos,
+                scope,
+            )),
+            Node::If {
+                true_body,
+                false_body,
+                ..
+            } => {
+                collect_stmts(true_body, source, scope, symbols);
+                collect_stmts(false_body, source, scope, symbols);
+            }
+            Node::While { body, .. } | Node::Repeat { body, .. } | Node::For { body, .. } => {
+                collect_stmts(body, source, scope, symbols)
+            }
+            Node::Switch {
+                cases, otherwise, ..
+            } => {
+                for case in cases {
+                    if let Node::Case { children, .. } = case.deref() {
+                        collect_stmts(children, source, scope, symbols);
+                    }
+                }
+                collect_stmts(otherwise, source, scope, symbols);
+            }
+            _ => {}
+        }
+    }
+}
+
+// A function or procedure, with its parameters and local variables as children
+fn routine(node: &Box<Node>, source: &SourceFile, scope: Option<Position>) -> Option<Symbol> {
+    let (name, params, returns, children, pos, kind) = match node.deref() {
+        Node::Function {
+            name,
+            params,
+            return_type,
+            children,
+            pos,
+            ..
+        } => (
+            name,
+            params,
+            Some(return_type),
+            children,
+            pos,
+            Kind::Function,
+        ),
+        Node::Procedure {
+            name,
+            params,
+            children,
+            pos,
+            ..
+        } => (name, params, None, children, pos, Kind::Procedure),
+        _ => return None,
+    };
+    let Node::String { val, .. } = name.deref() else {
+        return None;
+    };
+    // Parameters come first so the signature uses their names as written
+    let mut locals = Vec::new();
+    for param in params {
+        if let Node::Declare {
+            t,
+            children,
+            byref,
+            pos,
+            ..
+        } = param.deref()
+        {
+            locals.push(symbol(
+                source,
+                &children[0],
+                Kind::Parameter,
+                format!(
+                    "{}{} : {}",
+                    if *byref { "BYREF " } else { "" },
+                    children[0],
+                    type_name(t)
+                ),
+                pos,
+                Some(*pos_of(node)),
+            ));
+        }
+    }
+    let signature: Vec<&str> = locals.iter().map(|param| param.detail.as_str()).collect();
+    let detail = match returns {
+        Some(returns) => format!(
+            "FUNCTION {}({}) RETURNS {}",
+            val,
+            signature.join(", "),
+            type_name(returns)
+        ),
+        None => format!("PROCEDURE {}({})", val, signature.join(", ")),
+    };
+    let mut routine = symbol(source, val, kind, detail, pos, scope);
+    routine.children = locals;
+    collect_stmts(children, source, Some(*pos_of(node)), &mut routine.children);
+    Some(routine)
+}
+
+fn pos_of(node: &Box<Node>) -> &Position {
+    match node.deref() {
+        Node::Function { pos, .. } | Node::Procedure { pos, .. } => pos,
+        _ => unreachable!(),
+    }
+}
+
+fn symbol(
+    source: &SourceFile,
+    name: &str,
+    kind: Kind,
+    detail: String,
+    range: &Position,
+    scope: Option<Position>,
+) -> Symbol {
+    // Keywords come before the name, so it is searched for from the start of the declaration
+    let skip = match kind {
+        Kind::Variable | Kind::Field | Kind::Parameter | Kind::Constant => 0,
+        _ => 1,
+    };
+    let pos = find_name(source, range, name, range.pos_start + skip);
+    let text = &source.file[pos.line_start - 1];
+    let written = text.get(pos.pos_start..pos.pos_end).unwrap_or(name);
+    // Names are written the way they were declared, not in the parser's lowercase
+    let detail = match written.eq_ignore_ascii_case(name) {
+        true => replace_word(&detail, name, written),
+        false => detail,
+    };
+    Symbol {
+        name: written.to_string(),
+        kind,
+        detail,
+        pos,
+        range: *range,
+        scope,
+        children: Vec::new(),
+    }
+}
+
+// Where name is written as a whole word on the first line of a declaration
+fn find_name(source: &SourceFile, range: &Position, name: &str, from: usize) -> Position {
+    let fallback = Position::from(range.line_start, range.pos_start, 0);
+    let Some(line) = source.file.get(range.line_start.wrapping_sub(1)) else {
+        return fallback;
+    };
+    let lower = line.to_lowercase();
+    let mut start = from.min(lower.len());
+    while let Some(found) = lower[start..].find(name) {
+        let begin = start + found;
+        let end = begin + name.len();
+        let before = lower[..begin].chars().last();
+        let after = lower[end..].chars().next();
+        let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if !word(before) && !word(after) {
+            return Position::from(range.line_start, begin, name.len());
+        }
+        start = end;
+    }
+    fallback
+}
+
+fn replace_word(detail: &str, name: &str, written: &str) -> String {
+    match detail.find(name) {
+        Some(i) => format!("{}{}{}", &detail[..i], written, &detail[i + name.len()..]),
+        None => detail.to_string(),
+    }
+}
+
+pub fn type_name(t: &VariableType) -> String {
+    match t {
+        VariableType::Array { shape, t } => {
+            let bounds: Vec<String> = shape
+                .iter()
+                .map(|index| format!("{}:{}", index.lower, index.upper))
+                .collect();
+            format!("ARRAY[{}] OF {}", bounds.join(", "), type_name(t))
+        }
+        VariableType::Pointer(t) => format!("^{}", type_name(t)),
+        _ => t.str(),
+    }
+}
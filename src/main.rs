@@ -1,32 +1,20 @@
-#![allow(warnings)]
-use std::cell::RefCell;
-use std::fmt::format;
-use std::rc::Rc;
-use std::{env, fs::read_to_string, io::Read};
-
-use clap::{Arg, Command};
-
-mod enums;
-mod executor;
-mod lexer;
-mod parser;
-mod tokens;
-mod utils;
-
-#[derive(Clone)]
-struct SourceFile {
-    name: String,
-    file: Vec<String>,
-}
+use std::fs::{self, read_to_string};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::Duration;
 
-impl SourceFile {
-    pub fn new() -> Self {
-        SourceFile {
-            name: String::new(),
-            file: Vec::new(),
-        }
-    }
-}
+use clap::{value_parser, Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use pseudoengine::executor::file_system;
+use pseudoengine::{
+    Backend, ChartFormat, FileSystem, Interpreter, Jail, Language, Limits, MemoryFileSystem,
+    PseudoError, RootedFileSystem, SourceFile, TableFormat, TraceTable,
+};
+
+mod console;
+mod debugger;
+mod lsp;
+mod repl;
+mod test_runner;
 
 const HEADER: &str = r#"
 ██████╗ ███████╗███████╗██╗   ██╗██████╗  ██████╗ ███████╗███╗   ██╗ ██████╗ ██╗███╗   ██╗███████╗
@@ -39,12 +27,155 @@ const HEADER: &str = r#"
 An interpreter for the A-Level pseudocode syntax.
 Created by Jaxon Moh & Jin Wei Tan."#;
 
-thread_local! {
-    pub static SOURCE_FILE: Rc<RefCell<SourceFile>> = Rc::new(RefCell::new(SourceFile::new()));
-}
+const MAX_CALL_DEPTH_ALLOWED: u64 = 10_000;
 
 fn main() {
-    let cli = Command::new("pseudoengine")
+    let cli = command().get_matches();
+
+    match cli.subcommand() {
+        Some(("run", args)) => {
+            let file: &String = args.get_one("file").expect("File name not provided");
+            let interpreter = jail(interpreter(file), args).limits(limits(args));
+            let outcome = match args.get_one::<String>("trace-table") {
+                Some(format) => {
+                    let mut table = TraceTable::new();
+                    if let Some(vars) = args.get_one::<String>("trace-vars") {
+                        table = table.select(&vars.split(',').collect::<Vec<&str>>());
+                    }
+                    let outcome = interpreter.hook(table.clone()).run();
+                    print!("\n{}", table.render(table_format(format)));
+                    outcome
+                }
+                None => {
+                    let backend = match args.get_one::<String>("backend").map(|b| b.as_str()) {
+                        Some("vm") => Backend::Vm,
+                        _ => Backend::Tree,
+                    };
+                    interpreter.backend(backend).run()
+                }
+            };
+            if let Some(error) = outcome.render_error() {
+                anstream::eprintln!("{}", error);
+                std::process::exit(outcome.status);
+            }
+        }
+        Some(("repl", _)) => {
+            with_stack(|| repl::repl(BufReader::new(io::stdin()), anstream::stdout()))
+        }
+        Some(("debug", args)) => {
+            let file: &String = args.get_one("file").unwrap();
+            let buf = read_program(file);
+            if let Err(errors) = with_stack(|| {
+                debugger::debug(file, &buf, BufReader::new(io::stdin()), io::stdout())
+            }) {
+                fail(&errors, &SourceFile::new(file, &buf));
+            }
+        }
+        Some(("check", args)) => {
+            let file: &String = args.get_one("file").unwrap();
+            let errors = check(file);
+            if errors.is_empty() {
+                println!("No problems found");
+            } else {
+                println!("{} problem(s) found", errors.len());
+                std::process::exit(1);
+            }
+        }
+        Some(("test", args)) => {
+            let dir: &String = args.get_one("dir").unwrap();
+            match test_runner::run_tests(Path::new(dir)) {
+                Ok(summary) if summary.failed == 0 => {}
+                Ok(_) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("Cannot read directory {}: {}", dir, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("fmt", args)) => {
+            let file: &String = args.get_one("file").unwrap();
+            let buf = read_program(file);
+            match pseudoengine::formatter::format(&buf) {
+                Ok(formatted) if formatted == buf => {}
+                Ok(_) if args.get_flag("check") => {
+                    println!("{} is not formatted", file);
+                    std::process::exit(1);
+                }
+                Ok(formatted) => {
+                    if fs::write(file, formatted).is_err() {
+                        eprintln!("Cannot write {}", file);
+                        std::process::exit(1);
+                    }
+                }
+                Err(errors) => {
+                    let errors: Vec<PseudoError> =
+                        errors.into_iter().map(|e| e.with_source(file)).collect();
+                    fail(&errors, &SourceFile::new(file, &buf))
+                }
+            }
+        }
+        Some(("transpile", args)) => {
+            let file: &String = args.get_one("file").unwrap();
+            let buf = read_program(file);
+            let to = match args.get_one::<String>("to").map(|to| to.as_str()) {
+                Some("python") => Language::Python,
+                Some("java") => Language::Java,
+                Some("vbnet") => Language::VbNet,
+                _ => unreachable!(),
+            };
+            let interpreter = Interpreter::new(file, &buf);
+            match interpreter.transpile(to) {
+                Ok(code) => print!("{}", code),
+                Err(errors) => fail(&errors, interpreter.source()),
+            }
+        }
+        Some(("flowchart", args)) => {
+            let file: &String = args.get_one("file").unwrap();
+            let buf = read_program(file);
+            let routine = args.get_one::<String>("proc").map(|name| name.as_str());
+            let interpreter = Interpreter::new(file, &buf);
+            match interpreter.flowchart(routine) {
+                Ok(chart) => print!("{}", chart.render(chart_format(args))),
+                Err(errors) => fail(&errors, interpreter.source()),
+            }
+        }
+        Some(("structure-chart", args)) => {
+            let file: &String = args.get_one("file").unwrap();
+            let buf = read_program(file);
+            let interpreter = Interpreter::new(file, &buf);
+            match interpreter.call_graph() {
+                Ok(graph) if args.get_one::<String>("format").unwrap() == "list" => {
+                    print!("{}", graph.listing())
+                }
+                Ok(graph) => print!("{}", graph.chart().render(chart_format(args))),
+                Err(errors) => fail(&errors, interpreter.source()),
+            }
+        }
+        Some(("identifiers", args)) => {
+            let file: &String = args.get_one("file").unwrap();
+            let buf = read_program(file);
+            let interpreter = Interpreter::new(file, &buf);
+            match interpreter.identifiers() {
+                Ok(table) => {
+                    let format: &String = args.get_one("format").unwrap();
+                    print!("{}", table.render(table_format(format)))
+                }
+                Err(errors) => fail(&errors, interpreter.source()),
+            }
+        }
+        Some(("lsp", _)) => {
+            if let Err(e) = lsp::serve(BufReader::new(io::stdin()), io::stdout()) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+// The subcommands and their arguments
+fn command() -> Command {
+    Command::new("pseudoengine")
         .about(HEADER)
         .version("0.0.1")
         .subcommand_required(true)
@@ -52,67 +183,1438 @@ fn main() {
         .subcommand(
             Command::new("run")
                 .about("Run the program.")
-                .arg(Arg::new("file").help("Filepath of the program")),
+                .arg(Arg::new("file").help("Filepath of the program"))
+                .arg(
+                    Arg::new("trace-table")
+                        .long("trace-table")
+                        .help("Print a trace table of every assignment, INPUT and OUTPUT once the program ends, as --trace-table=csv for another format")
+                        .num_args(0..=1)
+                        .require_equals(true)
+                        .default_missing_value("plain")
+                        .value_parser(["plain", "csv", "markdown"]),
+                )
+                .arg(
+                    Arg::new("trace-vars")
+                        .long("trace-vars")
+                        .help("Comma separated variables to show in the trace table, OUTPUT included")
+                        .requires("trace-table"),
+                )
+                .arg(
+                    Arg::new("backend")
+                        .long("backend")
+                        .help("Walk the parsed program, or compile it to bytecode and run that on a VM")
+                        .default_value("tree")
+                        .value_parser(["tree", "vm"])
+                        .conflicts_with("trace-table"),
+                )
+                .arg(
+                    Arg::new("max-steps")
+                        .long("max-steps")
+                        .value_name("N")
+                        .help("Stop the program after N statements and loop iterations, exiting with 3")
+                        .value_parser(value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("max-time")
+                        .long("max-time")
+                        .value_name("SECONDS")
+                        .help("Stop the program once it has run for this long, exiting with 4")
+                        .value_parser(parse_seconds),
+                )
+                .arg(
+                    Arg::new("max-call-depth")
+                        .long("max-call-depth")
+                        .value_name("N")
+                        .help("Stop the program when calls are nested N deep, exiting with 5 [default: 1000]")
+                        .value_parser(value_parser!(u64).range(1..=MAX_CALL_DEPTH_ALLOWED)),
+                )
+                .arg(
+                    Arg::new("max-output-bytes")
+                        .long("max-output-bytes")
+                        .value_name("N")
+                        .help("Stop the program before OUTPUT prints more than N bytes, exiting with 6")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("max-array-elements")
+                        .long("max-array-elements")
+                        .value_name("N")
+                        .help("Stop the program before it declares an array of more than N elements, exiting with 7")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("fs-root")
+                        .long("fs-root")
+                        .value_name("DIR")
+                        .help("Keep the files the program opens inside DIR, refusing absolute paths and paths leading out of it"),
+                )
+                .arg(
+                    Arg::new("fs-memory")
+                        .long("fs-memory")
+                        .help("Keep the files the program opens in memory, as --fs-root does on disk. They are gone once it ends")
+                        .action(ArgAction::SetTrue),
+                )
+                .group(ArgGroup::new("jail").args(["fs-root", "fs-memory"]))
+                .arg(
+                    Arg::new("fs-seed")
+                        .long("fs-seed")
+                        .value_name("DIR")
+                        .help("Copy the files in DIR into the --fs-root or --fs-memory files before the program runs")
+                        .requires("jail"),
+                ),
+        )
+        .subcommand(
+            Command::new("repl").about("Start an interactive session. Press Ctrl-D to exit."),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("debug")
+                .about("Run the program one statement at a time. Type help at the prompt for the commands.")
+                .arg(Arg::new("file").help("Filepath of the program").required(true)),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Report problems in the program without running it.")
+                .arg(Arg::new("file").help("Filepath of the program").required(true)),
+        )
+        .subcommand(
+            Command::new("test")
+                .about("Run every .pseudo program in a directory and compare its output with the .out file next to it. A .in file next to it is used as INPUT.")
+                .arg(Arg::new("dir").help("Directory of the programs").required(true)),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("Rewrite the program in the layout used by Cambridge mark schemes.")
+                .arg(Arg::new("file").help("Filepath of the program").required(true))
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .help("Only report whether the program is formatted, exiting with 1 if not")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("transpile")
+                .about("Write the program in another language, printing it to stdout.")
+                .arg(Arg::new("file").help("Filepath of the program").required(true))
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .help("Language to write the program in")
+                        .default_value("python")
+                        .value_parser(["python", "java", "vbnet"]),
+                ),
+        )
+        .subcommand(
+            Command::new("flowchart")
+                .about("Draw the flowchart of the program, or of one of its procedures or functions, printing it to stdout.")
+                .arg(Arg::new("file").help("Filepath of the program").required(true))
+                .arg(
+                    Arg::new("proc")
+                        .long("proc")
+                        .value_name("NAME")
+                        .help("Procedure or function to draw instead of the main program"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Graphviz DOT to lay out with dot, or an SVG picture")
+                        .default_value("dot")
+                        .value_parser(["dot", "svg"]),
+                ),
+        )
+        .subcommand(
+            Command::new("structure-chart")
+                .about("Draw the structure chart of the program, showing which procedures and functions call which, printing it to stdout.")
+                .arg(Arg::new("file").help("Filepath of the program").required(true))
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Graphviz DOT to lay out with dot, an SVG picture, or a list of the calls")
+                        .default_value("dot")
+                        .value_parser(["dot", "svg", "list"]),
+                ),
+        )
+        .subcommand(
+            Command::new("identifiers")
+                .about("Print an identifier table of every name the program declares, with its type, scope and the comment on its line.")
+                .arg(Arg::new("file").help("Filepath of the program").required(true))
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("Format of the table")
+                        .default_value("plain")
+                        .value_parser(["plain", "csv", "markdown"]),
+                ),
+        )
+        .subcommand(
+            Command::new("lsp")
+                .about("Start a language server on stdin and stdout for editors."),
+        )
+}
 
-    if let Some(command) = cli.subcommand_name() {
-        if let Some(args) = cli.subcommand_matches(command) {
-            let file: &String = args.get_one("file").expect("File name not provided");
-            match command {
-                "run" => execute(file),
-                _ => unreachable!(),
-            };
+// The program read from filepath, using the console for INPUT and OUTPUT
+fn interpreter(filepath: &str) -> Interpreter {
+    let buf = read_program(filepath);
+    Interpreter::new(filepath, &buf)
+        .stdin(BufReader::new(io::stdin()))
+        .stdout(io::stdout())
+}
+
+// The format a chart subcommand was asked for
+fn chart_format(args: &ArgMatches) -> ChartFormat {
+    match args
+        .get_one::<String>("format")
+        .map(|format| format.as_str())
+    {
+        Some("svg") => ChartFormat::Svg,
+        _ => ChartFormat::Dot,
+    }
+}
+
+// The format a table was asked for
+fn table_format(format: &str) -> TableFormat {
+    match format {
+        "csv" => TableFormat::Csv,
+        "markdown" => TableFormat::Markdown,
+        _ => TableFormat::Plain,
+    }
+}
+
+// The limits given to the run subcommand
+fn limits(args: &ArgMatches) -> Limits {
+    let mut limits = Limits {
+        max_steps: args.get_one::<u64>("max-steps").copied(),
+        max_time: args.get_one::<Duration>("max-time").copied(),
+        max_output_bytes: args.get_one::<usize>("max-output-bytes").copied(),
+        max_array_elements: args.get_one::<usize>("max-array-elements").copied(),
+        ..Default::default()
+    };
+    if let Some(depth) = args.get_one::<u64>("max-call-depth") {
+        limits.max_call_depth = *depth as usize;
+    }
+    limits
+}
+
+// The program kept to the files under --fs-root or in memory, when asked
+fn jail(interpreter: Interpreter, args: &ArgMatches) -> Interpreter {
+    let seed = args.get_one::<String>("fs-seed");
+    if let Some(root) = args.get_one::<String>("fs-root") {
+        match RootedFileSystem::new(Path::new(root)) {
+            Ok(fs) => interpreter.filesystem(seeded(Jail::new(fs), seed)),
+            Err(_) => {
+                eprintln!("Directory {} not found", root);
+                std::process::exit(1);
+            }
         }
+    } else if args.get_flag("fs-memory") {
+        interpreter.filesystem(seeded(Jail::new(MemoryFileSystem::new()), seed))
+    } else {
+        interpreter
     }
 }
 
-fn execute(filepath: &str) {
-    let mut buf = read_to_string(filepath).expect(format!("File {} not found", filepath).as_str());
-    let lines = buf
-        .clone()
-        .lines()
-        .map(|line| line.to_string())
-        .collect::<Vec<String>>();
-    SOURCE_FILE.with(|file| {
-        file.replace(SourceFile {
-            name: filepath.to_string(),
-            file: lines.clone(),
-        })
-    });
-    // Trim and end a newline for better error reporting
-    buf = buf.parse().unwrap();
-    buf.push('\n');
-    let mut lex = lexer::lexer(&mut buf.chars()).into_iter().peekable();
-    let ast = parser::parse_file(&mut lex);
-    executor::run(ast);
+fn seeded<F: FileSystem>(mut fs: F, seed: Option<&String>) -> F {
+    if let Some(dir) = seed {
+        if let Err(e) = file_system::seed(&mut fs, Path::new(dir)) {
+            eprintln!("Cannot copy the files in {}: {}", dir, e);
+            std::process::exit(1);
+        }
+    }
+    fs
+}
+
+fn parse_seconds(text: &str) -> Result<Duration, String> {
+    match text.parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(time)) if !time.is_zero() => Ok(time),
+        _ => Err("expected a number of seconds above 0".to_string()),
+    }
+}
+
+// Print every problem in the program and return them
+fn check(filepath: &str) -> Vec<PseudoError> {
+    let buf = read_program(filepath);
+    let interpreter = Interpreter::new(filepath, &buf);
+    let errors = interpreter.check();
+    print_errors(&errors, interpreter.source());
+    errors
+}
+
+// The repl and debugger run programs on the thread they are called from, which
+// needs room for calls nested as deep as the default limit allows
+fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .stack_size(Limits::default().stack_size())
+            .spawn_scoped(scope, f)
+            .unwrap();
+        match thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    })
+}
+
+// The text of a program, stopping with status 1 if it cannot be read
+fn read_program(file: &str) -> String {
+    read_to_string(file).unwrap_or_else(|_| {
+        eprintln!("File {} not found", file);
+        std::process::exit(1);
+    })
+}
+
+// Errors as annotated snippets of the program
+fn print_errors(errors: &[PseudoError], source: &SourceFile) {
+    for error in errors {
+        anstream::eprintln!("{}", error.render(source));
+    }
+}
+
+// Print the errors that stop a subcommand and exit with status 1
+fn fail(errors: &[PseudoError], source: &SourceFile) -> ! {
+    print_errors(errors, source);
+    std::process::exit(1);
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::execute;
+    use crate::debugger::debug;
+    use crate::repl::repl;
+    use crate::test_runner::run_tests;
+    use crate::{check, command, interpreter, lsp};
+    use pseudoengine::enums::{Node, Position};
+    use pseudoengine::executor::file_system::seed;
+    use pseudoengine::executor::MAX_CALL_DEPTH;
+    use pseudoengine::formatter::format;
+    use pseudoengine::tokens::TToken;
+    use pseudoengine::{
+        lexer, parser, vm, Backend, ChartFormat, ErrorKind, Interpreter, Jail, Language, Limit,
+        Limits, MemoryFileSystem, MemoryIo, Outcome, RootedFileSystem, SourceFile, TableFormat,
+        TraceTable,
+    };
+    use serde_json::{json, Value};
+    use std::cell::RefCell;
+    use std::fs::read_to_string;
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    fn execute(filepath: &str) -> Outcome {
+        interpreter(filepath).run()
+    }
+
+    // Output that can still be read once it has been handed over
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
     fn stmt_test() {
-        execute("tests/stmt_test.txt");
+        assert_eq!(execute("tests/stmt_test.txt").error, None);
     }
     #[test]
     fn builtin_func_test() {
-        execute("tests/builtin_test.txt");
+        assert_eq!(execute("tests/builtin.txt").error, None);
     }
     #[test]
     fn func_test() {
-        execute("tests/func_test.txt");
+        assert_eq!(execute("tests/func_test.txt").error, None);
     }
     #[test]
     fn file_test() {
-        execute("tests/file_test.txt");
+        assert_eq!(execute("tests/file_test.txt").error, None);
     }
     #[test]
     fn class_test() {
-        execute("tests/class_test.txt");
+        assert_eq!(execute("tests/class_test.txt").error, None);
     }
     #[test]
     fn pointer_test() {
-        execute("tests/pointer_test.txt");
+        assert_eq!(execute("tests/pointer_test.txt").error, None);
+    }
+    #[test]
+    fn error_test() {
+        let e = execute("tests/error_test.txt").error.unwrap();
+        assert_eq!(e.kind, ErrorKind::Runtime);
+        assert_eq!((e.pos.line_start, e.pos.pos_start), (3, 7));
+        assert_eq!(e.message, "'count' is not declared");
+    }
+    #[test]
+    fn syntax_error_test() {
+        let outcome = execute("tests/syntax_error_test.txt");
+        assert!(outcome.errors.iter().all(|e| e.kind == ErrorKind::Syntax));
+        let found: Vec<(usize, &str)> = outcome
+            .errors
+            .iter()
+            .map(|e| (e.pos.line_start, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, "Expected espression"),
+                (7, "'ENDIF' expected"),
+                (9, ": expected"),
+                (13, "Expected espression"),
+                (16, "')' expected. Newline found"),
+            ]
+        );
+        assert_eq!(outcome.output, "");
+    }
+    #[test]
+    fn check_test() {
+        let errors = check("tests/check_test.txt");
+        assert!(errors.iter().all(|e| e.kind == ErrorKind::Semantic));
+        let found: Vec<(usize, &str)> = errors
+            .iter()
+            .map(|e| (e.pos.line_start, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (5, "Missing return statement"),
+                (18, "Cannot assign type \"STRING\" to type \"INTEGER\""),
+                (19, "'limit' is a constant, it's value cannot be modified"),
+                (20, "'total' is not declared"),
+                (21, "Number of parameters doesnt match function definition"),
+                (22, "Cannot pass this value byref"),
+                (24, "Cannot assign type \"INTEGER\" to type \"STRING\""),
+                (
+                    24,
+                    "Cannot assign type \"STRING\" to parameter of type \"INTEGER\""
+                ),
+                (26, "Cannot assign type \"INTEGER\" to type \"STRING\""),
+            ]
+        );
+        for program in [
+            "tests/stmt_test.txt",
+            "tests/func_test.txt",
+            "tests/class_test.txt",
+            "tests/pointer_test.txt",
+        ] {
+            assert!(check(program).is_empty(), "{}", program);
+        }
+    }
+    #[test]
+    fn debug_test() {
+        let text = read_to_string("tests/debug_test.txt").unwrap();
+        let commands = "next\nbreak 4\ncontinue\nlocals\nbacktrace\nprint N * 10\ndelete 4\nnext\nnext\ncontinue\n";
+        let output = Buffer::default();
+        debug("debug_test", &text, Cursor::new(commands), output.clone()).unwrap();
+        let transcript = String::from_utf8(output.0.take()).unwrap();
+        let expected = [
+            "   1 | DECLARE Total : INTEGER",
+            "(debug)    7 | Total <- 0",
+            "(debug) Breakpoint set at line 4",
+            "(debug)    4 |     R <- N * N",
+            "(debug) n : INTEGER = 1",
+            "r : INTEGER = 0",
+            "(debug) #0 square at line 4",
+            "#1 main at line 9",
+            "(debug) 10",
+            "(debug) Breakpoint at line 4 removed",
+            "(debug)    5 |     RETURN R",
+            "(debug)    9 |     Total <- Total + Square(i)",
+            "(debug) 14",
+            "Program finished",
+        ];
+        assert_eq!(transcript.lines().collect::<Vec<&str>>(), expected);
+    }
+    #[test]
+    fn repl_test() {
+        let session = [
+            "DECLARE X : INTEGER",
+            "X <- 1",
+            "IF X > 0 THEN",
+            "    OUTPUT \"positive\"",
+            "ENDIF",
+            "PROCEDURE Greet(Name : STRING)",
+            "    OUTPUT \"Hello \", Name",
+            "ENDPROCEDURE",
+            "WHILE X < 3",
+            "    X <- X + 1",
+            "ENDWHILE",
+            "X",
+            "OUTPUT 1 +",
+            "CALL Greet(\"Ada\")",
+            "X <- Y",
+            "DECLARE N : STRING",
+            "INPUT N",
+            "Grace",
+            "CALL Greet(N)",
+        ]
+        .join("\n");
+        let output = Buffer::default();
+        repl(Cursor::new(session), output.clone());
+        let transcript = String::from_utf8(output.0.take()).unwrap();
+        let transcript = anstream::adapter::strip_str(&transcript).to_string();
+        let lines: Vec<&str> = transcript.lines().map(|line| line.trim_end()).collect();
+        let expected = [
+            // Blocks are run once their END keyword is reached
+            ">>> >>> >>> ... ... positive",
+            ">>> ... ... >>> ... ... >>> 3",
+            // A syntax error is reported and the session carries on
+            ">>> error: Expected espression",
+            "  --> <repl>:13:11",
+            "   |",
+            "13 | OUTPUT 1 +",
+            "   |           ^ Expected espression",
+            "   |",
+            // The procedure declared earlier is still there
+            ">>> Hello Ada",
+            ">>> error: 'y' is not declared",
+            "  --> <repl>:15:6",
+            "   |",
+            "15 | X <- Y",
+            "   |      ^ 'y' is not declared",
+            "   |",
+            // As are the variables, after a runtime error
+            ">>> >>> >>> Hello Grace",
+            ">>>",
+        ];
+        assert_eq!(lines, expected);
+    }
+    #[test]
+    fn trace_test() {
+        let text = read_to_string("tests/trace_test.txt").unwrap();
+        let table = TraceTable::new();
+        let outcome = Interpreter::new("trace", &text).hook(table.clone()).run();
+        assert_eq!(outcome.error, None);
+        assert_eq!(
+            table.render(TableFormat::Csv),
+            "Line,Total,Count,Nums[1],Nums[2],Nums[3],OUTPUT\n4,0,,,,,\n5,,1,,,,\n6,,,1,,,\n7,1,,,,,\n5,,2,,,,\n6,,,,4,,\n7,5,,,,,\n5,,3,,,,\n6,,,,,9,\n7,14,,,,,\n9,,,,,,Total: 14\n"
+        );
+        // The format is only taken when given with =, so the file can come after
+        for (args, format) in [
+            (vec!["run", "--trace-table", "prog.txt"], "plain"),
+            (vec!["run", "--trace-table=csv", "prog.txt"], "csv"),
+            (vec!["run", "prog.txt", "--trace-table"], "plain"),
+        ] {
+            let cli = command()
+                .try_get_matches_from([&["pseudoengine"], args.as_slice()].concat())
+                .unwrap();
+            let (_, args) = cli.subcommand().unwrap();
+            assert_eq!(args.get_one::<String>("file").unwrap(), "prog.txt");
+            assert_eq!(args.get_one::<String>("trace-table").unwrap(), format);
+        }
+
+        let table = TraceTable::new().select(&["Nums", "Count"]);
+        Interpreter::new("trace", &text).hook(table.clone()).run();
+        assert_eq!(
+            table.render(TableFormat::Markdown),
+            "| Line | Nums[1] | Nums[2] | Nums[3] | Count |\n| --- | --- | --- | --- | --- |\n| 5 |  |  |  | 1 |\n| 6 | 1 |  |  |  |\n| 5 |  |  |  | 2 |\n| 6 |  | 4 |  |  |\n| 5 |  |  |  | 3 |\n| 6 |  |  | 9 |  |\n"
+        );
+    }
+    #[test]
+    fn unicode_test() {
+        let outcome = execute("tests/unicode_test.txt");
+        assert_eq!(outcome.error, None);
+        assert_eq!(outcome.output, "Ada scored 10.5 of 10.5\n");
+
+        // Columns are bytes, '←' and the curly quotes take three each
+        let text = "DECLARE Name : STRING\nName ← “Ada” + Missing";
+        let e = Interpreter::new("unicode", text).run().error.unwrap();
+        assert_eq!(
+            (e.pos.line_start, e.pos.pos_start, e.pos.pos_end),
+            (2, 21, 28)
+        );
+        let rendered = e.render(&SourceFile::new("unicode", text));
+        let rendered = anstream::adapter::strip_str(&rendered).to_string();
+        let caret = rendered.lines().find(|line| line.contains('^')).unwrap();
+        let code = rendered
+            .lines()
+            .find(|line| line.contains("Missing"))
+            .unwrap();
+        assert_eq!(
+            caret.chars().position(|c| c == '^'),
+            code.chars().position(|c| c == 'M')
+        );
+
+        // String builtins count characters rather than bytes
+        let program = "DECLARE W : STRING\nW <- \"héllo\"\nOUTPUT LENGTH(W), \" \", LEFT(W, 2), \" \", RIGHT(\"é\", 1), \" \", MID(W, 2, 3)\nOUTPUT RIGHT(W, 6)";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("unicode", program).backend(backend).run();
+            assert_eq!(outcome.output, "5 hé é éll\n");
+            assert_eq!(
+                outcome.error.unwrap().message,
+                "Length for 'RIGHT' function cannot exceed string length"
+            );
+        }
+
+        // Quotes close only with their partner, so other kinds can sit inside
+        let literals = |text: &str| -> Vec<TToken> {
+            lexer::lexer(&mut format!("OUTPUT {}\n", text).chars()).unwrap()[1..]
+                .iter()
+                .map(|token| token.t.clone())
+                .filter(|t| !matches!(t, TToken::Newline | TToken::EOF))
+                .collect()
+        };
+        assert_eq!(
+            literals("\"say “hi” now\""),
+            vec![TToken::StringLit("say “hi” now".to_string())]
+        );
+        assert_eq!(
+            literals("“say \"hi\" now”"),
+            vec![TToken::StringLit("say \"hi\" now".to_string())]
+        );
+        assert_eq!(
+            literals("'‘', ‘'’"),
+            vec![TToken::CharLit('‘'), TToken::Comma, TToken::CharLit('\'')]
+        );
+    }
+    #[test]
+    fn char_test() {
+        let outcome = execute("tests/char_test.txt");
+        assert_eq!(outcome.error, None);
+        assert_eq!(
+            outcome.output,
+            "Jaxabc\nX2\nordered\ncase matters\n65 B z\nfirst half\npromoted\n"
+        );
+        assert!(check("tests/char_test.txt").is_empty());
+
+        let message = |text: &str| {
+            let interpreter = Interpreter::new("char", text);
+            let checked: Vec<String> = interpreter.check().into_iter().map(|e| e.message).collect();
+            let ran = interpreter
+                .run()
+                .error
+                .map(|e| (e.pos.line_start, e.message));
+            (checked, ran)
+        };
+        let too_long = "Cannot assign a STRING of 2 characters to a CHAR";
+        // Literals are caught before running, other strings when the line runs
+        assert_eq!(
+            message("DECLARE C : CHAR\nC <- \"AB\""),
+            (vec![too_long.to_string()], Some((2, too_long.to_string())))
+        );
+        assert_eq!(
+            message("DECLARE C : CHAR\nDECLARE S : STRING\nS <- \"AB\"\nC <- S"),
+            (vec![], Some((4, too_long.to_string())))
+        );
+        assert_eq!(
+            message("DECLARE C : CHAR\nC <- 'AB'").1.unwrap().1,
+            "CHAR literal must be exactly one character, use \" for a STRING"
+        );
+        assert_eq!(
+            message("DECLARE C : CHAR\nOUTPUT C < 1").0,
+            vec!["Cannot compare types CHAR AND INTEGER"]
+        );
+
+        // A CHAR is a STRING of one character to = and <> and wherever a STRING is stored
+        let program = "DECLARE S : STRING\nS <- CHR(65)\nOUTPUT S, CHR(65) = \"A\", 'B' <> \"B\"\nPROCEDURE Show(T : STRING)\n  OUTPUT T & \"!\"\nENDPROCEDURE\nCALL Show('C')";
+        assert!(Interpreter::new("promote", program).check().is_empty());
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("promote", program).backend(backend).run();
+            assert_eq!(outcome.error, None);
+            assert_eq!(outcome.output, "ATRUEFALSE\nC!\n");
+        }
+    }
+    #[test]
+    fn fmt_test() {
+        let text = read_to_string("tests/fmt_test.txt").unwrap();
+        let expected = read_to_string("tests/fmt_test.out").unwrap();
+        assert_eq!(format(&text).unwrap(), expected);
+        assert_eq!(format(&expected).unwrap(), expected);
+        for program in ["tests/stmt_test.txt", "tests/class_test.txt"] {
+            let text = read_to_string(program).unwrap();
+            let formatted = format(&text).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted, "{}", program);
+            let outcome = Interpreter::new(program, &formatted).run();
+            assert_eq!(outcome.output, execute(program).output, "{}", program);
+        }
+        let errors = format(&read_to_string("tests/syntax_error_test.txt").unwrap()).unwrap_err();
+        assert_eq!(errors.len(), 5);
+    }
+    #[test]
+    fn lsp_test() {
+        let text = read_to_string("tests/lsp_test.txt").unwrap();
+        let uri = "file:///lsp_test.txt";
+        let doc = json!({ "uri": uri });
+        let at = |line: u64, character: u64| json!({ "textDocument": doc, "position": { "line": line, "character": character } });
+        let requests = [
+            json!({ "id": 1, "method": "initialize", "params": {} }),
+            json!({ "method": "initialized", "params": {} }),
+            json!({ "method": "textDocument/didOpen", "params": { "textDocument": { "uri": uri, "text": text } } }),
+            json!({ "id": 2, "method": "textDocument/completion", "params": at(18, 0) }),
+            json!({ "id": 3, "method": "textDocument/hover", "params": at(10, 20) }),
+            json!({ "id": 4, "method": "textDocument/hover", "params": at(15, 12) }),
+            json!({ "id": 5, "method": "textDocument/definition", "params": at(19, 7) }),
+            json!({ "id": 6, "method": "textDocument/documentSymbol", "params": { "textDocument": doc } }),
+            json!({ "method": "textDocument/didChange", "params": { "textDocument": doc, "contentChanges": [{ "text": "IF TRUE\nENDIF" }] } }),
+            json!({ "id": 7, "method": "shutdown" }),
+            json!({ "method": "exit" }),
+        ];
+        let mut input = String::new();
+        for request in requests {
+            let body = request.to_string();
+            input += &format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        }
+        let mut output = Vec::new();
+        lsp::serve(Cursor::new(input), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let messages: Vec<Value> = output
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|message| serde_json::from_str(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect();
+        assert_eq!(messages.len(), 9);
+        assert_eq!(messages[0]["result"]["capabilities"]["hoverProvider"], true);
+
+        let diagnostics = &messages[1]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(
+            diagnostics[0]["message"],
+            "Cannot assign type \"STRING\" to type \"INTEGER\""
+        );
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 18, "character": 0 })
+        );
+
+        let labels: Vec<&str> = messages[2]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        for label in [
+            "DECLARE",
+            "ENDPROCEDURE",
+            "Scores",
+            "Total",
+            "Point",
+            "Count",
+            "Sum",
+        ] {
+            assert!(labels.contains(&label), "{}", label);
+        }
+
+        let hover = |i: usize| messages[i]["result"]["contents"]["value"].as_str().unwrap();
+        assert_eq!(hover(3), "```\nCONSTANT Bonus = 5\n```");
+        assert_eq!(
+            hover(4),
+            "```\nFUNCTION Total(Count : INTEGER) RETURNS INTEGER\n```"
+        );
+        assert_eq!(
+            messages[5]["result"]["range"],
+            json!({ "start": { "line": 14, "character": 10 }, "end": { "line": 14, "character": 14 } })
+        );
+
+        let symbols: Vec<(&str, u64)> = messages[6]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| (s["name"].as_str().unwrap(), s["kind"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("Scores", 13),
+                ("Bonus", 14),
+                ("Point", 23),
+                ("Total", 12),
+                ("Show", 12)
+            ]
+        );
+        let total = &messages[6]["result"][3];
+        assert_eq!(total["children"][0]["detail"], "Count : INTEGER");
+        assert_eq!(total["children"][1]["detail"], "DECLARE Sum : INTEGER");
+
+        assert_eq!(
+            messages[7]["params"]["diagnostics"][0]["message"],
+            "'THEN' expected. Newline found"
+        );
+        assert_eq!(messages[8]["id"], 7);
+
+        // Every keyword has a token, the lexer reads no other word as one
+        for keyword in lexer::KEYWORDS {
+            let tokens = lexer::lexer(&mut format!("{}\n", keyword).chars()).unwrap();
+            assert!(!matches!(tokens[0].t, TToken::Identifier(_)), "{}", keyword);
+        }
+    }
+    #[test]
+    fn interpreter_test() {
+        let outcome =
+            Interpreter::new("sum", "DECLARE x : INTEGER\nx <- 2\nOUTPUT x + 1, \"!\"").run();
+        assert!(outcome.success());
+        assert_eq!(outcome.output, "3!\n");
+        assert_eq!(outcome.status, 0);
+    }
+    #[test]
+    fn memory_io_test() {
+        let io = MemoryIo::new("Ada\n36");
+        io.files.insert("names.txt", "Grace\n");
+        let program = "DECLARE Name : STRING\nDECLARE Age : INTEGER\nINPUT Name\nINPUT Age\nOPENFILE \"names.txt\" FOR APPEND\nWRITEFILE \"names.txt\", Name\nCLOSEFILE \"names.txt\"\nOUTPUT Name, \" \", Age + 1";
+        let outcome = Interpreter::new("memory", program).io(io.clone()).run();
+        assert_eq!(outcome.error, None);
+        assert_eq!(io.output(), "Ada 37\n");
+        assert_eq!(io.files.get("names.txt").unwrap(), "Grace\nAda\n");
+    }
+    #[test]
+    fn jail_test() {
+        let program = "DECLARE Line : STRING\nOPENFILE \"./data/../names.txt\" FOR READ\nREADFILE \"./data/../names.txt\", Line\nOUTPUT Line\nOPENFILE \"data/answer.txt\" FOR READ\nREADFILE \"data/answer.txt\", Line\nOUTPUT Line\nOPENFILE \"out/copy.txt\" FOR WRITE\nWRITEFILE \"out/copy.txt\", Line";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let files = MemoryFileSystem::new();
+            let mut jail = Jail::new(files.clone());
+            assert_eq!(seed(&mut jail, Path::new("tests/jail")).unwrap(), 2);
+            let outcome = Interpreter::new("jail", program)
+                .filesystem(jail)
+                .backend(backend)
+                .run();
+            assert_eq!(outcome.error, None);
+            assert_eq!(outcome.output, "Ada\n42\n");
+            assert_eq!(files.get("out/copy.txt").unwrap(), "42\n");
+
+            // Paths leaving the jail are refused before anything is opened
+            for (path, reason) in [
+                (
+                    "../names.txt",
+                    "the path leads outside the file system root",
+                ),
+                (
+                    "data/../../names.txt",
+                    "the path leads outside the file system root",
+                ),
+                ("/etc/passwd", "absolute paths are not allowed"),
+                ("C:/names.txt", "absolute paths are not allowed"),
+            ] {
+                let program = format!("OPENFILE \"{}\" FOR WRITE", path);
+                let outcome = Interpreter::new("escape", &program)
+                    .filesystem(Jail::new(MemoryFileSystem::new()))
+                    .backend(backend)
+                    .run();
+                let error = outcome.error.unwrap();
+                assert_eq!(
+                    error.message,
+                    format!("File {} cannot be opened, {}", path, reason)
+                );
+            }
+        }
+
+        // On disk the files stay inside the root
+        let root = std::env::temp_dir().join(format!("pseudoengine-jail-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut jail = Jail::new(RootedFileSystem::new(&root).unwrap());
+        seed(&mut jail, Path::new("tests/jail")).unwrap();
+        let outcome = Interpreter::new("jail", program).filesystem(jail).run();
+        assert_eq!(outcome.output, "Ada\n42\n");
+        assert_eq!(read_to_string(root.join("out/copy.txt")).unwrap(), "42\n");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    #[test]
+    fn vm_test() {
+        // Both backends print the same and fail the same way
+        let programs = [
+            "tests/stmt_test.txt",
+            "tests/builtin.txt",
+            "tests/func_test.txt",
+            "tests/char_test.txt",
+            "tests/error_test.txt",
+            "tests/unicode_test.txt",
+            "tests/golden/factorial.pseudo",
+            "tests/golden/grades.pseudo",
+            "tests/golden/undeclared.pseudo",
+        ];
+        for path in programs {
+            let text = read_to_string(path).unwrap();
+            let input = read_to_string(Path::new(path).with_extension("in")).unwrap_or_default();
+            let mut ast = text.clone();
+            ast.push('\n');
+            let tokens = lexer::lexer(&mut ast.chars()).unwrap();
+            let nodes = parser::parse_program(&mut tokens.into_iter().peekable()).unwrap();
+            assert!(vm::compile(&nodes).is_ok(), "{}", path);
+
+            let run = |backend| {
+                Interpreter::new(path, &text)
+                    .io(MemoryIo::new(&input))
+                    .backend(backend)
+                    .run()
+            };
+            let (tree, machine) = (run(Backend::Tree), run(Backend::Vm));
+            assert_eq!(tree.output, machine.output, "{}", path);
+            assert_eq!(tree.error, machine.error, "{}", path);
+        }
+
+        // Programs with classes are left to the tree-walker
+        let text = read_to_string("tests/class_test.txt").unwrap() + "\n";
+        let tokens = lexer::lexer(&mut text.chars()).unwrap();
+        let nodes = parser::parse_program(&mut tokens.into_iter().peekable()).unwrap();
+        assert!(matches!(
+            vm::compile(&nodes),
+            Err(vm::CompileError::Unsupported { .. })
+        ));
+        let outcome = interpreter("tests/class_test.txt")
+            .backend(Backend::Vm)
+            .run();
+        assert_eq!(outcome.error, None);
+
+        let program = "DECLARE A : ARRAY[1:3] OF INTEGER\nPROCEDURE Fill(BYREF X : ARRAY[1:3] OF INTEGER)\n  FOR I <- 1 TO 3\n    X[I] <- X[I] + I * 10\n  NEXT I\nENDPROCEDURE\nCALL Fill(A)\nCALL Fill(A)\nOUTPUT A[1], A[2], A[3]\nA[4] <- 1";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("byref", program).backend(backend).run();
+            assert_eq!(outcome.output, "204060\n");
+            assert_eq!(outcome.status, 1);
+            assert_eq!(outcome.error.unwrap().pos.line_start, 10);
+        }
+
+        // Any other failure to compile is an error rather than a reason to fall back
+        let pos = Position::new(1, 1, 0, 1);
+        let range = Box::new(Node::Range {
+            start: Box::new(Node::Int { val: 1, pos }),
+            end: Box::new(Node::Int { val: 2, pos }),
+            pos,
+        });
+        let nodes = vec![Box::new(Node::Main {
+            children: vec![range],
+        })];
+        assert!(matches!(
+            vm::compile(&nodes),
+            Err(vm::CompileError::Invalid(_))
+        ));
+    }
+    #[test]
+    fn value_test() {
+        // Numbers compare by value whatever their type, on both backends
+        let program = "DECLARE R : REAL\nR <- 2.0\nOUTPUT 1 = 1.0, 2 <> R, (0.5 * 2) = 1\nDECLARE N : INTEGER\nN <- 1\nCASE OF N\n  1.0 : OUTPUT \"one\"\n  OTHERWISE : OUTPUT \"other\"\nENDCASE\nTYPE Colour = (Red, Green)\nDECLARE C : Colour\nOUTPUT C = Red\nC <- Green\nOUTPUT C = Green, C <> Red";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("value", program).backend(backend).run();
+            assert_eq!(outcome.error, None);
+            assert_eq!(outcome.output, "TRUEFALSETRUE\none\nFALSE\nTRUETRUE\n");
+        }
+        // INTEGERs too big for a REAL to hold exactly still compare exactly, with
+        // each other and with whole REALs
+        let program = "DECLARE A, B : INTEGER\nA <- 9007199254740993\nB <- 9007199254740992\nOUTPUT A = B, A > B, B < A, A <> B\nOUTPUT A = 9007199254740992.0, B = 9007199254740992.0, A > 9007199254740992.0, 9007199254740992.0 < A, A < 0.5";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("big", program).backend(backend).run();
+            assert_eq!(outcome.error, None);
+            assert_eq!(
+                outcome.output,
+                "FALSETRUETRUETRUE\nFALSETRUETRUETRUEFALSE\n"
+            );
+        }
+        let outcome = Interpreter::new("value", "OUTPUT \"1\" = 1").run();
+        assert_eq!(
+            outcome.error.unwrap().message,
+            "Cannot compare types STRING AND INTEGER"
+        );
+
+        // A FOR loop counts in the variable a BYREF parameter was given
+        let program = "DECLARE N : INTEGER\nPROCEDURE Count(BYREF I : INTEGER)\n  FOR I <- 1 TO 3\n  NEXT I\nENDPROCEDURE\nCALL Count(N)\nOUTPUT N";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("count", program).backend(backend).run();
+            assert_eq!(outcome.error, None);
+            assert_eq!(outcome.output, "3\n");
+        }
+    }
+    #[test]
+    fn scope_test() {
+        // A procedure sees the globals but not the locals of its caller
+        let program = "DECLARE Total : INTEGER\nPROCEDURE Inner()\n  OUTPUT Total\n  OUTPUT Count\nENDPROCEDURE\nPROCEDURE Outer()\n  DECLARE Count : INTEGER\n  Count <- 3\n  CALL Inner()\nENDPROCEDURE\nTotal <- 5\nCALL Outer()";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("scope", program).backend(backend).run();
+            assert_eq!(outcome.output, "5\n");
+            let error = outcome.error.unwrap();
+            assert_eq!(
+                error.message,
+                "'count' is local to outer, pass it as a parameter to use it in inner"
+            );
+            assert_eq!(error.pos.line_start, 4);
+        }
+
+        // Arguments to a method are worked out where it is called
+        let program = "CLASS Counter\n  PRIVATE Count : INTEGER\n  PUBLIC PROCEDURE NEW()\n    Count <- 0\n  ENDPROCEDURE\n  PUBLIC PROCEDURE Add(N : INTEGER)\n    Count <- Count + N\n  ENDPROCEDURE\n  PUBLIC FUNCTION Total() RETURNS INTEGER\n    RETURN Count\n  ENDFUNCTION\nENDCLASS\nPROCEDURE Run()\n  DECLARE Count : INTEGER\n  DECLARE C : Counter\n  C <- NEW Counter()\n  Count <- 7\n  C.Add(Count)\n  C.Add(Count)\n  OUTPUT C.Total(), \" \", Count\nENDPROCEDURE\nCALL Run()";
+        let outcome = Interpreter::new("method", program).run();
+        assert_eq!(outcome.error, None);
+        assert_eq!(outcome.output, "14 7\n");
+    }
+    #[test]
+    fn hoist_test() {
+        // Subroutines and types can be used above where they are defined
+        let program = "CALL Greet(\"Ada\")\nDECLARE P : Point\nP.Size.Width <- 3\nOUTPUT Double(P.Size.Width)\nTYPE Point\n  DECLARE Size : Box\nENDTYPE\nTYPE Box\n  DECLARE Width : INTEGER\nENDTYPE\nPROCEDURE Greet(Name : STRING)\n  OUTPUT \"Hi \", Name\nENDPROCEDURE\nFUNCTION Double(N : INTEGER) RETURNS INTEGER\n  RETURN N * 2\nENDFUNCTION";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("hoist", program).backend(backend).run();
+            assert_eq!(outcome.error, None);
+            assert_eq!(outcome.output, "Hi Ada\n6\n");
+        }
+
+        // A name defined twice is reported before anything runs
+        let program = "OUTPUT \"start\"\nPROCEDURE A()\nENDPROCEDURE\nFUNCTION A() RETURNS INTEGER\n  RETURN 1\nENDFUNCTION";
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("twice", program).backend(backend).run();
+            assert_eq!(outcome.output, "");
+            let error = outcome.error.unwrap();
+            assert_eq!(error.message, "'a' is already declared");
+            assert_eq!(error.pos.line_start, 4);
+        }
+    }
+    #[test]
+    fn call_stack_test() {
+        // An error inside a call lists the calls still running, with their arguments
+        let program = "FUNCTION Fact(N : INTEGER) RETURNS INTEGER\n  IF N = 0 THEN\n    RETURN LENGTH(LEFT(\"ab\", 5))\n  ENDIF\n  RETURN N * Fact(N - 1)\nENDFUNCTION\nPROCEDURE Greet(Name : STRING, C : CHAR)\n  OUTPUT Fact(2)\nENDPROCEDURE\nCALL Greet(\"bob\", 'x')";
+        let mut errors = vec![];
+        for backend in [Backend::Tree, Backend::Vm] {
+            let outcome = Interpreter::new("stack", program).backend(backend).run();
+            errors.push(outcome.error.unwrap());
+        }
+        assert_eq!(errors[0], errors[1]);
+        let frames: Vec<String> = errors[0].backtrace.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            frames,
+            [
+                "fact(0) called at line 5",
+                "fact(1) called at line 5",
+                "fact(2) called at line 8",
+                "greet(\"bob\", 'x') called at line 10"
+            ]
+        );
+
+        // Unbounded recursion is stopped with an error rather than crashing, even
+        let program = "FUNCTION Down(N : INTEGER) RETURNS INTEGER\n  RETURN Down(N + 1)\nENDFUNCTION\nOUTPUT Down(0)";
+        // on a thread with no more than the usual stack
+        let errors = std::thread::spawn(move || {
+            [Backend::Tree, Backend::Vm].map(|backend| {
+                Interpreter::new("down", program)
+                    .backend(backend)
+                    .run()
+                    .error
+                    .unwrap()
+            })
+        })
+        .join()
+        .unwrap();
+        assert_eq!(errors[0], errors[1]);
+        assert!(errors[0]
+            .message
+            .starts_with("Stack overflow, calls were nested 1000 deep"));
+        assert_eq!(errors[0].backtrace.len(), MAX_CALL_DEPTH);
+        let rendered = errors[0].render(&SourceFile::new("down", program));
+        assert!(rendered.contains("  down(999) called at line 2\n  ... 997 more calls of down from there\n  down(1) called at line 2\n  down(0) called at line 4"));
+    }
+    #[test]
+    fn limits_test() {
+        let run = |program: &str, limits: Limits, backend: Backend| {
+            Interpreter::new("limits", program)
+                .limits(limits)
+                .backend(backend)
+                .run()
+        };
+        for backend in [Backend::Tree, Backend::Vm] {
+            // A loop with nothing in it still counts as a step each time round
+            let limits = Limits {
+                max_steps: Some(50),
+                ..Limits::default()
+            };
+            let outcome = run("WHILE TRUE\nENDWHILE", limits, backend);
+            let error = outcome.error.unwrap();
+            assert_eq!(error.kind, ErrorKind::Limit(Limit::Steps));
+            assert_eq!(outcome.status, 3);
+
+            let limits = Limits {
+                max_time: Some(Duration::from_millis(50)),
+                ..Limits::default()
+            };
+            let outcome = run("FOR I <- 1 TO 2 STEP 0\nNEXT I", limits, backend);
+            assert_eq!(outcome.error.unwrap().kind, ErrorKind::Limit(Limit::Time));
+            assert_eq!(outcome.status, 4);
+
+            let limits = Limits {
+                max_call_depth: 10,
+                ..Limits::default()
+            };
+            let program = "PROCEDURE P()\n  CALL P()\nENDPROCEDURE\nCALL P()";
+            let outcome = run(program, limits, backend);
+            let error = outcome.error.unwrap();
+            assert_eq!(error.kind, ErrorKind::Limit(Limit::CallDepth));
+            assert_eq!(error.backtrace.len(), 10);
+            assert_eq!(outcome.status, 5);
+
+            // The line going over the limit is not printed
+            let limits = Limits {
+                max_output_bytes: Some(10),
+                ..Limits::default()
+            };
+            let outcome = run("REPEAT\n  OUTPUT \"abc\"\nUNTIL FALSE", limits, backend);
+            assert_eq!(outcome.output, "abc\nabc\n");
+            assert_eq!(outcome.error.unwrap().kind, ErrorKind::Limit(Limit::Output));
+            assert_eq!(outcome.status, 6);
+
+            let limits = Limits {
+                max_array_elements: Some(1000),
+                ..Limits::default()
+            };
+            let program =
+                "DECLARE A : ARRAY[1:10, 1:100] OF INTEGER\nDECLARE B : ARRAY[0:1000] OF INTEGER";
+            let outcome = run(program, limits, backend);
+            let error = outcome.error.unwrap();
+            assert_eq!(
+                error.message,
+                "ARRAY of 1001 elements is over the limit of 1000"
+            );
+            assert_eq!(error.pos.line_start, 2);
+            assert_eq!(outcome.status, 7);
+
+            // Other errors keep their status
+            let outcome = run("OUTPUT X", Limits::default(), backend);
+            assert_eq!(outcome.status, 1);
+        }
+    }
+    // Whether a command runs at all, to skip what needs tools that are not installed
+    fn installed(command: &str, arg: &str) -> bool {
+        std::process::Command::new(command)
+            .arg(arg)
+            .output()
+            .is_ok_and(|out| out.status.success())
+    }
+    #[test]
+    fn transpile_test() {
+        // Programs transpiled to Python and Java print what the interpreter prints,
+        // when there is a python3 or a JDK to run them with
+        let languages = [
+            (Language::Python, "# ", installed("python3", "--version")),
+            (
+                Language::Java,
+                "// ",
+                installed("javac", "-version") && installed("java", "-version"),
+            ),
+            (Language::VbNet, "' ", false),
+        ];
+        let programs = [
+            ("tests/transpile_test.txt", ""),
+            ("tests/stmt_test.txt", ""),
+            ("tests/builtin.txt", ""),
+            ("tests/func_test.txt", ""),
+            ("tests/class_test.txt", ""),
+            ("tests/char_test.txt", ""),
+            ("tests/test.txt", ""),
+            ("tests/trace_test.txt", ""),
+            ("tests/unicode_test.txt", ""),
+            ("tests/golden/factorial.pseudo", ""),
+            ("tests/golden/grades.pseudo", "tests/golden/grades.in"),
+        ];
+        for (i, (path, input)) in programs.into_iter().enumerate() {
+            let text = read_to_string(path).unwrap();
+            let input = match input {
+                "" => String::new(),
+                input => read_to_string(input).unwrap(),
+            };
+            for (language, comment, runnable) in languages {
+                let code = Interpreter::new(path, &text).transpile(language).unwrap();
+                assert!(code.starts_with(&format!("{}Transpiled from {}", comment, path)));
+                if !runnable {
+                    continue;
+                }
+
+                // Each runs in a directory of its own, as they write files
+                let root = std::env::temp_dir().join(format!(
+                    "pseudoengine-transpile-{}-{}-{}",
+                    std::process::id(),
+                    i,
+                    comment.len()
+                ));
+                std::fs::create_dir_all(root.join("interpreter")).unwrap();
+                std::fs::create_dir_all(root.join("program")).unwrap();
+                let expected = Interpreter::new(path, &text)
+                    .stdin(Cursor::new(input.clone()))
+                    .filesystem(RootedFileSystem::new(&root.join("interpreter")).unwrap())
+                    .run();
+                assert_eq!(expected.error, None, "{}", path);
+                let mut command = match language {
+                    Language::Java => {
+                        // javac wants the file named after the class
+                        let class = code
+                            .lines()
+                            .find_map(|line| line.strip_prefix("public class "))
+                            .and_then(|line| line.split(' ').next())
+                            .unwrap();
+                        let source = root.join(format!("{}.java", class));
+                        std::fs::write(&source, &code).unwrap();
+                        let compiled = std::process::Command::new("javac")
+                            .args(["-encoding", "UTF-8", "-d"])
+                            .arg(root.join("classes"))
+                            .arg(&source)
+                            .output()
+                            .unwrap();
+                        assert!(
+                            compiled.status.success(),
+                            "{}\n{}",
+                            path,
+                            String::from_utf8_lossy(&compiled.stderr)
+                        );
+                        let mut command = std::process::Command::new("java");
+                        command.arg("-cp").arg(root.join("classes")).arg(class);
+                        command
+                    }
+                    _ => {
+                        std::fs::write(root.join("program.py"), &code).unwrap();
+                        let mut command = std::process::Command::new("python3");
+                        command.arg(root.join("program.py"));
+                        command
+                    }
+                };
+                let mut child = command
+                    .current_dir(root.join("program"))
+                    .stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped())
+                    .spawn()
+                    .unwrap();
+                child
+                    .stdin
+                    .take()
+                    .unwrap()
+                    .write_all(input.as_bytes())
+                    .unwrap();
+                let out = child.wait_with_output().unwrap();
+                assert_eq!(
+                    String::from_utf8_lossy(&out.stdout),
+                    expected.output,
+                    "{}\n{}",
+                    path,
+                    String::from_utf8_lossy(&out.stderr)
+                );
+                std::fs::remove_dir_all(&root).unwrap();
+            }
+        }
+
+        // Lower bounds, records, classes and BYREF are written the Python way
+        let code = Interpreter::new(
+            "transpile",
+            &read_to_string("tests/transpile_test.txt").unwrap(),
+        )
+        .transpile(Language::Python)
+        .unwrap();
+        for line in [
+            "@dataclass\nclass Point:\n    x: int = 0",
+            "class Savings(Account):",
+            "        super().__init__(given_owner)",
+            "    def _get_rate(self)",
+            "grid[i - 1][j - 2] = ",
+            "temperatures[2] = temperatures[0] * 2",
+            "first, second = swap(first, second)",
+            "take_one_result, left = take_one(left)",
+            "q = copy.deepcopy(p)",
+            "files[\"points.dat\"].put_record(saved)",
+            "print(f\"{DAY(day)}/{MONTH(day)}/{YEAR(day)}",
+        ] {
+            assert!(code.contains(line), "{}", line);
+        }
+
+        // Java and VB.NET keep to their own conventions as well
+        let text = read_to_string("tests/transpile_test.txt").unwrap();
+        let code = Interpreter::new("transpile", &text)
+            .transpile(Language::Java)
+            .unwrap();
+        for line in [
+            "public class Transpile {",
+            "static class Savings extends Account {",
+            "super(givenOwner);",
+            "grid[i - 1][j - 2] = ",
+            "static void swap(IntRef a, IntRef b) {",
+            "q = p.copy();",
+        ] {
+            assert!(code.contains(line), "{}", line);
+        }
+        let code = Interpreter::new("transpile", &text)
+            .transpile(Language::VbNet)
+            .unwrap();
+        for line in [
+            "Module Transpile",
+            "    Class Savings\n        Inherits Account",
+            "            MyBase.New(givenOwner)",
+            "        Public Overridable Function Describe() As String",
+            "    Sub Swap(ByRef a As Integer, ByRef b As Integer)",
+            "                grid(i - 1, j - 2) = i * (10 + j)",
+            "        q = p.Copy()",
+            "        Console.WriteLine(7 \\ 2 & \" \" & Show(7.0 / 2)",
+            "        Loop Until k > 50",
+            "                Case 90 To 100",
+            "        PutRecord(\"points.dat\", saved)",
+            "End Module",
+        ] {
+            assert!(code.contains(line), "{}", line);
+        }
+
+        // Programs with problems are not transpiled
+        let errors = Interpreter::new("broken", "x <- 1")
+            .transpile(Language::Python)
+            .unwrap_err();
+        assert_eq!(errors[0].message, "'x' is not declared");
+    }
+    #[test]
+    fn flowchart_test() {
+        let text = read_to_string("tests/flowchart_test.txt").unwrap();
+        let interpreter = Interpreter::new("flowchart", &text);
+        let dot = interpreter
+            .flowchart(None)
+            .unwrap()
+            .render(ChartFormat::Dot);
+        for line in [
+            "digraph \"flowchart\" {",
+            "n0 [label=\"START\", shape=box, style=rounded];",
+            "n3 [label=\"INPUT Mark\", shape=parallelogram];",
+            "n4 [label=\"Mark <> 999?\", shape=diamond];",
+            "n9 [label=\"CALL Grade(Mark)\", shape=box];",
+            "n12 [label=\"Mark <- 1\", shape=box];",
+            "n13 [label=\"Mark <= 10?\", shape=diamond];",
+            "n15 [label=\"Mark <- Mark + 3\", shape=box];",
+            "n19 [label=\"STOP\", shape=box, style=rounded];",
+            // Both branches of the IF meet again
+            "n4 -> n5 [label=\"Yes\"];",
+            "n5 -> n8 [label=\"No\"];",
+            "n7 -> n9;",
+            "n8 -> n9;",
+            // Loops go back to their condition, or to the start of a REPEAT
+            "n11 -> n4;",
+            "n4 -> n12 [label=\"No\"];",
+            "n15 -> n13;",
+            "n17 -> n16 [label=\"No\"];",
+            "n17 -> n18 [label=\"Yes\"];",
+        ] {
+            assert!(dot.contains(line), "{}", line);
+        }
+
+        // Subroutines start with their header, and CASE is a decision per branch
+        let dot = interpreter
+            .flowchart(Some("GRADE"))
+            .unwrap()
+            .render(ChartFormat::Dot);
+        for line in [
+            "digraph \"Grade\" {",
+            "n0 [label=\"PROCEDURE Grade(Mark : INTEGER)\", shape=box, style=rounded];",
+            "n1 [label=\"Mark >= 90 AND Mark <= 100?\", shape=diamond];",
+            "n3 [label=\"Mark = 50?\", shape=diamond];",
+            "n3 -> n5 [label=\"No\"];",
+            "n6 [label=\"END\", shape=box, style=rounded];",
+        ] {
+            assert!(dot.contains(line), "{}", line);
+        }
+        let dot = interpreter
+            .flowchart(Some("double"))
+            .unwrap()
+            .render(ChartFormat::Dot);
+        assert!(dot.contains("n1 [label=\"RETURN N * 2\", shape=box];\n"));
+        assert!(dot.contains("n1 -> n2;"));
+
+        // The SVG draws every box, without needing Graphviz
+        let svg = interpreter
+            .flowchart(None)
+            .unwrap()
+            .render(ChartFormat::Svg);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<polygon").count(), 10);
+        assert_eq!(svg.matches("<rect x=").count(), 10);
+        assert!(svg.contains(">Mark &lt;&gt; 999?</text>"));
+
+        let errors = interpreter.flowchart(Some("missing")).err().unwrap();
+        assert_eq!(errors[0].message, "No PROCEDURE or FUNCTION named missing");
+    }
+
+    #[test]
+    fn structure_chart_test() {
+        let text = read_to_string("tests/structure_chart_test.txt").unwrap();
+        let graph = Interpreter::new("structure", &text).call_graph().unwrap();
+        let dot = graph.chart().render(ChartFormat::Dot);
+        for line in [
+            "n0 [label=\"Main program\", shape=box];",
+            "n1 [label=\"Swap\", shape=box];",
+            "n0 -> n1 [label=\"↕ A\\n↕ B\"];",
+            "n0 -> n2 [label=\"↓ N\\n↑ INTEGER\"];",
+            "n2 -> n2 [label=\"↓ N\\n↑ INTEGER\", color=red, fontcolor=red];",
+            "n3 -> n4 [label=\"↓ N\\n↑ BOOLEAN\", color=red, fontcolor=red];",
+            "n5 -> n3 [label=\"↓ N\\n↑ BOOLEAN\"];",
+        ] {
+            assert!(dot.contains(line), "{}", line);
+        }
+        // One arrow for each module called, however many times it is called
+        assert_eq!(dot.matches(" -> ").count(), 8);
+
+        let svg = graph.chart().render(ChartFormat::Svg);
+        assert_eq!(svg.matches("<rect x=").count(), 6);
+        assert_eq!(svg.matches("stroke=\"red\"").count(), 3);
+
+        assert_eq!(
+            graph.listing(),
+            "Main program
+    calls Swap(BYREF A, BYREF B)
+    calls Report(Low, High)
+    calls Factorial(N) RETURNS INTEGER
+Swap(BYREF A : INTEGER, BYREF B : INTEGER)
+Factorial(N : INTEGER) RETURNS INTEGER
+    calls Factorial(N) RETURNS INTEGER  [recursive]
+IsEven(N : INTEGER) RETURNS BOOLEAN
+    calls IsOdd(N) RETURNS BOOLEAN  [recursive]
+IsOdd(N : INTEGER) RETURNS BOOLEAN
+    calls IsEven(N) RETURNS BOOLEAN  [recursive]
+Report(Low : INTEGER, High : INTEGER)
+    calls Factorial(N) RETURNS INTEGER
+    calls IsEven(N) RETURNS BOOLEAN
+
+Recursion
+    Factorial -> Factorial
+    IsEven -> IsOdd -> IsEven
+"
+        );
+    }
+
+    #[test]
+    fn identifiers_test() {
+        let text = read_to_string("tests/identifiers_test.txt").unwrap();
+        let table = Interpreter::new("identifiers", &text)
+            .identifiers()
+            .unwrap();
+        let markdown = table.render(TableFormat::Markdown);
+        for row in [
+            "| Identifier | Kind | Data type | Scope | Description |",
+            "| MaxMarks | Constant | INTEGER | Global | Highest mark a student can get |",
+            "| Site | Constant | STRING | Global | Where results are sent |",
+            "| Autumn | Enumerated value | Season | Global |  |",
+            "| Student | Type | Record | Global | One student and their marks |",
+            "| Marks | Field | ARRAY[1:3] OF INTEGER | TYPE Student |  |",
+            "| StudentPointer | Type | ^Student | Global |  |",
+            "| Name | Private property | STRING | CLASS Pet | What the pet answers to |",
+            "| GivenName | Parameter | STRING | PROCEDURE NEW in CLASS Pet |  |",
+            // The comment on a header describes the routine, not its parameters
+            "| A | BYREF parameter | INTEGER | PROCEDURE Swap |  |",
+            "| Temp | Variable | INTEGER | PROCEDURE Swap | Holds A while it is overwritten |",
+            "| Count | Parameter | INTEGER | FUNCTION Average |  |",
+            "| Best | Variable | Student | Global | The student with the highest marks |",
+            "| Y | Variable | INTEGER | Global | Numbers to swap |",
+            "| Message | Variable | STRING | Global |  |",
+        ] {
+            assert!(markdown.contains(&format!("{}\n", row)), "{}", row);
+        }
+        assert_eq!(markdown.lines().count(), 26);
+
+        let csv = table.render(TableFormat::Csv);
+        assert!(csv.starts_with("Identifier,Kind,Data type,Scope,Description\n"));
+        assert!(csv.contains("X,Variable,INTEGER,Global,Numbers to swap\n"));
+        let plain = table.render(TableFormat::Plain);
+        assert!(plain
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("MaxMarks       | Constant "));
+    }
+    #[test]
+    fn golden_test() {
+        let summary = run_tests(Path::new("tests/golden")).unwrap();
+        assert_eq!(summary.failed, 0);
+        assert!(summary.passed > 0);
+
+        // Only a directory that exists and holds no programs passes empty
+        assert!(run_tests(Path::new("tests/golden/missing")).is_err());
+        let empty =
+            std::env::temp_dir().join(format!("pseudoengine-golden-{}", std::process::id()));
+        std::fs::create_dir_all(&empty).unwrap();
+        let summary = run_tests(&empty).unwrap();
+        std::fs::remove_dir_all(&empty).unwrap();
+        assert_eq!((summary.passed, summary.failed), (0, 0));
     }
 }
@@ -1,5 +1,4 @@
 use std::ops::Deref;
-use std::vec;
 
 use crate::enums::{Node, Position, Token};
 use crate::lexer::Lexer;
@@ -12,7 +11,7 @@ use crate::parser::parse_io::*;
 use crate::parser::parse_loop::{parse_for, parse_repeat, parse_while};
 use crate::parser::parse_selection::{parse_case, parse_if};
 use crate::tokens::TToken;
-use crate::utils::err;
+use crate::utils::{err, ErrorKind, PseudoError};
 
 mod parse_class;
 mod parse_declare;
@@ -23,16 +22,53 @@ mod parse_io;
 mod parse_loop;
 mod parse_selection;
 
-pub fn parse_file(lexer: &mut Lexer) -> Vec<Box<Node>> {
+// Stops at the first syntax error, use parse_program to get all of them
+pub fn parse_file(lexer: &mut Lexer) -> Result<Vec<Box<Node>>, PseudoError> {
+    parse_program(lexer).map_err(|mut errors| errors.remove(0))
+}
+
+// Parse the whole file, carrying on past mistakes so every syntax error is reported
+pub fn parse_program(lexer: &mut Lexer) -> Result<Vec<Box<Node>>, Vec<PseudoError>> {
+    let (nodes, errors) = parse_partial(lexer);
+    if errors.is_empty() {
+        Ok(nodes)
+    } else {
+        Err(errors)
+    }
+}
+
+// The statements that could be parsed along with the syntax errors, for tools
+// that still want to look at a program while it is being written
+pub fn parse_partial(lexer: &mut Lexer) -> (Vec<Box<Node>>, Vec<PseudoError>) {
+    // Syntax errors recovered from, in the order they are found
+    let mut errors = vec![];
+    let nodes = parse_main(lexer, &mut errors);
+    let errors = errors
+        .into_iter()
+        .map(|e| e.with_kind(ErrorKind::Syntax))
+        .collect();
+    (nodes, errors)
+}
+
+fn parse_main(lexer: &mut Lexer, errors: &mut Vec<PseudoError>) -> Vec<Box<Node>> {
     let mut nodes = Vec::new();
     let mut main_children = Vec::<Box<Node>>::new();
 
-    while lexer.peek().is_some() {
-        match lexer.peek().unwrap().t {
-            TToken::Procedure => main_children.push(parse_procedure(lexer, false)),
-            TToken::Function => main_children.push(parse_function(lexer, false)),
-            TToken::Class => main_children.push(parse_class(lexer)),
-            _ => main_children.push(parse_line(lexer)),
+    while let Some(token) = lexer.peek() {
+        match token.t {
+            TToken::Procedure => {
+                let node = parse_procedure(lexer, errors, false);
+                collect(lexer, errors, &mut main_children, node)
+            }
+            TToken::Function => {
+                let node = parse_function(lexer, errors, false);
+                collect(lexer, errors, &mut main_children, node)
+            }
+            TToken::Class => {
+                let node = parse_class(lexer, errors);
+                collect(lexer, errors, &mut main_children, node)
+            }
+            _ => parse_stmt(lexer, errors, &mut main_children),
         }
     }
 
@@ -43,32 +79,118 @@ pub fn parse_file(lexer: &mut Lexer) -> Vec<Box<Node>> {
     nodes
 }
 
-pub fn parse_line(lexer: &mut Lexer) -> Box<Node> {
+// Parse one statement into body. A statement that fails to parse is reported
+// and skipped so the lines after it are still parsed.
+pub fn parse_stmt(lexer: &mut Lexer, errors: &mut Vec<PseudoError>, body: &mut Vec<Box<Node>>) {
+    let node = parse_line(lexer, errors);
+    collect(lexer, errors, body, node)
+}
+
+pub fn collect(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+    body: &mut Vec<Box<Node>>,
+    node: Result<Box<Node>, PseudoError>,
+) {
+    match node {
+        Ok(node) => body.push(node),
+        Err(e) => {
+            errors.push(e);
+            synchronise(lexer);
+        }
+    }
+}
+
+// Parse the opening line of a block. On failure the rest of the line is skipped
+// and None returned, so the body of the block is still parsed.
+pub fn recover<T>(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+    parse: impl FnOnce(&mut Lexer) -> Result<T, PseudoError>,
+) -> Option<T> {
+    match parse(lexer) {
+        Ok(val) => Some(val),
+        Err(e) => {
+            errors.push(e);
+            synchronise(lexer);
+            None
+        }
+    }
+}
+
+// Report a block that was not closed before `token`
+pub fn unclosed(errors: &mut Vec<PseudoError>, message: &str, token: &Token) {
+    errors.push(PseudoError::new(ErrorKind::Syntax, message, &token.pos));
+}
+
+// The end of the file or a keyword that closes a block. Reaching one of these
+// inside a block that it does not close means that block is missing its terminator.
+pub fn ends_block(t: &TToken) -> bool {
+    matches!(
+        t,
+        TToken::EOF
+            | TToken::Else
+            | TToken::EndIf
+            | TToken::Otherwise
+            | TToken::EndCase
+            | TToken::EndWhile
+            | TToken::Until
+            | TToken::Next
+            | TToken::EndFunction
+            | TToken::EndProcedure
+            | TToken::EndClass
+    )
+}
+
+// Skip to the start of the next line, or to a keyword closing a block so the
+// block can still be closed. The first token is always skipped so parsing moves on.
+fn synchronise(lexer: &mut Lexer) {
+    let mut first = true;
+    while let Some(token) = lexer.peek() {
+        match token.t {
+            TToken::EOF => break,
+            TToken::Newline => {
+                lexer.next();
+                break;
+            }
+            ref t if !first && ends_block(t) => break,
+            _ => {
+                lexer.next();
+            }
+        }
+        first = false;
+    }
+}
+
+pub fn parse_line(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     let token = lexer.peek().unwrap();
-    match token.t {
-        TToken::Declare => parse_declare(lexer),
-        TToken::Constant => parse_constant(lexer),
-        TToken::While => parse_while(lexer),
-        TToken::For => parse_for(lexer),
-        TToken::Repeat => parse_repeat(lexer),
-        TToken::Input => parse_input(lexer),
-        TToken::Output => parse_output(lexer),
-        TToken::OpenFile => parse_open_file(lexer),
-        TToken::CloseFile => parse_close_file(lexer),
-        TToken::ReadFile => parse_read_file(lexer),
-        TToken::WriteFile => parse_write_file(lexer),
-        TToken::Seek => parse_seek_file(lexer),
-        TToken::GetRecord => parse_get_record(lexer),
-        TToken::PutRecord => parse_put_record(lexer),
-        TToken::If => parse_if(lexer),
-        TToken::Type => parse_user_defined_data(lexer),
-        TToken::Case => parse_case(lexer),
-        TToken::Return => parse_return(lexer),
+    Ok(match token.t {
+        TToken::Declare => parse_declare(lexer)?,
+        TToken::Constant => parse_constant(lexer)?,
+        TToken::While => parse_while(lexer, errors)?,
+        TToken::For => parse_for(lexer, errors)?,
+        TToken::Repeat => parse_repeat(lexer, errors)?,
+        TToken::Input => parse_input(lexer)?,
+        TToken::Output => parse_output(lexer)?,
+        TToken::OpenFile => parse_open_file(lexer)?,
+        TToken::CloseFile => parse_close_file(lexer)?,
+        TToken::ReadFile => parse_read_file(lexer)?,
+        TToken::WriteFile => parse_write_file(lexer)?,
+        TToken::Seek => parse_seek_file(lexer)?,
+        TToken::GetRecord => parse_get_record(lexer)?,
+        TToken::PutRecord => parse_put_record(lexer)?,
+        TToken::If => parse_if(lexer, errors)?,
+        TToken::Type => parse_user_defined_data(lexer, errors)?,
+        TToken::Case => parse_case(lexer, errors)?,
+        TToken::Return => parse_return(lexer)?,
         TToken::Identifier(_) => {
-            let lhs = parse_expression(lexer);
-            try_parse_assign(lexer, lhs)
+            let lhs = parse_expression(lexer)?;
+            try_parse_assign(lexer, lhs)?
         }
-        TToken::Call => parse_call(lexer),
+        TToken::Call => parse_call(lexer)?,
         TToken::Newline | TToken::EOF => {
             lexer.next();
             Box::new(Node::Null)
@@ -76,32 +198,32 @@ pub fn parse_line(lexer: &mut Lexer) -> Box<Node> {
         TToken::Procedure | TToken::Function => err(
             "Procedure and Function can only be declared in the global scope",
             &lexer.peek().unwrap().pos,
-        ),
+        )?,
         TToken::Class => err(
             "Class can only be declared in the global scope",
             &lexer.peek().unwrap().pos,
-        ),
-        _ => err("Expected statement", &token.pos),
-    }
+        )?,
+        _ => err("Expected statement", &token.pos)?,
+    })
 }
 
-fn parse_call(lexer: &mut Lexer) -> Box<Node> {
+fn parse_call(lexer: &mut Lexer) -> Result<Box<Node>, PseudoError> {
     lexer.next();
-    let func_call = parse_identifier(lexer);
+    let func_call = parse_identifier(lexer)?;
     if let Node::FunctionCall { .. } = func_call.deref() {
-        func_call
+        Ok(func_call)
     } else {
-        err("PROCEDURE expected", &lexer.peek().unwrap().pos);
+        err("PROCEDURE expected", &lexer.peek().unwrap().pos)
     }
 }
 
-fn try_parse_assign(lexer: &mut Lexer, lhs: Box<Node>) -> Box<Node> {
-    match lexer.next().unwrap().t {
+fn try_parse_assign(lexer: &mut Lexer, lhs: Box<Node>) -> Result<Box<Node>, PseudoError> {
+    Ok(match lexer.next().unwrap().t {
         TToken::Assignment => {
-            let rhs = parse_expression(lexer);
+            let rhs = parse_expression(lexer)?;
             let pos = Position::range(lhs.pos(), rhs.pos());
             Box::from(Node::Assignment { lhs, rhs, pos })
         }
         _ => lhs,
-    }
+    })
 }
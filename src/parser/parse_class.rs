@@ -2,50 +2,52 @@ use crate::enums::{Node, Position, Token};
 use crate::lexer::Lexer;
 use crate::parser::parse_declare::parse_declaration;
 use crate::parser::parse_func::{parse_function, parse_procedure};
-use crate::parser::parse_line;
+use crate::parser::{collect, ends_block, parse_stmt, recover, unclosed};
 use crate::tokens::TToken;
-use crate::utils::{err, expect_token};
+use crate::utils::{expect_token, PseudoError};
 
-pub fn parse_class(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_class(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     // skip CLASS token
     let token = lexer.next().unwrap();
-    let Token {
-        t: TToken::Identifier(name),
-        pos: ident_pos,
-    } = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")
-    else {
-        unreachable!()
-    };
-    let mut base = Box::from(Node::Null);
-    let mut children = vec![];
-    // Check for inheritance pattern
-    if let Some(Token {
-        t: TToken::Inherits,
-        pos: _,
-    }) = lexer.peek()
-    {
-        lexer.next();
+    let header = recover(lexer, errors, |lexer| {
         let Token {
-            t: TToken::Identifier(val),
-            pos,
-        } = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")
+            t: TToken::Identifier(name),
+            pos: ident_pos,
+        } = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")?
         else {
             unreachable!()
         };
-        base = Box::from(Node::String { val, pos })
-    }
+        let mut base = Box::from(Node::Null);
+        // Check for inheritance pattern
+        if let Some(Token {
+            t: TToken::Inherits,
+            pos: _,
+        }) = lexer.peek()
+        {
+            lexer.next();
+            let Token {
+                t: TToken::Identifier(val),
+                pos,
+            } = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")?
+            else {
+                unreachable!()
+            };
+            base = Box::from(Node::String { val, pos })
+        }
+        Ok((name, ident_pos, base))
+    });
+    let mut children = vec![];
 
     let end = loop {
         match lexer.peek() {
-            Some(Token {
-                t: TToken::EOF,
-                pos,
-            }) => err("ENDCLASS expected", &pos),
             Some(Token {
                 t: TToken::EndClass,
                 pos,
             }) => {
-                let pos = pos.clone();
+                let pos = *pos;
                 lexer.next();
                 break pos;
             }
@@ -54,40 +56,51 @@ pub fn parse_class(lexer: &mut Lexer) -> Box<Node> {
                 pos: _,
             }) => {
                 lexer.next();
-                children.push(match lexer.peek() {
+                let child = match lexer.peek() {
                     Some(Token {
                         t: TToken::Procedure,
                         ..
-                    }) => Box::from(parse_procedure(lexer, true)),
+                    }) => parse_procedure(lexer, errors, true),
                     Some(Token {
                         t: TToken::Function,
                         ..
-                    }) => Box::from(parse_function(lexer, true)),
-                    _ => Box::from(parse_declaration(lexer, false, true)),
-                });
+                    }) => parse_function(lexer, errors, true),
+                    _ => parse_declaration(lexer, false, true),
+                };
+                collect(lexer, errors, &mut children, child);
             }
             Some(Token {
                 t: TToken::Public,
                 pos: _,
             }) => {
                 lexer.next();
-                children.push(match lexer.peek() {
+                let child = match lexer.peek() {
                     Some(Token {
                         t: TToken::Procedure,
                         ..
-                    }) => parse_procedure(lexer, false),
+                    }) => parse_procedure(lexer, errors, false),
                     Some(Token {
                         t: TToken::Function,
                         ..
-                    }) => parse_function(lexer, false),
+                    }) => parse_function(lexer, errors, false),
                     _ => parse_declaration(lexer, false, false),
-                });
+                };
+                collect(lexer, errors, &mut children, child);
             }
-            _ => children.push(parse_line(lexer)),
+            Some(token) if ends_block(&token.t) => {
+                let pos = token.pos;
+                unclosed(errors, "ENDCLASS expected", token);
+                break pos;
+            }
+            _ => parse_stmt(lexer, errors, &mut children),
         }
     };
+    // The class is dropped when its first line could not be parsed
+    let Some((name, ident_pos, base)) = header else {
+        return Ok(Box::new(Node::Null));
+    };
     let pos = Position::range(token.pos, end);
-    Box::from(Node::Class {
+    Ok(Box::from(Node::Class {
         name: Box::from(Node::String {
             val: name,
             pos: ident_pos,
@@ -95,5 +108,5 @@ pub fn parse_class(lexer: &mut Lexer) -> Box<Node> {
         base,
         children,
         pos,
-    })
+    }))
 }
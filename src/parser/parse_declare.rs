@@ -1,12 +1,16 @@
-use crate::enums::{Array, Index, Node, Position, Token, VariableType};
+use crate::enums::{Index, Node, Position, Token, VariableType};
 use crate::lexer::Lexer;
+use crate::parser::collect;
 use crate::tokens::TToken;
-use crate::utils::{err, expect_token};
+use crate::utils::{err, expect_token, PseudoError};
 
-pub fn parse_user_defined_data(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_user_defined_data(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     // Skip Type token
     let token = lexer.next().unwrap();
-    let name_token = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier");
+    let name_token = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")?;
     let name = match name_token.t {
         TToken::Identifier(name) => name,
         _ => unreachable!(),
@@ -16,19 +20,20 @@ pub fn parse_user_defined_data(lexer: &mut Lexer) -> Box<Node> {
         lexer,
         &[TToken::Newline, TToken::Operator("=".to_string())],
         "DECLARE or '='",
-    );
+    )?;
     match next_token.t {
         TToken::Newline => {
             // This is a record data type pattern
-            expect_token(lexer, &[TToken::Declare], "DECLARE");
+            expect_token(lexer, &[TToken::Declare], "DECLARE")?;
             let mut fields = Vec::new();
             let end = loop {
-                fields.push(parse_declaration(lexer, false, false));
+                let field = parse_declaration(lexer, false, false);
+                collect(lexer, errors, &mut fields, field);
                 // parse_declaration does not consume the trailing newline token
                 if lexer.peek().unwrap().t == TToken::Newline {
                     lexer.next();
                 }
-                let end = expect_token(lexer, &[TToken::Declare, TToken::EndType], "EndType");
+                let end = expect_token(lexer, &[TToken::Declare, TToken::EndType], "EndType")?;
                 match end.t {
                     TToken::EndType => break end.pos,
                     TToken::Declare => continue,
@@ -36,20 +41,20 @@ pub fn parse_user_defined_data(lexer: &mut Lexer) -> Box<Node> {
                 }
             };
             let pos = Position::range(token.pos, end);
-            Box::from(Node::Record {
+            Ok(Box::from(Node::Record {
                 name: Box::new(Node::String {
                     val: name,
                     pos: name_token.pos,
                 }),
                 children: fields,
                 pos,
-            })
+            }))
         }
         TToken::Operator(op) => {
             if op != "=" {
-                err("Expected '='", &next_token.pos)
+                err("Expected '='", &next_token.pos)?
             }
-            match expect_token(lexer, &[TToken::LParen, TToken::Caret], "'(' or '^'").t {
+            match expect_token(lexer, &[TToken::LParen, TToken::Caret], "'(' or '^'")?.t {
                 TToken::LParen => parse_enum(lexer, token, name),
                 TToken::Caret => parse_pointer(lexer, token, &name),
                 _ => unreachable!(),
@@ -59,7 +64,7 @@ pub fn parse_user_defined_data(lexer: &mut Lexer) -> Box<Node> {
     }
 }
 
-fn parse_pointer(lexer: &mut Lexer, start: Token, name: &String) -> Box<Node> {
+fn parse_pointer(lexer: &mut Lexer, start: Token, name: &str) -> Result<Box<Node>, PseudoError> {
     let vtype = expect_token(
         lexer,
         &[
@@ -67,21 +72,21 @@ fn parse_pointer(lexer: &mut Lexer, start: Token, name: &String) -> Box<Node> {
             TToken::VarType(VariableType::String),
         ],
         "Data type",
-    );
+    )?;
     let ref_to = Box::from(match vtype.t {
         TToken::Identifier(name) => VariableType::Custom(name),
         TToken::VarType(vt) => vt,
         _ => unreachable!(),
     });
     let pos = Position::range(start.pos, vtype.pos);
-    Box::from(Node::PointerDef {
-        name: name.clone(),
+    Ok(Box::from(Node::PointerDef {
+        name: name.to_string(),
         ref_to,
         pos,
-    })
+    }))
 }
 
-fn parse_enum(lexer: &mut Lexer, start: Token, name: String) -> Box<Node> {
+fn parse_enum(lexer: &mut Lexer, start: Token, name: String) -> Result<Box<Node>, PseudoError> {
     let mut expect_ident = false;
     let mut variants = Vec::<String>::new();
     let mut current;
@@ -101,7 +106,7 @@ fn parse_enum(lexer: &mut Lexer, start: Token, name: String) -> Box<Node> {
                 pos,
             } => {
                 if expect_ident {
-                    err("Enum value expected", &pos);
+                    err("Enum value expected", &pos)?;
                 }
                 expect_ident = true;
             }
@@ -110,12 +115,12 @@ fn parse_enum(lexer: &mut Lexer, start: Token, name: String) -> Box<Node> {
     }
     let current = current.unwrap();
     if expect_ident {
-        err("Enum value expected", &current.pos);
+        err("Enum value expected", &current.pos)?;
     } else if current.t != TToken::RParen {
-        err(") expected", &current.pos);
+        err(") expected", &current.pos)?;
     }
     let pos = Position::range(start.pos, current.pos);
-    Box::from(Node::Enum {
+    Ok(Box::from(Node::Enum {
         name,
         variants: variants
             .into_iter()
@@ -127,77 +132,79 @@ fn parse_enum(lexer: &mut Lexer, start: Token, name: String) -> Box<Node> {
             })
             .collect(),
         pos,
-    })
+    }))
 }
 
-pub fn parse_constant(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_constant(lexer: &mut Lexer) -> Result<Box<Node>, PseudoError> {
     // Skip Constant token
-    lexer.next();
+    let start = lexer.next().unwrap().pos;
     let name;
-    let mut val = Box::from(Node::Null);
     if let TToken::Identifier(_name) =
-        expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier").t
+        expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")?.t
     {
         name = _name
     } else {
         unreachable!()
     }
-    if let Token { t, pos } = expect_token(lexer, &[TToken::Operator("".to_string())], "'='") {
-        if let TToken::Operator(_op) = t {
-            if _op != "=" {
-                err("'=' expected", &pos)
-            }
-        } else {
-            unreachable!()
+    let Token { t, pos } = expect_token(lexer, &[TToken::Operator("".to_string())], "'='")?;
+    if let TToken::Operator(_op) = t {
+        if _op != "=" {
+            err("'=' expected", &pos)?
         }
+    } else {
+        unreachable!()
     }
 
-    if let token = lexer.next().unwrap() {
-        val = Box::from(match token.t {
-            TToken::IntegerLit(val) => Node::Int {
-                val,
-                pos: token.pos,
-            },
-            TToken::RealLit(val) => Node::Real {
-                val,
-                pos: token.pos,
-            },
-            TToken::StringLit(val) => Node::String {
-                val,
-                pos: token.pos,
-            },
-            TToken::DateLit(val) => Node::Date {
-                val,
-                pos: token.pos,
-            },
-            TToken::BoolLit(val) => Node::Boolean {
-                val,
-                pos: token.pos,
-            },
-            _ => err("Literal value expected", &token.pos),
-        })
-    }
+    let token = lexer.next().unwrap();
+    let val = Box::from(match token.t {
+        TToken::IntegerLit(val) => Node::Int {
+            val,
+            pos: token.pos,
+        },
+        TToken::RealLit(val) => Node::Real {
+            val,
+            pos: token.pos,
+        },
+        TToken::StringLit(val) => Node::String {
+            val,
+            pos: token.pos,
+        },
+        TToken::CharLit(val) => Node::Char {
+            val,
+            pos: token.pos,
+        },
+        TToken::DateLit(val) => Node::Date {
+            val,
+            pos: token.pos,
+        },
+        TToken::BoolLit(val) => Node::Boolean {
+            val,
+            pos: token.pos,
+        },
+        _ => err("Literal value expected", &token.pos)?,
+    });
 
-    Box::from(Node::Const {
-        name,
-        val,
-        pos: Position::invalid(),
-    })
+    let pos = Position::range(start, val.pos());
+    Ok(Box::from(Node::Const { name, val, pos }))
 }
 
-pub fn parse_declare(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_declare(lexer: &mut Lexer) -> Result<Box<Node>, PseudoError> {
     // Skip Declare token
     let token = lexer.next().unwrap();
-    let mut node = parse_declaration(lexer, false, false);
+    let mut node = parse_declaration(lexer, false, false)?;
     let new_pos = Position::range(token.pos, node.pos());
     if let Node::Declare { pos, .. } = &mut *node {
         *pos = new_pos;
     }
-    node
+    Ok(node)
 }
 
 // Actual parsing of declaration content
-pub fn parse_declaration(lexer: &mut Lexer, byref: bool, private: bool) -> Box<Node> {
+pub fn parse_declaration(
+    lexer: &mut Lexer,
+    byref: bool,
+    private: bool,
+) -> Result<Box<Node>, PseudoError> {
     let mut start = Position::invalid();
     let mut expect_ident = false;
     let mut vars = Vec::<String>::new();
@@ -223,7 +230,7 @@ pub fn parse_declaration(lexer: &mut Lexer, byref: bool, private: bool) -> Box<N
                 pos,
             } => {
                 if expect_ident {
-                    err("Identifier expected", &pos);
+                    err("Identifier expected", &pos)?;
                 }
                 expect_ident = true;
             }
@@ -233,46 +240,46 @@ pub fn parse_declaration(lexer: &mut Lexer, byref: bool, private: bool) -> Box<N
     let current = current.unwrap();
 
     if expect_ident {
-        err("Identifier expected", &current.pos);
+        err("Identifier expected", &current.pos)?;
     } else if current.t != TToken::Colon {
-        err(": expected", &current.pos);
+        err(": expected", &current.pos)?;
     }
 
     // Handle variable type
     let vtype = lexer.next().unwrap();
     let t = match vtype.t {
         TToken::VarType(vt) => Box::from(vt),
-        TToken::Array => parse_array(lexer),
+        TToken::Array => parse_array(lexer)?,
         TToken::Identifier(name) => Box::from(VariableType::Custom(name)),
-        _ => err("Type expected", &vtype.pos),
+        _ => err("Type expected", &vtype.pos)?,
     };
 
     let pos = Position::range(start, vtype.pos);
-    Box::new(Node::Declare {
+    Ok(Box::new(Node::Declare {
         t,
         byref,
         private,
         children: vars,
         pos,
-    })
+    }))
 }
 
-pub fn parse_array(lexer: &mut Lexer) -> Box<VariableType> {
-    expect_token(lexer, &[TToken::LSqrBracket], "[");
+pub fn parse_array(lexer: &mut Lexer) -> Result<Box<VariableType>, PseudoError> {
+    expect_token(lexer, &[TToken::LSqrBracket], "[")?;
 
     parse_array_dimension(lexer)
 }
 
-fn parse_array_dimension(lexer: &mut Lexer) -> Box<VariableType> {
+fn parse_array_dimension(lexer: &mut Lexer) -> Result<Box<VariableType>, PseudoError> {
     let mut shape = Vec::new();
     loop {
         let mut index = Index { lower: 0, upper: 0 };
-        if let TToken::IntegerLit(val) = expect_token(lexer, &[TToken::IntegerLit(0)], "Integer").t
+        if let TToken::IntegerLit(val) = expect_token(lexer, &[TToken::IntegerLit(0)], "Integer")?.t
         {
             index.lower = val
         }
-        expect_token(lexer, &[TToken::Colon], ":");
-        if let TToken::IntegerLit(val) = expect_token(lexer, &[TToken::IntegerLit(0)], "Integer").t
+        expect_token(lexer, &[TToken::Colon], ":")?;
+        if let TToken::IntegerLit(val) = expect_token(lexer, &[TToken::IntegerLit(0)], "Integer")?.t
         {
             index.upper = val
         }
@@ -282,17 +289,17 @@ fn parse_array_dimension(lexer: &mut Lexer) -> Box<VariableType> {
         match token.t {
             TToken::Comma => {}
             TToken::RSqrBracket => break,
-            _ => err(" ']' or ',' expected", &token.pos),
+            _ => err(" ']' or ',' expected", &token.pos)?,
         }
     }
-    expect_token(lexer, &[TToken::Of], "'Of'");
+    expect_token(lexer, &[TToken::Of], "'Of'")?;
     let token = lexer.next().unwrap();
     if let TToken::VarType(t) = token.t {
-        Box::from(VariableType::Array {
+        Ok(Box::from(VariableType::Array {
             shape,
             t: Box::new(t),
-        })
+        }))
     } else {
-        err("Type expected", &token.pos);
+        err("Type expected", &token.pos)
     }
 }
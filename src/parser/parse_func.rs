@@ -2,50 +2,57 @@ use crate::enums::{Node, Position, Token, VariableType};
 use crate::lexer::Lexer;
 use crate::parser::parse_declare::{parse_array, parse_declaration};
 use crate::parser::parse_expr::parse_expression;
-use crate::parser::parse_line;
+use crate::parser::{ends_block, parse_stmt, recover, unclosed};
 use crate::tokens::TToken;
-use crate::utils::{err, expect_token};
+use crate::utils::{expect_token, PseudoError};
 
-pub fn parse_function(lexer: &mut Lexer, private: bool) -> Box<Node> {
+pub fn parse_function(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+    private: bool,
+) -> Result<Box<Node>, PseudoError> {
     // skip FUNCTION token
     let token = lexer.next().unwrap();
-    let name = match expect_token(
-        lexer,
-        &[TToken::Identifier("".to_string()), TToken::New],
-        "Function name",
-    ) {
-        Token {
-            t: TToken::Identifier(val),
-            pos,
-        } => Box::from(Node::String { val, pos }),
-        Token {
-            t: TToken::New,
-            pos,
-        } => Box::from(Node::String {
-            val: "new".to_string(),
-            pos,
-        }),
-        _ => unreachable!(),
-    };
-    let params = parse_params(lexer);
-    expect_token(lexer, &[TToken::ReturnType], "'RETURNS' expected");
+    let header = recover(lexer, errors, |lexer| {
+        let name = match expect_token(
+            lexer,
+            &[TToken::Identifier("".to_string()), TToken::New],
+            "Function name",
+        )? {
+            Token {
+                t: TToken::Identifier(val),
+                pos,
+            } => Box::from(Node::String { val, pos }),
+            Token {
+                t: TToken::New,
+                pos,
+            } => Box::from(Node::String {
+                val: "new".to_string(),
+                pos,
+            }),
+            _ => unreachable!(),
+        };
+        let params = parse_params(lexer)?;
+        expect_token(lexer, &[TToken::ReturnType], "'RETURNS' expected")?;
 
-    let return_type = match expect_token(
-        lexer,
-        &[
-            TToken::VarType(VariableType::Integer),
-            TToken::Identifier("".to_string()),
-            TToken::Array,
-        ],
-        "TYPE expected",
-    )
-    .t
-    {
-        TToken::VarType(vt) => Box::from(vt),
-        TToken::Identifier(vt) => Box::from(VariableType::Custom(vt)),
-        TToken::Array => parse_array(lexer),
-        _ => unreachable!(),
-    };
+        let return_type = match expect_token(
+            lexer,
+            &[
+                TToken::VarType(VariableType::Integer),
+                TToken::Identifier("".to_string()),
+                TToken::Array,
+            ],
+            "TYPE expected",
+        )?
+        .t
+        {
+            TToken::VarType(vt) => Box::from(vt),
+            TToken::Identifier(vt) => Box::from(VariableType::Custom(vt)),
+            TToken::Array => parse_array(lexer)?,
+            _ => unreachable!(),
+        };
+        Ok((name, params, return_type))
+    });
 
     let mut children = vec![];
     let end = loop {
@@ -54,54 +61,64 @@ pub fn parse_function(lexer: &mut Lexer, private: bool) -> Box<Node> {
                 t: TToken::EndFunction,
                 pos,
             }) => {
-                let pos = pos.clone();
+                let pos = *pos;
                 lexer.next();
                 break pos;
             }
-            Some(Token {
-                t: TToken::EOF,
-                pos,
-            }) => {
-                err("ENDFUNCTION expected", pos);
+            Some(token) if ends_block(&token.t) => {
+                let pos = token.pos;
+                unclosed(errors, "ENDFUNCTION expected", token);
+                break pos;
             }
-            _ => children.push(parse_line(lexer)),
+            _ => parse_stmt(lexer, errors, &mut children),
         }
     };
+    // The function is dropped when its first line could not be parsed
+    let Some((name, params, return_type)) = header else {
+        return Ok(Box::new(Node::Null));
+    };
 
     let pos = Position::range(token.pos, end);
-    Box::from(Node::Function {
+    Ok(Box::from(Node::Function {
         name,
         params,
         return_type,
         children,
         pos,
         private,
-    })
+    }))
 }
 
-pub fn parse_procedure(lexer: &mut Lexer, private: bool) -> Box<Node> {
+pub fn parse_procedure(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+    private: bool,
+) -> Result<Box<Node>, PseudoError> {
     // skip PROCEDURE token
     let token = lexer.next().unwrap();
-    let name = match expect_token(
-        lexer,
-        &[TToken::Identifier("".to_string()), TToken::New],
-        "Procedure name",
-    ) {
-        Token {
-            t: TToken::Identifier(val),
-            pos,
-        } => Box::from(Node::String { val, pos }),
-        Token {
-            t: TToken::New,
-            pos,
-        } => Box::from(Node::String {
-            val: "new".to_string(),
-            pos,
-        }),
-        _ => unreachable!(),
-    };
+    let header = recover(lexer, errors, |lexer| {
+        let name = match expect_token(
+            lexer,
+            &[TToken::Identifier("".to_string()), TToken::New],
+            "Procedure name",
+        )? {
+            Token {
+                t: TToken::Identifier(val),
+                pos,
+            } => Box::from(Node::String { val, pos }),
+            Token {
+                t: TToken::New,
+                pos,
+            } => Box::from(Node::String {
+                val: "new".to_string(),
+                pos,
+            }),
+            _ => unreachable!(),
+        };
 
-    let params = parse_params(lexer);
+        let params = parse_params(lexer)?;
+        Ok((name, params))
+    });
 
     let mut children = vec![];
     let end = loop {
@@ -110,33 +127,36 @@ pub fn parse_procedure(lexer: &mut Lexer, private: bool) -> Box<Node> {
                 t: TToken::EndProcedure,
                 pos,
             }) => {
-                let pos = pos.clone();
+                let pos = *pos;
                 lexer.next();
                 break pos;
             }
-            Some(Token {
-                t: TToken::EOF,
-                pos,
-            }) => {
-                err("ENDPROCEDURE expected", pos);
+            Some(token) if ends_block(&token.t) => {
+                let pos = token.pos;
+                unclosed(errors, "ENDPROCEDURE expected", token);
+                break pos;
             }
-            _ => children.push(parse_line(lexer)),
+            _ => parse_stmt(lexer, errors, &mut children),
         }
     };
+    // The procedure is dropped when its first line could not be parsed
+    let Some((name, params)) = header else {
+        return Ok(Box::new(Node::Null));
+    };
 
     let pos = Position::range(token.pos, end);
-    Box::from(Node::Procedure {
+    Ok(Box::from(Node::Procedure {
         name,
         params,
         children,
         pos,
         private,
-    })
+    }))
 }
 
-fn parse_params(lexer: &mut Lexer) -> Vec<Box<Node>> {
+fn parse_params(lexer: &mut Lexer) -> Result<Vec<Box<Node>>, PseudoError> {
     let mut params = vec![];
-    expect_token(lexer, &[TToken::LParen], "'('");
+    expect_token(lexer, &[TToken::LParen], "'('")?;
     loop {
         match lexer.peek() {
             Some(&Token {
@@ -152,7 +172,7 @@ fn parse_params(lexer: &mut Lexer) -> Vec<Box<Node>> {
             }) => {
                 let by = by.clone();
                 lexer.next();
-                params.push(parse_declaration(lexer, by == "BYREF", false));
+                params.push(parse_declaration(lexer, by == "BYREF", false)?);
             }
             Some(&Token {
                 t: TToken::Comma,
@@ -160,16 +180,16 @@ fn parse_params(lexer: &mut Lexer) -> Vec<Box<Node>> {
             }) => {
                 lexer.next();
             }
-            _ => params.push(parse_declaration(lexer, false, false)),
+            _ => params.push(parse_declaration(lexer, false, false)?),
         }
     }
 
-    params
+    Ok(params)
 }
 
-pub fn parse_return(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_return(lexer: &mut Lexer) -> Result<Box<Node>, PseudoError> {
     let token = lexer.next().unwrap();
-    let expr = parse_expression(lexer);
+    let expr = parse_expression(lexer)?;
     let pos = Position::range(token.pos, expr.pos());
-    Box::from(Node::Return { expr, pos })
+    Ok(Box::from(Node::Return { expr, pos }))
 }
@@ -1,17 +1,17 @@
-use crate::enums::{Node, Position, Token};
+use crate::enums::{Node, Position};
 use crate::lexer::Lexer;
 use crate::parser::parse_expr::parse_expression;
 use crate::tokens::TToken;
-use crate::utils::err;
+use crate::utils::{err, PseudoError};
 
 // Function handles variable, array and function call
-pub fn parse_identifier(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_identifier(lexer: &mut Lexer) -> Result<Box<Node>, PseudoError> {
     let mut children = Vec::new();
     loop {
         let ident = lexer.next().unwrap();
         let name = match ident.t {
             TToken::Identifier(name) => name,
-            _ => err("Expected identifier", &ident.pos),
+            _ => err("Expected identifier", &ident.pos)?,
         };
 
         let token = lexer.peek().unwrap().clone();
@@ -20,13 +20,13 @@ pub fn parse_identifier(lexer: &mut Lexer) -> Box<Node> {
                 lexer.next();
                 let mut indices = Vec::new();
                 loop {
-                    indices.push(parse_expression(lexer));
+                    indices.push(parse_expression(lexer)?);
                     if lexer.peek().unwrap().t == TToken::RSqrBracket {
                         break;
                     }
                     let token = lexer.next().unwrap();
                     if token.t != TToken::Comma {
-                        err("Expected comma", &token.pos);
+                        err("Expected comma", &token.pos)?;
                     }
                 }
                 let end = lexer.next().unwrap();
@@ -38,13 +38,13 @@ pub fn parse_identifier(lexer: &mut Lexer) -> Box<Node> {
                 let mut params = Vec::new();
                 if TToken::RParen != lexer.peek().unwrap().t {
                     loop {
-                        params.push(parse_expression(lexer));
+                        params.push(parse_expression(lexer)?);
                         if lexer.peek().unwrap().t == TToken::RParen {
                             break;
                         }
                         let token = lexer.next().unwrap();
                         if token.t != TToken::Comma {
-                            err("Expected comma", &token.pos);
+                            err("Expected comma", &token.pos)?;
                         }
                     }
                 }
@@ -53,13 +53,12 @@ pub fn parse_identifier(lexer: &mut Lexer) -> Box<Node> {
                 children.push(Box::from(Node::FunctionCall { name, params, pos }))
             }
             _ => {
-                let pos = ident.pos.clone();
+                let pos = ident.pos;
                 children.push(Box::from(Node::Var { name, pos }));
             }
         }
 
-        let token = lexer.peek().unwrap();
-        let name = match token.t {
+        match lexer.peek().unwrap().t {
             TToken::Period => lexer.next(),
             _ => break,
         };
@@ -70,8 +69,8 @@ pub fn parse_identifier(lexer: &mut Lexer) -> Box<Node> {
             children.first().unwrap().pos(),
             children.last().unwrap().pos(),
         );
-        Box::new(Node::Composite { children, pos })
+        Ok(Box::new(Node::Composite { children, pos }))
     } else {
-        children[0].clone()
+        Ok(children[0].clone())
     }
 }
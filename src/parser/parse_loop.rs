@@ -1,100 +1,108 @@
 use crate::enums::{Node, Position, Token};
 use crate::lexer::Lexer;
 use crate::parser::parse_expr::parse_expression;
-use crate::parser::parse_line;
+use crate::parser::{ends_block, parse_stmt, recover, unclosed};
 use crate::tokens::TToken;
-use crate::utils::{err, expect_token};
+use crate::utils::{err, expect_token, PseudoError};
 
-pub fn parse_while(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_while(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     // Skip while token
     let token = lexer.next().unwrap();
 
     let mut body = Vec::new();
-    let cond = parse_expression(lexer);
+    let cond = recover(lexer, errors, parse_expression).unwrap_or(Box::new(Node::Null));
 
     let end = loop {
         match lexer.peek() {
-            Some(Token {
-                t: TToken::EOF,
-                pos,
-            }) => err("'ENDWHILE' expected", pos),
             Some(Token {
                 t: TToken::EndWhile,
                 pos,
             }) => {
-                let pos = pos.clone();
+                let pos = *pos;
                 lexer.next();
                 break pos;
             }
-            _ => body.push(parse_line(lexer)),
+            Some(token) if ends_block(&token.t) => {
+                let pos = token.pos;
+                unclosed(errors, "'ENDWHILE' expected", token);
+                break pos;
+            }
+            _ => parse_stmt(lexer, errors, &mut body),
         }
     };
     let pos = Position::range(token.pos, end);
-    Box::from(Node::While { cond, body, pos })
+    Ok(Box::from(Node::While { cond, body, pos }))
 }
 
-pub fn parse_repeat(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_repeat(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     // skip REPEAT token
     let token = lexer.next().unwrap();
 
     let mut body = vec![];
-    loop {
+    let (cond, end) = loop {
         match lexer.peek() {
             Some(Token {
-                t: TToken::EOF,
-                pos,
-            }) => err("'UNTIL' expected", pos),
-            Some(Token {
-                t: TToken::Until,
-                pos,
+                t: TToken::Until, ..
             }) => {
                 lexer.next();
-                break;
+                let cond = parse_expression(lexer)?;
+                let end = cond.pos();
+                break (cond, end);
             }
-            _ => body.push(parse_line(lexer)),
+            Some(token) if ends_block(&token.t) => {
+                let pos = token.pos;
+                unclosed(errors, "'UNTIL' expected", token);
+                break (Box::new(Node::Null), pos);
+            }
+            _ => parse_stmt(lexer, errors, &mut body),
         }
-    }
+    };
 
-    let cond = parse_expression(lexer);
-    let pos = Position::range(token.pos, cond.pos());
-    Box::from(Node::Repeat { cond, body, pos })
+    let pos = Position::range(token.pos, end);
+    Ok(Box::from(Node::Repeat { cond, body, pos }))
 }
 
-pub fn parse_for(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_for(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     // skip for token
     let token = lexer.next().unwrap();
-    let iter;
+    let header = recover(lexer, errors, |lexer| {
+        let iter;
+        if let Token {
+            t: TToken::Identifier(name),
+            pos,
+        } = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")?
+        {
+            iter = Box::from(Node::Var { name, pos })
+        } else {
+            unreachable!()
+        }
+        expect_token(lexer, &[TToken::Assignment], "<-")?;
 
-    if let Token {
-        t: TToken::Identifier(name),
-        pos,
-    } = expect_token(lexer, &[TToken::Identifier("".to_string())], "Identifier")
-    {
-        iter = Box::from(Node::Var { name, pos })
-    } else {
-        unreachable!()
-    }
-    expect_token(lexer, &[TToken::Assignment], "<-");
+        let start = parse_expression(lexer)?;
+        expect_token(lexer, &[TToken::To], "'TO'")?;
+        let end = parse_expression(lexer)?;
 
-    let start = parse_expression(lexer);
-    expect_token(lexer, &[TToken::To], "'TO'");
-    let end = parse_expression(lexer);
+        let step = if lexer.peek().unwrap().t == TToken::Step {
+            lexer.next();
+            parse_expression(lexer)?
+        } else {
+            Box::from(Node::Null)
+        };
+        Ok((iter, start, end, step))
+    });
 
     let mut body = Vec::new();
-    let step;
-    if lexer.peek().unwrap().t == TToken::Step {
-        lexer.next();
-        step = parse_expression(lexer);
-    } else {
-        step = Box::from(Node::Null)
-    }
-
     loop {
         match lexer.peek() {
-            Some(Token {
-                t: TToken::EOF,
-                pos,
-            }) => err("'NEXT' expected", pos),
             Some(Token {
                 t: TToken::Next,
                 pos: _,
@@ -102,9 +110,20 @@ pub fn parse_for(lexer: &mut Lexer) -> Box<Node> {
                 lexer.next();
                 break;
             }
-            _ => body.push(parse_line(lexer)),
+            Some(token) if ends_block(&token.t) => {
+                unclosed(errors, "'NEXT' expected", token);
+                return Ok(Box::new(Node::Null));
+            }
+            _ => parse_stmt(lexer, errors, &mut body),
         }
     }
+    // The loop is dropped when its first line could not be parsed
+    let Some((iter, start, end, step)) = header else {
+        if let TToken::Identifier(_) = lexer.peek().unwrap().t {
+            lexer.next();
+        }
+        return Ok(Box::new(Node::Null));
+    };
 
     // Check for optional appearance of iter variable
     let ident = lexer.peek().unwrap().clone();
@@ -119,24 +138,24 @@ pub fn parse_for(lexer: &mut Lexer) -> Box<Node> {
             } => {
                 // Identifier must match the earlier specified one
                 if *name != *_name {
-                    err(format!("Identifier must be '{}' ", _name).as_str(), &pos);
+                    err(format!("Identifier must be '{}' ", _name).as_str(), &pos)?;
                 }
                 lexer.next();
             }
             _ => unreachable!(),
         }
     } else {
-        err("Identifier expected", &ident.pos)
+        err("Identifier expected", &ident.pos)?
     };
     let pos = Position::range(start.pos(), end.pos());
     let range = Box::new(Node::Range { start, end, pos });
 
     let pos = Position::range(token.pos, ident.pos);
-    Box::from(Node::For {
+    Ok(Box::from(Node::For {
         iter,
         range,
         step,
         body,
         pos,
-    })
+    }))
 }
@@ -1,17 +1,23 @@
 use crate::enums::{Node, Position, Token};
-use crate::lexer::{self, Lexer};
+use crate::lexer::Lexer;
 use crate::parser::parse_expr::parse_expression;
 use crate::parser::parse_identifier::parse_identifier;
-use crate::parser::{parse_line, try_parse_assign};
+use crate::parser::{ends_block, parse_stmt, recover, unclosed};
 use crate::tokens::TToken;
-use crate::utils::{err, expect_token};
-use std::ops::Deref;
+use crate::utils::{err, expect_token, PseudoError};
 
-pub fn parse_if(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_if(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     // skip IF token
     let token = lexer.next().unwrap();
-    let cond = parse_expression(lexer);
-    expect_token(lexer, &[TToken::Then], "'THEN'");
+    let cond = recover(lexer, errors, |lexer| {
+        let cond = parse_expression(lexer)?;
+        expect_token(lexer, &[TToken::Then], "'THEN'")?;
+        Ok(cond)
+    })
+    .unwrap_or(Box::new(Node::Null));
 
     let mut true_body = vec![];
     let mut else_encountered = false;
@@ -19,15 +25,11 @@ pub fn parse_if(lexer: &mut Lexer) -> Box<Node> {
 
     let end = loop {
         match lexer.peek() {
-            Some(Token {
-                t: TToken::EOF,
-                pos,
-            }) => err("'ENDIF' expected", pos),
             Some(Token {
                 t: TToken::EndIf,
                 pos,
             }) => {
-                let pos = pos.clone();
+                let pos = *pos;
                 lexer.next();
                 break pos;
             }
@@ -38,75 +40,97 @@ pub fn parse_if(lexer: &mut Lexer) -> Box<Node> {
                 else_encountered = true;
                 lexer.next();
             }
+            Some(token) if ends_block(&token.t) => {
+                let pos = token.pos;
+                unclosed(errors, "'ENDIF' expected", token);
+                break pos;
+            }
             _ => {
                 if else_encountered {
-                    false_body.push(parse_line(lexer))
+                    parse_stmt(lexer, errors, &mut false_body)
                 } else {
-                    true_body.push(parse_line(lexer))
+                    parse_stmt(lexer, errors, &mut true_body)
                 }
             }
         }
     };
-    let pos = Position::range(token.pos, end.clone());
-    Box::from(Node::If {
+    let pos = Position::range(token.pos, end);
+    Ok(Box::from(Node::If {
         cond,
         true_body,
         false_body,
         pos,
-    })
+    }))
 }
 
-pub fn parse_case(lexer: &mut Lexer) -> Box<Node> {
+pub fn parse_case(
+    lexer: &mut Lexer,
+    errors: &mut Vec<PseudoError>,
+) -> Result<Box<Node>, PseudoError> {
     // skip CASE token
     let token = lexer.next().unwrap();
-    expect_token(lexer, &[TToken::Of], "'Of'");
-    let cmp = parse_identifier(lexer);
-    // skip NEWLINE token
-    lexer.next();
+    let cmp = recover(lexer, errors, |lexer| {
+        expect_token(lexer, &[TToken::Of], "'Of'")?;
+        let cmp = parse_identifier(lexer)?;
+        // skip NEWLINE token
+        lexer.next();
+        Ok(cmp)
+    })
+    .unwrap_or(Box::new(Node::Null));
     let mut cases = vec![];
     let mut otherwise = vec![];
 
     let end = loop {
         let token = lexer.peek().unwrap().clone();
         match token.t {
-            TToken::EndCase => break token.pos,
-            TToken::EOF => err("ENDCASE expected", &token.pos),
+            TToken::EndCase => {
+                lexer.next();
+                break token.pos;
+            }
             TToken::Otherwise => {
                 lexer.next();
-                expect_token(lexer, &[TToken::Colon], "':'");
+                recover(lexer, errors, |lexer| {
+                    expect_token(lexer, &[TToken::Colon], "':'")
+                });
                 loop {
-                    match lexer.peek().unwrap() {
-                        Token {
-                            t: TToken::EndCase, ..
-                        } => break,
-                        Token { t: TToken::EOF, .. } => break,
-                        _ => otherwise.push(parse_line(lexer)),
+                    match lexer.peek().unwrap().t {
+                        ref t if ends_block(t) => break,
+                        _ => parse_stmt(lexer, errors, &mut otherwise),
                     }
                 }
             }
+            ref t if ends_block(t) => {
+                unclosed(errors, "ENDCASE expected", &token);
+                break token.pos;
+            }
             _ => {
                 let mut children = Vec::new();
-                let mut start = Box::new(parse_literal(lexer));
-                if lexer.peek().unwrap().t == TToken::To {
-                    lexer.next();
-                    let end = Box::new(parse_literal(lexer));
-                    let pos = Position::range(token.pos, end.pos());
-                    start = Box::new(Node::Range { start, end, pos });
-                }
-                expect_token(lexer, &[TToken::Colon], "':'");
+                let start = recover(lexer, errors, |lexer| {
+                    let mut start = Box::new(parse_literal(lexer)?);
+                    if lexer.peek().unwrap().t == TToken::To {
+                        lexer.next();
+                        let end = Box::new(parse_literal(lexer)?);
+                        let pos = Position::range(token.pos, end.pos());
+                        start = Box::new(Node::Range { start, end, pos });
+                    }
+                    expect_token(lexer, &[TToken::Colon], "':'")?;
+                    Ok(start)
+                })
+                .unwrap_or(Box::new(Node::Null));
                 loop {
                     if try_parse_literal(lexer).is_some() {
                         break;
                     }
                     match lexer.peek().unwrap().t {
-                        TToken::EndCase | TToken::Otherwise => break,
-                        _ => children.push(parse_line(lexer)),
+                        ref t if ends_block(t) => break,
+                        _ => parse_stmt(lexer, errors, &mut children),
                     }
                 }
-                while Node::Null == **children.last().unwrap() {
+                while children.last().is_some_and(|child| Node::Null == **child) {
                     children.pop();
                 }
-                let pos = Position::range(token.pos, children.last().unwrap().pos());
+                let end = children.last().map_or(token.pos, |child| child.pos());
+                let pos = Position::range(token.pos, end);
                 cases.push(Box::from(Node::Case {
                     expr: start,
                     children,
@@ -115,24 +139,23 @@ pub fn parse_case(lexer: &mut Lexer) -> Box<Node> {
             }
         }
     };
-    lexer.next();
 
     let pos = Position::range(token.pos, end);
-    Box::from(Node::Switch {
+    Ok(Box::from(Node::Switch {
         cmp,
         cases,
         otherwise,
         pos,
-    })
+    }))
 }
 
-fn parse_literal(lexer: &mut Lexer) -> Node {
+fn parse_literal(lexer: &mut Lexer) -> Result<Node, PseudoError> {
     let token = lexer.peek().unwrap().clone();
     if let Some(literal) = try_parse_literal(lexer) {
         lexer.next();
-        return literal;
+        return Ok(literal);
     }
-    err("Expected literal", &token.pos);
+    err("Expected literal", &token.pos)
 }
 
 fn try_parse_literal(lexer: &mut Lexer) -> Option<Node> {
@@ -140,6 +163,7 @@ fn try_parse_literal(lexer: &mut Lexer) -> Option<Node> {
     let pos = literal.pos;
     match literal.t.clone() {
         TToken::StringLit(val) => Some(Node::String { val, pos }),
+        TToken::CharLit(val) => Some(Node::Char { val, pos }),
         TToken::IntegerLit(val) => Some(Node::Int { val, pos }),
         TToken::RealLit(val) => Some(Node::Real { val, pos }),
         TToken::BoolLit(val) => Some(Node::Boolean { val, pos }),
@@ -0,0 +1,162 @@
+use std::io::{BufRead, Write};
+use std::ops::Deref;
+
+use pseudoengine::enums::{Node, Token};
+use pseudoengine::executor::{self, Executor};
+use pseudoengine::tokens::TToken;
+use pseudoengine::{lexer, parser, SourceFile};
+
+use crate::console::Console;
+
+const PROMPT: &str = ">>> ";
+const CONTINUE_PROMPT: &str = "... ";
+
+// Read statements from input and run them as they are completed, writing the
+// prompts, the program's OUTPUT and any errors to output. The program's INPUT is
+// read from the same input.
+pub fn repl(input: impl BufRead + 'static, output: impl Write + 'static) {
+    let console = Console::new(input, output);
+    // Every line is kept so errors in earlier definitions can still be reported
+    let mut transcript = SourceFile::new("<repl>", "");
+
+    let mut executor = Executor::with_io(Box::new(console.clone()));
+    let mut buf = String::new();
+    // Line number of the first line in buf
+    let mut chunk_start = 1;
+
+    loop {
+        console.print(match buf.is_empty() {
+            true => PROMPT,
+            false => CONTINUE_PROMPT,
+        });
+        let Some(line) = console.read_line() else {
+            break;
+        };
+        if buf.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+
+        transcript.file.push(line.clone());
+        if buf.is_empty() {
+            chunk_start = transcript.file.len();
+        }
+        buf.push_str(&line);
+        buf.push('\n');
+
+        // Errors are reported and the session carries on from the prompt
+        let tokens = match lexer::lexer_from_line(&mut buf.chars(), chunk_start) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                console.print(&format!(
+                    "{}\n",
+                    e.with_source(&transcript.name).render(&transcript)
+                ));
+                buf.clear();
+                continue;
+            }
+        };
+        if open_blocks(&tokens) > 0 {
+            continue;
+        }
+        buf.clear();
+
+        let mut lex = tokens.into_iter().peekable();
+        let nodes = match parser::parse_program(&mut lex) {
+            Ok(nodes) => nodes,
+            Err(errors) => {
+                for e in errors {
+                    console.print(&format!(
+                        "{}\n",
+                        e.with_source(&transcript.name).render(&transcript)
+                    ));
+                }
+                continue;
+            }
+        };
+        let nodes = nodes
+            .into_iter()
+            .map(|node| match *node {
+                Node::Main { children } => Box::new(Node::Main {
+                    children: children.into_iter().map(echo_expression).collect(),
+                }),
+                node => Box::new(node),
+            })
+            .collect();
+        if let Err(e) = executor::run_with(&mut executor, nodes) {
+            console.print(&format!(
+                "{}\n",
+                e.with_source(&transcript.name).render(&transcript)
+            ));
+            // Drop any procedure scopes left behind by the failed statement
+            executor.scopes.truncate(1);
+        }
+    }
+    console.print("\n");
+}
+
+// Bare expressions typed at the prompt are printed as if passed to OUTPUT
+fn echo_expression(node: Box<Node>) -> Box<Node> {
+    match node.deref() {
+        Node::Var { .. } | Node::ArrayVar { .. } | Node::Unary { .. } | Node::Binary { .. } => {
+            let pos = node.pos();
+            Box::new(Node::Output {
+                children: vec![node],
+                pos,
+            })
+        }
+        Node::Composite { children, .. } => match children.last().unwrap().deref() {
+            Node::FunctionCall { .. } => node,
+            _ => {
+                let pos = node.pos();
+                Box::new(Node::Output {
+                    children: vec![node],
+                    pos,
+                })
+            }
+        },
+        _ => node,
+    }
+}
+
+// Number of blocks opened but not yet closed in the buffered input
+fn open_blocks(tokens: &[Token]) -> i32 {
+    let mut depth = 0;
+    let mut line_start = true;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.t {
+            TToken::If
+            | TToken::While
+            | TToken::For
+            | TToken::Repeat
+            | TToken::Case
+            | TToken::Procedure
+            | TToken::Function
+            | TToken::Class
+                if line_start =>
+            {
+                depth += 1
+            }
+            // Only the record form of TYPE spans multiple lines
+            TToken::Type if line_start => {
+                if let Some(Token {
+                    t: TToken::Newline, ..
+                }) = tokens.get(i + 2)
+                {
+                    depth += 1
+                }
+            }
+            TToken::EndIf
+            | TToken::EndWhile
+            | TToken::Next
+            | TToken::Until
+            | TToken::EndCase
+            | TToken::EndProcedure
+            | TToken::EndFunction
+            | TToken::EndClass
+            | TToken::EndType => depth -= 1,
+            _ => {}
+        }
+        line_start = matches!(token.t, TToken::Newline | TToken::Public | TToken::Private);
+    }
+    depth
+}
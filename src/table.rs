@@ -0,0 +1,74 @@
+// The ways a table, such as a trace table or an identifier table, can be written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableFormat {
+    Plain,
+    Csv,
+    Markdown,
+}
+
+// A table with its headings in the first row
+pub(crate) fn render(table: &[Vec<String>], format: TableFormat) -> String {
+    match format {
+        TableFormat::Plain => plain(table),
+        TableFormat::Csv => csv(table),
+        TableFormat::Markdown => markdown(table),
+    }
+}
+
+fn plain(table: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|i| {
+            table
+                .iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |row: &Vec<String>| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        cells.join(" | ").trim_end().to_string() + "\n"
+    };
+    let mut text = line(&table[0]);
+    let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    text.push_str(&(rule.join("-+-") + "\n"));
+    for row in &table[1..] {
+        text.push_str(&line(row));
+    }
+    text
+}
+
+fn csv(table: &[Vec<String>]) -> String {
+    let mut text = String::new();
+    for row in table {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|cell| {
+                if cell.contains([',', '"', '\n']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.clone()
+                }
+            })
+            .collect();
+        text.push_str(&(cells.join(",") + "\n"));
+    }
+    text
+}
+
+fn markdown(table: &[Vec<String>]) -> String {
+    let line = |row: &Vec<String>| {
+        let cells: Vec<String> = row.iter().map(|cell| cell.replace('|', "\\|")).collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let mut text = line(&table[0]);
+    text.push_str(&format!("|{}\n", " --- |".repeat(table[0].len())));
+    for row in &table[1..] {
+        text.push_str(&line(row));
+    }
+    text
+}
@@ -0,0 +1,137 @@
+use std::fs::{self, read_to_string};
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use pseudoengine::{Interpreter, Jail, Limits, MemoryFileSystem};
+
+// Lines of a diff shown for each failing program before it is cut short
+const DIFF_LIMIT: usize = 10;
+// Submissions cannot be trusted to finish, one that never does fails instead
+const MAX_STEPS: u64 = 10_000_000;
+const MAX_TIME: Duration = Duration::from_secs(10);
+
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+// Run every *.pseudo program in dir, feeding it the sibling .in file as INPUT
+// and comparing what it prints against the sibling .out file
+pub fn run_tests(dir: &Path) -> io::Result<Summary> {
+    let mut summary = Summary {
+        passed: 0,
+        failed: 0,
+    };
+    let programs = programs(dir)?;
+    if programs.is_empty() {
+        println!("No programs found in {}", dir.display());
+        return Ok(summary);
+    }
+    for program in programs {
+        let name = program.display().to_string();
+        match run_test(&program) {
+            Ok(()) => {
+                summary.passed += 1;
+                println!("PASS {}", name);
+            }
+            Err(diff) => {
+                summary.failed += 1;
+                println!("FAIL {}", name);
+                for line in diff {
+                    println!("    {}", line);
+                }
+            }
+        }
+    }
+    println!(
+        "\n{} passed, {} failed, {} total",
+        summary.passed,
+        summary.failed,
+        summary.passed + summary.failed
+    );
+    Ok(summary)
+}
+
+// A directory that cannot be read is an error rather than one with no programs
+fn programs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut programs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "pseudo") {
+            programs.push(path);
+        }
+    }
+    programs.sort();
+    Ok(programs)
+}
+
+fn run_test(program: &Path) -> Result<(), Vec<String>> {
+    let source = read_to_string(program).map_err(|_| vec!["program cannot be read".to_string()])?;
+    let expected = match read_to_string(program.with_extension("out")) {
+        Ok(expected) => expected,
+        Err(_) => return Err(vec!["missing .out file".to_string()]),
+    };
+    // Programs that never ask for INPUT do not need an .in file
+    let input = read_to_string(program.with_extension("in")).unwrap_or_default();
+
+    // Each program gets files of its own that are gone once it finishes
+    let outcome = Interpreter::new(&program.display().to_string(), &source)
+        .stdin(Cursor::new(input))
+        .filesystem(Jail::new(MemoryFileSystem::new()))
+        .limits(Limits {
+            max_steps: Some(MAX_STEPS),
+            max_time: Some(MAX_TIME),
+            ..Limits::default()
+        })
+        .run();
+    let mut actual = outcome.output;
+    // A program that stops on an error is expected to show the error as its last line,
+    // or a line for each syntax error when it never started
+    for error in outcome.errors {
+        actual.push_str(&format!("error: {}\n", error.message));
+    }
+
+    let diff = diff_lines(&expected, &actual);
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(diff)
+    }
+}
+
+// Line by line comparison, ignoring line endings and trailing blank lines
+fn diff_lines(expected: &str, actual: &str) -> Vec<String> {
+    let expected = trimmed_lines(expected);
+    let actual = trimmed_lines(actual);
+    let mut diff = Vec::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let want = expected.get(i);
+        let got = actual.get(i);
+        if want == got {
+            continue;
+        }
+        if diff.len() >= DIFF_LIMIT {
+            diff.push("...".to_string());
+            break;
+        }
+        let line = match (want, got) {
+            (Some(want), Some(got)) => {
+                format!("line {}: expected {:?}, got {:?}", i + 1, want, got)
+            }
+            (Some(want), None) => format!("line {}: expected {:?}, got nothing", i + 1, want),
+            (None, Some(got)) => format!("line {}: unexpected {:?}", i + 1, got),
+            (None, None) => unreachable!(),
+        };
+        diff.push(line);
+    }
+    diff
+}
+
+fn trimmed_lines(text: &str) -> Vec<&str> {
+    let mut lines = text.lines().collect::<Vec<&str>>();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
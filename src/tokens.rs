@@ -1,4 +1,4 @@
-use crate::enums::{Node, Token, VariableType};
+use crate::enums::VariableType;
 use chrono::NaiveDate;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -6,6 +6,7 @@ pub enum TToken {
     // Literals
     BoolLit(bool),
     StringLit(String),
+    CharLit(char),
     IntegerLit(i64),
     RealLit(f64),
     DateLit(NaiveDate),
@@ -78,6 +79,9 @@ pub enum TToken {
     WriteFile,
     Newline,
 
+    // Only kept by lexer_with_comments, the text after //
+    Comment(String),
+
     Identifier(String),
 
     EOF,
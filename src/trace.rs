@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::enums::{Node, Position};
+use crate::executor::hook::Hook;
+use crate::executor::Value;
+use crate::identifiers::IdentifierTable;
+use crate::table::{self, TableFormat};
+use crate::utils::SourceFile;
+
+const LINE: &str = "Line";
+const OUTPUT: &str = "OUTPUT";
+
+// Records every value given to a variable and every line printed, one row each,
+// to be shown as a trace table once the program ends. Clones share their rows.
+#[derive(Clone, Default)]
+pub struct TraceTable {
+    rows: Arc<Mutex<Vec<Row>>>,
+    // Only these columns, in this order, when set
+    selected: Option<Vec<String>>,
+    // Names as they are written where they are declared, by their name in lowercase
+    names: Arc<Mutex<HashMap<String, String>>>,
+}
+
+struct Row {
+    line: usize,
+    column: String,
+    value: String,
+}
+
+impl TraceTable {
+    pub fn new() -> Self {
+        TraceTable::default()
+    }
+
+    // Keep only the named variables. A name also selects the elements and
+    // properties of that variable, and OUTPUT selects the printed lines.
+    pub fn select(mut self, names: &[&str]) -> Self {
+        self.selected = Some(
+            names
+                .iter()
+                .map(|name| name.trim().to_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    pub fn render(&self, format: TableFormat) -> String {
+        let columns = self.columns();
+        let rows = self.rows.lock().unwrap();
+        let mut table = vec![columns.iter().map(|column| self.heading(column)).collect()];
+        for row in rows.iter() {
+            if let Some(i) = columns.iter().position(|column| *column == row.column) {
+                let mut cells = vec![String::new(); columns.len()];
+                cells[0] = row.line.to_string();
+                cells[i] = row.value.clone();
+                table.push(cells);
+            }
+        }
+        table::render(&table, format)
+    }
+
+    // Line first, then the variables in the order they were first changed, then OUTPUT
+    fn columns(&self) -> Vec<String> {
+        let rows = self.rows.lock().unwrap();
+        let mut seen: Vec<String> = Vec::new();
+        for row in rows.iter() {
+            if !seen.contains(&row.column) {
+                seen.push(row.column.clone());
+            }
+        }
+        let mut columns = vec![LINE.to_string()];
+        match &self.selected {
+            Some(selected) => {
+                for name in selected {
+                    for column in &seen {
+                        if matches(column, name) && !columns.contains(column) {
+                            columns.push(column.clone());
+                        }
+                    }
+                }
+            }
+            None => {
+                columns.extend(seen.iter().filter(|column| *column != OUTPUT).cloned());
+                if seen.iter().any(|column| column == OUTPUT) {
+                    columns.push(OUTPUT.to_string());
+                }
+            }
+        }
+        columns
+    }
+
+    // The column as the program spells its names, nums[1] as Nums[1]
+    fn heading(&self, column: &str) -> String {
+        let names = self.names.lock().unwrap();
+        let word = |c: char| c.is_alphanumeric() || c == '_';
+        let mut heading = String::new();
+        let mut rest = column;
+        while let Some(start) = rest.find(word) {
+            heading.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(|c: char| !word(c)).unwrap_or(rest.len());
+            let name = &rest[..end];
+            heading.push_str(names.get(name).map_or(name, |written| written.as_str()));
+            rest = &rest[end..];
+        }
+        heading + rest
+    }
+
+    fn record(&mut self, column: &str, value: String, pos: &Position) {
+        self.rows.lock().unwrap().push(Row {
+            line: pos.line_start,
+            column: column.to_string(),
+            value,
+        });
+    }
+}
+
+impl Hook for TraceTable {
+    fn program(&mut self, source: &SourceFile, ast: &Vec<Box<Node>>) {
+        let mut names = self.names.lock().unwrap();
+        for name in IdentifierTable::new(source, ast).names() {
+            names
+                .entry(name.to_lowercase())
+                .or_insert_with(|| name.to_string());
+        }
+    }
+
+    fn assigned(&mut self, target: &str, value: &Value, pos: &Position) {
+        // Values are written the way OUTPUT prints them
+        self.record(target, value.to_string(), pos);
+    }
+
+    fn output(&mut self, line: &str, pos: &Position) {
+        self.record(OUTPUT, line.to_string(), pos);
+    }
+}
+
+// nums selects nums itself as well as nums[1] and nums[2]
+fn matches(column: &str, name: &str) -> bool {
+    let column = column.to_lowercase();
+    match column.strip_prefix(name) {
+        Some(rest) => rest.is_empty() || rest.starts_with('[') || rest.starts_with('.'),
+        None => false,
+    }
+}
@@ -0,0 +1,2109 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+
+use crate::checker::{Signature, TypeDef};
+use crate::enums::{Index, Node, VariableType};
+use crate::executor::run_builtins::builtin;
+use crate::tokens::TToken;
+use crate::transpile::lower::{
+    body, bracket, declared_at_top, everywhere, is_super_new, lower_bounds, methods, returns,
+    routine_name, shifted, variable, walk, Boxing, Expr, Program,
+};
+use crate::transpile::{
+    camel_case, class_name, const_int, pascal_case, upper_snake_case, Names, Part, Style, Writer,
+};
+
+const INDENT: &str = "    ";
+
+// Keywords, and the classes and prelude names the translation relies on
+const RESERVED: &[&str] = &[
+    "abstract",
+    "assert",
+    "boolean",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extends",
+    "final",
+    "finally",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "int",
+    "interface",
+    "long",
+    "native",
+    "new",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "static",
+    "strictfp",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "transient",
+    "try",
+    "void",
+    "volatile",
+    "while",
+    "true",
+    "false",
+    "null",
+    "var",
+    "record",
+    "yield",
+    "args",
+    "copy",
+    "main",
+    "Arrays",
+    "Boolean",
+    "Character",
+    "Double",
+    "Integer",
+    "LocalDate",
+    "Math",
+    "Object",
+    "Array",
+    "Field",
+    "Json",
+    "Modifier",
+    "Stream",
+    "String",
+    "System",
+    "show",
+    "input",
+    "stdin",
+    "files",
+    "openFile",
+    "openRandom",
+    "closeFile",
+    "readFile",
+    "writeFile",
+    "seek",
+    "getRecord",
+    "putRecord",
+    "eof",
+    "right",
+    "mid",
+    "strToNum",
+    "isNum",
+    "RandomFile",
+    "IntRef",
+    "DoubleRef",
+    "BooleanRef",
+    "CharRef",
+    "Ref",
+];
+
+// In the order the parts are written out, at the end of the class
+const PRELUDE: &[Part] = &[
+    Part {
+        name: "show",
+        imports: &[
+            "java.math.BigDecimal",
+            "java.time.LocalDate",
+            "java.time.format.DateTimeFormatter",
+        ],
+        needs: &[],
+        code: r#"// The value as OUTPUT writes it
+static String show(Object value) {
+    if (value instanceof Boolean b) {
+        return b ? "TRUE" : "FALSE";
+    }
+    if (value instanceof Double d) {
+        if (d.isNaN()) {
+            return "NaN";
+        }
+        if (d.isInfinite()) {
+            return d > 0 ? "inf" : "-inf";
+        }
+        return new BigDecimal(Double.toString(d)).stripTrailingZeros().toPlainString();
+    }
+    if (value instanceof LocalDate date) {
+        return date.format(DateTimeFormatter.ofPattern("dd-MM-yyyy"));
+    }
+    return String.valueOf(value);
+}"#,
+    },
+    Part {
+        name: "input",
+        imports: &[
+            "java.io.BufferedReader",
+            "java.io.IOException",
+            "java.io.InputStreamReader",
+            "java.io.UncheckedIOException",
+        ],
+        needs: &[],
+        code: r#"static final BufferedReader stdin = new BufferedReader(new InputStreamReader(System.in));
+
+static String input() {
+    try {
+        String line = stdin.readLine();
+        return line == null ? "" : line;
+    } catch (IOException e) {
+        throw new UncheckedIOException(e);
+    }
+}"#,
+    },
+    Part {
+        name: "files",
+        imports: &[
+            "java.io.Closeable",
+            "java.io.IOException",
+            "java.io.UncheckedIOException",
+            "java.util.HashMap",
+            "java.util.Map",
+        ],
+        needs: &[],
+        code: r#"// Open files by the name they were opened with
+static final Map<String, Closeable> files = new HashMap<>();
+
+static void closeFile(String name) {
+    try {
+        files.remove(name).close();
+    } catch (IOException e) {
+        throw new UncheckedIOException(e);
+    }
+}"#,
+    },
+    Part {
+        name: "openFile",
+        imports: &[
+            "java.io.BufferedReader",
+            "java.io.FileReader",
+            "java.io.FileWriter",
+            "java.io.IOException",
+            "java.io.PrintWriter",
+            "java.io.UncheckedIOException",
+        ],
+        needs: &["files"],
+        code: r#"static void openFile(String name, String mode) {
+    try {
+        files.put(name, switch (mode) {
+            case "WRITE" -> new PrintWriter(new FileWriter(name));
+            case "APPEND" -> new PrintWriter(new FileWriter(name, true));
+            default -> new BufferedReader(new FileReader(name));
+        });
+    } catch (IOException e) {
+        throw new UncheckedIOException(e);
+    }
+}"#,
+    },
+    Part {
+        name: "readFile",
+        imports: &[
+            "java.io.BufferedReader",
+            "java.io.IOException",
+            "java.io.UncheckedIOException",
+        ],
+        needs: &["files"],
+        code: r#"static String readFile(String name) {
+    try {
+        String line = ((BufferedReader) files.get(name)).readLine();
+        return line == null ? "" : line;
+    } catch (IOException e) {
+        throw new UncheckedIOException(e);
+    }
+}"#,
+    },
+    Part {
+        name: "writeFile",
+        imports: &["java.io.PrintWriter"],
+        needs: &["files"],
+        code: r#"static void writeFile(String name, String line) {
+    ((PrintWriter) files.get(name)).print(line + "\n");
+}"#,
+    },
+    Part {
+        name: "eof",
+        imports: &[
+            "java.io.BufferedReader",
+            "java.io.IOException",
+            "java.io.UncheckedIOException",
+        ],
+        needs: &["files"],
+        code: r#"static boolean eof(String name) {
+    BufferedReader reader = (BufferedReader) files.get(name);
+    try {
+        reader.mark(1);
+        boolean atEnd = reader.read() == -1;
+        reader.reset();
+        return atEnd;
+    } catch (IOException e) {
+        throw new UncheckedIOException(e);
+    }
+}"#,
+    },
+    Part {
+        name: "random",
+        imports: &[
+            "java.io.Closeable",
+            "java.io.IOException",
+            "java.io.UncheckedIOException",
+            "java.lang.reflect.Array",
+            "java.lang.reflect.Field",
+            "java.lang.reflect.Modifier",
+            "java.nio.file.Files",
+            "java.nio.file.Path",
+            "java.time.LocalDate",
+            "java.util.ArrayList",
+            "java.util.Arrays",
+            "java.util.HashMap",
+            "java.util.List",
+            "java.util.Map",
+        ],
+        needs: &["files"],
+        code: r#"// A file opened for RANDOM, holding one record per line as JSON
+static class RandomFile implements Closeable {
+    final Path path;
+    final List<String> lines = new ArrayList<>();
+    int position = 1;
+
+    RandomFile(String name) {
+        path = Path.of(name);
+        try {
+            if (Files.exists(path)) {
+                lines.addAll(Files.readAllLines(path));
+            }
+        } catch (IOException e) {
+            throw new UncheckedIOException(e);
+        }
+    }
+
+    void getRecord(Object record) {
+        fill(record, (Map<?, ?>) new Json(lines.get(position - 1)).value());
+    }
+
+    void putRecord(Object record) {
+        while (lines.size() < position) {
+            lines.add("");
+        }
+        lines.set(position - 1, json(record));
+    }
+
+    @Override
+    public void close() throws IOException {
+        Files.writeString(path, String.join("\n", lines));
+    }
+
+    // Fields are matched to keys by name, as the interpreter spells them
+    static String key(Field field) {
+        return field.getName().replace("_", "").toLowerCase();
+    }
+
+    static List<Field> fields(Object record) {
+        return Arrays.stream(record.getClass().getDeclaredFields())
+            .filter(field -> !Modifier.isStatic(field.getModifiers()))
+            .toList();
+    }
+
+    static void fill(Object record, Map<?, ?> data) {
+        try {
+            for (Field field : fields(record)) {
+                for (Map.Entry<?, ?> entry : data.entrySet()) {
+                    if (entry.getKey().toString().replace("_", "").equals(key(field))) {
+                        field.set(record, convert(field.getType(), field.get(record), entry.getValue()));
+                    }
+                }
+            }
+        } catch (IllegalAccessException e) {
+            throw new IllegalStateException(e);
+        }
+    }
+
+    static Object convert(Class<?> type, Object current, Object value) {
+        if (type == int.class) {
+            return ((Number) value).intValue();
+        } else if (type == double.class) {
+            return ((Number) value).doubleValue();
+        } else if (type == char.class) {
+            String text = (String) value;
+            return text.isEmpty() ? ' ' : text.charAt(0);
+        } else if (type == LocalDate.class) {
+            return LocalDate.parse((String) value);
+        } else if (type.isArray()) {
+            fillArray(current, (List<?>) value, 0);
+            return current;
+        } else if (value instanceof Map<?, ?> map) {
+            fill(current, map);
+            return current;
+        }
+        return value;
+    }
+
+    // Arrays of several dimensions are kept as one list, row after row
+    static int fillArray(Object array, List<?> values, int from) {
+        Class<?> type = array.getClass().getComponentType();
+        for (int i = 0; i < Array.getLength(array) && from < values.size(); i++) {
+            Object element = Array.get(array, i);
+            if (type.isArray()) {
+                from = fillArray(element, values, from);
+            } else {
+                Array.set(array, i, convert(type, element, values.get(from++)));
+            }
+        }
+        return from;
+    }
+
+    static String json(Object value) {
+        if (value == null || value instanceof Boolean || value instanceof Integer) {
+            return String.valueOf(value);
+        } else if (value instanceof Double || value instanceof Float) {
+            return value.toString();
+        } else if (value instanceof String || value instanceof Character || value instanceof LocalDate) {
+            StringBuilder text = new StringBuilder("\"");
+            for (char letter : value.toString().toCharArray()) {
+                switch (letter) {
+                    case '"' -> text.append("\\\"");
+                    case '\\' -> text.append("\\\\");
+                    case '\n' -> text.append("\\n");
+                    case '\r' -> text.append("\\r");
+                    case '\t' -> text.append("\\t");
+                    default -> text.append(letter < ' ' ? String.format("\\u%04x", (int) letter) : letter);
+                }
+            }
+            return text.append('"').toString();
+        } else if (value.getClass().isArray()) {
+            List<String> values = new ArrayList<>();
+            flatten(value, values);
+            return "[" + String.join(",", values) + "]";
+        }
+        List<String> pairs = new ArrayList<>();
+        try {
+            for (Field field : fields(value)) {
+                pairs.add(json(key(field)) + ":" + json(field.get(value)));
+            }
+        } catch (IllegalAccessException e) {
+            throw new IllegalStateException(e);
+        }
+        return "{" + String.join(",", pairs) + "}";
+    }
+
+    static void flatten(Object array, List<String> values) {
+        for (int i = 0; i < Array.getLength(array); i++) {
+            Object element = Array.get(array, i);
+            if (element != null && element.getClass().isArray()) {
+                flatten(element, values);
+            } else {
+                values.add(json(element));
+            }
+        }
+    }
+}
+
+// Reads the JSON a RANDOM file holds
+static class Json {
+    final String text;
+    int at = 0;
+
+    Json(String text) {
+        this.text = text;
+    }
+
+    Object value() {
+        skip();
+        char next = text.charAt(at);
+        if (next == '{') {
+            Map<String, Object> map = new HashMap<>();
+            at++;
+            while (skip() != '}') {
+                String key = (String) value();
+                skip();
+                at++;
+                map.put(key, value());
+                if (skip() == ',') {
+                    at++;
+                }
+            }
+            at++;
+            return map;
+        } else if (next == '[') {
+            List<Object> list = new ArrayList<>();
+            at++;
+            while (skip() != ']') {
+                list.add(value());
+                if (skip() == ',') {
+                    at++;
+                }
+            }
+            at++;
+            return list;
+        } else if (next == '"') {
+            StringBuilder value = new StringBuilder();
+            at++;
+            while (text.charAt(at) != '"') {
+                char letter = text.charAt(at++);
+                if (letter == '\\') {
+                    letter = text.charAt(at++);
+                    switch (letter) {
+                        case 'n' -> letter = '\n';
+                        case 'r' -> letter = '\r';
+                        case 't' -> letter = '\t';
+                        case 'b' -> letter = '\b';
+                        case 'f' -> letter = '\f';
+                        case 'u' -> {
+                            letter = (char) Integer.parseInt(text.substring(at, at + 4), 16);
+                            at += 4;
+                        }
+                        default -> {
+                        }
+                    }
+                }
+                value.append(letter);
+            }
+            at++;
+            return value.toString();
+        }
+        int start = at;
+        while (at < text.length() && ",}] \t".indexOf(text.charAt(at)) < 0) {
+            at++;
+        }
+        String word = text.substring(start, at);
+        return switch (word) {
+            case "true" -> true;
+            case "false" -> false;
+            case "null" -> null;
+            default -> Double.parseDouble(word);
+        };
+    }
+
+    char skip() {
+        while (Character.isWhitespace(text.charAt(at))) {
+            at++;
+        }
+        return text.charAt(at);
+    }
+}
+
+static void openRandom(String name) {
+    files.put(name, new RandomFile(name));
+}
+
+static void seek(String name, int position) {
+    ((RandomFile) files.get(name)).position = position;
+}
+
+static void getRecord(String name, Object record) {
+    ((RandomFile) files.get(name)).getRecord(record);
+}
+
+static void putRecord(String name, Object record) {
+    ((RandomFile) files.get(name)).putRecord(record);
+}"#,
+    },
+    Part {
+        name: "right",
+        imports: &[],
+        needs: &[],
+        code: r#"static String right(String text, int length) {
+    return text.substring(text.length() - length);
+}"#,
+    },
+    Part {
+        name: "mid",
+        imports: &[],
+        needs: &[],
+        code: r#"static String mid(String text, int start, int length) {
+    return text.substring(start - 1, start - 1 + length);
+}"#,
+    },
+    Part {
+        name: "strToNum",
+        imports: &[],
+        needs: &[],
+        code: r#"static double strToNum(String text) {
+    return Double.parseDouble(text);
+}"#,
+    },
+    Part {
+        name: "isNum",
+        imports: &[],
+        needs: &[],
+        code: r#"static boolean isNum(String text) {
+    try {
+        Double.parseDouble(text);
+        return true;
+    } catch (NumberFormatException e) {
+        return false;
+    }
+}"#,
+    },
+    Part {
+        name: "IntRef",
+        imports: &[],
+        needs: &[],
+        code: r#"// An INTEGER variable shared with a BYREF parameter or a pointer
+static class IntRef {
+    int value;
+
+    IntRef(int value) {
+        this.value = value;
+    }
+}"#,
+    },
+    Part {
+        name: "DoubleRef",
+        imports: &[],
+        needs: &[],
+        code: r#"// A REAL variable shared with a BYREF parameter or a pointer
+static class DoubleRef {
+    double value;
+
+    DoubleRef(double value) {
+        this.value = value;
+    }
+}"#,
+    },
+    Part {
+        name: "BooleanRef",
+        imports: &[],
+        needs: &[],
+        code: r#"// A BOOLEAN variable shared with a BYREF parameter or a pointer
+static class BooleanRef {
+    boolean value;
+
+    BooleanRef(boolean value) {
+        this.value = value;
+    }
+}"#,
+    },
+    Part {
+        name: "CharRef",
+        imports: &[],
+        needs: &[],
+        code: r#"// A CHAR variable shared with a BYREF parameter or a pointer
+static class CharRef {
+    char value;
+
+    CharRef(char value) {
+        this.value = value;
+    }
+}"#,
+    },
+    Part {
+        name: "Ref",
+        imports: &[],
+        needs: &[],
+        code: r#"// Any other variable shared with a BYREF parameter or a pointer
+static class Ref<T> {
+    T value;
+
+    Ref(T value) {
+        this.value = value;
+    }
+}"#,
+    },
+];
+
+// How tightly an expression binds, so operands are bracketed only when they must be
+const OR: u8 = 1;
+const AND: u8 = 2;
+const EQ: u8 = 3;
+const CMP: u8 = 4;
+const ADD: u8 = 5;
+const MUL: u8 = 6;
+const UNARY: u8 = 7;
+const ATOM: u8 = 8;
+
+// A piece of a line of OUTPUT or WRITEFILE
+enum Piece {
+    Literal(String),
+    // The expression, and whether it is already a String
+    Value(Expr, bool),
+}
+
+struct Java<'a> {
+    names: Names,
+    program: Program<'a>,
+    out: Writer,
+    // Globals kept as fields of the class, the others are local to main
+    statics: HashSet<String>,
+    // The statements of the scope being written out, to tell how FOR counters are used
+    scope_body: Vec<Box<Node>>,
+    // Names given to CONSTANTs, which Java folds into the expressions using them
+    constants: HashSet<String>,
+    // Assignments putting back what a procedure call changed in holders made for
+    // properties and elements handed to BYREF parameters
+    write_back: Option<Vec<String>>,
+}
+
+pub(crate) fn emit(name: &str, text: &str, children: &[Box<Node>]) -> String {
+    let style = Style {
+        value: camel_case,
+        constant: upper_snake_case,
+        type_name: pascal_case,
+        variant: upper_snake_case,
+        routine: camel_case,
+        ignore_case: false,
+    };
+    // Simple variables handed to a BYREF parameter or pointed to are kept in a holder
+    let boxing = Boxing {
+        byref: true,
+        pointers: true,
+    };
+    let mut names = Names::new(text, children, RESERVED, style);
+    let class = names.fresh(&class_name(name));
+    let mut java = Java {
+        names,
+        program: Program::new(children, boxing),
+        out: Writer::new(INDENT, PRELUDE),
+        statics: HashSet::new(),
+        scope_body: vec![],
+        constants: HashSet::new(),
+        write_back: None,
+    };
+    for node in everywhere(children) {
+        if let Node::Const { name, .. } = node.deref() {
+            java.constants.insert(name.clone());
+        }
+    }
+    java.statics = java.program.statics(children);
+    java.out.depth = 1;
+
+    let main = java.program.main.clone();
+    let statements: Vec<Box<Node>> = main.iter().map(|node| (*node).clone()).collect();
+    let mut constants = vec![];
+    walk(&statements, &mut |node| {
+        if let Node::Const { .. } = node {
+            constants.push(node.clone());
+        }
+    });
+    for node in &constants {
+        if let Node::Const { name, val, .. } = node {
+            let t = java.program.type_of(val);
+            let value = java.expr(val).0;
+            let t = t.map_or("Object".to_string(), |t| java.java_type(&t));
+            let name = java.names.get(name);
+            java.out
+                .line(format!("static final {} {} = {};", t, name, value));
+        }
+    }
+    java.out.blank(1);
+    for (name, t) in java.program.variables() {
+        if java.statics.contains(&name) {
+            let declaration = java.declaration(&name, &t);
+            java.out.line(format!("static {};", declaration));
+        }
+    }
+    let definitions: Vec<&Box<Node>> = java
+        .program
+        .types
+        .iter()
+        .chain(&java.program.routines)
+        .copied()
+        .collect();
+    for node in definitions {
+        java.out.blank(1);
+        java.definition(node);
+    }
+    java.out.blank(1);
+    java.out
+        .line("public static void main(String[] args) {".to_string());
+    java.out.depth += 1;
+    java.scope_body = statements.clone();
+    // FOR counters never declared are declared by the loop
+    for (name, _) in java.program.variables() {
+        if !java.statics.contains(&name) && !declared_at_top(&main, &name) {
+            let declaration = java.declaration(&name, &VariableType::Integer);
+            java.out.line(format!("{};", declaration));
+        }
+    }
+    java.stmts(&statements);
+    java.out.depth -= 1;
+    java.out.line("}".to_string());
+    java.finish(name, &class)
+}
+
+impl<'a> Java<'a> {
+    // The header, imports and prelude around the class
+    fn finish(mut self, name: &str, class: &str) -> String {
+        let mut out = self.out.header("//", name);
+        out.push(format!("// Save as {}.java, for Java 17 or later", class));
+        let prelude = self.out.prelude();
+        let body = self.out.body();
+        if !self.out.imports.is_empty() {
+            out.push(String::new());
+            out.extend(
+                self.out
+                    .imports
+                    .iter()
+                    .map(|import| format!("import {};", import)),
+            );
+        }
+        out.push(String::new());
+        out.push(format!("public class {} {{", class));
+        out.extend(body);
+        for code in prelude {
+            out.push(String::new());
+            out.extend(code.lines().map(|line| match line.is_empty() {
+                true => String::new(),
+                false => format!("{}{}", INDENT, line),
+            }));
+        }
+        out.push("}".to_string());
+        out.join("\n") + "\n"
+    }
+
+    fn java_type(&mut self, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Integer => "int".to_string(),
+            VariableType::Real => "double".to_string(),
+            VariableType::Boolean => "boolean".to_string(),
+            VariableType::Char => "char".to_string(),
+            VariableType::String => "String".to_string(),
+            VariableType::Date => {
+                self.out.import("java.time.LocalDate");
+                "LocalDate".to_string()
+            }
+            VariableType::Array { shape, t } => {
+                format!("{}{}", self.java_type(&t), "[]".repeat(shape.len()))
+            }
+            VariableType::Pointer(t) => match self.program.plain(&t) {
+                true => self.ref_type(&t),
+                false => self.java_type(&t),
+            },
+            VariableType::Custom(name) => self.names.type_name(&name),
+        }
+    }
+
+    // The holder a simple variable is kept in when it is shared
+    fn ref_type(&mut self, t: &VariableType) -> String {
+        let t = self.program.checker.resolve(t);
+        let holder = match t {
+            VariableType::Integer => "IntRef",
+            VariableType::Real => "DoubleRef",
+            VariableType::Boolean => "BooleanRef",
+            VariableType::Char => "CharRef",
+            _ => "Ref",
+        };
+        self.out.use_part(holder);
+        match holder {
+            "Ref" => format!("Ref<{}>", self.java_type(&t)),
+            _ => holder.to_string(),
+        }
+    }
+
+    // A new holder for a value, which the type can be inferred for
+    fn new_ref(&mut self, t: &VariableType, value: String) -> String {
+        let holder = self.ref_type(t);
+        match holder.starts_with("Ref<") {
+            true => format!("new Ref<>({})", value),
+            false => format!("new {}({})", holder, value),
+        }
+    }
+
+    // The value a variable of this type starts out with
+    fn default(&mut self, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Integer => "0".to_string(),
+            VariableType::Real => "0.0".to_string(),
+            VariableType::Boolean => "false".to_string(),
+            VariableType::String => "\"\"".to_string(),
+            VariableType::Char => "' '".to_string(),
+            VariableType::Date => {
+                self.out.import("java.time.LocalDate");
+                "LocalDate.of(1970, 1, 1)".to_string()
+            }
+            VariableType::Array { shape, t } => self.array(&shape, &t),
+            VariableType::Custom(name) if self.program.is_record(&name) => {
+                format!("new {}()", self.names.type_name(&name))
+            }
+            VariableType::Custom(_) | VariableType::Pointer(_) => "null".to_string(),
+        }
+    }
+
+    // A new array, its elements filled with their starting value when Java's differs
+    fn array(&mut self, shape: &[Index], t: &VariableType) -> String {
+        let lengths: Vec<i64> = shape
+            .iter()
+            .map(|index| (index.upper - index.lower + 1).max(0))
+            .collect();
+        let element = self.java_type(t);
+        let t = self.program.checker.resolve(t);
+        let zeroed = matches!(
+            t,
+            VariableType::Integer | VariableType::Real | VariableType::Boolean
+        ) || self.program.default_is_null(&t);
+        if zeroed {
+            let dims: String = lengths.iter().map(|n| format!("[{}]", n)).collect();
+            return format!("new {}{}", element, dims);
+        }
+        self.out.import("java.util.stream.Stream");
+        let (mut value, mut dims, skip) = match t {
+            VariableType::Char => (
+                format!("\" \".repeat({}).toCharArray()", lengths.last().unwrap()),
+                1,
+                1,
+            ),
+            t => (self.default(&t), 0, 0),
+        };
+        for length in lengths.iter().rev().skip(skip) {
+            dims += 1;
+            value = format!(
+                "Stream.generate(() -> {}).limit({}).toArray({}{}::new)",
+                value,
+                length,
+                element,
+                "[]".repeat(dims)
+            );
+        }
+        value
+    }
+
+    // `type name = value`, with the holder when the variable is shared
+    fn declaration(&mut self, name: &String, t: &VariableType) -> String {
+        let var = self.names.get(name);
+        if self.program.is_boxed(name) {
+            let holder = self.ref_type(t);
+            let default = self.default(t);
+            let value = self.new_ref(t, default);
+            return format!("{} {} = {}", holder, var, value);
+        }
+        let java_type = self.java_type(t);
+        let default = self.default(t);
+        format!("{} {} = {}", java_type, var, default)
+    }
+
+    // The copy made when an array or record is assigned
+    fn copy(&mut self, value: Expr, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Array { shape, t } => {
+                let element = self.java_type(&t);
+                let record =
+                    matches!(&*t, VariableType::Custom(name) if self.program.is_record(name));
+                if shape.len() == 1 && !record {
+                    return format!("{}.clone()", bracket(value, ATOM));
+                }
+                self.out.import("java.util.Arrays");
+                let mut map = match record {
+                    true => format!("{}::copy", element),
+                    false => format!("{}[]::clone", element),
+                };
+                let start = if record { 1 } else { 2 };
+                for dims in start..shape.len() {
+                    map = format!(
+                        "a -> Arrays.stream(a).map({}).toArray({}{}::new)",
+                        map,
+                        element,
+                        "[]".repeat(dims)
+                    );
+                }
+                format!(
+                    "Arrays.stream({}).map({}).toArray({}{}::new)",
+                    value.0,
+                    map,
+                    element,
+                    "[]".repeat(shape.len())
+                )
+            }
+            _ => format!("{}.copy()", bracket(value, ATOM)),
+        }
+    }
+
+    fn definition(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Enum { name, variants, .. } => {
+                let class = self.names.type_name(name);
+                let members: Vec<(String, String)> = variants
+                    .iter()
+                    .filter_map(|variant| match variant.deref() {
+                        Node::String { val, .. } => Some((self.names.get(val), val.clone())),
+                        _ => None,
+                    })
+                    .collect();
+                self.out.line(format!("enum {} {{", class));
+                self.out.depth += 1;
+                // Enums are written out by the name the interpreter gives them
+                let named = members
+                    .iter()
+                    .all(|(member, val)| member.to_lowercase() == *val);
+                if named {
+                    let list: Vec<String> = members.into_iter().map(|(member, _)| member).collect();
+                    self.out.line(format!("{};", list.join(", ")));
+                    self.out.blank(1);
+                    self.out.line("@Override".to_string());
+                    self.out.line("public String toString() {".to_string());
+                    self.out
+                        .line("    return name().toLowerCase();".to_string());
+                } else {
+                    let count = members.len();
+                    for (i, (member, val)) in members.into_iter().enumerate() {
+                        let end = if i + 1 == count { ";" } else { "," };
+                        self.out.line(format!("{}({}){}", member, quote(&val), end));
+                    }
+                    self.out.blank(1);
+                    self.out.line("private final String text;".to_string());
+                    self.out.blank(1);
+                    self.out.line(format!("{}(String text) {{", class));
+                    self.out.line("    this.text = text;".to_string());
+                    self.out.line("}".to_string());
+                    self.out.blank(1);
+                    self.out.line("@Override".to_string());
+                    self.out.line("public String toString() {".to_string());
+                    self.out.line("    return text;".to_string());
+                }
+                self.out.line("}".to_string());
+                self.out.depth -= 1;
+                self.out.line("}".to_string());
+            }
+            Node::Record { name, children, .. } => {
+                let Node::String { val: record, .. } = name.deref() else {
+                    return;
+                };
+                let class = self.names.type_name(record);
+                self.out.line(format!("static class {} {{", class));
+                self.out.depth += 1;
+                let mut fields = vec![];
+                for child in children {
+                    if let Node::Declare { t, children, .. } = child.deref() {
+                        for field in children {
+                            let declaration = self.declaration(field, t);
+                            self.out.line(format!("{};", declaration));
+                            fields.push((self.names.get(field), *t.clone()));
+                        }
+                    }
+                }
+                // Assigning a record copies it, arrays and records inside it included
+                self.out.blank(1);
+                self.out.line(format!("{} copy() {{", class));
+                self.out.depth += 1;
+                self.out.line(format!("{} copy = new {}();", class, class));
+                for (field, t) in fields {
+                    let resolved = Some(self.program.checker.resolve(&t));
+                    let value = match self.program.copied(&resolved) {
+                        true => self.copy((field.clone(), ATOM), &t),
+                        false => field.clone(),
+                    };
+                    self.out.line(format!("copy.{} = {};", field, value));
+                }
+                self.out.line("return copy;".to_string());
+                self.out.depth -= 1;
+                self.out.line("}".to_string());
+                self.out.depth -= 1;
+                self.out.line("}".to_string());
+            }
+            Node::Class {
+                name,
+                base,
+                children,
+                ..
+            } => self.class(name, base, children),
+            Node::Function { .. } | Node::Procedure { .. } => self.routine(node, None),
+            // Pointer types are written out as the type they point to
+            _ => {}
+        }
+    }
+
+    fn class(&mut self, name: &Box<Node>, base: &Box<Node>, children: &Vec<Box<Node>>) {
+        let Node::String { val: class, .. } = name.deref() else {
+            return;
+        };
+        let header = match base.deref() {
+            Node::String { val, .. } => format!(
+                "static class {} extends {} {{",
+                self.names.type_name(class),
+                self.names.type_name(val)
+            ),
+            _ => format!("static class {} {{", self.names.type_name(class)),
+        };
+        self.out.line(header);
+        self.out.depth += 1;
+        for child in children {
+            if let Node::Declare {
+                t,
+                children,
+                private,
+                ..
+            } = child.deref()
+            {
+                for property in children {
+                    let declaration = self.declaration(property, t);
+                    let access = if *private { "private" } else { "public" };
+                    self.out.line(format!("{} {};", access, declaration));
+                }
+            }
+        }
+        if self.program.needs_bare_constructor(class) {
+            self.out.blank(1);
+            let class = self.names.type_name(class);
+            // For subclasses whose constructor does not call this one's
+            self.out.line(format!("protected {}() {{", class));
+            self.out.line("}".to_string());
+        }
+        for method in methods(children) {
+            self.out.blank(1);
+            self.routine(method, Some(class));
+        }
+        self.out.depth -= 1;
+        self.out.line("}".to_string());
+    }
+
+    fn routine(&mut self, node: &Box<Node>, class: Option<&String>) {
+        let name = routine_name(node).to_string();
+        let mut statements = self.program.enter(node, class);
+        let scope = self.program.scope.as_ref().unwrap();
+        let signature = scope.signature.clone();
+        let boxed = scope.boxed.clone();
+        let implicit = self.program.implicit(&statements);
+
+        let mut params = vec![];
+        let mut unboxed = vec![];
+        for param in &signature.params {
+            let var = self.names.get(&param.name);
+            if param.byref && self.program.plain(&param.t) {
+                let holder = self.ref_type(&param.t);
+                params.push(format!("{} {}", holder, var));
+            } else if boxed.contains(&param.name) {
+                // Shared by this subroutine but given by value
+                let given = self.names.fresh(&format!("{}Given", var));
+                let java_type = self.java_type(&param.t);
+                params.push(format!("{} {}", java_type, given));
+                unboxed.push((param.clone(), given));
+            } else {
+                let java_type = self.java_type(&param.t);
+                params.push(format!("{} {}", java_type, var));
+            }
+        }
+        let header = match (class, name.as_str()) {
+            (Some(class), "new") => format!(
+                "{} {}({}) {{",
+                access(signature.private),
+                self.names.type_name(class),
+                params.join(", ")
+            ),
+            _ => {
+                let returns = match &signature.returns {
+                    Some(t) => self.java_type(t),
+                    None => "void".to_string(),
+                };
+                let modifier = match class {
+                    Some(_) => access(signature.private),
+                    None => "static",
+                };
+                format!(
+                    "{} {} {}({}) {{",
+                    modifier,
+                    returns,
+                    self.names.get(&name),
+                    params.join(", ")
+                )
+            }
+        };
+        if class.is_some_and(|class| self.overrides(class, &name)) {
+            self.out.line("@Override".to_string());
+        }
+        self.out.line(header);
+        self.out.depth += 1;
+        if class.is_some() && name == "new" && statements.first().is_some_and(is_super_new) {
+            self.stmt(&statements.remove(0));
+        }
+        for (param, given) in unboxed {
+            let var = self.names.get(&param.name);
+            let holder = self.ref_type(&param.t);
+            let value = self.new_ref(&param.t, given);
+            self.out.line(format!("{} {} = {};", holder, var, value));
+        }
+        // A BYVAL array or record is the caller's to keep
+        for (param, t) in self.program.copies() {
+            let var = self.names.get(&param);
+            let copy = self.copy((var.clone(), ATOM), &t);
+            self.out.line(format!("{} = {};", var, copy));
+        }
+        for name in implicit {
+            let declaration = self.declaration(&name, &VariableType::Integer);
+            self.out.line(format!("{};", declaration));
+        }
+        self.scope_body = statements.clone();
+        self.stmts(&statements);
+        if signature.returns.is_some() && !returns(&statements) {
+            self.out.line(
+                "throw new IllegalStateException(\"FUNCTION ended without RETURN\");".to_string(),
+            );
+        }
+        self.out.depth -= 1;
+        self.out.line("}".to_string());
+        self.program.leave();
+    }
+
+    // Whether a method takes the place of one the class inherits
+    fn overrides(&self, class: &String, name: &String) -> bool {
+        if name == "new" {
+            return false;
+        }
+        match self.program.checker.types.get(class) {
+            Some(TypeDef::Class {
+                base: Some(base), ..
+            }) => self.program.checker.get_method(base, name).is_some(),
+            _ => false,
+        }
+    }
+
+    // Statements of a block. Java rejects statements it can never reach, so those
+    // after a RETURN are left out
+    fn stmts(&mut self, nodes: &[Box<Node>]) {
+        let nodes = body(nodes);
+        for (i, node) in nodes.iter().enumerate() {
+            self.stmt(node);
+            if matches!(node.as_ref(), Node::Return { .. }) && i + 1 < nodes.len() {
+                let line = nodes[i + 1].pos().line_start;
+                self.out.note(
+                    line,
+                    "statements after RETURN are left out, Java cannot reach them",
+                );
+                break;
+            }
+        }
+    }
+
+    fn block(&mut self, nodes: &[Box<Node>]) {
+        self.out.depth += 1;
+        self.stmts(nodes);
+        self.out.depth -= 1;
+    }
+
+    fn stmt(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Declare { t, children, .. } => {
+                for name in children {
+                    if self.program.scope.is_none() && self.statics.contains(name) {
+                        continue;
+                    }
+                    let declaration = self.declaration(name, t);
+                    self.out.line(format!("{};", declaration));
+                }
+            }
+            Node::Const { name, val, .. } => {
+                // Constants of the main program are fields of the class
+                if self.program.scope.is_some() {
+                    let t = self.program.type_of(val);
+                    let value = self.value(val);
+                    let t = t.map_or("Object".to_string(), |t| self.java_type(&t));
+                    let name = self.names.get(name);
+                    self.out.line(format!("final {} {} = {};", t, name, value));
+                }
+            }
+            Node::Assignment { lhs, rhs, .. } => self.assign(lhs, rhs),
+            Node::Output { children, .. } => {
+                let pieces = self.pieces(children, false);
+                let line = match pieces.as_slice() {
+                    [] => String::new(),
+                    [Piece::Value(value, _)] => value.0.clone(),
+                    _ => text(pieces),
+                };
+                self.out.line(format!("System.out.println({});", line));
+            }
+            Node::Input { child, .. } => {
+                self.out.use_part("input");
+                let target = self.value(child);
+                let value = match self.program.type_of(child) {
+                    Some(VariableType::Integer) => "Integer.parseInt(input())",
+                    Some(VariableType::Real) => "Double.parseDouble(input())",
+                    Some(VariableType::Boolean) => "input().equalsIgnoreCase(\"TRUE\")",
+                    Some(VariableType::Char) => "input().charAt(0)",
+                    _ => "input()",
+                };
+                self.out.line(format!("{} = {};", target, value));
+            }
+            Node::If { .. } => self.if_chain(node),
+            Node::While { cond, body, .. } => {
+                let cond = self.loop_cond(cond);
+                self.out.line(format!("while ({}) {{", cond.0));
+                self.block(body);
+                self.out.line("}".to_string());
+            }
+            Node::Repeat { cond, body, .. } => {
+                self.out.line("do {".to_string());
+                self.block(body);
+                let cond = self.loop_cond(cond);
+                self.out
+                    .line(format!("}} while (!{});", bracket(cond, ATOM)));
+            }
+            Node::For {
+                iter,
+                range,
+                step,
+                body,
+                ..
+            } => self.for_loop(iter, range, step, body),
+            Node::Switch {
+                cmp,
+                cases,
+                otherwise,
+                ..
+            } => self.switch(cmp, cases, otherwise),
+            Node::Return { expr, .. } => {
+                let returns = self
+                    .program
+                    .scope
+                    .as_ref()
+                    .and_then(|scope| scope.signature.returns.clone());
+                let value = self.coerced(expr, returns.as_ref());
+                self.out.line(format!("return {};", value));
+            }
+            Node::FunctionCall { .. } | Node::Composite { .. } => {
+                self.write_back = Some(vec![]);
+                let call = self.value(node);
+                let write_back = self.write_back.take().unwrap_or_default();
+                self.out.line(format!("{};", call));
+                for line in write_back {
+                    self.out.line(line);
+                }
+            }
+            Node::OpenFile { filename, mode, .. } => {
+                let file = self.value(filename);
+                let mode = match mode {
+                    TToken::FileMode(mode) => mode.as_str(),
+                    _ => "READ",
+                };
+                if mode == "RANDOM" {
+                    self.out.use_part("random");
+                    self.out.line(format!("openRandom({});", file));
+                } else {
+                    self.out.use_part("openFile");
+                    self.out
+                        .line(format!("openFile({}, {});", file, quote(mode)));
+                }
+            }
+            Node::CloseFile { filename, .. } => {
+                self.out.use_part("files");
+                let file = self.value(filename);
+                self.out.line(format!("closeFile({});", file));
+            }
+            Node::ReadFile { filename, var, .. } => {
+                self.out.use_part("readFile");
+                let file = self.value(filename);
+                let target = self.value(var);
+                self.out.line(format!("{} = readFile({});", target, file));
+            }
+            Node::WriteFile { filename, expr, .. } => {
+                self.out.use_part("writeFile");
+                let file = self.value(filename);
+                let pieces = self.pieces(std::slice::from_ref(expr), true);
+                self.out
+                    .line(format!("writeFile({}, {});", file, text(pieces)));
+            }
+            Node::SeekFile { filename, expr, .. } => {
+                let file = self.value(filename);
+                let position = self.value(expr);
+                self.out.line(format!("seek({}, {});", file, position));
+            }
+            Node::GetRecord { filename, var, .. } => {
+                let file = self.value(filename);
+                let record = self.value(var);
+                self.out.line(format!("getRecord({}, {});", file, record));
+            }
+            Node::PutRecord { filename, var, .. } => {
+                let file = self.value(filename);
+                let record = self.value(var);
+                self.out.line(format!("putRecord({}, {});", file, record));
+            }
+            Node::Null => {}
+            _ => {
+                let value = self.value(node);
+                self.out.line(format!("{};", value));
+            }
+        }
+    }
+
+    fn assign(&mut self, lhs: &Box<Node>, rhs: &Box<Node>) {
+        let target = self.value(lhs);
+        let t = self.program.type_of(lhs);
+        if let Node::Binary {
+            op,
+            lhs: left,
+            rhs: right,
+            ..
+        } = rhs.deref()
+        {
+            let op = match op.as_str() {
+                "+" | "-" | "*" | "%" => Some(op.as_str()),
+                "&" if t == Some(VariableType::String) => Some("+"),
+                _ => None,
+            };
+            if let Some(op) = op {
+                if self.value(left) == target && !target.contains('(') {
+                    let right = self.value(right);
+                    self.out.line(format!("{} {}= {};", target, op, right));
+                    return;
+                }
+            }
+        }
+        let value = self.coerced(rhs, t.as_ref());
+        self.out.line(format!("{} = {};", target, value));
+    }
+
+    // A value given to a variable, parameter or RETURN of a type, copied or
+    // converted as the interpreter would
+    fn coerced(&mut self, node: &Box<Node>, to: Option<&VariableType>) -> String {
+        let from = self.program.type_of(node);
+        let value = self.expr(node);
+        let copies = matches!(
+            node.deref(),
+            Node::Var { .. }
+                | Node::ArrayVar { .. }
+                | Node::Composite { .. }
+                | Node::Dereference { .. }
+        );
+        match (to, &from) {
+            (_, Some(t)) if copies && self.program.copied(&from) => self.copy(value, t),
+            (Some(VariableType::Char), Some(VariableType::String)) => match node.deref() {
+                Node::String { val, .. } if val.chars().count() == 1 => {
+                    char_literal(val.chars().next().unwrap())
+                }
+                _ => format!("{}.charAt(0)", bracket(value, ATOM)),
+            },
+            (Some(VariableType::String), Some(VariableType::Char)) => {
+                format!("String.valueOf({})", value.0)
+            }
+            // STR_TO_NUM gives an INTEGER for whole numbers, and a double here
+            (Some(VariableType::Integer), None) => format!("(int) {}", bracket(value, UNARY)),
+            _ => value.0,
+        }
+    }
+
+    // A condition Java can work out before running makes the loop or what follows it
+    // unreachable, which it rejects, so it is hidden from the compiler
+    fn loop_cond(&mut self, cond: &Box<Node>) -> Expr {
+        let mut constant = true;
+        walk(std::slice::from_ref(cond), &mut |node| match node {
+            Node::Var { name, .. } if self.constants.contains(name) => {}
+            Node::Var { .. }
+            | Node::ArrayVar { .. }
+            | Node::FunctionCall { .. }
+            | Node::Composite { .. }
+            | Node::Dereference { .. } => constant = false,
+            _ => {}
+        });
+        let cond = self.expr(cond);
+        match constant {
+            true => (format!("Boolean.valueOf({})", cond.0), ATOM),
+            false => cond,
+        }
+    }
+
+    fn if_chain(&mut self, node: &Box<Node>) {
+        let mut node = node.clone();
+        let mut first = true;
+        loop {
+            let Node::If {
+                cond,
+                true_body,
+                false_body,
+                ..
+            } = node.deref()
+            else {
+                return;
+            };
+            let cond = self.value(cond);
+            match first {
+                true => self.out.line(format!("if ({}) {{", cond)),
+                false => self.out.line(format!("}} else if ({}) {{", cond)),
+            }
+            self.block(true_body);
+            let rest = body(false_body);
+            match rest.as_slice() {
+                [] => break,
+                [only] if matches!(only.as_ref(), Node::If { .. }) => {
+                    node = only.clone();
+                    first = false;
+                }
+                _ => {
+                    self.out.line("} else {".to_string());
+                    self.block(&rest);
+                    break;
+                }
+            }
+        }
+        self.out.line("}".to_string());
+    }
+
+    fn for_loop(
+        &mut self,
+        iter: &Box<Node>,
+        range: &Box<Node>,
+        step: &Box<Node>,
+        body: &[Box<Node>],
+    ) {
+        let (Node::Var { name, .. }, Node::Range { start, end, .. }) =
+            (iter.deref(), range.deref())
+        else {
+            return;
+        };
+        let counter = self.value(iter);
+        let start = self.value(start);
+        let step_value = match step.deref() {
+            Node::Null => Some(1),
+            step => const_int(step),
+        };
+        // The interpreter leaves the counter at its last value, Java one step past it
+        let plain = self
+            .program
+            .counts_itself(&self.statics, &self.scope_body, name);
+        let var = match plain {
+            true => counter.clone(),
+            false => {
+                let next = format!("{}Next", self.names.get(name));
+                self.names.fresh(&next)
+            }
+        };
+        let end = bracket(self.expr(end), CMP + 1);
+        let (cond, update) = match step_value {
+            Some(1) => (format!("{} <= {}", var, end), format!("{}++", var)),
+            Some(-1) => (format!("{} >= {}", var, end), format!("{}--", var)),
+            Some(step) if step < 0 => (
+                format!("{} >= {}", var, end),
+                format!("{} -= {}", var, -step),
+            ),
+            Some(step) => (
+                format!("{} <= {}", var, end),
+                format!("{} += {}", var, step),
+            ),
+            None => {
+                let step = bracket(self.expr(step), EQ + 1);
+                (
+                    format!("{} > 0 ? {} <= {} : {} >= {}", step, var, end, var, end),
+                    format!("{} += {}", var, step),
+                )
+            }
+        };
+        let init = match plain {
+            true => format!("{} = {}", var, start),
+            false => format!("int {} = {}", var, start),
+        };
+        self.out
+            .line(format!("for ({}; {}; {}) {{", init, cond, update));
+        if !plain {
+            self.out.depth += 1;
+            self.out.line(format!("{} = {};", counter, var));
+            self.out.depth -= 1;
+        }
+        self.block(body);
+        self.out.line("}".to_string());
+    }
+
+    fn switch(&mut self, cmp: &Box<Node>, cases: &Vec<Box<Node>>, otherwise: &[Box<Node>]) {
+        let t = self.program.type_of(cmp);
+        let mut labels = HashSet::new();
+        let mut literal = true;
+        for case in cases {
+            if let Node::Case { expr, .. } = case.deref() {
+                literal &= matches!(
+                    expr.deref(),
+                    Node::Int { .. } | Node::Char { .. } | Node::String { .. }
+                ) && labels.insert(format!("{:?}", self.expr(expr).0));
+            }
+        }
+        let switchable = matches!(
+            t,
+            Some(VariableType::Integer | VariableType::Char | VariableType::String)
+        );
+        if switchable && literal && !cases.is_empty() {
+            let subject = self.value(cmp);
+            self.out.line(format!("switch ({}) {{", subject));
+            self.out.depth += 1;
+            for case in cases {
+                if let Node::Case { expr, children, .. } = case.deref() {
+                    let label = match (expr.deref(), &t) {
+                        // A one letter STRING label of a CHAR
+                        (Node::String { val, .. }, Some(VariableType::Char)) => {
+                            char_literal(val.chars().next().unwrap_or(' '))
+                        }
+                        _ => self.value(expr),
+                    };
+                    self.rule(&format!("case {}", label), children);
+                }
+            }
+            if !otherwise.is_empty() {
+                self.rule("default", otherwise);
+            }
+            self.out.depth -= 1;
+            self.out.line("}".to_string());
+            return;
+        }
+
+        // Ranges and REAL values are tested one after another
+        let mut subject = self.expr(cmp);
+        if !matches!(cmp.deref(), Node::Var { .. }) {
+            let choice = self.names.fresh("choice");
+            let java_type = t
+                .as_ref()
+                .map_or("Object".to_string(), |t| self.java_type(t));
+            self.out
+                .line(format!("{} {} = {};", java_type, choice, subject.0));
+            subject = (choice, ATOM);
+        }
+        let equals = matches!(t, Some(VariableType::String | VariableType::Date));
+        let mut first = true;
+        for case in cases {
+            let Node::Case { expr, children, .. } = case.deref() else {
+                continue;
+            };
+            let cond = match expr.deref() {
+                Node::Range { start, end, .. } => {
+                    let start = bracket(self.expr(start), CMP + 1);
+                    let end = bracket(self.expr(end), CMP + 1);
+                    let subject = bracket(subject.clone(), CMP + 1);
+                    format!("{} >= {} && {} <= {}", subject, start, subject, end)
+                }
+                _ if equals => {
+                    let value = self.value(expr);
+                    format!("{}.equals({})", bracket(subject.clone(), ATOM), value)
+                }
+                _ => {
+                    let value = bracket(self.expr(expr), EQ + 1);
+                    format!("{} == {}", bracket(subject.clone(), EQ + 1), value)
+                }
+            };
+            match first {
+                true => self.out.line(format!("if ({}) {{", cond)),
+                false => self.out.line(format!("}} else if ({}) {{", cond)),
+            }
+            self.block(children);
+            first = false;
+        }
+        if first {
+            self.stmts(otherwise);
+            return;
+        }
+        if !otherwise.is_empty() {
+            self.out.line("} else {".to_string());
+            self.block(otherwise);
+        }
+        self.out.line("}".to_string());
+    }
+
+    // A rule of a switch, on one line when its only statement is an expression
+    fn rule(&mut self, label: &str, children: &[Box<Node>]) {
+        let children = body(children);
+        let start = self.out.lines.len();
+        self.out.line(format!("{} -> {{", label));
+        self.block(&children);
+        let simple = matches!(
+            children.as_slice(),
+            [only] if matches!(
+                only.as_ref(),
+                Node::Output { .. }
+                    | Node::Assignment { .. }
+                    | Node::FunctionCall { .. }
+                    | Node::Composite { .. }
+                    | Node::Input { .. }
+            )
+        );
+        if simple && self.out.lines.len() == start + 2 {
+            let statement = self.out.lines.pop().unwrap();
+            self.out.lines.pop();
+            self.out
+                .line(format!("{} -> {}", label, statement.trim_start()));
+            return;
+        }
+        self.out.line("}".to_string());
+    }
+
+    // What OUTPUT or WRITEFILE writes, each value turned into the text the interpreter gives
+    fn pieces(&mut self, children: &[Box<Node>], file: bool) -> Vec<Piece> {
+        let mut pieces = vec![];
+        for child in children {
+            let literal = match child.deref() {
+                Node::String { val, .. } => Some(val.clone()),
+                Node::Char { val, .. } => Some(val.to_string()),
+                Node::Int { val, .. } => Some(val.to_string()),
+                Node::Boolean { val, .. } => Some(match (file, val) {
+                    (true, _) => val.to_string(),
+                    (false, true) => "TRUE".to_string(),
+                    (false, false) => "FALSE".to_string(),
+                }),
+                _ => None,
+            };
+            if let Some(literal) = literal {
+                pieces.push(Piece::Literal(literal));
+                continue;
+            }
+            let t = self.program.type_of(child);
+            let value = self.expr(child);
+            let enumeration = matches!(self.program.type_def(&t), Some(TypeDef::Enum));
+            pieces.push(match t {
+                Some(VariableType::String) => Piece::Value(value, true),
+                Some(VariableType::Char | VariableType::Integer) => Piece::Value(value, false),
+                Some(VariableType::Date | VariableType::Boolean) if file => {
+                    Piece::Value(value, false)
+                }
+                _ if enumeration => Piece::Value(value, false),
+                _ => {
+                    self.out.use_part("show");
+                    Piece::Value((format!("show({})", value.0), ATOM), true)
+                }
+            });
+        }
+        pieces
+    }
+
+    // The value of an expression as Java, with the precedence it binds with
+    fn expr(&mut self, node: &Box<Node>) -> Expr {
+        match node.deref() {
+            Node::Int { val, pos } => {
+                if i32::try_from(*val).is_err() {
+                    self.out.note(
+                        pos.line_start,
+                        "INTEGER values are int in Java, which holds 32 bits",
+                    );
+                    return (format!("{}L", val), ATOM);
+                }
+                (val.to_string(), ATOM)
+            }
+            Node::Real { val, .. } => {
+                let text = val.to_string();
+                match text.contains(['.', 'e', 'N', 'i']) {
+                    true => (text, ATOM),
+                    false => (text + ".0", ATOM),
+                }
+            }
+            Node::String { val, .. } => (quote(val), ATOM),
+            Node::Char { val, .. } => (char_literal(*val), ATOM),
+            Node::Boolean { val, .. } => (val.to_string(), ATOM),
+            Node::Date { val, .. } => {
+                self.out.import("java.time.LocalDate");
+                let text = val.format("LocalDate.of(%Y, %m, %d)").to_string();
+                (text.replace(", 0", ", "), ATOM)
+            }
+            Node::Var { name, .. } => (self.var(name), ATOM),
+            Node::ArrayVar { name, indices, pos } => {
+                let array = self.names.get(name);
+                let t = self.program.type_of(&Box::new(Node::Var {
+                    name: name.clone(),
+                    pos: *pos,
+                }));
+                let subscripts = self.subscripts(indices, &t);
+                (array + &subscripts, ATOM)
+            }
+            Node::FunctionCall { name, params, .. } => self.call(name, params),
+            Node::CreateObject { call, .. } => match call.deref() {
+                Node::FunctionCall { name, params, .. } => {
+                    let signature = self.program.checker.get_method(name, &"new".to_string());
+                    let args = self.args(signature, params);
+                    (
+                        format!("new {}({})", self.names.type_name(name), args),
+                        ATOM,
+                    )
+                }
+                _ => ("null".to_string(), ATOM),
+            },
+            Node::Composite { children, .. } => self.composite(children),
+            Node::Reference { expr, pos } => {
+                let t = self.program.type_of(expr);
+                match (expr.deref(), &t) {
+                    (_, Some(t)) if !self.program.plain(t) => self.expr(expr),
+                    (Node::Var { name, .. }, _)
+                        if self.program.is_boxed(name) && !self.program.field(name) =>
+                    {
+                        (self.names.get(name), ATOM)
+                    }
+                    (_, t) => {
+                        self.out.note(
+                            pos.line_start,
+                            "Java cannot point to this, the pointer holds a copy of the value",
+                        );
+                        let value = self.value(expr);
+                        let t = t.clone().unwrap_or(VariableType::Integer);
+                        (self.new_ref(&t, value), ATOM)
+                    }
+                }
+            }
+            Node::Dereference { expr, .. } => {
+                let value = self.expr(expr);
+                match self.program.type_of(expr) {
+                    Some(VariableType::Pointer(t)) if self.program.plain(&t) => {
+                        (format!("{}.value", bracket(value, ATOM)), ATOM)
+                    }
+                    _ => value,
+                }
+            }
+            Node::Unary { op, expr, .. } => {
+                let value = self.expr(expr);
+                // Two signs in a row would read as ++ or --
+                let value = match value.0.starts_with(['-', '+']) {
+                    true => format!("({})", value.0),
+                    false => bracket(value, UNARY),
+                };
+                match op.as_str() {
+                    "!" => (format!("!{}", value), UNARY),
+                    "_-" => (format!("-{}", value), UNARY),
+                    _ => (format!("+{}", value), UNARY),
+                }
+            }
+            Node::Binary { op, lhs, rhs, .. } => self.binary(op, lhs, rhs),
+            _ => ("null".to_string(), ATOM),
+        }
+    }
+
+    fn value(&mut self, node: &Box<Node>) -> String {
+        self.expr(node).0
+    }
+
+    fn binary(&mut self, op: &str, lhs: &Box<Node>, rhs: &Box<Node>) -> Expr {
+        let lhs_type = self.program.type_of(lhs);
+        let rhs_type = self.program.type_of(rhs);
+        let integers =
+            lhs_type == Some(VariableType::Integer) && rhs_type == Some(VariableType::Integer);
+        let objects =
+            |t: &Option<VariableType>| matches!(t, Some(VariableType::String | VariableType::Date));
+        match op {
+            "=" | "!=" if objects(&lhs_type) || objects(&rhs_type) => {
+                // A CHAR compares as a STRING of one character
+                let left = match lhs_type {
+                    Some(VariableType::Char) => format!("String.valueOf({})", self.value(lhs)),
+                    _ => bracket(self.expr(lhs), ATOM),
+                };
+                let right = match rhs_type {
+                    Some(VariableType::Char) => format!("String.valueOf({})", self.value(rhs)),
+                    _ => self.value(rhs),
+                };
+                let equals = format!("{}.equals({})", left, right);
+                return match op {
+                    "=" => (equals, ATOM),
+                    _ => (format!("!{}", equals), UNARY),
+                };
+            }
+            "//" => {
+                let left = self.value(lhs);
+                let right = self.value(rhs);
+                return match integers {
+                    true => (format!("Math.floorDiv({}, {})", left, right), ATOM),
+                    false => {
+                        let left = bracket(self.expr(lhs), MUL);
+                        let right = bracket(self.expr(rhs), MUL + 1);
+                        (format!("Math.floor({} / {})", left, right), ATOM)
+                    }
+                };
+            }
+            _ => {}
+        }
+        let (symbol, prec) = match op {
+            "||" => ("||", OR),
+            "&&" => ("&&", AND),
+            "=" => ("==", EQ),
+            "!=" => ("!=", EQ),
+            "<" | ">" | "<=" | ">=" => (op, CMP),
+            "+" | "&" => ("+", ADD),
+            "-" => ("-", ADD),
+            "*" => ("*", MUL),
+            "/" => ("/", MUL),
+            "%" => ("%", MUL),
+            _ => (op, ATOM),
+        };
+        // Comparisons are bracketed inside each other, where Java would reject or
+        // misread them
+        let left = match prec {
+            EQ | CMP => prec + 1,
+            prec => prec,
+        };
+        let mut left = bracket(self.expr(lhs), left);
+        if op == "&"
+            && lhs_type == Some(VariableType::Char)
+            && rhs_type != Some(VariableType::String)
+        {
+            // Two chars would be added as numbers
+            left = format!("String.valueOf({})", left);
+        }
+        // Joining onto a String gives the same whichever way round it is grouped
+        let joined = op == "&"
+            && lhs_type == Some(VariableType::String)
+            && matches!(rhs.as_ref(), Node::Binary { op, .. } if op == "&");
+        let right = match joined {
+            true => self.value(rhs),
+            false => bracket(self.expr(rhs), prec + 1),
+        };
+        (format!("{} {} {}", left, symbol, right), prec)
+    }
+
+    // A variable by name, which inside a method may be a property of the object
+    fn var(&mut self, name: &String) -> String {
+        variable(&self.program, &mut self.names, name, "value")
+    }
+
+    // Java arrays start at 0, so each index is moved down by its lower bound
+    fn subscripts(&mut self, indices: &[Box<Node>], t: &Option<VariableType>) -> String {
+        let lower = lower_bounds(t);
+        let mut out = String::new();
+        for (i, index) in indices.iter().enumerate() {
+            let lower = lower.get(i).copied().unwrap_or(0);
+            let index = shifted(index, -lower, |expr| bracket(self.expr(expr), ADD));
+            out += &format!("[{}]", index);
+        }
+        out
+    }
+
+    // A call to a builtin, subroutine or method of the object
+    fn call(&mut self, name: &String, params: &[Box<Node>]) -> Expr {
+        if builtin(name).is_some() {
+            return self.builtin(&name.to_uppercase(), params);
+        }
+        let signature = self.program.signature(name);
+        let args = self.args(signature, params);
+        (format!("{}({})", self.names.get(name), args), ATOM)
+    }
+
+    fn args(&mut self, signature: Option<Signature>, params: &[Box<Node>]) -> String {
+        let mut args = vec![];
+        for (i, param) in params.iter().enumerate() {
+            let declared = signature
+                .as_ref()
+                .and_then(|signature| signature.params.get(i))
+                .cloned();
+            let arg = match declared {
+                Some(declared) if declared.byref && self.program.plain(&declared.t) => {
+                    self.shared(param, &declared.t)
+                }
+                // A subroutine copies the arrays and records it changes itself
+                Some(declared) if self.program.copied(&Some(declared.t.clone())) => {
+                    self.value(param)
+                }
+                Some(declared) => self.coerced(param, Some(&declared.t)),
+                None => self.value(param),
+            };
+            args.push(arg);
+        }
+        args.join(", ")
+    }
+
+    // The holder handed to a BYREF parameter of a simple type
+    fn shared(&mut self, param: &Box<Node>, t: &VariableType) -> String {
+        if let Node::Var { name, .. } = param.deref() {
+            if self.program.is_boxed(name) && !self.program.field(name) {
+                return self.names.get(name);
+            }
+        }
+        if matches!(
+            param.deref(),
+            Node::Var { .. } | Node::ArrayVar { .. } | Node::Composite { .. }
+        ) {
+            // A procedure called on its own has its changes put back after it returns
+            if self.write_back.is_some() {
+                let mut base = "arg".to_string();
+                walk(std::slice::from_ref(param), &mut |node| match node {
+                    Node::Var { name, .. } | Node::ArrayVar { name, .. } if base == "arg" => {
+                        base = self.names.get(name)
+                    }
+                    _ => {}
+                });
+                let holder = self.names.fresh(&format!("{}Ref", base));
+                let target = self.value(param);
+                let value = self.new_ref(t, target.clone());
+                let t = self.ref_type(t);
+                self.out.pre.push(format!("{} {} = {};", t, holder, value));
+                if let Some(write_back) = &mut self.write_back {
+                    write_back.push(format!("{} = {}.value;", target, holder));
+                }
+                return holder;
+            }
+            self.out.note(
+                param.pos().line_start,
+                "Java cannot pass this BYREF, the subroutine changes a copy",
+            );
+        }
+        let value = self.value(param);
+        self.new_ref(t, value)
+    }
+
+    fn builtin(&mut self, name: &str, params: &[Box<Node>]) -> Expr {
+        // ASC takes a one letter STRING as well as a CHAR
+        if name == "ASC" {
+            let letter = self.coerced(&params[0], Some(&VariableType::Char));
+            return (format!("(int) {}", letter), UNARY);
+        }
+        let mut args: Vec<Expr> = params.iter().map(|param| self.expr(param)).collect();
+        let mut arg = |i: usize, prec: u8| bracket(std::mem::take(&mut args[i]), prec);
+        let (text, prec) = match name {
+            "LEFT" => {
+                let (text, length) = (arg(0, ATOM), arg(1, 0));
+                (format!("{}.substring(0, {})", text, length), ATOM)
+            }
+            "LENGTH" => (format!("{}.length()", arg(0, ATOM)), ATOM),
+            "TO_UPPER" => (format!("{}.toUpperCase()", arg(0, ATOM)), ATOM),
+            "TO_LOWER" => (format!("{}.toLowerCase()", arg(0, ATOM)), ATOM),
+            "CHR" => (format!("(char) {}", arg(0, UNARY)), UNARY),
+            "INT" => (format!("(int) {}", arg(0, UNARY)), UNARY),
+            "RAND" => (format!("Math.random() * {}", arg(0, MUL + 1)), MUL),
+            "DAY" => (format!("{}.getDayOfMonth()", arg(0, ATOM)), ATOM),
+            "MONTH" => (format!("{}.getMonthValue()", arg(0, ATOM)), ATOM),
+            "YEAR" => (format!("{}.getYear()", arg(0, ATOM)), ATOM),
+            // Sunday is 1
+            "DAYINDEX" => (
+                format!("{}.getDayOfWeek().getValue() % 7 + 1", arg(0, ATOM)),
+                ADD,
+            ),
+            "SETDATE" => {
+                let (day, month, year) = (arg(0, 0), arg(1, 0), arg(2, 0));
+                (format!("LocalDate.of({}, {}, {})", year, month, day), ATOM)
+            }
+            "TODAY" => ("LocalDate.now()".to_string(), ATOM),
+            "NUM_TO_STR" => ("show".to_string(), 0),
+            "RIGHT" => ("right".to_string(), 0),
+            "MID" => ("mid".to_string(), 0),
+            "STR_TO_NUM" => ("strToNum".to_string(), 0),
+            "IS_NUM" => ("isNum".to_string(), 0),
+            "EOF" => ("eof".to_string(), 0),
+            _ => ("null".to_string(), ATOM),
+        };
+        if matches!(name, "SETDATE" | "TODAY") {
+            self.out.import("java.time.LocalDate");
+        }
+        if prec > 0 {
+            return (text, prec);
+        }
+        // The rest are in the prelude
+        let part = PRELUDE.iter().find(|part| part.name == text).unwrap();
+        self.out.use_part(part.name);
+        let args: Vec<String> = params.iter().map(|param| self.value(param)).collect();
+        (format!("{}({})", text, args.join(", ")), ATOM)
+    }
+
+    // obj.prop, obj.arr[i] and obj.method() chains
+    fn composite(&mut self, children: &[Box<Node>]) -> Expr {
+        let first = &children[0];
+        let mut text = match first.deref() {
+            Node::Var { name, .. } if name == "super" => "super".to_string(),
+            _ => bracket(self.expr(first), ATOM),
+        };
+        let mut t = self.program.type_of(first);
+        if let Some(VariableType::Pointer(to)) = t {
+            t = Some(self.program.checker.resolve(&to));
+        }
+        for child in children.iter().skip(1) {
+            let class = match &t {
+                Some(VariableType::Custom(name)) => Some(name.clone()),
+                _ => None,
+            };
+            match child.deref() {
+                Node::Var { name, .. } | Node::ArrayVar { name, .. } => {
+                    let field =
+                        class.and_then(|class| self.program.checker.get_field(&class, name));
+                    text = format!("{}.{}", text, self.names.get(name));
+                    t = field.map(|field| self.program.checker.resolve(&field.t));
+                    if let Node::ArrayVar { indices, .. } = child.deref() {
+                        text += &self.subscripts(indices, &t);
+                        t = match t {
+                            Some(VariableType::Array { t, .. }) => Some(*t),
+                            _ => None,
+                        };
+                    }
+                }
+                Node::FunctionCall { name, params, .. } => {
+                    let method =
+                        class.and_then(|class| self.program.checker.get_method(&class, name));
+                    t = method
+                        .as_ref()
+                        .and_then(|method| method.returns.as_ref())
+                        .map(|returns| self.program.checker.resolve(returns));
+                    let args = self.args(method, params);
+                    text = match (text.as_str(), name.as_str()) {
+                        ("super", "new") => format!("super({})", args),
+                        _ => format!("{}.{}({})", text, self.names.get(name), args),
+                    };
+                }
+                _ => {}
+            }
+        }
+        (text, ATOM)
+    }
+}
+
+impl<'a> Program<'a> {
+    // Whether Java's own starting value for an array element, null, is the right one
+    fn default_is_null(&self, t: &VariableType) -> bool {
+        match t {
+            VariableType::Custom(name) => !self.is_record(name),
+            VariableType::Pointer(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn access(private: bool) -> &'static str {
+    match private {
+        true => "private",
+        false => "public",
+    }
+}
+
+// A Java string literal. Pseudocode strings have no escapes, so backslashes are kept
+fn quote(text: &str) -> String {
+    format!("\"{}\"", escape(text, '"'))
+}
+
+fn char_literal(c: char) -> String {
+    format!("'{}'", escape(&c.to_string(), '\''))
+}
+
+fn escape(text: &str, quote: char) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\t' => out += "\\t",
+            '\r' => out += "\\r",
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// The pieces joined into a String, which has to come first for + to join rather than add
+fn text(pieces: Vec<Piece>) -> String {
+    let mut parts: Vec<String> = vec![];
+    let mut literal: Option<String> = None;
+    let mut string_first = false;
+    for (i, piece) in pieces.into_iter().enumerate() {
+        match piece {
+            Piece::Literal(text) => {
+                if i == 0 {
+                    string_first = true;
+                }
+                literal.get_or_insert_with(String::new).push_str(&text);
+            }
+            Piece::Value(value, string) => {
+                if i == 0 {
+                    string_first = string;
+                }
+                if let Some(text) = literal.take() {
+                    parts.push(quote(&text));
+                }
+                parts.push(bracket(value, MUL));
+            }
+        }
+    }
+    if let Some(text) = literal.take() {
+        parts.push(quote(&text));
+    }
+    match (parts.len(), string_first) {
+        (0, _) => "\"\"".to_string(),
+        (1, false) => format!("String.valueOf({})", parts[0]),
+        (_, false) => format!("\"\" + {}", parts.join(" + ")),
+        (_, true) => parts.join(" + "),
+    }
+}
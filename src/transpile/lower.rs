@@ -0,0 +1,826 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+
+use crate::checker::check_expr::check_expr;
+use crate::checker::check_stmt::{check_stmts, enter_routine, leave_routine};
+use crate::checker::{Checker, Signature, TypeDef};
+use crate::enums::{Node, VariableType};
+use crate::executor::hoist::{hoisted, is_definition};
+use crate::transpile::{offset, Kind, Names};
+
+// Which variables a language has to keep in a holder object, as none of them can
+// point to a variable or share one with a subroutine the way pseudocode does
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Boxing {
+    // Those handed to a BYREF parameter of a simple type
+    pub byref: bool,
+    // Those a pointer is made to
+    pub pointers: bool,
+}
+
+// The program as every language writes it out: the definitions and main program
+// apart, and what each scope needs to know about its variables
+pub(crate) struct Program<'a> {
+    // Knows the type of every expression, given the scope being written out
+    pub checker: Checker,
+    pub classes: HashMap<String, &'a Box<Node>>,
+    // The enum each variant belongs to
+    pub variants: HashMap<String, String>,
+    pub globals: HashSet<String>,
+    // Globals kept in a holder object
+    pub boxed: HashSet<String>,
+    // Definitions, types before the subroutines so these can refer to them
+    pub types: Vec<&'a Box<Node>>,
+    pub routines: Vec<&'a Box<Node>>,
+    // The statements of the main program, blank lines left out
+    pub main: Vec<&'a Box<Node>>,
+    pub scope: Option<Scope>,
+    boxing: Boxing,
+}
+
+// The procedure, function or method being written out
+pub(crate) struct Scope {
+    pub class: Option<String>,
+    pub signature: Signature,
+    // Parameters and the variables of its own, which hide properties and globals
+    pub locals: HashSet<String>,
+    // BYREF parameters of simple types, which the caller's variable cannot simply be
+    // handed to
+    pub plain: Vec<String>,
+    // Globals it stores a whole new value in
+    pub global: Vec<String>,
+    // BYVAL arrays and records it changes, which are the caller's to keep
+    pub copies: Vec<String>,
+    // Variables of its own kept in a holder object
+    pub boxed: HashSet<String>,
+}
+
+impl<'a> Program<'a> {
+    pub fn new(children: &'a [Box<Node>], boxing: Boxing) -> Self {
+        let mut classes = HashMap::new();
+        let mut variants = HashMap::new();
+        for node in children {
+            match node.deref() {
+                Node::Class { name, .. } => {
+                    if let Node::String { val, .. } = name.deref() {
+                        classes.insert(val.clone(), node);
+                    }
+                }
+                Node::Enum {
+                    name,
+                    variants: values,
+                    ..
+                } => {
+                    for value in values {
+                        if let Node::String { val, .. } = value.deref() {
+                            variants.insert(val.clone(), name.clone());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut checker = Checker::new();
+        let list = children.to_vec();
+        checker.collect(&list);
+        check_stmts(&mut checker, &list);
+        let mut globals = declared(children);
+        globals.extend(rebound(&checker, children));
+
+        let definitions = hoisted(children).unwrap_or_default();
+        let (routines, types): (Vec<&Box<Node>>, Vec<&Box<Node>>) =
+            definitions.into_iter().partition(|node| {
+                matches!(
+                    node.as_ref(),
+                    Node::Function { .. } | Node::Procedure { .. }
+                )
+            });
+        let main = children
+            .iter()
+            .filter(|node| !is_definition(node) && !matches!(node.as_ref(), Node::Null))
+            .collect();
+        let mut program = Program {
+            checker,
+            classes,
+            variants,
+            globals,
+            boxed: HashSet::new(),
+            types,
+            routines,
+            main,
+            scope: None,
+            boxing,
+        };
+        program.boxed = program.box_globals(children);
+        program
+    }
+
+    pub fn type_of(&mut self, node: &Box<Node>) -> Option<VariableType> {
+        let t = check_expr(&mut self.checker, node);
+        self.checker.errors.clear();
+        t
+    }
+
+    pub fn type_def(&self, t: &Option<VariableType>) -> Option<&TypeDef> {
+        match t {
+            Some(VariableType::Custom(name)) => self.checker.types.get(name),
+            _ => None,
+        }
+    }
+
+    // Arrays and records are copied when assigned or passed BYVAL, objects never are
+    pub fn copied(&self, t: &Option<VariableType>) -> bool {
+        matches!(t, Some(VariableType::Array { .. }))
+            || matches!(self.type_def(t), Some(TypeDef::Record { .. }))
+    }
+
+    // Whether a value of this type is a simple one, which a BYREF parameter cannot
+    // change in place
+    pub fn plain(&self, t: &VariableType) -> bool {
+        let t = Some(self.checker.resolve(t));
+        !self.copied(&t) && !matches!(self.type_def(&t), Some(TypeDef::Class { .. }))
+    }
+
+    pub fn is_record(&self, name: &String) -> bool {
+        matches!(self.checker.types.get(name), Some(TypeDef::Record { .. }))
+    }
+
+    // Properties of a class and the classes it inherits from
+    pub fn fields(&self, class: &str) -> HashSet<String> {
+        let mut fields = HashSet::new();
+        let mut next = Some(class.to_string());
+        while let Some(class) = next.take() {
+            if let Some(TypeDef::Class {
+                base, fields: own, ..
+            }) = self.checker.types.get(&class)
+            {
+                fields.extend(own.keys().cloned());
+                next = base.clone();
+            }
+        }
+        fields
+    }
+
+    // The class a method is written in, when inside one
+    pub fn class(&self) -> Option<String> {
+        self.scope.as_ref().and_then(|scope| scope.class.clone())
+    }
+
+    // Whether a variable by this name is kept in a holder object where it is used
+    pub fn is_boxed(&self, name: &String) -> bool {
+        match &self.scope {
+            Some(scope) if scope.locals.contains(name) => scope.boxed.contains(name),
+            Some(scope) if scope.class.is_some() && self.field(name) => false,
+            _ => self.boxed.contains(name),
+        }
+    }
+
+    // Whether a name inside a method stands for a property of the object
+    pub fn field(&self, name: &String) -> bool {
+        match &self.scope {
+            Some(Scope {
+                class: Some(class),
+                locals,
+                ..
+            }) => !locals.contains(name) && self.checker.get_field(class, name).is_some(),
+            _ => false,
+        }
+    }
+
+    // Start writing out a subroutine, with the checker seeing what it sees
+    pub fn enter(&mut self, node: &Box<Node>, class: Option<&String>) -> Vec<Box<Node>> {
+        let children = match node.deref() {
+            Node::Function { children, .. } | Node::Procedure { children, .. } => children,
+            _ => unreachable!(),
+        };
+        let signature = enter_routine(&mut self.checker, node, class);
+        check_stmts(&mut self.checker, children);
+
+        let fields = match class {
+            Some(class) => self.fields(class),
+            None => HashSet::new(),
+        };
+        let mut locals: HashSet<String> = signature.params.iter().map(|p| p.name.clone()).collect();
+        locals.extend(declared(children));
+        let rebound = rebound(&self.checker, children);
+        for name in &rebound {
+            if !self.globals.contains(name) && !fields.contains(name) {
+                locals.insert(name.clone());
+            }
+        }
+        let plain: Vec<String> = signature
+            .params
+            .iter()
+            .filter(|param| param.byref && self.plain(&param.t))
+            .map(|param| param.name.clone())
+            .collect();
+        let global: Vec<String> = rebound
+            .iter()
+            .filter(|name| self.globals.contains(*name) && !locals.contains(*name))
+            .filter(|name| !fields.contains(*name))
+            .cloned()
+            .collect();
+        let copies = signature
+            .params
+            .iter()
+            .filter(|param| {
+                let t = Some(self.checker.resolve(&param.t));
+                !param.byref && self.copied(&t) && mutated(children, &param.name)
+            })
+            .map(|param| param.name.clone())
+            .collect();
+        let mut boxed: HashSet<String> = self
+            .shared(children)
+            .into_iter()
+            .filter(|name| locals.contains(name))
+            .collect();
+        if self.boxing.byref {
+            boxed.extend(plain.iter().cloned());
+        }
+        self.scope = Some(Scope {
+            class: class.cloned(),
+            signature,
+            locals,
+            plain,
+            global,
+            copies,
+            boxed,
+        });
+        body(children)
+    }
+
+    pub fn leave(&mut self) {
+        self.scope = None;
+        leave_routine(&mut self.checker);
+    }
+
+    // Globals a subroutine uses, or declared where the main program cannot keep them
+    // local to it, in languages that scope variables to their block
+    pub fn statics(&self, children: &[Box<Node>]) -> HashSet<String> {
+        let mut statics = HashSet::new();
+        for node in everywhere_routines(children) {
+            let (params, children) = match node.deref() {
+                Node::Function {
+                    params, children, ..
+                }
+                | Node::Procedure {
+                    params, children, ..
+                } => (params, children),
+                _ => continue,
+            };
+            let mut hidden = declared(children);
+            hidden.extend(declared(params));
+            walk(children, &mut |node| match node {
+                Node::Var { name, .. } | Node::ArrayVar { name, .. } if !hidden.contains(name) => {
+                    statics.insert(name.clone());
+                }
+                _ => {}
+            });
+        }
+        let statements: Vec<Box<Node>> = self.main.iter().map(|node| (*node).clone()).collect();
+        let declared = declared(&statements);
+        for (name, _) in self.variables() {
+            if !declared_at_top(&self.main, &name) && declared.contains(&name) {
+                statics.insert(name);
+            }
+        }
+        statics.retain(|name| self.globals.contains(name));
+        statics
+    }
+
+    // Variables of the main program in the order they are declared, with their types
+    pub fn variables(&self) -> Vec<(String, VariableType)> {
+        let main: Vec<Box<Node>> = self.main.iter().map(|node| (*node).clone()).collect();
+        let mut globals = vec![];
+        let mut seen = HashSet::new();
+        walk(&main, &mut |node| match node {
+            Node::Declare { t, children, .. } => {
+                for name in children {
+                    if seen.insert(name.clone()) {
+                        globals.push((name.clone(), *t.clone()));
+                    }
+                }
+            }
+            Node::For { iter, .. } => {
+                if let Node::Var { name, .. } = iter.deref() {
+                    if seen.insert(name.clone()) {
+                        globals.push((name.clone(), VariableType::Integer));
+                    }
+                }
+            }
+            _ => {}
+        });
+        let declared = declared(&main);
+        // Counters of loops that come before their DECLARE are still declared by it
+        globals
+            .into_iter()
+            .filter(|(name, t)| {
+                *t != VariableType::Integer || !declared.contains(name) || {
+                    let mut typed = false;
+                    walk(&main, &mut |node| {
+                        if let Node::Declare { children, .. } = node {
+                            typed |= children.contains(name);
+                        }
+                    });
+                    typed
+                }
+            })
+            .collect()
+    }
+
+    // Globals that something anywhere in the program shares
+    // The signature of a call by name: a method of the object when inside a class
+    // that has one, otherwise a procedure or function
+    pub fn signature(&self, name: &String) -> Option<Signature> {
+        let method = self
+            .class()
+            .and_then(|class| self.checker.get_method(&class, name));
+        match method {
+            Some(method) => Some(method),
+            None => self.checker.routines.get(name).cloned(),
+        }
+    }
+
+    // Whether a subclass constructs itself without calling this class's constructor,
+    // which takes parameters
+    pub fn needs_bare_constructor(&self, class: &String) -> bool {
+        let takes_params = self
+            .checker
+            .get_method(class, &"new".to_string())
+            .is_some_and(|signature| !signature.params.is_empty());
+        takes_params
+            && self.classes.values().any(|node| match node.as_ref() {
+                Node::Class { base, children, .. } => {
+                    matches!(base.deref(), Node::String { val, .. } if val == class)
+                        && !methods(children).iter().any(|method| {
+                            routine_name(method) == "new"
+                                && match method.as_ref() {
+                                    Node::Procedure { children, .. } => {
+                                        body(children).first().is_some_and(is_super_new)
+                                    }
+                                    _ => false,
+                                }
+                        })
+                }
+                _ => false,
+            })
+    }
+
+    // Locals of the subroutine being written out that are neither parameters nor
+    // declared in its statements, as FOR counters may be, in order
+    pub fn implicit(&self, statements: &[Box<Node>]) -> Vec<String> {
+        let scope = self.scope.as_ref().unwrap();
+        let declared = declared(statements);
+        let mut names: Vec<String> = scope
+            .locals
+            .iter()
+            .filter(|name| {
+                !scope
+                    .signature
+                    .params
+                    .iter()
+                    .any(|param| &param.name == *name)
+                    && !declared.contains(*name)
+            })
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    // The BYVAL arrays and records the subroutine being written out changes, with
+    // their types, each to be copied before it starts
+    pub fn copies(&self) -> Vec<(String, VariableType)> {
+        let scope = self.scope.as_ref().unwrap();
+        scope
+            .copies
+            .iter()
+            .filter_map(|name| {
+                let param = scope.signature.params.iter().find(|p| &p.name == name)?;
+                Some((name.clone(), param.t.clone()))
+            })
+            .collect()
+    }
+
+    // Whether a FOR loop can count with the counter itself: a variable of the scope
+    // being written out, given the variables the main program keeps as statics, that
+    // is only used by the loops over it in body
+    pub fn counts_itself(
+        &self,
+        statics: &HashSet<String>,
+        body: &[Box<Node>],
+        name: &String,
+    ) -> bool {
+        let own = match &self.scope {
+            Some(scope) => scope.locals.contains(name),
+            None => !statics.contains(name),
+        };
+        own && counter_only(&self.checker, body, name)
+    }
+
+    fn box_globals(&mut self, children: &[Box<Node>]) -> HashSet<String> {
+        let mut boxed: HashSet<String> = self.shared(children).into_iter().collect();
+        let mut routines: Vec<(Box<Node>, Option<String>)> = vec![];
+        for node in children {
+            match node.deref() {
+                Node::Function { .. } | Node::Procedure { .. } => {
+                    routines.push((node.clone(), None));
+                }
+                Node::Class { name, children, .. } => {
+                    if let Node::String { val, .. } = name.deref() {
+                        for child in children {
+                            if matches!(
+                                child.as_ref(),
+                                Node::Function { .. } | Node::Procedure { .. }
+                            ) {
+                                routines.push((child.clone(), Some(val.clone())));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (node, class) in routines {
+            let children = match node.deref() {
+                Node::Function { children, .. } | Node::Procedure { children, .. } => children,
+                _ => continue,
+            };
+            let signature = self.checker.signature(&node);
+            let mut hidden: HashSet<String> = signature
+                .params
+                .into_iter()
+                .map(|param| param.name)
+                .collect();
+            hidden.extend(declared(children));
+            if let Some(class) = &class {
+                hidden.extend(self.fields(class));
+            }
+            boxed.extend(
+                self.shared(children)
+                    .into_iter()
+                    .filter(|name| !hidden.contains(name)),
+            );
+        }
+        boxed.retain(|name| self.globals.contains(name));
+        boxed
+    }
+
+    // Variables handed to a BYREF parameter of a simple type or pointed to, as far
+    // as the language needs to know
+    fn shared(&self, nodes: &[Box<Node>]) -> Vec<String> {
+        let mut names = vec![];
+        walk(nodes, &mut |node| match node {
+            Node::FunctionCall { name, params, .. } if self.boxing.byref => {
+                if let Some(signature) = self.checker.routines.get(name) {
+                    for (param, arg) in signature.params.iter().zip(params) {
+                        if let (true, Node::Var { name, .. }) = (param.byref, arg.deref()) {
+                            if self.plain(&param.t) {
+                                names.push(name.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            Node::Reference { expr, .. } if self.boxing.pointers => {
+                if let Node::Var { name, .. } = expr.deref() {
+                    names.push(name.clone());
+                }
+            }
+            _ => {}
+        });
+        names
+    }
+}
+
+// Statements of a block, blank lines left out as they are parsed as statements of their own
+pub(crate) fn body(nodes: &[Box<Node>]) -> Vec<Box<Node>> {
+    nodes
+        .iter()
+        .filter(|node| !matches!(node.as_ref(), Node::Null))
+        .cloned()
+        .collect()
+}
+
+// The names a routine or the main program declares for itself
+pub(crate) fn declared(nodes: &[Box<Node>]) -> HashSet<String> {
+    let mut names = HashSet::new();
+    walk(nodes, &mut |node| match node {
+        Node::Declare { children, .. } => names.extend(children.iter().cloned()),
+        Node::Const { name, .. } => {
+            names.insert(name.clone());
+        }
+        _ => {}
+    });
+    names
+}
+
+// The variables statements store a whole new value in, in the order they first do
+pub(crate) fn rebound(checker: &Checker, nodes: &[Box<Node>]) -> Vec<String> {
+    let mut names = vec![];
+    walk(nodes, &mut |node| match node {
+        Node::Assignment { lhs: target, .. }
+        | Node::Input { child: target, .. }
+        | Node::ReadFile { var: target, .. }
+        | Node::For { iter: target, .. } => {
+            if let Node::Var { name, .. } = target.deref() {
+                names.push(name.clone());
+            }
+        }
+        Node::FunctionCall { name, params, .. } => {
+            if let Some(signature) = checker.routines.get(name) {
+                for (param, arg) in signature.params.iter().zip(params) {
+                    if let (true, Node::Var { name, .. }) = (param.byref, arg.deref()) {
+                        names.push(name.clone());
+                    }
+                }
+            }
+        }
+        _ => {}
+    });
+    let mut seen = HashSet::new();
+    names.retain(|name| seen.insert(name.clone()));
+    names
+}
+
+// Whether statements change an array or record in place, or pass it on where it could be
+pub(crate) fn mutated(nodes: &[Box<Node>], name: &String) -> bool {
+    let root = |node: &Box<Node>| match node.deref() {
+        Node::Var { name: var, .. } | Node::ArrayVar { name: var, .. } => var == name,
+        Node::Composite { children, .. } => matches!(
+            children[0].deref(),
+            Node::Var { name: var, .. } | Node::ArrayVar { name: var, .. } if var == name
+        ),
+        _ => false,
+    };
+    let mut found = false;
+    walk(nodes, &mut |node| match node {
+        Node::Assignment { lhs: target, .. }
+        | Node::Input { child: target, .. }
+        | Node::ReadFile { var: target, .. }
+        | Node::GetRecord { var: target, .. } => found |= root(target),
+        Node::FunctionCall { params, .. } => found |= params.iter().any(&root),
+        Node::Composite { children, .. } => {
+            found |= children.len() > 1
+                && matches!(children.last().unwrap().deref(), Node::FunctionCall { .. })
+                && root(&children[0]);
+        }
+        _ => {}
+    });
+    found
+}
+
+// Visit every statement and every expression within them, nested blocks included
+pub(crate) fn walk(nodes: &[Box<Node>], visit: &mut dyn FnMut(&Node)) {
+    for node in nodes {
+        visit(node);
+        let one = std::slice::from_ref;
+        match node.deref() {
+            Node::If {
+                cond,
+                true_body,
+                false_body,
+                ..
+            } => {
+                walk(one(cond), visit);
+                walk(true_body, visit);
+                walk(false_body, visit);
+            }
+            Node::While { cond, body, .. } | Node::Repeat { cond, body, .. } => {
+                walk(one(cond), visit);
+                walk(body, visit);
+            }
+            Node::For {
+                range, step, body, ..
+            } => {
+                walk(one(range), visit);
+                walk(one(step), visit);
+                walk(body, visit);
+            }
+            Node::Range { start, end, .. } => {
+                walk(one(start), visit);
+                walk(one(end), visit);
+            }
+            Node::Switch {
+                cmp,
+                cases,
+                otherwise,
+                ..
+            } => {
+                walk(one(cmp), visit);
+                walk(cases, visit);
+                walk(otherwise, visit);
+            }
+            Node::Case { expr, children, .. } => {
+                walk(one(expr), visit);
+                walk(children, visit);
+            }
+            Node::Assignment { lhs, rhs, .. } => {
+                walk(one(lhs), visit);
+                walk(one(rhs), visit);
+            }
+            Node::Output { children, .. } | Node::Composite { children, .. } => {
+                walk(children, visit)
+            }
+            Node::Return { expr, .. }
+            | Node::Unary { expr, .. }
+            | Node::Reference { expr, .. }
+            | Node::Dereference { expr, .. } => walk(one(expr), visit),
+            Node::CreateObject { call, .. } => walk(one(call), visit),
+            Node::Binary { lhs, rhs, .. } => {
+                walk(one(lhs), visit);
+                walk(one(rhs), visit);
+            }
+            Node::FunctionCall { params, .. } => walk(params, visit),
+            Node::ArrayVar { indices, .. } => walk(indices, visit),
+            Node::Input { child, .. } => walk(one(child), visit),
+            Node::OpenFile { filename, .. } | Node::CloseFile { filename, .. } => {
+                walk(one(filename), visit)
+            }
+            Node::ReadFile { filename, var, .. }
+            | Node::GetRecord { filename, var, .. }
+            | Node::PutRecord { filename, var, .. } => {
+                walk(one(filename), visit);
+                walk(one(var), visit);
+            }
+            Node::WriteFile { filename, expr, .. } | Node::SeekFile { filename, expr, .. } => {
+                walk(one(filename), visit);
+                walk(one(expr), visit);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn routine_name(node: &Box<Node>) -> &str {
+    match node.deref() {
+        Node::Function { name, .. } | Node::Procedure { name, .. } => match name.deref() {
+            Node::String { val, .. } => val,
+            _ => "",
+        },
+        _ => "",
+    }
+}
+
+// SUPER.NEW(...) as the first statement of a constructor
+pub(crate) fn is_super_new(node: &Box<Node>) -> bool {
+    match node.deref() {
+        Node::Composite { children, .. } => matches!(
+            (children.first().map(|c| c.deref()), children.get(1).map(|c| c.deref())),
+            (Some(Node::Var { name, .. }), Some(Node::FunctionCall { name: method, .. }))
+                if name == "super" && method == "new"
+        ),
+        _ => false,
+    }
+}
+
+// The methods a class writes itself, in order
+pub(crate) fn methods(children: &[Box<Node>]) -> Vec<&Box<Node>> {
+    children
+        .iter()
+        .filter(|child| {
+            matches!(
+                child.as_ref(),
+                Node::Function { .. } | Node::Procedure { .. }
+            )
+        })
+        .collect()
+}
+
+// The lower bound of each dimension of an array, which its indices are moved down by
+// in languages whose arrays start at 0
+pub(crate) fn lower_bounds(t: &Option<VariableType>) -> Vec<i64> {
+    match t {
+        Some(VariableType::Array { shape, .. }) => shape.iter().map(|index| index.lower).collect(),
+        _ => vec![],
+    }
+}
+
+// Every statement of the main program and of each subroutine and method
+pub(crate) fn everywhere(children: &[Box<Node>]) -> Vec<Box<Node>> {
+    let mut nodes = vec![];
+    let mut visit = |node: &Node| nodes.push(Box::new(node.clone()));
+    walk(children, &mut visit);
+    for routine in everywhere_routines(children) {
+        if let Node::Function { children, .. } | Node::Procedure { children, .. } = routine.deref()
+        {
+            walk(children, &mut visit);
+        }
+    }
+    nodes
+}
+
+pub(crate) fn everywhere_routines(children: &[Box<Node>]) -> Vec<&Box<Node>> {
+    let mut routines = vec![];
+    for node in children {
+        match node.deref() {
+            Node::Function { .. } | Node::Procedure { .. } => routines.push(node),
+            Node::Class { children, .. } => routines.extend(methods(children)),
+            _ => {}
+        }
+    }
+    routines
+}
+
+// Whether a variable of the main program is declared by a DECLARE outside any block
+pub(crate) fn declared_at_top(main: &[&Box<Node>], name: &String) -> bool {
+    main.iter().any(
+        |node| matches!(node.as_ref(), Node::Declare { children, .. } if children.contains(name)),
+    )
+}
+
+// Whether a FOR counter is only used by the loops over it, so a loop that leaves it
+// one step past the end, rather than at the last value, behaves the same
+pub(crate) fn counter_only(checker: &Checker, nodes: &[Box<Node>], name: &String) -> bool {
+    let uses = |nodes: &[Box<Node>]| {
+        let mut count = 0;
+        walk(nodes, &mut |node| match node {
+            Node::Var { name: var, .. } | Node::ArrayVar { name: var, .. } if var == name => {
+                count += 1
+            }
+            _ => {}
+        });
+        count
+    };
+    let mut inside = 0;
+    let mut changed = false;
+    walk(nodes, &mut |node| {
+        if let Node::For { iter, body, .. } = node {
+            if matches!(iter.deref(), Node::Var { name: var, .. } if var == name) {
+                inside += uses(body);
+                changed |= rebound(checker, body).contains(name);
+            }
+        }
+    });
+    !changed && uses(nodes) == inside
+}
+
+// Whether every way through statements ends in a RETURN, as a compiler that rejects
+// falling off the end of a function would see it
+pub(crate) fn returns(nodes: &[Box<Node>]) -> bool {
+    nodes.iter().any(|node| match node.deref() {
+        Node::Return { .. } => true,
+        Node::If {
+            true_body,
+            false_body,
+            ..
+        } => returns(true_body) && returns(false_body),
+        Node::Switch {
+            cases, otherwise, ..
+        } => {
+            !otherwise.is_empty()
+                && returns(otherwise)
+                && cases.iter().all(|case| match case.deref() {
+                    Node::Case { children, .. } => returns(children),
+                    _ => false,
+                })
+        }
+        _ => false,
+    })
+}
+
+// An expression as written out, with the precedence of its outermost operator
+pub(crate) type Expr = (String, u8);
+
+// An expression, bracketed unless its operator binds at least as tightly as min
+pub(crate) fn bracket((text, prec): Expr, min: u8) -> String {
+    match prec < min {
+        true => format!("({})", text),
+        false => text,
+    }
+}
+
+// An INTEGER expression moved by a fixed amount, folded into any literal it ends in.
+// The rest of it is written out by expr, bracketed to have a number added to it.
+pub(crate) fn shifted(
+    node: &Box<Node>,
+    by: i64,
+    expr: impl FnOnce(&Box<Node>) -> String,
+) -> String {
+    match offset(node) {
+        (None, val) => (val + by).to_string(),
+        (Some(node), val) => {
+            let expr = expr(node);
+            match val + by {
+                0 => expr,
+                shift if shift > 0 => format!("{} + {}", expr, shift),
+                shift => format!("{} - {}", expr, -shift),
+            }
+        }
+    }
+}
+
+// A variable by name where it is used: a property inside a method, an enumerated
+// value by its type, or the field of its holder object when it is kept in one
+pub(crate) fn variable(program: &Program, names: &mut Names, name: &String, field: &str) -> String {
+    if program.field(name) {
+        return names.get(name);
+    }
+    if let (Kind::Variant, Some(enumeration)) = (names.kind(name), program.variants.get(name)) {
+        return format!("{}.{}", names.type_name(enumeration), names.get(name));
+    }
+    match program.is_boxed(name) {
+        true => format!("{}.{}", names.get(name), field),
+        false => names.get(name),
+    }
+}
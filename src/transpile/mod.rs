@@ -0,0 +1,410 @@
+mod java;
+pub(crate) mod lower;
+mod python;
+mod vbnet;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::Deref;
+use std::path::Path;
+
+use crate::enums::Node;
+use crate::lexer;
+use crate::tokens::TToken;
+
+// The languages a program can be written out in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    Python,
+    Java,
+    VbNet,
+}
+
+// Write a program that checks without problems in another language. What the
+// language cannot say the same way is listed in a comment at the top.
+pub(crate) fn transpile(name: &str, text: &str, ast: &[Box<Node>], to: Language) -> String {
+    let children = match ast.first().map(|node| node.deref()) {
+        Some(Node::Main { children }) => children,
+        _ => return String::new(),
+    };
+    match to {
+        Language::Python => python::emit(name, text, children),
+        Language::Java => java::emit(name, text, children),
+        Language::VbNet => vbnet::emit(name, text, children),
+    }
+}
+
+// A part of the runtime support, written out only when the program uses it
+pub(crate) struct Part {
+    pub name: &'static str,
+    pub imports: &'static [&'static str],
+    // Other parts it calls on
+    pub needs: &'static [&'static str],
+    pub code: &'static str,
+}
+
+// The lines of a program being written out, and what it needs besides them
+pub(crate) struct Writer {
+    indent: &'static str,
+    // In the order the parts are written out
+    prelude: &'static [Part],
+    pub lines: Vec<String>,
+    pub depth: usize,
+    // Statements an expression needs run before the one it is part of
+    pub pre: Vec<String>,
+    pub notes: Vec<String>,
+    pub parts: HashSet<&'static str>,
+    pub imports: BTreeSet<String>,
+}
+
+impl Writer {
+    pub fn new(indent: &'static str, prelude: &'static [Part]) -> Self {
+        Writer {
+            indent,
+            prelude,
+            lines: vec![],
+            depth: 0,
+            pre: vec![],
+            notes: vec![],
+            parts: HashSet::new(),
+            imports: BTreeSet::new(),
+        }
+    }
+
+    // A line at the current depth, after the lines its expressions need run first
+    pub fn line(&mut self, text: String) {
+        let indent = self.indent.repeat(self.depth);
+        for pre in std::mem::take(&mut self.pre) {
+            self.lines.push(format!("{}{}", indent, pre));
+        }
+        self.lines.push(format!("{}{}", indent, text));
+    }
+
+    // Blank lines between definitions, never more than asked for
+    pub fn blank(&mut self, count: usize) {
+        let blanks = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| line.is_empty())
+            .count();
+        for _ in blanks..count {
+            self.lines.push(String::new());
+        }
+    }
+
+    pub fn note(&mut self, line: usize, note: &str) {
+        let note = format!("line {}: {}", line, note);
+        if !self.notes.contains(&note) {
+            self.notes.push(note);
+        }
+    }
+
+    pub fn use_part(&mut self, name: &'static str) {
+        if self.parts.insert(name) {
+            if let Some(part) = self.prelude.iter().find(|part| part.name == name) {
+                for need in part.needs {
+                    self.use_part(need);
+                }
+            }
+        }
+    }
+
+    pub fn import(&mut self, import: &str) {
+        self.imports.insert(import.to_string());
+    }
+
+    // The comment naming the program and listing what was not translated faithfully
+    pub fn header(&self, comment: &str, name: &str) -> Vec<String> {
+        let mut out = vec![format!(
+            "{} Transpiled from {} by pseudoengine",
+            comment, name
+        )];
+        if !self.notes.is_empty() {
+            out.push(format!("{} Not translated faithfully:", comment));
+            for note in &self.notes {
+                out.push(format!("{} - {}", comment, note));
+            }
+        }
+        out
+    }
+
+    // The code of the parts used, with their imports added to the rest
+    pub fn prelude(&mut self) -> Vec<&'static str> {
+        let mut code = vec![];
+        for part in self.prelude {
+            if self.parts.contains(part.name) {
+                self.imports
+                    .extend(part.imports.iter().map(|import| import.to_string()));
+                code.push(part.code);
+            }
+        }
+        code
+    }
+
+    // The lines written, without the blank ones they start with
+    pub fn body(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.lines)
+            .into_iter()
+            .skip_while(|line| line.is_empty())
+            .collect()
+    }
+}
+
+// What kind of thing a name of the program stands for, which decides how it is cased
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Kind {
+    Value,
+    Constant,
+    Type,
+    Variant,
+    Routine,
+}
+
+// How a language cases each kind of name
+pub(crate) struct Style {
+    pub value: fn(&str) -> String,
+    pub constant: fn(&str) -> String,
+    pub type_name: fn(&str) -> String,
+    pub variant: fn(&str) -> String,
+    pub routine: fn(&str) -> String,
+    // Names that differ only in case are the same name, as in VB.NET
+    pub ignore_case: bool,
+}
+
+// Names as written in the program, turned into names that follow the target
+// language's conventions without clashing with its keywords or each other
+pub(crate) struct Names {
+    // The lexer lowercases identifiers, so their spelling is recovered from the source
+    written: HashMap<String, String>,
+    kinds: HashMap<String, Kind>,
+    given: HashMap<(String, Kind, bool), String>,
+    taken: HashSet<String>,
+    style: Style,
+}
+
+impl Names {
+    pub fn new(text: &str, children: &[Box<Node>], reserved: &[&str], style: Style) -> Self {
+        let mut names = Names {
+            written: HashMap::new(),
+            kinds: HashMap::new(),
+            given: HashMap::new(),
+            taken: HashSet::new(),
+            style,
+        };
+        for word in reserved {
+            names.unique(word.to_string());
+        }
+        let mut buf = text.to_string();
+        buf.push('\n');
+        let source: Vec<&str> = text.lines().collect();
+        for token in lexer::lexer(&mut buf.chars()).unwrap_or_default() {
+            if let TToken::Identifier(name) = &token.t {
+                let pos = &token.pos;
+                let spelling = source
+                    .get(pos.line_start.wrapping_sub(1))
+                    .and_then(|line| line.get(pos.pos_start..pos.pos_end))
+                    .filter(|spelling| spelling.to_lowercase() == *name);
+                if let Some(spelling) = spelling {
+                    names
+                        .written
+                        .entry(name.clone())
+                        .or_insert(spelling.to_string());
+                }
+            }
+        }
+        for node in children {
+            match node.deref() {
+                Node::Const { name, .. } => {
+                    names.kinds.insert(name.clone(), Kind::Constant);
+                }
+                Node::Enum { variants, .. } => {
+                    for variant in variants {
+                        if let Node::String { val, .. } = variant.deref() {
+                            names.kinds.insert(val.clone(), Kind::Variant);
+                        }
+                    }
+                }
+                Node::Function { .. } | Node::Procedure { .. } => {
+                    names.routine(node);
+                }
+                Node::Class { children, .. } => {
+                    for child in children {
+                        names.routine(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+
+    fn routine(&mut self, node: &Box<Node>) {
+        if let Node::Function { name, .. } | Node::Procedure { name, .. } = node.deref() {
+            if let Node::String { val, .. } = name.deref() {
+                self.kinds.entry(val.clone()).or_insert(Kind::Routine);
+            }
+        }
+    }
+
+    pub fn kind(&self, name: &str) -> Kind {
+        self.kinds.get(name).copied().unwrap_or(Kind::Value)
+    }
+
+    // The name in the target language, the same every time it is asked for
+    pub fn get(&mut self, name: &str) -> String {
+        self.cased(name, self.kind(name), false)
+    }
+
+    // Names of private members start with an underscore
+    pub fn private(&mut self, name: &str) -> String {
+        self.cased(name, self.kind(name), true)
+    }
+
+    // Types are named apart from variables, which may share their name
+    pub fn type_name(&mut self, name: &str) -> String {
+        self.cased(name, Kind::Type, false)
+    }
+
+    // A name of the translator's own, kept apart from the program's names
+    pub fn fresh(&mut self, base: &str) -> String {
+        self.unique(base.to_string())
+    }
+
+    fn cased(&mut self, name: &str, kind: Kind, private: bool) -> String {
+        let key = (name.to_string(), kind, private);
+        if let Some(given) = self.given.get(&key) {
+            return given.clone();
+        }
+        let written = self.written.get(name).cloned().unwrap_or(name.to_string());
+        let cased = match kind {
+            Kind::Value => (self.style.value)(&written),
+            Kind::Constant => (self.style.constant)(&written),
+            Kind::Type => (self.style.type_name)(&written),
+            Kind::Variant => (self.style.variant)(&written),
+            Kind::Routine => (self.style.routine)(&written),
+        };
+        let cased = match private {
+            true => format!("_{}", cased),
+            false => cased,
+        };
+        let given = self.unique(cased);
+        self.given.insert(key, given.clone());
+        given
+    }
+
+    fn unique(&mut self, mut name: String) -> String {
+        let ignore_case = self.style.ignore_case;
+        let key = |name: &String| match ignore_case {
+            true => name.to_lowercase(),
+            false => name.clone(),
+        };
+        while self.taken.contains(&key(&name)) {
+            name.push('_');
+        }
+        self.taken.insert(key(&name));
+        name
+    }
+}
+
+// The class or module a program is written in is named after its file, as Java
+// requires of a public class
+pub(crate) fn class_name(path: &str) -> String {
+    let stem = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem: String = stem
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let name = pascal_case(&snake_case(&stem));
+    match name.chars().next() {
+        Some(first) if !first.is_ascii_digit() => name,
+        _ => format!("Program{}", name),
+    }
+}
+
+// NumberOfStudents and numberOfStudents both become number_of_students
+pub(crate) fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            // A new word starts after a lowercase letter or digit, or at the last
+            // capital of an acronym, as in HTTPServer
+            if prev != '_' && (prev.is_lowercase() || prev.is_ascii_digit() || next_lower) {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+// NUMBER_OF_STUDENTS, for constants
+pub(crate) fn upper_snake_case(name: &str) -> String {
+    snake_case(name).to_uppercase()
+}
+
+// number_of_students and NumberOfStudents both become numberOfStudents
+pub(crate) fn camel_case(name: &str) -> String {
+    let pascal = pascal_case(&snake_case(name));
+    let mut chars = pascal.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => pascal,
+    }
+}
+
+// student_record and studentRecord both become StudentRecord
+pub(crate) fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap();
+            first.to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+// The value of an INTEGER expression made only of literals
+pub(crate) fn const_int(node: &Node) -> Option<i64> {
+    match node {
+        Node::Int { val, .. } => Some(*val),
+        Node::Unary { op, expr, .. } => match op.as_str() {
+            "_-" => const_int(expr)?.checked_neg(),
+            "_+" => const_int(expr),
+            _ => None,
+        },
+        Node::Binary { op, lhs, rhs, .. } => {
+            let (lhs, rhs) = (const_int(lhs)?, const_int(rhs)?);
+            match op.as_str() {
+                "+" => lhs.checked_add(rhs),
+                "-" => lhs.checked_sub(rhs),
+                "*" => lhs.checked_mul(rhs),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// An index written as an expression plus or minus a literal, split into the two
+pub(crate) fn offset(node: &Box<Node>) -> (Option<&Box<Node>>, i64) {
+    if let Some(val) = const_int(node) {
+        return (None, val);
+    }
+    if let Node::Binary { op, lhs, rhs, .. } = node.deref() {
+        if let Some(val) = const_int(rhs) {
+            match op.as_str() {
+                "+" => return (Some(lhs), val),
+                "-" => return (Some(lhs), -val),
+                _ => {}
+            }
+        }
+    }
+    (Some(node), 0)
+}
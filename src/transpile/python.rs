@@ -0,0 +1,1427 @@
+use std::ops::Deref;
+
+use crate::checker::{Signature, TypeDef};
+use crate::enums::{Node, VariableType};
+use crate::executor::hoist::is_definition;
+use crate::executor::run_builtins::builtin;
+use crate::tokens::TToken;
+use crate::transpile::lower::{
+    bracket, is_super_new, lower_bounds, methods, routine_name, shifted, Boxing, Expr, Program,
+};
+use crate::transpile::{
+    const_int, pascal_case, snake_case, upper_snake_case, Kind, Names, Part, Style, Writer,
+};
+
+const INDENT: &str = "    ";
+
+// Keywords, and the builtins, modules and prelude names the translation relies on
+const RESERVED: &[&str] = &[
+    "False",
+    "None",
+    "True",
+    "and",
+    "as",
+    "assert",
+    "async",
+    "await",
+    "break",
+    "class",
+    "continue",
+    "def",
+    "del",
+    "elif",
+    "else",
+    "except",
+    "finally",
+    "for",
+    "from",
+    "global",
+    "if",
+    "import",
+    "in",
+    "is",
+    "lambda",
+    "nonlocal",
+    "not",
+    "or",
+    "pass",
+    "raise",
+    "return",
+    "try",
+    "while",
+    "with",
+    "yield",
+    "abs",
+    "all",
+    "any",
+    "bool",
+    "chr",
+    "dict",
+    "enumerate",
+    "filter",
+    "float",
+    "format",
+    "id",
+    "input",
+    "int",
+    "iter",
+    "len",
+    "list",
+    "map",
+    "max",
+    "min",
+    "next",
+    "object",
+    "open",
+    "ord",
+    "print",
+    "range",
+    "repr",
+    "round",
+    "set",
+    "sorted",
+    "str",
+    "sum",
+    "super",
+    "tuple",
+    "type",
+    "zip",
+    "self",
+    "copy",
+    "json",
+    "math",
+    "random",
+    "date",
+    "dataclass",
+    "field",
+    "asdict",
+    "is_dataclass",
+    "Decimal",
+    "Enum",
+    "show",
+    "files",
+    "RandomFile",
+    "LEFT",
+    "RIGHT",
+    "MID",
+    "LENGTH",
+    "TO_UPPER",
+    "TO_LOWER",
+    "NUM_TO_STR",
+    "STR_TO_NUM",
+    "IS_NUM",
+    "ASC",
+    "CHR",
+    "INT",
+    "RAND",
+    "DAY",
+    "MONTH",
+    "YEAR",
+    "DAYINDEX",
+    "SETDATE",
+    "TODAY",
+    "EOF",
+];
+
+// In the order the parts are written out
+const PRELUDE: &[Part] = &[
+    Part {
+        name: "show",
+        imports: &[
+            "import math",
+            "from datetime import date",
+            "from decimal import Decimal",
+            "from enum import Enum",
+        ],
+        needs: &[],
+        code: r#"def show(value) -> str:
+    """The value as OUTPUT writes it."""
+    if isinstance(value, bool):
+        return "TRUE" if value else "FALSE"
+    if isinstance(value, float):
+        if math.isnan(value):
+            return "NaN"
+        if math.isinf(value):
+            return "inf" if value > 0 else "-inf"
+        return format(Decimal(repr(value)), "f").removesuffix(".0")
+    if isinstance(value, date):
+        return value.strftime("%d-%m-%Y")
+    if isinstance(value, Enum):
+        return value.value
+    return str(value)"#,
+    },
+    Part {
+        name: "files",
+        imports: &[],
+        needs: &[],
+        code: "# Open files by the name they were opened with\nfiles = {}",
+    },
+    Part {
+        name: "RandomFile",
+        imports: &[
+            "import json",
+            "from dataclasses import asdict, is_dataclass",
+            "from datetime import date",
+        ],
+        needs: &[],
+        code: r#"class RandomFile:
+    """A file opened for RANDOM, holding one record per line as JSON."""
+
+    def __init__(self, filename: str):
+        self.filename = filename
+        self.position = 1
+        try:
+            with open(filename) as file:
+                self.lines = file.read().splitlines()
+        except FileNotFoundError:
+            self.lines = []
+
+    def seek(self, position: int) -> None:
+        self.position = position
+
+    def get_record(self, record) -> None:
+        self._fill(record, json.loads(self.lines[self.position - 1]))
+
+    def put_record(self, record) -> None:
+        while len(self.lines) < self.position:
+            self.lines.append("")
+        self.lines[self.position - 1] = json.dumps(asdict(record), default=str)
+
+    def close(self) -> None:
+        with open(self.filename, "w") as file:
+            file.write("\n".join(self.lines))
+
+    def _fill(self, record, data: dict) -> None:
+        for name, value in data.items():
+            current = getattr(record, name)
+            if is_dataclass(current):
+                self._fill(current, value)
+            elif isinstance(current, date):
+                setattr(record, name, date.fromisoformat(value))
+            else:
+                setattr(record, name, value)"#,
+    },
+    Part {
+        name: "LEFT",
+        imports: &[],
+        needs: &[],
+        code: "def LEFT(text: str, length: int) -> str:\n    return text[:length]",
+    },
+    Part {
+        name: "RIGHT",
+        imports: &[],
+        needs: &[],
+        code: "def RIGHT(text: str, length: int) -> str:\n    return text[len(text) - length:]",
+    },
+    Part {
+        name: "MID",
+        imports: &[],
+        needs: &[],
+        code: "def MID(text: str, start: int, length: int) -> str:\n    return text[start - 1:start - 1 + length]",
+    },
+    Part {
+        name: "LENGTH",
+        imports: &[],
+        needs: &[],
+        code: "def LENGTH(text: str) -> int:\n    return len(text)",
+    },
+    Part {
+        name: "TO_UPPER",
+        imports: &[],
+        needs: &[],
+        code: "def TO_UPPER(text: str) -> str:\n    return text.upper()",
+    },
+    Part {
+        name: "TO_LOWER",
+        imports: &[],
+        needs: &[],
+        code: "def TO_LOWER(text: str) -> str:\n    return text.lower()",
+    },
+    Part {
+        name: "NUM_TO_STR",
+        imports: &[],
+        needs: &["show"],
+        code: "def NUM_TO_STR(number: float) -> str:\n    return show(number)",
+    },
+    Part {
+        name: "STR_TO_NUM",
+        imports: &[],
+        needs: &[],
+        code: r#"def STR_TO_NUM(text: str) -> int | float:
+    try:
+        return int(text)
+    except ValueError:
+        return float(text)"#,
+    },
+    Part {
+        name: "IS_NUM",
+        imports: &[],
+        needs: &[],
+        code: r#"def IS_NUM(text: str) -> bool:
+    try:
+        float(text)
+        return True
+    except ValueError:
+        return False"#,
+    },
+    Part {
+        name: "ASC",
+        imports: &[],
+        needs: &[],
+        code: "def ASC(char: str) -> int:\n    return ord(char)",
+    },
+    Part {
+        name: "CHR",
+        imports: &[],
+        needs: &[],
+        code: "def CHR(code: int) -> str:\n    return chr(code)",
+    },
+    Part {
+        name: "INT",
+        imports: &[],
+        needs: &[],
+        code: "def INT(number: float) -> int:\n    return int(number)",
+    },
+    Part {
+        name: "RAND",
+        imports: &["import random"],
+        needs: &[],
+        code: "def RAND(upper: int) -> float:\n    return random.random() * upper",
+    },
+    Part {
+        name: "DAY",
+        imports: &["from datetime import date"],
+        needs: &[],
+        code: "def DAY(day: date) -> int:\n    return day.day",
+    },
+    Part {
+        name: "MONTH",
+        imports: &["from datetime import date"],
+        needs: &[],
+        code: "def MONTH(day: date) -> int:\n    return day.month",
+    },
+    Part {
+        name: "YEAR",
+        imports: &["from datetime import date"],
+        needs: &[],
+        code: "def YEAR(day: date) -> int:\n    return day.year",
+    },
+    Part {
+        name: "DAYINDEX",
+        imports: &["from datetime import date"],
+        needs: &[],
+        code: "def DAYINDEX(day: date) -> int:\n    # Sunday is 1\n    return day.isoweekday() % 7 + 1",
+    },
+    Part {
+        name: "SETDATE",
+        imports: &["from datetime import date"],
+        needs: &[],
+        code: "def SETDATE(day: int, month: int, year: int) -> date:\n    return date(year, month, day)",
+    },
+    Part {
+        name: "TODAY",
+        imports: &["from datetime import date"],
+        needs: &[],
+        code: "def TODAY() -> date:\n    return date.today()",
+    },
+    Part {
+        name: "EOF",
+        imports: &[],
+        needs: &["files"],
+        code: r#"def EOF(filename: str) -> bool:
+    file = files[filename]
+    position = file.tell()
+    at_end = file.readline() == ""
+    file.seek(position)
+    return at_end"#,
+    },
+];
+
+// How tightly an expression binds, so operands are bracketed only when they must be
+const OR: u8 = 1;
+const AND: u8 = 2;
+const NOT: u8 = 3;
+const CMP: u8 = 4;
+const ADD: u8 = 5;
+const MUL: u8 = 6;
+const UNARY: u8 = 7;
+const ATOM: u8 = 8;
+
+// A piece of a line of OUTPUT or WRITEFILE
+enum Piece {
+    Literal(String),
+    // The expression, and whether it is already a str
+    Value(String, bool),
+}
+
+struct Python<'a> {
+    names: Names,
+    program: Program<'a>,
+    out: Writer,
+}
+
+pub(crate) fn emit(name: &str, text: &str, children: &[Box<Node>]) -> String {
+    let style = Style {
+        value: snake_case,
+        constant: upper_snake_case,
+        type_name: pascal_case,
+        variant: upper_snake_case,
+        routine: snake_case,
+        ignore_case: false,
+    };
+    // BYREF parameters are handed back instead
+    let boxing = Boxing {
+        byref: false,
+        pointers: false,
+    };
+    let mut python = Python {
+        names: Names::new(text, children, RESERVED, style),
+        program: Program::new(children, boxing),
+        out: Writer::new(INDENT, PRELUDE),
+    };
+    let definitions: Vec<&Box<Node>> = python
+        .program
+        .types
+        .iter()
+        .chain(&python.program.routines)
+        .copied()
+        .collect();
+    for node in definitions {
+        python.out.blank(2);
+        python.definition(node);
+    }
+    python.out.blank(2);
+    for node in python.program.main.clone() {
+        python.stmt(node);
+    }
+    python.finish(name)
+}
+
+impl<'a> Python<'a> {
+    // The header, imports and prelude above the program
+    fn finish(mut self, name: &str) -> String {
+        let mut out = self.out.header("#", name);
+        let prelude = self.out.prelude();
+        if !self.out.imports.is_empty() {
+            out.push(String::new());
+            // Plain imports go above the from imports
+            let (plain, from): (Vec<&String>, Vec<&String>) = self
+                .out
+                .imports
+                .iter()
+                .partition(|import| import.starts_with("import "));
+            out.extend(plain.into_iter().chain(from).cloned());
+        }
+        for code in prelude {
+            out.push(String::new());
+            out.push(String::new());
+            out.push(code.to_string());
+        }
+        let body = self.out.body();
+        if !body.is_empty() {
+            out.push(String::new());
+            out.push(String::new());
+            out.extend(body);
+        }
+        out.join("\n") + "\n"
+    }
+
+    fn block(&mut self, nodes: &Vec<Box<Node>>) {
+        self.out.depth += 1;
+        let start = self.out.lines.len();
+        for node in nodes {
+            self.stmt(node);
+        }
+        if self.out.lines.len() == start {
+            self.out.line("pass".to_string());
+        }
+        self.out.depth -= 1;
+    }
+
+    fn annotation(&mut self, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Integer => "int".to_string(),
+            VariableType::Real => "float".to_string(),
+            VariableType::Boolean => "bool".to_string(),
+            VariableType::Char | VariableType::String => "str".to_string(),
+            VariableType::Date => {
+                self.out.import("from datetime import date");
+                "date".to_string()
+            }
+            VariableType::Array { shape, t } => {
+                let mut annotation = self.annotation(&t);
+                for _ in shape {
+                    annotation = format!("list[{}]", annotation);
+                }
+                annotation
+            }
+            VariableType::Pointer(t) => format!("{} | None", self.annotation(&t)),
+            VariableType::Custom(name) => {
+                let class = self.names.type_name(&name);
+                match self.program.checker.types.get(&name) {
+                    Some(TypeDef::Record { .. }) => class,
+                    _ => format!("{} | None", class),
+                }
+            }
+        }
+    }
+
+    // The value a variable of this type starts out with
+    fn default(&mut self, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Integer => "0".to_string(),
+            VariableType::Real => "0.0".to_string(),
+            VariableType::Boolean => "False".to_string(),
+            VariableType::String => "\"\"".to_string(),
+            VariableType::Char => "\" \"".to_string(),
+            VariableType::Date => {
+                self.out.import("from datetime import date");
+                "date(1970, 1, 1)".to_string()
+            }
+            VariableType::Array { shape, t } => {
+                let element = self.default(&t);
+                // Elements that can be changed in place each need one of their own
+                let shared = !self.program.copied(&Some(*t));
+                let mut value = element;
+                for (i, index) in shape.iter().enumerate().rev() {
+                    let length = (index.upper - index.lower + 1).max(0);
+                    value = match shared && i == shape.len() - 1 {
+                        true => format!("[{}] * {}", value, length),
+                        false => format!("[{} for _ in range({})]", value, length),
+                    };
+                }
+                value
+            }
+            VariableType::Custom(name) => match self.program.checker.types.get(&name) {
+                Some(TypeDef::Record { .. }) => format!("{}()", self.names.type_name(&name)),
+                _ => "None".to_string(),
+            },
+            VariableType::Pointer(_) => "None".to_string(),
+        }
+    }
+
+    fn definition(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Enum { name, variants, .. } => {
+                self.out.import("from enum import Enum");
+                let class = self.names.type_name(name);
+                self.out.line(format!("class {}(Enum):", class));
+                self.out.depth += 1;
+                for variant in variants {
+                    if let Node::String { val, .. } = variant.deref() {
+                        // Enums are written out by the name the interpreter gives them
+                        let member = self.names.get(val);
+                        self.out.line(format!("{} = {}", member, quote(val)));
+                    }
+                }
+                self.out.depth -= 1;
+            }
+            Node::Record { name, children, .. } => {
+                self.out.import("from dataclasses import dataclass");
+                let class = match name.deref() {
+                    Node::String { val, .. } => self.names.type_name(val),
+                    _ => return,
+                };
+                self.out.line("@dataclass".to_string());
+                self.out.line(format!("class {}:", class));
+                self.out.depth += 1;
+                let start = self.out.lines.len();
+                for child in children {
+                    if let Node::Declare { t, children, .. } = child.deref() {
+                        for field in children {
+                            let field = self.names.get(field);
+                            let annotation = self.annotation(t);
+                            let mut default = self.default(t);
+                            if self.program.copied(&Some(self.program.checker.resolve(t))) {
+                                self.out.import("from dataclasses import field");
+                                default = match default.ends_with("()") {
+                                    true => format!(
+                                        "field(default_factory={})",
+                                        &default[..default.len() - 2]
+                                    ),
+                                    false => format!("field(default_factory=lambda: {})", default),
+                                };
+                            }
+                            self.out
+                                .line(format!("{}: {} = {}", field, annotation, default));
+                        }
+                    }
+                }
+                if self.out.lines.len() == start {
+                    self.out.line("pass".to_string());
+                }
+                self.out.depth -= 1;
+            }
+            Node::Class {
+                name,
+                base,
+                children,
+                ..
+            } => self.class(name, base, children),
+            Node::Function { .. } | Node::Procedure { .. } => self.routine(node, None),
+            // Pointer types are written out as the type they point to
+            _ => {}
+        }
+    }
+
+    fn class(&mut self, name: &Box<Node>, base: &Box<Node>, children: &[Box<Node>]) {
+        let Node::String { val: class, .. } = name.deref() else {
+            return;
+        };
+        let header = match base.deref() {
+            Node::String { val, .. } => {
+                format!(
+                    "class {}({}):",
+                    self.names.type_name(class),
+                    self.names.type_name(val)
+                )
+            }
+            _ => format!("class {}:", self.names.type_name(class)),
+        };
+        self.out.line(header);
+        self.out.depth += 1;
+        let methods = methods(children);
+        let constructor = methods.iter().any(|method| routine_name(method) == "new");
+        if !constructor {
+            self.out.line("def __init__(self) -> None:".to_string());
+            self.out.depth += 1;
+            let start = self.out.lines.len();
+            self.properties(class, true);
+            if self.out.lines.len() == start {
+                self.out.line("pass".to_string());
+            }
+            self.out.depth -= 1;
+        }
+        for (i, method) in methods.into_iter().enumerate() {
+            if i > 0 || !constructor {
+                self.out.blank(1);
+            }
+            self.routine(method, Some(class));
+        }
+        self.out.depth -= 1;
+    }
+
+    // The properties a class starts out with, those of its ancestors as well when its
+    // constructor does not call theirs
+    fn properties(&mut self, class: &String, inherited: bool) {
+        let Some(Node::Class { base, children, .. }) =
+            self.program.classes.get(class).copied().map(|c| c.as_ref())
+        else {
+            return;
+        };
+        if let (true, Node::String { val, .. }) = (inherited, base.deref()) {
+            self.properties(val, true);
+        }
+        for child in children {
+            if let Node::Declare {
+                t,
+                children,
+                private,
+                ..
+            } = child.deref()
+            {
+                for property in children {
+                    let member = self.member(property, *private);
+                    let annotation = self.annotation(t);
+                    let default = self.default(t);
+                    self.out
+                        .line(format!("self.{}: {} = {}", member, annotation, default));
+                }
+            }
+        }
+    }
+
+    fn member(&mut self, name: &str, private: bool) -> String {
+        match private {
+            true => self.names.private(name),
+            false => self.names.get(name),
+        }
+    }
+
+    fn routine(&mut self, node: &Box<Node>, class: Option<&String>) {
+        let name = routine_name(node).to_string();
+        let mut body = self.program.enter(node, class);
+        let scope = self.program.scope.as_ref().unwrap();
+        let (signature, returned) = (scope.signature.clone(), scope.plain.clone());
+        let (global, copies) = (scope.global.clone(), scope.copies.clone());
+
+        let mut header = match (class.is_some(), name.as_str()) {
+            (true, "new") => "__init__".to_string(),
+            (true, _) => self.member(&name, signature.private),
+            _ => self.names.get(&name),
+        };
+        let mut args: Vec<String> = match class {
+            Some(_) => vec!["self".to_string()],
+            None => vec![],
+        };
+        for param in &signature.params {
+            let annotation = self.annotation(&param.t);
+            args.push(format!("{}: {}", self.names.get(&param.name), annotation));
+        }
+        let mut results = vec![];
+        if let Some(returns) = &signature.returns {
+            results.push(self.annotation(returns));
+        }
+        for param in signature
+            .params
+            .iter()
+            .filter(|p| returned.contains(&p.name))
+        {
+            results.push(self.annotation(&param.t));
+        }
+        let returns = match results.len() {
+            0 => "None".to_string(),
+            1 => results.remove(0),
+            _ => format!("tuple[{}]", results.join(", ")),
+        };
+        header = format!("def {}({}) -> {}:", header, args.join(", "), returns);
+        self.out.line(header);
+
+        self.out.depth += 1;
+        let start = self.out.lines.len();
+        if !global.is_empty() {
+            let global: Vec<String> = global.iter().map(|name| self.names.get(name)).collect();
+            self.out.line(format!("global {}", global.join(", ")));
+        }
+        // A BYVAL array or record is the caller's to keep
+        for param in &copies {
+            self.out.import("import copy");
+            let param = self.names.get(param);
+            self.out
+                .line(format!("{} = copy.deepcopy({})", param, param));
+        }
+        if let (Some(class), "new") = (class, name.as_str()) {
+            let calls_base = body.first().is_some_and(is_super_new);
+            if calls_base {
+                self.stmt(&body.remove(0));
+            }
+            self.properties(class, !calls_base);
+        }
+        for node in &body {
+            self.stmt(node);
+        }
+        if signature.returns.is_none() && !returned.is_empty() {
+            let returned: Vec<String> = returned.iter().map(|name| self.names.get(name)).collect();
+            self.out.line(format!("return {}", returned.join(", ")));
+        }
+        if self.out.lines.len() == start {
+            self.out.line("pass".to_string());
+        }
+        self.out.depth -= 1;
+        self.program.leave();
+    }
+
+    fn stmt(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Declare { t, children, .. } => {
+                for name in children {
+                    let var = self.var(name);
+                    let annotation = self.annotation(t);
+                    let default = self.default(t);
+                    self.out
+                        .line(format!("{}: {} = {}", var, annotation, default));
+                }
+            }
+            Node::Const { name, val, .. } => {
+                let val = self.value(val);
+                let name = self.var(name);
+                self.out.line(format!("{} = {}", name, val));
+            }
+            Node::Assignment { lhs, rhs, .. } => self.assign(lhs, rhs),
+            Node::Output { children, .. } => {
+                let pieces = self.pieces(children, false);
+                let line = print(pieces);
+                self.out.line(line);
+            }
+            Node::Input { child, .. } => {
+                let target = self.value(child);
+                let value = match self.program.type_of(child) {
+                    Some(VariableType::Integer) => "int(input())",
+                    Some(VariableType::Real) => "float(input())",
+                    Some(VariableType::Boolean) => "input().upper() == \"TRUE\"",
+                    _ => "input()",
+                };
+                self.out.line(format!("{} = {}", target, value));
+            }
+            Node::If { .. } => self.if_chain(node, false),
+            Node::While { cond, body, .. } => {
+                let (cond, prec) = self.expr(cond);
+                if self.out.pre.is_empty() {
+                    self.out.line(format!("while {}:", cond));
+                    self.block(body);
+                } else {
+                    // The condition needs statements of its own run before each check
+                    let pre = std::mem::take(&mut self.out.pre);
+                    self.out.line("while True:".to_string());
+                    self.out.depth += 1;
+                    self.out.pre = pre;
+                    self.out
+                        .line(format!("if not {}:", bracket((cond, prec), NOT)));
+                    self.out.depth += 1;
+                    self.out.line("break".to_string());
+                    self.out.depth -= 1;
+                    for node in body {
+                        self.stmt(node);
+                    }
+                    self.out.depth -= 1;
+                }
+            }
+            Node::Repeat { cond, body, .. } => {
+                self.out.line("while True:".to_string());
+                self.out.depth += 1;
+                for node in body {
+                    self.stmt(node);
+                }
+                let cond = self.value(cond);
+                self.out.line(format!("if {}:", cond));
+                self.out.depth += 1;
+                self.out.line("break".to_string());
+                self.out.depth -= 2;
+            }
+            Node::For {
+                iter,
+                range,
+                step,
+                body,
+                ..
+            } => {
+                let counter = self.value(iter);
+                let Node::Range { start, end, .. } = range.deref() else {
+                    return;
+                };
+                let range = self.range(start, end, step);
+                self.out.line(format!("for {} in {}:", counter, range));
+                self.block(body);
+            }
+            Node::Switch {
+                cmp,
+                cases,
+                otherwise,
+                ..
+            } => self.switch(cmp, cases, otherwise),
+            Node::Return { expr, .. } => {
+                let mut values = vec![self.value(expr)];
+                if let Some(scope) = &self.program.scope {
+                    let returned = scope.plain.clone();
+                    values.extend(returned.iter().map(|name| self.names.get(name)));
+                }
+                self.out.line(format!("return {}", values.join(", ")));
+            }
+            Node::FunctionCall { name, params, .. } => {
+                let (call, returned) = self.call(name, params);
+                self.call_stmt(call, returned, node);
+            }
+            Node::Composite { children, .. } => {
+                let (call, returned, _) = self.composite(children, true);
+                self.call_stmt(call.0, returned, node);
+            }
+            Node::OpenFile { filename, mode, .. } => {
+                self.out.use_part("files");
+                let file = self.value(filename);
+                let opened = match mode {
+                    TToken::FileMode(mode) => match mode.as_str() {
+                        "WRITE" => format!("open({}, \"w\")", file),
+                        "APPEND" => format!("open({}, \"a\")", file),
+                        "RANDOM" => {
+                            self.out.use_part("RandomFile");
+                            format!("RandomFile({})", file)
+                        }
+                        _ => format!("open({})", file),
+                    },
+                    _ => format!("open({})", file),
+                };
+                self.out.line(format!("files[{}] = {}", file, opened));
+            }
+            Node::CloseFile { filename, .. } => {
+                let file = self.value(filename);
+                self.out.line(format!("files.pop({}).close()", file));
+            }
+            Node::ReadFile { filename, var, .. } => {
+                let file = self.value(filename);
+                let target = self.value(var);
+                self.out.line(format!(
+                    "{} = files[{}].readline().rstrip(\"\\n\")",
+                    target, file
+                ));
+            }
+            Node::WriteFile { filename, expr, .. } => {
+                let file = self.value(filename);
+                let pieces = self.pieces(std::slice::from_ref(expr), true);
+                self.out
+                    .line(format!("files[{}].write({})", file, text_line(pieces)));
+            }
+            Node::SeekFile { filename, expr, .. } => {
+                let file = self.value(filename);
+                let position = self.value(expr);
+                self.out.line(format!("files[{}].seek({})", file, position));
+            }
+            Node::GetRecord { filename, var, .. } => {
+                let file = self.value(filename);
+                let record = self.value(var);
+                self.out
+                    .line(format!("files[{}].get_record({})", file, record));
+            }
+            Node::PutRecord { filename, var, .. } => {
+                let file = self.value(filename);
+                let record = self.value(var);
+                self.out
+                    .line(format!("files[{}].put_record({})", file, record));
+            }
+            Node::Null => {}
+            node if is_definition(&Box::new(node.clone())) => {
+                self.definition(&Box::new(node.clone()))
+            }
+            _ => {
+                let value = self.value(node);
+                self.out.line(value);
+            }
+        }
+    }
+
+    fn call_stmt(&mut self, call: String, returned: Vec<String>, node: &Box<Node>) {
+        if returned.is_empty() {
+            self.out.line(call);
+            return;
+        }
+        // A function called as a statement still hands back its value first
+        let mut targets = returned;
+        if self.program.type_of(node).is_some() {
+            targets.insert(0, "_".to_string());
+        }
+        self.out.line(format!("{} = {}", targets.join(", "), call));
+    }
+
+    fn assign(&mut self, lhs: &Box<Node>, rhs: &Box<Node>) {
+        let target = self.value(lhs);
+        if let Node::Binary {
+            op,
+            lhs: left,
+            rhs: right,
+            ..
+        } = rhs.deref()
+        {
+            let op = match op.as_str() {
+                "+" | "&" => Some("+"),
+                "-" => Some("-"),
+                "*" => Some("*"),
+                "//" => Some("//"),
+                _ => None,
+            };
+            if let Some(op) = op {
+                let same = match (lhs.deref(), left.deref()) {
+                    (Node::Var { name, .. }, Node::Var { name: other, .. }) => name == other,
+                    (Node::ArrayVar { .. } | Node::Composite { .. }, _) => {
+                        self.value(left) == target && !target.contains('(')
+                    }
+                    _ => false,
+                };
+                if same {
+                    let right = self.value(right);
+                    self.out.line(format!("{} {}= {}", target, op, right));
+                    return;
+                }
+            }
+        }
+        let mut value = self.value(rhs);
+        let copies = matches!(
+            rhs.deref(),
+            Node::Var { .. }
+                | Node::ArrayVar { .. }
+                | Node::Composite { .. }
+                | Node::Dereference { .. }
+        );
+        let t = self.program.type_of(rhs);
+        if copies && self.program.copied(&t) {
+            self.out.import("import copy");
+            value = format!("copy.deepcopy({})", value);
+        }
+        self.out.line(format!("{} = {}", target, value));
+    }
+
+    fn if_chain(&mut self, node: &Box<Node>, elif: bool) {
+        let Node::If {
+            cond,
+            true_body,
+            false_body,
+            ..
+        } = node.deref()
+        else {
+            return;
+        };
+        let cond = self.value(cond);
+        // A condition needing statements run first cannot follow elif
+        if elif && !self.out.pre.is_empty() {
+            let pre = std::mem::take(&mut self.out.pre);
+            self.out.line("else:".to_string());
+            self.out.depth += 1;
+            self.out.pre = pre;
+            self.out.line(format!("if {}:", cond));
+            self.rest_of_if(true_body, false_body);
+            self.out.depth -= 1;
+            return;
+        }
+        let keyword = if elif { "elif" } else { "if" };
+        self.out.line(format!("{} {}:", keyword, cond));
+        self.rest_of_if(true_body, false_body);
+    }
+
+    fn rest_of_if(&mut self, true_body: &Vec<Box<Node>>, false_body: &Vec<Box<Node>>) {
+        self.block(true_body);
+        match false_body.as_slice() {
+            [] => {}
+            [node] if matches!(node.deref(), Node::If { .. }) => self.if_chain(node, true),
+            _ => {
+                self.out.line("else:".to_string());
+                self.block(false_body);
+            }
+        }
+    }
+
+    // The range a FOR loop counts over, its end included
+    fn range(&mut self, start: &Box<Node>, end: &Box<Node>, step: &Box<Node>) -> String {
+        let start_value = self.value(start);
+        let step_value = match step.deref() {
+            Node::Null => Some(1),
+            step => const_int(step),
+        };
+        let Some(step_value) = step_value else {
+            let step = self.value(step);
+            let end = bracket(self.expr(end), ADD);
+            return format!(
+                "range({}, {} + (1 if {} > 0 else -1), {})",
+                start_value, end, step, step
+            );
+        };
+        let stop = shifted(end, step_value.signum(), |expr| {
+            bracket(self.expr(expr), ADD)
+        });
+        match (step_value, const_int(start)) {
+            (1, Some(0)) => format!("range({})", stop),
+            (1, _) => format!("range({}, {})", start_value, stop),
+            _ => format!("range({}, {}, {})", start_value, stop, step_value),
+        }
+    }
+
+    fn switch(&mut self, cmp: &Box<Node>, cases: &Vec<Box<Node>>, otherwise: &Vec<Box<Node>>) {
+        let mut subject = self.expr(cmp);
+        // The value is worked out once, as CASE does
+        if subject.0.contains('(') {
+            let choice = self.names.fresh("choice");
+            self.out.line(format!("{} = {}", choice, subject.0));
+            subject = (choice, ATOM);
+        }
+        let subject = bracket(subject, CMP + 1);
+        let mut first = true;
+        for case in cases {
+            let Node::Case { expr, children, .. } = case.deref() else {
+                continue;
+            };
+            let cond = match expr.deref() {
+                Node::Range { start, end, .. } => {
+                    let start = bracket(self.expr(start), CMP + 1);
+                    let end = bracket(self.expr(end), CMP + 1);
+                    format!("{} <= {} <= {}", start, subject, end)
+                }
+                _ => format!("{} == {}", subject, bracket(self.expr(expr), CMP + 1)),
+            };
+            let keyword = if first { "if" } else { "elif" };
+            self.out.line(format!("{} {}:", keyword, cond));
+            self.block(children);
+            first = false;
+        }
+        if first {
+            for node in otherwise {
+                self.stmt(node);
+            }
+        } else if !otherwise.is_empty() {
+            self.out.line("else:".to_string());
+            self.block(otherwise);
+        }
+    }
+
+    // What OUTPUT or WRITEFILE writes, each value turned into the text the interpreter gives
+    fn pieces(&mut self, children: &[Box<Node>], file: bool) -> Vec<Piece> {
+        let mut pieces = vec![];
+        for child in children {
+            let literal = match child.deref() {
+                Node::String { val, .. } => Some(val.clone()),
+                Node::Char { val, .. } => Some(val.to_string()),
+                Node::Int { val, .. } => Some(val.to_string()),
+                Node::Boolean { val, .. } => Some(match (file, val) {
+                    (true, _) => val.to_string(),
+                    (false, true) => "TRUE".to_string(),
+                    (false, false) => "FALSE".to_string(),
+                }),
+                _ => None,
+            };
+            if let Some(literal) = literal {
+                pieces.push(Piece::Literal(literal));
+                continue;
+            }
+            let t = self.program.type_of(child);
+            let value = self.expr(child);
+            pieces.push(match t {
+                Some(VariableType::String | VariableType::Char) => Piece::Value(value.0, true),
+                Some(VariableType::Integer) => Piece::Value(value.0, false),
+                Some(VariableType::Date) if file => Piece::Value(value.0, false),
+                Some(VariableType::Boolean) if file => {
+                    Piece::Value(format!("str({}).lower()", value.0), true)
+                }
+                _ => {
+                    self.out.use_part("show");
+                    Piece::Value(format!("show({})", value.0), true)
+                }
+            });
+        }
+        pieces
+    }
+
+    // The value of an expression as Python, with the precedence it binds with
+    fn expr(&mut self, node: &Box<Node>) -> Expr {
+        match node.deref() {
+            Node::Int { val, .. } => (val.to_string(), ATOM),
+            Node::Real { val, .. } => {
+                let text = val.to_string();
+                match text.contains(['.', 'e', 'N', 'i']) {
+                    true => (text, ATOM),
+                    false => (text + ".0", ATOM),
+                }
+            }
+            Node::String { val, .. } => (quote(val), ATOM),
+            Node::Char { val, .. } => (quote(&val.to_string()), ATOM),
+            Node::Boolean { val, .. } => (if *val { "True" } else { "False" }.to_string(), ATOM),
+            Node::Date { val, .. } => {
+                self.out.import("from datetime import date");
+                let text = val.format("date(%Y, %m, %d)").to_string();
+                (text.replace(", 0", ", "), ATOM)
+            }
+            Node::Var { name, .. } => (self.var(name), ATOM),
+            Node::ArrayVar { name, indices, pos } => {
+                let array = self.var(name);
+                let t = self.program.type_of(&Box::new(Node::Var {
+                    name: name.clone(),
+                    pos: *pos,
+                }));
+                let subscripts = self.subscripts(indices, &t);
+                (array + &subscripts, ATOM)
+            }
+            Node::FunctionCall { name, params, .. } => {
+                let (call, returned) = self.call(name, params);
+                (self.hoist(name, call, returned), ATOM)
+            }
+            Node::CreateObject { call, .. } => match call.deref() {
+                Node::FunctionCall { name, params, .. } => {
+                    let args: Vec<String> = params.iter().map(|param| self.value(param)).collect();
+                    (
+                        format!("{}({})", self.names.type_name(name), args.join(", ")),
+                        ATOM,
+                    )
+                }
+                _ => ("None".to_string(), ATOM),
+            },
+            Node::Composite { children, .. } => self.composite(children, false).0,
+            Node::Reference { expr, pos } => {
+                let t = self.program.type_of(expr);
+                if !matches!(
+                    self.program.type_def(&t),
+                    Some(TypeDef::Record { .. } | TypeDef::Class { .. })
+                ) {
+                    self.out.note(
+                        pos.line_start,
+                        "Python has no pointers to variables, the pointer holds a copy of the value",
+                    );
+                }
+                self.expr(expr)
+            }
+            Node::Dereference { expr, .. } => self.expr(expr),
+            Node::Unary { op, expr, .. } => match op.as_str() {
+                "!" => (format!("not {}", bracket(self.expr(expr), NOT)), NOT),
+                "_-" => (format!("-{}", bracket(self.expr(expr), UNARY)), UNARY),
+                _ => (format!("+{}", bracket(self.expr(expr), UNARY)), UNARY),
+            },
+            Node::Binary { op, lhs, rhs, .. } => self.binary(op, lhs, rhs),
+            _ => ("None".to_string(), ATOM),
+        }
+    }
+
+    fn value(&mut self, node: &Box<Node>) -> String {
+        self.expr(node).0
+    }
+
+    fn binary(&mut self, op: &str, lhs: &Box<Node>, rhs: &Box<Node>) -> Expr {
+        let integers = self.program.type_of(lhs) == Some(VariableType::Integer)
+            && self.program.type_of(rhs) == Some(VariableType::Integer);
+        // The remainder takes the sign of the number divided, where % in Python
+        // takes the sign of the divisor. DIV rounds down in both.
+        if op == "%" {
+            self.out.import("import math");
+            let left = self.value(lhs);
+            let right = self.value(rhs);
+            return match integers {
+                true => (format!("int(math.fmod({}, {}))", left, right), ATOM),
+                false => (format!("math.fmod({}, {})", left, right), ATOM),
+            };
+        }
+        let (symbol, prec) = match op {
+            "||" => ("or", OR),
+            "&&" => ("and", AND),
+            "=" => ("==", CMP),
+            "!=" => ("!=", CMP),
+            "<" | ">" | "<=" | ">=" => (op, CMP),
+            "+" | "&" => ("+", ADD),
+            "-" => ("-", ADD),
+            "*" => ("*", MUL),
+            "/" => ("/", MUL),
+            "//" => ("//", MUL),
+            _ => (op, ATOM),
+        };
+        // Comparisons would chain in Python, so they are bracketed inside each other
+        let left = match prec {
+            CMP => CMP + 1,
+            prec => prec,
+        };
+        let left = bracket(self.expr(lhs), left);
+        // Joining strings gives the same whichever way round it is grouped
+        let joined = op == "&" && matches!(rhs.as_ref(), Node::Binary { op, .. } if op == "&");
+        let right = match joined {
+            true => self.value(rhs),
+            false => bracket(self.expr(rhs), prec + 1),
+        };
+        match op == "/" && integers {
+            // Dividing two INTEGERs gives an INTEGER, cut towards zero
+            true => (format!("int({} / {})", left, right), ATOM),
+            false => (format!("{} {} {}", left, symbol, right), prec),
+        }
+    }
+
+    // A variable by name, which inside a method may be a property of the object
+    fn var(&mut self, name: &String) -> String {
+        if self.program.field(name) {
+            let class = self.program.class().unwrap();
+            let private = self
+                .program
+                .checker
+                .get_field(&class, name)
+                .unwrap()
+                .private;
+            return format!("self.{}", self.member(name, private));
+        }
+        match (
+            self.names.kind(name),
+            self.program.variants.get(name).cloned(),
+        ) {
+            (Kind::Variant, Some(enumeration)) => {
+                format!(
+                    "{}.{}",
+                    self.names.type_name(&enumeration),
+                    self.names.get(name)
+                )
+            }
+            _ => self.names.get(name),
+        }
+    }
+
+    // Python lists start at 0, so each index is moved down by its lower bound
+    fn subscripts(&mut self, indices: &[Box<Node>], t: &Option<VariableType>) -> String {
+        let lower = lower_bounds(t);
+        let mut out = String::new();
+        for (i, index) in indices.iter().enumerate() {
+            let lower = lower.get(i).copied().unwrap_or(0);
+            let index = shifted(index, -lower, |expr| bracket(self.expr(expr), ADD));
+            out += &format!("[{}]", index);
+        }
+        out
+    }
+
+    // A call to a builtin, procedure, function or method of the object, along with
+    // the arguments its BYREF parameters hand back
+    fn call(&mut self, name: &String, params: &[Box<Node>]) -> (String, Vec<String>) {
+        if builtin(name).is_some() {
+            let builtin = name.to_uppercase();
+            if let Some(part) = PRELUDE.iter().find(|part| part.name == builtin) {
+                self.out.use_part(part.name);
+            }
+            let args: Vec<String> = params.iter().map(|param| self.value(param)).collect();
+            return (format!("{}({})", builtin, args.join(", ")), vec![]);
+        }
+        let class = self.program.class();
+        let method = class.and_then(|class| self.program.checker.get_method(&class, name));
+        let (callee, signature) = match method {
+            Some(method) => (
+                format!("self.{}", self.member(name, method.private)),
+                Some(method),
+            ),
+            None => (
+                self.names.get(name),
+                self.program.checker.routines.get(name).cloned(),
+            ),
+        };
+        self.args(callee, signature, params)
+    }
+
+    fn args(
+        &mut self,
+        callee: String,
+        signature: Option<Signature>,
+        params: &[Box<Node>],
+    ) -> (String, Vec<String>) {
+        let mut args = vec![];
+        let mut returned = vec![];
+        for (i, param) in params.iter().enumerate() {
+            let arg = self.value(param);
+            let byref = signature
+                .as_ref()
+                .and_then(|signature| signature.params.get(i));
+            if byref.is_some_and(|p| p.byref && self.program.plain(&p.t)) {
+                returned.push(arg.clone());
+            }
+            args.push(arg);
+        }
+        (format!("{}({})", callee, args.join(", ")), returned)
+    }
+
+    // A function handing back BYREF arguments is called before the statement using its value
+    fn hoist(&mut self, name: &str, call: String, returned: Vec<String>) -> String {
+        if returned.is_empty() {
+            return call;
+        }
+        let base = format!("{}_result", self.names.get(name).trim_end_matches('_'));
+        let result = self.names.fresh(&base);
+        self.out
+            .pre
+            .push(format!("{}, {} = {}", result, returned.join(", "), call));
+        result
+    }
+
+    // obj.prop, obj.arr[i] and obj.method() chains, with the arguments the last call
+    // hands back when it is a statement of its own
+    fn composite(
+        &mut self,
+        children: &[Box<Node>],
+        statement: bool,
+    ) -> (Expr, Vec<String>, Option<VariableType>) {
+        let first = &children[0];
+        let mut text = match first.deref() {
+            Node::Var { name, .. } if name == "super" => "super()".to_string(),
+            _ => self.value(first),
+        };
+        let mut t = self.program.type_of(first);
+        let mut returned = vec![];
+        for (i, child) in children.iter().enumerate().skip(1) {
+            let class = match &t {
+                Some(VariableType::Custom(name)) => Some(name.clone()),
+                _ => None,
+            };
+            match child.deref() {
+                Node::Var { name, .. } | Node::ArrayVar { name, .. } => {
+                    let field =
+                        class.and_then(|class| self.program.checker.get_field(&class, name));
+                    let private = field.as_ref().is_some_and(|field| field.private);
+                    text = format!("{}.{}", text, self.member(name, private));
+                    t = field.map(|field| self.program.checker.resolve(&field.t));
+                    if let Node::ArrayVar { indices, .. } = child.deref() {
+                        text += &self.subscripts(indices, &t);
+                        t = match t {
+                            Some(VariableType::Array { t, .. }) => Some(*t),
+                            _ => None,
+                        };
+                    }
+                }
+                Node::FunctionCall { name, params, .. } => {
+                    let method =
+                        class.and_then(|class| self.program.checker.get_method(&class, name));
+                    let callee = match (text.as_str(), name.as_str()) {
+                        ("super()", "new") => "super().__init__".to_string(),
+                        _ => {
+                            let private = method.as_ref().is_some_and(|method| method.private);
+                            format!("{}.{}", text, self.member(name, private))
+                        }
+                    };
+                    t = method
+                        .as_ref()
+                        .and_then(|method| method.returns.as_ref())
+                        .map(|returns| self.program.checker.resolve(returns));
+                    let (call, handed) = self.args(callee, method, params);
+                    if statement && i == children.len() - 1 {
+                        text = call;
+                        returned = handed;
+                    } else {
+                        text = self.hoist(name, call, handed);
+                    }
+                }
+                _ => {}
+            }
+        }
+        ((text, ATOM), returned, t)
+    }
+}
+
+// A Python string literal. Pseudocode strings have no escapes, so backslashes are kept
+fn quote(text: &str) -> String {
+    format!("\"{}\"", escape(text))
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => out += "\\\\",
+            '"' => out += "\\\"",
+            '\n' => out += "\\n",
+            '\t' => out += "\\t",
+            '\r' => out += "\\r",
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// An f-string, unless a value in it has quotes or braces an f-string cannot hold
+fn f_string(pieces: &[Piece], end: &str) -> Option<String> {
+    let mut out = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Literal(text) => out += &escape(text).replace('{', "{{").replace('}', "}}"),
+            Piece::Value(value, _) => {
+                if value.contains(['"', '\\', '{', '}']) {
+                    return None;
+                }
+                out += &format!("{{{}}}", value);
+            }
+        }
+    }
+    Some(format!("f\"{}{}\"", out, escape(end)))
+}
+
+fn print(pieces: Vec<Piece>) -> String {
+    match pieces.as_slice() {
+        [] => "print()".to_string(),
+        [Piece::Literal(text)] => format!("print({})", quote(text)),
+        [Piece::Value(value, _)] => format!("print({})", value),
+        _ => match f_string(&pieces, "") {
+            Some(text) => format!("print({})", text),
+            None => {
+                let args: Vec<String> = pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Literal(text) => quote(text),
+                        Piece::Value(value, _) => value.clone(),
+                    })
+                    .collect();
+                format!("print({}, sep=\"\")", args.join(", "))
+            }
+        },
+    }
+}
+
+// A line of a file, newline included
+fn text_line(pieces: Vec<Piece>) -> String {
+    match pieces.as_slice() {
+        [Piece::Literal(text)] => quote(&format!("{}\n", text)),
+        [Piece::Value(value, true)] => format!("{} + \"\\n\"", value),
+        [Piece::Value(value, false)] if value.contains(['"', '\\', '{', '}']) => {
+            format!("str({}) + \"\\n\"", value)
+        }
+        _ => f_string(&pieces, "\n").unwrap_or_default(),
+    }
+}
@@ -0,0 +1,1781 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+
+use crate::checker::{Signature, TypeDef};
+use crate::enums::{Index, Node, VariableType};
+use crate::executor::run_builtins::builtin;
+use crate::tokens::TToken;
+use crate::transpile::lower::{
+    body, bracket, declared_at_top, is_super_new, lower_bounds, methods, returns, routine_name,
+    shifted, variable, walk, Boxing, Expr, Program,
+};
+use crate::transpile::{
+    camel_case, class_name, const_int, pascal_case, Names, Part, Style, Writer,
+};
+
+const INDENT: &str = "    ";
+
+// Keywords, which VB.NET reserves whatever their case, and the names from the
+// framework and prelude the translation relies on
+const RESERVED: &[&str] = &[
+    "AddHandler",
+    "AddressOf",
+    "Alias",
+    "And",
+    "AndAlso",
+    "As",
+    "Boolean",
+    "ByRef",
+    "Byte",
+    "ByVal",
+    "Call",
+    "Case",
+    "Catch",
+    "CBool",
+    "CByte",
+    "CChar",
+    "CDate",
+    "CDbl",
+    "CDec",
+    "Char",
+    "CInt",
+    "Class",
+    "CLng",
+    "CObj",
+    "Const",
+    "Continue",
+    "CSByte",
+    "CShort",
+    "CSng",
+    "CStr",
+    "CType",
+    "CUInt",
+    "CULng",
+    "CUShort",
+    "Date",
+    "Decimal",
+    "Declare",
+    "Default",
+    "Delegate",
+    "Dim",
+    "DirectCast",
+    "Do",
+    "Double",
+    "Each",
+    "Else",
+    "ElseIf",
+    "End",
+    "EndIf",
+    "Enum",
+    "Erase",
+    "Error",
+    "Event",
+    "Exit",
+    "False",
+    "Finally",
+    "For",
+    "Friend",
+    "Function",
+    "Get",
+    "GetType",
+    "GetXMLNamespace",
+    "Global",
+    "GoSub",
+    "GoTo",
+    "Handles",
+    "If",
+    "Implements",
+    "Imports",
+    "In",
+    "Inherits",
+    "Integer",
+    "Interface",
+    "Is",
+    "IsNot",
+    "Let",
+    "Lib",
+    "Like",
+    "Long",
+    "Loop",
+    "Me",
+    "Mod",
+    "Module",
+    "MustInherit",
+    "MustOverride",
+    "MyBase",
+    "MyClass",
+    "NameOf",
+    "Namespace",
+    "Narrowing",
+    "New",
+    "Next",
+    "Not",
+    "Nothing",
+    "NotInheritable",
+    "NotOverridable",
+    "Object",
+    "Of",
+    "On",
+    "Operator",
+    "Option",
+    "Optional",
+    "Or",
+    "OrElse",
+    "Out",
+    "Overloads",
+    "Overridable",
+    "Overrides",
+    "ParamArray",
+    "Partial",
+    "Private",
+    "Property",
+    "Protected",
+    "Public",
+    "RaiseEvent",
+    "ReadOnly",
+    "ReDim",
+    "REM",
+    "RemoveHandler",
+    "Resume",
+    "Return",
+    "SByte",
+    "Select",
+    "Set",
+    "Shadows",
+    "Shared",
+    "Short",
+    "Single",
+    "Static",
+    "Step",
+    "Stop",
+    "String",
+    "Structure",
+    "Sub",
+    "SyncLock",
+    "Then",
+    "Throw",
+    "To",
+    "True",
+    "Try",
+    "TryCast",
+    "Type",
+    "TypeOf",
+    "UInteger",
+    "ULong",
+    "UShort",
+    "Using",
+    "Variant",
+    "Wend",
+    "When",
+    "While",
+    "Widening",
+    "With",
+    "WithEvents",
+    "WriteOnly",
+    "Xor",
+    "Main",
+    "Copy",
+    "Clone",
+    "Item",
+    "Array",
+    "Console",
+    "Math",
+    "Random",
+    "CultureInfo",
+    "NumberStyles",
+    "File",
+    "StreamReader",
+    "StreamWriter",
+    "FieldInfo",
+    "JsonDocument",
+    "JsonElement",
+    "JsonSerializer",
+    "Left",
+    "Right",
+    "Mid",
+    "Len",
+    "UCase",
+    "LCase",
+    "AscW",
+    "ChrW",
+    "Fix",
+    "Weekday",
+    "Show",
+    "Files",
+    "OpenFile",
+    "CloseFile",
+    "ReadFile",
+    "WriteFile",
+    "EndOfFile",
+    "RandomFile",
+    "OpenRandom",
+    "SeekFile",
+    "GetRecord",
+    "PutRecord",
+    "StrToNum",
+    "IsNum",
+    "Filled",
+    "CopyAll",
+    "Indices",
+    "Ref",
+];
+
+// In the order the parts are written out, at the end of the module
+const PRELUDE: &[Part] = &[
+    Part {
+        name: "Show",
+        imports: &["System.Globalization"],
+        needs: &[],
+        code: r#"' The value as OUTPUT writes it
+Function Show(value As Object) As String
+    If TypeOf value Is Boolean Then
+        Return If(CBool(value), "TRUE", "FALSE")
+    ElseIf TypeOf value Is Double Then
+        Dim number As Double = CDbl(value)
+        If Double.IsNaN(number) Then
+            Return "NaN"
+        ElseIf Double.IsInfinity(number) Then
+            Return If(number > 0, "inf", "-inf")
+        End If
+        ' The shortest digits that read back the same, without an exponent
+        Dim text As String = number.ToString("R", CultureInfo.InvariantCulture)
+        Dim e As Integer = text.IndexOf("E"c)
+        If e < 0 Then
+            Return text
+        End If
+        Dim sign As String = If(text.StartsWith("-"), "-", "")
+        Dim mantissa As String = text.Substring(sign.Length, e - sign.Length)
+        Dim dot As Integer = If(mantissa.Contains("."c), mantissa.IndexOf("."c), mantissa.Length)
+        Dim digits As String = mantissa.Replace(".", "")
+        dot += Integer.Parse(text.Substring(e + 1), CultureInfo.InvariantCulture)
+        If dot <= 0 Then
+            Return sign & "0." & New String("0"c, -dot) & digits
+        ElseIf dot >= digits.Length Then
+            Return sign & digits & New String("0"c, dot - digits.Length)
+        End If
+        Return sign & digits.Substring(0, dot) & "." & digits.Substring(dot)
+    ElseIf TypeOf value Is Date Then
+        Return CDate(value).ToString("dd-MM-yyyy", CultureInfo.InvariantCulture)
+    ElseIf TypeOf value Is [Enum] Then
+        Return value.ToString().ToLower()
+    End If
+    Return Convert.ToString(value, CultureInfo.InvariantCulture)
+End Function"#,
+    },
+    Part {
+        name: "Files",
+        imports: &[],
+        needs: &[],
+        code: r#"' Open files by the name they were opened with
+ReadOnly Files As New Dictionary(Of String, IDisposable)
+
+Sub CloseFile(name As String)
+    Files(name).Dispose()
+    Files.Remove(name)
+End Sub"#,
+    },
+    Part {
+        name: "OpenFile",
+        imports: &["System.IO"],
+        needs: &["Files"],
+        code: r#"Sub OpenFile(name As String, mode As String)
+    Select Case mode
+        Case "WRITE"
+            Files(name) = New StreamWriter(name, False)
+        Case "APPEND"
+            Files(name) = New StreamWriter(name, True)
+        Case Else
+            Files(name) = New StreamReader(name)
+    End Select
+End Sub"#,
+    },
+    Part {
+        name: "ReadFile",
+        imports: &["System.IO"],
+        needs: &["Files"],
+        code: r#"Function ReadFile(name As String) As String
+    Return If(DirectCast(Files(name), StreamReader).ReadLine(), "")
+End Function"#,
+    },
+    Part {
+        name: "WriteFile",
+        imports: &["System.IO"],
+        needs: &["Files"],
+        code: r#"Sub WriteFile(name As String, line As String)
+    DirectCast(Files(name), StreamWriter).Write(line & vbLf)
+End Sub"#,
+    },
+    Part {
+        name: "EndOfFile",
+        imports: &["System.IO"],
+        needs: &["Files"],
+        code: r#"Function EndOfFile(name As String) As Boolean
+    Return DirectCast(Files(name), StreamReader).EndOfStream
+End Function"#,
+    },
+    Part {
+        name: "RandomFile",
+        imports: &[
+            "System.Globalization",
+            "System.IO",
+            "System.Reflection",
+            "System.Text.Json",
+        ],
+        needs: &["Files", "Indices"],
+        code: r#"' A file opened for RANDOM, holding one record per line as JSON
+Class RandomFile
+    Implements IDisposable
+
+    ReadOnly path As String
+    ReadOnly lines As New List(Of String)
+    Public Position As Integer = 1
+
+    Sub New(name As String)
+        path = name
+        If File.Exists(name) Then
+            lines.AddRange(File.ReadAllLines(name))
+        End If
+    End Sub
+
+    Sub GetRecord(record As Object)
+        Using document As JsonDocument = JsonDocument.Parse(lines(Position - 1))
+            Fill(record, document.RootElement)
+        End Using
+    End Sub
+
+    Sub PutRecord(record As Object)
+        While lines.Count < Position
+            lines.Add("")
+        End While
+        lines(Position - 1) = ToJson(record)
+    End Sub
+
+    Sub Dispose() Implements IDisposable.Dispose
+        File.WriteAllText(path, String.Join(vbLf, lines))
+    End Sub
+
+    ' Fields are matched to keys by name, as the interpreter spells them
+    Shared Function Key(field As FieldInfo) As String
+        Return field.Name.Replace("_", "").ToLower()
+    End Function
+
+    Shared Sub Fill(record As Object, data As JsonElement)
+        For Each field As FieldInfo In record.GetType().GetFields()
+            For Each entry As JsonProperty In data.EnumerateObject()
+                If entry.Name.Replace("_", "") = Key(field) Then
+                    field.SetValue(record, FromJson(field.FieldType, field.GetValue(record), entry.Value))
+                End If
+            Next
+        Next
+    End Sub
+
+    Shared Function FromJson(kind As System.Type, current As Object, value As JsonElement) As Object
+        If kind Is GetType(Integer) Then
+            Return value.GetInt32()
+        ElseIf kind Is GetType(Double) Then
+            Return value.GetDouble()
+        ElseIf kind Is GetType(Boolean) Then
+            Return value.GetBoolean()
+        ElseIf kind Is GetType(String) Then
+            Return value.GetString()
+        ElseIf kind Is GetType(Char) Then
+            Dim text As String = value.GetString()
+            Return If(text = "", " "c, text(0))
+        ElseIf kind Is GetType(Date) Then
+            Return Date.ParseExact(value.GetString(), "yyyy-MM-dd", CultureInfo.InvariantCulture)
+        ElseIf kind.IsArray Then
+            ' Arrays of several dimensions are kept as one list, row after row
+            Dim values As Array = DirectCast(current, Array)
+            Dim items As New List(Of JsonElement)(value.EnumerateArray())
+            For i As Integer = 0 To Math.Min(values.Length, items.Count) - 1
+                Dim index As Integer() = Indices(values, i)
+                values.SetValue(FromJson(kind.GetElementType(), values.GetValue(index), items(i)), index)
+            Next
+            Return values
+        End If
+        Fill(current, value)
+        Return current
+    End Function
+
+    Shared Function ToJson(value As Object) As String
+        If TypeOf value Is Boolean Then
+            Return If(CBool(value), "true", "false")
+        ElseIf TypeOf value Is Integer Then
+            Return value.ToString()
+        ElseIf TypeOf value Is Double Then
+            Dim text As String = CDbl(value).ToString("R", CultureInfo.InvariantCulture)
+            Return If(text.Contains("."c) OrElse text.Contains("E"c), text, text & ".0")
+        ElseIf TypeOf value Is Date Then
+            Return JsonSerializer.Serialize(CDate(value).ToString("yyyy-MM-dd", CultureInfo.InvariantCulture))
+        ElseIf TypeOf value Is String OrElse TypeOf value Is Char Then
+            Return JsonSerializer.Serialize(value.ToString())
+        ElseIf TypeOf value Is Array Then
+            Dim items As New List(Of String)
+            For Each element As Object In DirectCast(value, Array)
+                items.Add(ToJson(element))
+            Next
+            Return "[" & String.Join(",", items) & "]"
+        End If
+        Dim pairs As New List(Of String)
+        For Each field As FieldInfo In value.GetType().GetFields()
+            pairs.Add(JsonSerializer.Serialize(Key(field)) & ":" & ToJson(field.GetValue(value)))
+        Next
+        Return "{" & String.Join(",", pairs) & "}"
+    End Function
+End Class
+
+Sub OpenRandom(name As String)
+    Files(name) = New RandomFile(name)
+End Sub
+
+Sub SeekFile(name As String, position As Integer)
+    DirectCast(Files(name), RandomFile).Position = position
+End Sub
+
+Sub GetRecord(name As String, record As Object)
+    DirectCast(Files(name), RandomFile).GetRecord(record)
+End Sub
+
+Sub PutRecord(name As String, record As Object)
+    DirectCast(Files(name), RandomFile).PutRecord(record)
+End Sub"#,
+    },
+    Part {
+        name: "StrToNum",
+        imports: &["System.Globalization"],
+        needs: &[],
+        code: r#"Function StrToNum(text As String) As Double
+    Return Double.Parse(text, NumberStyles.Float, CultureInfo.InvariantCulture)
+End Function"#,
+    },
+    Part {
+        name: "IsNum",
+        imports: &["System.Globalization"],
+        needs: &[],
+        code: r#"Function IsNum(text As String) As Boolean
+    Dim number As Double
+    Return Double.TryParse(text, NumberStyles.Float, CultureInfo.InvariantCulture, number)
+End Function"#,
+    },
+    Part {
+        name: "Filled",
+        imports: &[],
+        needs: &["Indices"],
+        code: r#"' An array whose elements each start with a value of their own
+Function Filled(Of T)(make As Func(Of T), ParamArray lengths As Integer()) As Array
+    Dim values As Array = Array.CreateInstance(GetType(T), lengths)
+    For i As Integer = 0 To values.Length - 1
+        values.SetValue(make(), Indices(values, i))
+    Next
+    Return values
+End Function"#,
+    },
+    Part {
+        name: "CopyAll",
+        imports: &[],
+        needs: &["Indices"],
+        code: r#"' A copy of an array of several dimensions, each element copied as well
+Function CopyAll(Of T)(values As Array, copy As Func(Of T, T)) As Array
+    Dim result As Array = DirectCast(values.Clone(), Array)
+    For i As Integer = 0 To values.Length - 1
+        Dim index As Integer() = Indices(values, i)
+        result.SetValue(copy(DirectCast(values.GetValue(index), T)), index)
+    Next
+    Return result
+End Function"#,
+    },
+    Part {
+        name: "Indices",
+        imports: &[],
+        needs: &[],
+        code: r#"' The indices of an element of an array, counting row after row
+Function Indices(values As Array, position As Integer) As Integer()
+    Dim index(values.Rank - 1) As Integer
+    For dimension As Integer = values.Rank - 1 To 0 Step -1
+        index(dimension) = position Mod values.GetLength(dimension)
+        position \= values.GetLength(dimension)
+    Next
+    Return index
+End Function"#,
+    },
+    Part {
+        name: "Ref",
+        imports: &[],
+        needs: &[],
+        code: r#"' A variable a pointer is made to
+Class Ref(Of T)
+    Public Value As T
+
+    Sub New(value As T)
+        Me.Value = value
+    End Sub
+End Class"#,
+    },
+];
+
+// How tightly an expression binds, so operands are bracketed only when they must be
+const OR: u8 = 1;
+const AND: u8 = 2;
+const NOT: u8 = 3;
+const CMP: u8 = 4;
+const CONCAT: u8 = 5;
+const ADD: u8 = 6;
+const MOD: u8 = 7;
+const IDIV: u8 = 8;
+const MUL: u8 = 9;
+const UNARY: u8 = 10;
+const ATOM: u8 = 11;
+
+// A piece of a line of OUTPUT or WRITEFILE
+enum Piece {
+    Literal(String),
+    // The expression, and whether it is already a String
+    Value(Expr, bool),
+}
+
+struct VbNet<'a> {
+    names: Names,
+    program: Program<'a>,
+    out: Writer,
+    // Variables of the main program kept in the module rather than in Main
+    statics: HashSet<String>,
+    // The statements of the subroutine or main program being written out
+    scope_body: Vec<Box<Node>>,
+}
+
+pub(crate) fn emit(name: &str, text: &str, children: &[Box<Node>]) -> String {
+    let style = Style {
+        value: camel_case,
+        constant: pascal_case_of,
+        type_name: pascal_case_of,
+        variant: pascal_case_of,
+        routine: pascal_case_of,
+        ignore_case: true,
+    };
+    // BYREF is VB.NET's own, only variables pointed to are kept in a holder
+    let boxing = Boxing {
+        byref: false,
+        pointers: true,
+    };
+    let mut names = Names::new(text, children, RESERVED, style);
+    let module = names.fresh(&class_name(name));
+    let mut vb = VbNet {
+        names,
+        program: Program::new(children, boxing),
+        out: Writer::new(INDENT, PRELUDE),
+        statics: HashSet::new(),
+        scope_body: vec![],
+    };
+    vb.statics = vb.program.statics(children);
+    vb.out.depth = 1;
+
+    let main = vb.program.main.clone();
+    let statements: Vec<Box<Node>> = main.iter().map(|node| (*node).clone()).collect();
+    let mut constants = vec![];
+    walk(&statements, &mut |node| {
+        if let Node::Const { .. } = node {
+            constants.push(node.clone());
+        }
+    });
+    for node in &constants {
+        if let Node::Const { name, val, .. } = node {
+            let declaration = vb.constant(name, val);
+            vb.out.line(declaration);
+        }
+    }
+    vb.out.blank(1);
+    for (name, t) in vb.program.variables() {
+        if vb.statics.contains(&name) {
+            let declaration = vb.declaration(&name, &t);
+            vb.out.line(format!("Dim {}", declaration));
+        }
+    }
+    let definitions: Vec<&Box<Node>> = vb
+        .program
+        .types
+        .iter()
+        .chain(&vb.program.routines)
+        .copied()
+        .collect();
+    for node in definitions {
+        vb.out.blank(1);
+        vb.definition(node);
+    }
+    vb.out.blank(1);
+    vb.out.line("Sub Main()".to_string());
+    vb.out.depth += 1;
+    vb.scope_body = statements.clone();
+    // FOR counters never declared are declared by the loop
+    for (name, _) in vb.program.variables() {
+        if !vb.statics.contains(&name) && !declared_at_top(&main, &name) {
+            let declaration = vb.declaration(&name, &VariableType::Integer);
+            vb.out.line(format!("Dim {}", declaration));
+        }
+    }
+    vb.stmts(&statements);
+    vb.out.depth -= 1;
+    vb.out.line("End Sub".to_string());
+    vb.finish(name, &module)
+}
+
+// Constants, types, variants and subroutines are all PascalCase in VB.NET
+fn pascal_case_of(name: &str) -> String {
+    let camel = camel_case(name);
+    let mut chars = camel.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => pascal_case(name),
+    }
+}
+
+impl<'a> VbNet<'a> {
+    // The header, imports and prelude around the module
+    fn finish(mut self, name: &str, module: &str) -> String {
+        let mut out = self.out.header("'", name);
+        out.push(format!(
+            "' Save as {}.vb in a Visual Basic console project, for .NET 6 or later",
+            module
+        ));
+        let prelude = self.out.prelude();
+        let body = self.out.body();
+        if !self.out.imports.is_empty() {
+            out.push(String::new());
+            out.extend(
+                self.out
+                    .imports
+                    .iter()
+                    .map(|import| format!("Imports {}", import)),
+            );
+        }
+        out.push(String::new());
+        out.push(format!("Module {}", module));
+        out.extend(body);
+        for code in prelude {
+            out.push(String::new());
+            out.extend(code.lines().map(|line| match line.is_empty() {
+                true => String::new(),
+                false => format!("{}{}", INDENT, line),
+            }));
+        }
+        out.push("End Module".to_string());
+        out.join("\n") + "\n"
+    }
+
+    fn vb_type(&mut self, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Integer => "Integer".to_string(),
+            VariableType::Real => "Double".to_string(),
+            VariableType::Boolean => "Boolean".to_string(),
+            VariableType::Char => "Char".to_string(),
+            VariableType::String => "String".to_string(),
+            VariableType::Date => "Date".to_string(),
+            VariableType::Array { shape, t } => {
+                format!("{}({})", self.vb_type(&t), ",".repeat(shape.len() - 1))
+            }
+            VariableType::Pointer(t) => match self.program.plain(&t) {
+                true => self.ref_type(&t),
+                false => self.vb_type(&t),
+            },
+            VariableType::Custom(name) => self.names.type_name(&name),
+        }
+    }
+
+    // The holder a variable is kept in when a pointer is made to it
+    fn ref_type(&mut self, t: &VariableType) -> String {
+        self.out.use_part("Ref");
+        format!("Ref(Of {})", self.vb_type(t))
+    }
+
+    // The value a variable of this type starts out with
+    fn default(&mut self, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Integer => "0".to_string(),
+            VariableType::Real => "0.0".to_string(),
+            VariableType::Boolean => "False".to_string(),
+            VariableType::String => "\"\"".to_string(),
+            VariableType::Char => "\" \"c".to_string(),
+            VariableType::Date => "#1/1/1970#".to_string(),
+            VariableType::Custom(name) if self.program.is_record(&name) => {
+                format!("New {}()", self.names.type_name(&name))
+            }
+            _ => "Nothing".to_string(),
+        }
+    }
+
+    // `name As Type = value`, or the bounds of an array, with the holder when the
+    // variable is pointed to
+    fn declaration(&mut self, name: &String, t: &VariableType) -> String {
+        let var = self.names.get(name);
+        if self.program.is_boxed(name) {
+            let holder = self.ref_type(t);
+            let default = self.default(t);
+            return format!("{} As New {}({})", var, holder, default);
+        }
+        if let VariableType::Array { shape, t } = self.program.checker.resolve(t) {
+            let lengths: Vec<i64> = shape
+                .iter()
+                .map(|index| (index.upper - index.lower + 1).max(0))
+                .collect();
+            let element = self.vb_type(&t);
+            let t = self.program.checker.resolve(&t);
+            let zeroed = matches!(
+                t,
+                VariableType::Integer | VariableType::Real | VariableType::Boolean
+            ) || !matches!(t, VariableType::Custom(ref name) if self.program.is_record(name))
+                && matches!(t, VariableType::Custom(_) | VariableType::Pointer(_));
+            if zeroed {
+                let bounds: Vec<String> = lengths.iter().map(|n| (n - 1).to_string()).collect();
+                return format!("{}({}) As {}", var, bounds.join(", "), element);
+            }
+            return format!(
+                "{} As {} = {}",
+                var,
+                self.vb_type(&VariableType::Array {
+                    shape: shape.clone(),
+                    t: Box::new(t.clone())
+                }),
+                self.filled(&shape, &t)
+            );
+        }
+        let vb_type = self.vb_type(t);
+        match self.program.checker.resolve(t) {
+            VariableType::Custom(name) if self.program.is_record(&name) => {
+                format!("{} As New {}()", var, vb_type)
+            }
+            _ => {
+                let default = self.default(t);
+                format!("{} As {} = {}", var, vb_type, default)
+            }
+        }
+    }
+
+    // A new array whose elements start with a value other than the one VB.NET gives them
+    fn filled(&mut self, shape: &[Index], t: &VariableType) -> String {
+        self.out.use_part("Filled");
+        let lengths: Vec<String> = shape
+            .iter()
+            .map(|index| (index.upper - index.lower + 1).max(0).to_string())
+            .collect();
+        let default = self.default(t);
+        let array = self.vb_type(&VariableType::Array {
+            shape: shape.to_vec(),
+            t: Box::new(t.clone()),
+        });
+        format!(
+            "DirectCast(Filled(Function() {}, {}), {})",
+            default,
+            lengths.join(", "),
+            array
+        )
+    }
+
+    fn constant(&mut self, name: &str, val: &Box<Node>) -> String {
+        let t = self.program.type_of(val);
+        let value = self.value(val);
+        let t = t.map_or("Object".to_string(), |t| self.vb_type(&t));
+        format!("Const {} As {} = {}", self.names.get(name), t, value)
+    }
+
+    // The copy made when an array or record is assigned
+    fn copy(&mut self, value: Expr, t: &VariableType) -> String {
+        match self.program.checker.resolve(t) {
+            VariableType::Array { shape, t: element } => {
+                let array = self.vb_type(&VariableType::Array {
+                    shape: shape.clone(),
+                    t: element.clone(),
+                });
+                let record =
+                    matches!(&*element, VariableType::Custom(name) if self.program.is_record(name));
+                match (record, shape.len()) {
+                    (false, _) => {
+                        format!("DirectCast({}.Clone(), {})", bracket(value, ATOM), array)
+                    }
+                    (true, 1) => {
+                        format!("Array.ConvertAll({}, Function(item) item.Copy())", value.0)
+                    }
+                    (true, _) => {
+                        self.out.use_part("CopyAll");
+                        let element = self.vb_type(&element);
+                        format!(
+                            "DirectCast(CopyAll(Of {})({}, Function(item) item.Copy()), {})",
+                            element, value.0, array
+                        )
+                    }
+                }
+            }
+            _ => format!("{}.Copy()", bracket(value, ATOM)),
+        }
+    }
+
+    fn definition(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Enum {
+                name,
+                variants,
+                pos,
+            } => {
+                let enumeration = self.names.type_name(name);
+                self.out.line(format!("Enum {}", enumeration));
+                self.out.depth += 1;
+                for variant in variants {
+                    if let Node::String { val, .. } = variant.deref() {
+                        let member = self.names.get(val);
+                        // OUTPUT writes an enum by the name VB.NET gives it, lowercased
+                        if member.to_lowercase() != *val {
+                            self.out.note(
+                                pos.line_start,
+                                "enum values are written out by their VB.NET names",
+                            );
+                        }
+                        self.out.line(member);
+                    }
+                }
+                self.out.depth -= 1;
+                self.out.line("End Enum".to_string());
+            }
+            Node::Record { name, children, .. } => {
+                let Node::String { val: record, .. } = name.deref() else {
+                    return;
+                };
+                let class = self.names.type_name(record);
+                self.out.line(format!("Class {}", class));
+                self.out.depth += 1;
+                let mut copied = vec![];
+                for child in children {
+                    if let Node::Declare { t, children, .. } = child.deref() {
+                        for field in children {
+                            let declaration = self.declaration(field, t);
+                            self.out.line(format!("Public {}", declaration));
+                            let resolved = Some(self.program.checker.resolve(t));
+                            if self.program.copied(&resolved) {
+                                copied.push((self.names.get(field), *t.clone()));
+                            }
+                        }
+                    }
+                }
+                // Assigning a record copies it, arrays and records inside it included
+                self.out.blank(1);
+                self.out.line(format!("Function Copy() As {}", class));
+                self.out.depth += 1;
+                if copied.is_empty() {
+                    self.out
+                        .line(format!("Return DirectCast(MemberwiseClone(), {})", class));
+                } else {
+                    self.out.line(format!(
+                        "Dim clone As {} = DirectCast(MemberwiseClone(), {})",
+                        class, class
+                    ));
+                    for (field, t) in copied {
+                        let value = self.copy((field.clone(), ATOM), &t);
+                        self.out.line(format!("clone.{} = {}", field, value));
+                    }
+                    self.out.line("Return clone".to_string());
+                }
+                self.out.depth -= 1;
+                self.out.line("End Function".to_string());
+                self.out.depth -= 1;
+                self.out.line("End Class".to_string());
+            }
+            Node::Class {
+                name,
+                base,
+                children,
+                ..
+            } => self.class(name, base, children),
+            Node::Function { .. } | Node::Procedure { .. } => self.routine(node, None),
+            // Pointer types are written out as the type they point to
+            _ => {}
+        }
+    }
+
+    fn class(&mut self, name: &Box<Node>, base: &Box<Node>, children: &Vec<Box<Node>>) {
+        let Node::String { val: class, .. } = name.deref() else {
+            return;
+        };
+        let type_name = self.names.type_name(class);
+        self.out.line(format!("Class {}", type_name));
+        self.out.depth += 1;
+        if let Node::String { val, .. } = base.deref() {
+            let base = self.names.type_name(val);
+            self.out.line(format!("Inherits {}", base));
+            self.out.blank(1);
+        }
+        for child in children {
+            if let Node::Declare {
+                t,
+                children,
+                private,
+                ..
+            } = child.deref()
+            {
+                for property in children {
+                    let declaration = self.declaration(property, t);
+                    self.out
+                        .line(format!("{} {}", access(*private), declaration));
+                }
+            }
+        }
+        if self.program.needs_bare_constructor(class) {
+            self.out.blank(1);
+            // For subclasses whose constructor does not call this one's
+            self.out.line("Protected Sub New()".to_string());
+            self.out.line("End Sub".to_string());
+        }
+        for method in methods(children) {
+            self.out.blank(1);
+            self.routine(method, Some(class));
+        }
+        self.out.depth -= 1;
+        self.out.line("End Class".to_string());
+    }
+
+    fn routine(&mut self, node: &Box<Node>, class: Option<&String>) {
+        let name = routine_name(node).to_string();
+        let mut statements = self.program.enter(node, class);
+        let scope = self.program.scope.as_ref().unwrap();
+        let signature = scope.signature.clone();
+        let boxed = scope.boxed.clone();
+        let implicit = self.program.implicit(&statements);
+
+        let mut params = vec![];
+        let mut unboxed = vec![];
+        for param in &signature.params {
+            let var = self.names.get(&param.name);
+            let vb_type = self.vb_type(&param.t);
+            let var = match boxed.contains(&param.name) {
+                // Pointed to inside the subroutine, so kept in a holder of its own
+                true => {
+                    let given = self.names.fresh(&format!("{}Given", var));
+                    unboxed.push((param.clone(), given.clone()));
+                    given
+                }
+                false => var,
+            };
+            match param.byref {
+                true => params.push(format!("ByRef {} As {}", var, vb_type)),
+                false => params.push(format!("{} As {}", var, vb_type)),
+            }
+        }
+        let params = params.join(", ");
+        let modifiers = match class {
+            Some(class) => self.modifiers(class, &name, signature.private),
+            None => String::new(),
+        };
+        let (header, end) = match (&signature.returns, name.as_str()) {
+            (_, "new") if class.is_some() => {
+                (format!("{}Sub New({})", modifiers, params), "End Sub")
+            }
+            (Some(returns), _) => {
+                let returns = self.vb_type(returns);
+                let routine = self.names.get(&name);
+                (
+                    format!(
+                        "{}Function {}({}) As {}",
+                        modifiers, routine, params, returns
+                    ),
+                    "End Function",
+                )
+            }
+            (None, _) => {
+                let routine = self.names.get(&name);
+                (
+                    format!("{}Sub {}({})", modifiers, routine, params),
+                    "End Sub",
+                )
+            }
+        };
+        self.out.line(header);
+        self.out.depth += 1;
+        if class.is_some() && name == "new" && statements.first().is_some_and(is_super_new) {
+            self.stmt(&statements.remove(0));
+        }
+        for (param, given) in unboxed {
+            let var = self.names.get(&param.name);
+            let holder = self.ref_type(&param.t);
+            self.out
+                .line(format!("Dim {} As New {}({})", var, holder, given));
+        }
+        // A BYVAL array or record is the caller's to keep
+        for (param, t) in self.program.copies() {
+            let var = self.names.get(&param);
+            let copy = self.copy((var.clone(), ATOM), &t);
+            self.out.line(format!("{} = {}", var, copy));
+        }
+        for name in implicit {
+            let declaration = self.declaration(&name, &VariableType::Integer);
+            self.out.line(format!("Dim {}", declaration));
+        }
+        self.scope_body = statements.clone();
+        self.stmts(&statements);
+        if signature.returns.is_some() && !returns(&statements) {
+            self.out.line(
+                "Throw New InvalidOperationException(\"FUNCTION ended without RETURN\")"
+                    .to_string(),
+            );
+        }
+        self.out.depth -= 1;
+        self.out.line(end.to_string());
+        self.program.leave();
+    }
+
+    // `Public Overridable `, `Private ` and the like, before a method
+    fn modifiers(&self, class: &String, name: &String, private: bool) -> String {
+        let mut modifiers = vec![access(private)];
+        if name != "new" {
+            let inherited = match self.program.checker.types.get(class) {
+                Some(TypeDef::Class {
+                    base: Some(base), ..
+                }) => self.program.checker.get_method(base, name).is_some(),
+                _ => false,
+            };
+            if inherited {
+                modifiers.push("Overrides");
+            } else if !private && self.overridden(class, name) {
+                modifiers.push("Overridable");
+            }
+        }
+        modifiers.join(" ") + " "
+    }
+
+    // Whether a class inheriting from this one, however far down, has a method by
+    // the same name
+    fn overridden(&self, class: &String, name: &String) -> bool {
+        self.program.classes.values().any(|node| {
+            let Node::Class {
+                name: subclass,
+                children,
+                ..
+            } = node.as_ref()
+            else {
+                return false;
+            };
+            let Node::String { val: subclass, .. } = subclass.deref() else {
+                return false;
+            };
+            let mut base = self.base(subclass);
+            let mut inherits = false;
+            while let Some(next) = base {
+                inherits |= &next == class;
+                base = self.base(&next);
+            }
+            inherits
+                && methods(children)
+                    .iter()
+                    .any(|method| routine_name(method) == name)
+        })
+    }
+
+    fn base(&self, class: &String) -> Option<String> {
+        match self.program.checker.types.get(class) {
+            Some(TypeDef::Class { base, .. }) => base.clone(),
+            _ => None,
+        }
+    }
+
+    fn stmts(&mut self, nodes: &[Box<Node>]) {
+        for node in body(nodes) {
+            self.stmt(&node);
+        }
+    }
+
+    fn block(&mut self, nodes: &[Box<Node>]) {
+        self.out.depth += 1;
+        self.stmts(nodes);
+        self.out.depth -= 1;
+    }
+
+    fn stmt(&mut self, node: &Box<Node>) {
+        match node.deref() {
+            Node::Declare { t, children, .. } => {
+                for name in children {
+                    if self.program.scope.is_none() && self.statics.contains(name) {
+                        continue;
+                    }
+                    let declaration = self.declaration(name, t);
+                    self.out.line(format!("Dim {}", declaration));
+                }
+            }
+            Node::Const { name, val, .. } => {
+                // Constants of the main program belong to the module
+                if self.program.scope.is_some() {
+                    let declaration = self.constant(name, val);
+                    self.out.line(declaration);
+                }
+            }
+            Node::Assignment { lhs, rhs, .. } => self.assign(lhs, rhs),
+            Node::Output { children, .. } => {
+                let pieces = self.pieces(children, false);
+                let line = match pieces.as_slice() {
+                    [] => String::new(),
+                    [Piece::Value(value, _)] => value.0.clone(),
+                    _ => text(pieces),
+                };
+                self.out.line(format!("Console.WriteLine({})", line));
+            }
+            Node::Input { child, .. } => {
+                let target = self.value(child);
+                let value = match self.program.type_of(child) {
+                    Some(VariableType::Integer) => "Integer.Parse(Console.ReadLine())",
+                    Some(VariableType::Real) => {
+                        self.out.import("System.Globalization");
+                        "Double.Parse(Console.ReadLine(), CultureInfo.InvariantCulture)"
+                    }
+                    Some(VariableType::Boolean) => "UCase(Console.ReadLine()) = \"TRUE\"",
+                    Some(VariableType::Char) => "CChar(Console.ReadLine())",
+                    _ => "Console.ReadLine()",
+                };
+                self.out.line(format!("{} = {}", target, value));
+            }
+            Node::If { .. } => self.if_chain(node),
+            Node::While { cond, body, .. } => {
+                let cond = self.value(cond);
+                self.out.line(format!("While {}", cond));
+                self.block(body);
+                self.out.line("End While".to_string());
+            }
+            Node::Repeat { cond, body, .. } => {
+                self.out.line("Do".to_string());
+                self.block(body);
+                let cond = self.value(cond);
+                self.out.line(format!("Loop Until {}", cond));
+            }
+            Node::For {
+                iter,
+                range,
+                step,
+                body,
+                ..
+            } => self.for_loop(iter, range, step, body),
+            Node::Switch {
+                cmp,
+                cases,
+                otherwise,
+                ..
+            } => self.select(cmp, cases, otherwise),
+            Node::Return { expr, .. } => {
+                if let Node::Null = expr.deref() {
+                    self.out.line("Return".to_string());
+                    return;
+                }
+                let returns = self
+                    .program
+                    .scope
+                    .as_ref()
+                    .and_then(|scope| scope.signature.returns.clone());
+                let value = self.coerced(expr, returns.as_ref());
+                self.out.line(format!("Return {}", value));
+            }
+            Node::OpenFile { filename, mode, .. } => {
+                let file = self.value(filename);
+                let mode = match mode {
+                    TToken::FileMode(mode) => mode.as_str(),
+                    _ => "READ",
+                };
+                if mode == "RANDOM" {
+                    self.out.use_part("RandomFile");
+                    self.out.line(format!("OpenRandom({})", file));
+                } else {
+                    self.out.use_part("OpenFile");
+                    self.out
+                        .line(format!("OpenFile({}, {})", file, quote(mode)));
+                }
+            }
+            Node::CloseFile { filename, .. } => {
+                self.out.use_part("Files");
+                let file = self.value(filename);
+                self.out.line(format!("CloseFile({})", file));
+            }
+            Node::ReadFile { filename, var, .. } => {
+                self.out.use_part("ReadFile");
+                let file = self.value(filename);
+                let target = self.value(var);
+                self.out.line(format!("{} = ReadFile({})", target, file));
+            }
+            Node::WriteFile { filename, expr, .. } => {
+                self.out.use_part("WriteFile");
+                let file = self.value(filename);
+                let pieces = self.pieces(std::slice::from_ref(expr), true);
+                self.out
+                    .line(format!("WriteFile({}, {})", file, text(pieces)));
+            }
+            Node::SeekFile { filename, expr, .. } => {
+                let file = self.value(filename);
+                let position = self.value(expr);
+                self.out.line(format!("SeekFile({}, {})", file, position));
+            }
+            Node::GetRecord { filename, var, .. } => {
+                let file = self.value(filename);
+                let record = self.value(var);
+                self.out.line(format!("GetRecord({}, {})", file, record));
+            }
+            Node::PutRecord { filename, var, .. } => {
+                let file = self.value(filename);
+                let record = self.value(var);
+                self.out.line(format!("PutRecord({}, {})", file, record));
+            }
+            Node::Null => {}
+            _ => {
+                let value = self.value(node);
+                self.out.line(value);
+            }
+        }
+    }
+
+    fn assign(&mut self, lhs: &Box<Node>, rhs: &Box<Node>) {
+        let target = self.value(lhs);
+        let t = self.program.type_of(lhs);
+        if let Node::Binary {
+            op,
+            lhs: left,
+            rhs: right,
+            ..
+        } = rhs.deref()
+        {
+            let op = match op.as_str() {
+                "+" | "-" | "*" | "&" => Some(op.as_str()),
+                _ => None,
+            };
+            let mut calls = false;
+            walk(std::slice::from_ref(lhs), &mut |node| {
+                calls |= matches!(node, Node::FunctionCall { .. });
+            });
+            if let Some(op) = op {
+                if self.value(left) == target && !calls {
+                    let right = match op {
+                        "&" => self.value(right),
+                        _ => bracket(self.expr(right), ADD + 1),
+                    };
+                    self.out.line(format!("{} {}= {}", target, op, right));
+                    return;
+                }
+            }
+        }
+        let value = self.coerced(rhs, t.as_ref());
+        self.out.line(format!("{} = {}", target, value));
+    }
+
+    // A value given to a variable, parameter or RETURN of a type, copied or
+    // converted as the interpreter would
+    fn coerced(&mut self, node: &Box<Node>, to: Option<&VariableType>) -> String {
+        let from = self.program.type_of(node);
+        let value = self.expr(node);
+        let copies = matches!(
+            node.deref(),
+            Node::Var { .. }
+                | Node::ArrayVar { .. }
+                | Node::Composite { .. }
+                | Node::Dereference { .. }
+        );
+        match (to, &from) {
+            (_, Some(t)) if copies && self.program.copied(&from) => self.copy(value, t),
+            (Some(VariableType::Char), Some(VariableType::String)) => match node.deref() {
+                Node::String { val, .. } if val.chars().count() == 1 => {
+                    char_literal(val.chars().next().unwrap())
+                }
+                _ => format!("CChar({})", value.0),
+            },
+            (Some(VariableType::String), Some(VariableType::Char)) => format!("CStr({})", value.0),
+            // STR_TO_NUM gives an INTEGER for whole numbers, and a Double here
+            (Some(VariableType::Integer), None) => format!("CInt({})", value.0),
+            _ => value.0,
+        }
+    }
+
+    fn if_chain(&mut self, node: &Box<Node>) {
+        let mut node = node.clone();
+        let mut first = true;
+        loop {
+            let Node::If {
+                cond,
+                true_body,
+                false_body,
+                ..
+            } = node.deref()
+            else {
+                return;
+            };
+            let cond = self.value(cond);
+            match first {
+                true => self.out.line(format!("If {} Then", cond)),
+                false => self.out.line(format!("ElseIf {} Then", cond)),
+            }
+            self.block(true_body);
+            let rest = body(false_body);
+            match rest.as_slice() {
+                [] => break,
+                [only] if matches!(only.as_ref(), Node::If { .. }) => {
+                    node = only.clone();
+                    first = false;
+                }
+                _ => {
+                    self.out.line("Else".to_string());
+                    self.block(&rest);
+                    break;
+                }
+            }
+        }
+        self.out.line("End If".to_string());
+    }
+
+    fn for_loop(
+        &mut self,
+        iter: &Box<Node>,
+        range: &Box<Node>,
+        step: &Box<Node>,
+        body: &[Box<Node>],
+    ) {
+        let (Node::Var { name, .. }, Node::Range { start, end, .. }) =
+            (iter.deref(), range.deref())
+        else {
+            return;
+        };
+        let counter = self.value(iter);
+        let start = self.value(start);
+        let end = self.value(end);
+        // The interpreter leaves the counter at its last value, VB.NET one step past it
+        let plain = !self.program.is_boxed(name)
+            && self
+                .program
+                .counts_itself(&self.statics, &self.scope_body, name);
+        let var = match plain {
+            true => counter.clone(),
+            false => {
+                let next = format!("{}Next", self.names.get(name));
+                let next = self.names.fresh(&next);
+                format!("{} As Integer", next)
+            }
+        };
+        let step = match (step.deref(), const_int(step)) {
+            (Node::Null, _) | (_, Some(1)) => String::new(),
+            _ => format!(" Step {}", self.value(step)),
+        };
+        self.out
+            .line(format!("For {} = {} To {}{}", var, start, end, step));
+        if !plain {
+            let next = var.trim_end_matches(" As Integer").to_string();
+            self.out.depth += 1;
+            self.out.line(format!("{} = {}", counter, next));
+            self.out.depth -= 1;
+        }
+        self.block(body);
+        self.out.line("Next".to_string());
+    }
+
+    fn select(&mut self, cmp: &Box<Node>, cases: &Vec<Box<Node>>, otherwise: &[Box<Node>]) {
+        let t = self.program.type_of(cmp);
+        let subject = self.value(cmp);
+        self.out.line(format!("Select Case {}", subject));
+        self.out.depth += 1;
+        for case in cases {
+            let Node::Case { expr, children, .. } = case.deref() else {
+                continue;
+            };
+            let label = match (expr.deref(), &t) {
+                (Node::Range { start, end, .. }, _) => {
+                    format!("{} To {}", self.value(start), self.value(end))
+                }
+                // A one letter STRING label of a CHAR
+                (Node::String { val, .. }, Some(VariableType::Char)) => {
+                    char_literal(val.chars().next().unwrap_or(' '))
+                }
+                _ => self.value(expr),
+            };
+            self.out.line(format!("Case {}", label));
+            self.block(children);
+        }
+        if !otherwise.is_empty() {
+            self.out.line("Case Else".to_string());
+            self.block(otherwise);
+        }
+        self.out.depth -= 1;
+        self.out.line("End Select".to_string());
+    }
+
+    // What OUTPUT or WRITEFILE writes, each value turned into the text the interpreter gives
+    fn pieces(&mut self, children: &[Box<Node>], file: bool) -> Vec<Piece> {
+        let mut pieces = vec![];
+        for child in children {
+            let literal = match child.deref() {
+                Node::String { val, .. } => Some(val.clone()),
+                Node::Char { val, .. } => Some(val.to_string()),
+                Node::Int { val, .. } => Some(val.to_string()),
+                Node::Boolean { val, .. } => Some(match (file, val) {
+                    (true, _) => val.to_string(),
+                    (false, true) => "TRUE".to_string(),
+                    (false, false) => "FALSE".to_string(),
+                }),
+                _ => None,
+            };
+            if let Some(literal) = literal {
+                pieces.push(Piece::Literal(literal));
+                continue;
+            }
+            let t = self.program.type_of(child);
+            let value = self.expr(child);
+            pieces.push(match t {
+                Some(VariableType::String) => Piece::Value(value, true),
+                Some(VariableType::Char | VariableType::Integer) => Piece::Value(value, false),
+                Some(VariableType::Boolean) if file => {
+                    let value = format!("If({}, \"true\", \"false\")", value.0);
+                    Piece::Value((value, ATOM), true)
+                }
+                Some(VariableType::Date) if file => {
+                    let value = format!("{}.ToString(\"yyyy-MM-dd\")", bracket(value, ATOM));
+                    Piece::Value((value, ATOM), true)
+                }
+                _ => {
+                    self.out.use_part("Show");
+                    Piece::Value((format!("Show({})", value.0), ATOM), true)
+                }
+            });
+        }
+        pieces
+    }
+
+    // The value of an expression as VB.NET, with the precedence it binds with
+    fn expr(&mut self, node: &Box<Node>) -> Expr {
+        match node.deref() {
+            Node::Int { val, pos } => {
+                if i32::try_from(*val).is_err() {
+                    self.out.note(
+                        pos.line_start,
+                        "INTEGER values are Integer in VB.NET, which holds 32 bits",
+                    );
+                    return (format!("{}L", val), ATOM);
+                }
+                (val.to_string(), ATOM)
+            }
+            Node::Real { val, .. } => {
+                let text = match val {
+                    val if val.is_nan() => "Double.NaN".to_string(),
+                    val if val.is_infinite() && *val > 0.0 => "Double.PositiveInfinity".to_string(),
+                    val if val.is_infinite() => "Double.NegativeInfinity".to_string(),
+                    val => val.to_string(),
+                };
+                match text.contains(['.', 'e', 'y', 'N']) {
+                    true => (text, ATOM),
+                    false => (text + ".0", ATOM),
+                }
+            }
+            Node::String { val, .. } => (quote(val), ATOM),
+            Node::Char { val, .. } => (char_literal(*val), ATOM),
+            Node::Boolean { val, .. } => (
+                match val {
+                    true => "True".to_string(),
+                    false => "False".to_string(),
+                },
+                ATOM,
+            ),
+            Node::Date { val, .. } => (val.format("#%-m/%-d/%Y#").to_string(), ATOM),
+            Node::Var { name, .. } => (self.var(name), ATOM),
+            Node::ArrayVar { name, indices, pos } => {
+                let array = self.var(name);
+                let t = self.program.type_of(&Box::new(Node::Var {
+                    name: name.clone(),
+                    pos: *pos,
+                }));
+                let subscripts = self.subscripts(indices, &t);
+                (array + &subscripts, ATOM)
+            }
+            Node::FunctionCall { name, params, .. } => self.call(name, params),
+            Node::CreateObject { call, .. } => match call.deref() {
+                Node::FunctionCall { name, params, .. } => {
+                    let signature = self.program.checker.get_method(name, &"new".to_string());
+                    let args = self.args(signature, params);
+                    let class = self.names.type_name(name);
+                    (format!("New {}({})", class, args), UNARY)
+                }
+                _ => ("Nothing".to_string(), ATOM),
+            },
+            Node::Composite { children, .. } => self.composite(children),
+            Node::Reference { expr, pos } => {
+                let t = self.program.type_of(expr);
+                match (expr.deref(), &t) {
+                    (_, Some(t)) if !self.program.plain(t) => self.expr(expr),
+                    (Node::Var { name, .. }, _)
+                        if self.program.is_boxed(name) && !self.program.field(name) =>
+                    {
+                        (self.names.get(name), ATOM)
+                    }
+                    (_, t) => {
+                        self.out.note(
+                            pos.line_start,
+                            "VB.NET cannot point to this, the pointer holds a copy of the value",
+                        );
+                        let value = self.value(expr);
+                        let t = t.clone().unwrap_or(VariableType::Integer);
+                        let holder = self.ref_type(&t);
+                        (format!("New {}({})", holder, value), UNARY)
+                    }
+                }
+            }
+            Node::Dereference { expr, .. } => {
+                let value = self.expr(expr);
+                match self.program.type_of(expr) {
+                    Some(VariableType::Pointer(t)) if self.program.plain(&t) => {
+                        (format!("{}.Value", bracket(value, ATOM)), ATOM)
+                    }
+                    _ => value,
+                }
+            }
+            Node::Unary { op, expr, .. } => {
+                let value = self.expr(expr);
+                match op.as_str() {
+                    "!" => (format!("Not {}", bracket(value, UNARY)), NOT),
+                    _ => {
+                        let sign = if op == "_-" { "-" } else { "+" };
+                        // Two signs in a row are kept apart
+                        let value = match value.0.starts_with(['-', '+']) {
+                            true => format!("({})", value.0),
+                            false => bracket(value, UNARY),
+                        };
+                        (format!("{}{}", sign, value), UNARY)
+                    }
+                }
+            }
+            Node::Binary { op, lhs, rhs, .. } => self.binary(op, lhs, rhs),
+            _ => ("Nothing".to_string(), ATOM),
+        }
+    }
+
+    fn value(&mut self, node: &Box<Node>) -> String {
+        self.expr(node).0
+    }
+
+    fn binary(&mut self, op: &str, lhs: &Box<Node>, rhs: &Box<Node>) -> Expr {
+        let integers = self.program.type_of(lhs) == Some(VariableType::Integer)
+            && self.program.type_of(rhs) == Some(VariableType::Integer);
+        if op == "//" {
+            let left = bracket(self.expr(lhs), MUL);
+            let right = bracket(self.expr(rhs), MUL + 1);
+            let floor = format!("Math.Floor({} / {})", left, right);
+            return match integers {
+                true => (format!("CInt({})", floor), ATOM),
+                false => (floor, ATOM),
+            };
+        }
+        let (symbol, prec) = match op {
+            "||" => ("Or", OR),
+            "&&" => ("And", AND),
+            "=" => ("=", CMP),
+            "!=" => ("<>", CMP),
+            "<" | ">" | "<=" | ">=" => (op, CMP),
+            "&" => ("&", CONCAT),
+            "+" => ("+", ADD),
+            "-" => ("-", ADD),
+            "%" => ("Mod", MOD),
+            // INTEGER division drops the fraction
+            "/" if integers => ("\\", IDIV),
+            "*" => ("*", MUL),
+            "/" => ("/", MUL),
+            _ => (op, ATOM),
+        };
+        // Comparisons are bracketed inside each other, where VB.NET would misread them
+        let left = match prec {
+            CMP => prec + 1,
+            prec => prec,
+        };
+        let left = bracket(self.expr(lhs), left);
+        // Joining text gives the same whichever way round it is grouped
+        let joined = op == "&" && matches!(rhs.as_ref(), Node::Binary { op, .. } if op == "&");
+        let right = match joined {
+            true => self.value(rhs),
+            false => bracket(self.expr(rhs), prec + 1),
+        };
+        (format!("{} {} {}", left, symbol, right), prec)
+    }
+
+    // A variable by name, which inside a method may be a property of the object
+    fn var(&mut self, name: &String) -> String {
+        variable(&self.program, &mut self.names, name, "Value")
+    }
+
+    // VB.NET arrays start at 0, so each index is moved down by its lower bound
+    fn subscripts(&mut self, indices: &[Box<Node>], t: &Option<VariableType>) -> String {
+        let lower = lower_bounds(t);
+        let mut out = vec![];
+        for (i, index) in indices.iter().enumerate() {
+            let lower = lower.get(i).copied().unwrap_or(0);
+            out.push(shifted(index, -lower, |expr| bracket(self.expr(expr), ADD)));
+        }
+        format!("({})", out.join(", "))
+    }
+
+    // A call to a builtin, subroutine or method of the object
+    fn call(&mut self, name: &String, params: &[Box<Node>]) -> Expr {
+        if builtin(name).is_some() {
+            return self.builtin(&name.to_uppercase(), params);
+        }
+        let signature = self.program.signature(name);
+        let args = self.args(signature, params);
+        (format!("{}({})", self.names.get(name), args), ATOM)
+    }
+
+    fn args(&mut self, signature: Option<Signature>, params: &[Box<Node>]) -> String {
+        let mut args = vec![];
+        for (i, param) in params.iter().enumerate() {
+            let declared = signature
+                .as_ref()
+                .and_then(|signature| signature.params.get(i))
+                .cloned();
+            let arg = match declared {
+                // A BYREF parameter is handed the variable itself, and a subroutine
+                // copies the arrays and records it changes itself
+                Some(declared)
+                    if declared.byref || self.program.copied(&Some(declared.t.clone())) =>
+                {
+                    self.value(param)
+                }
+                Some(declared) => self.coerced(param, Some(&declared.t)),
+                None => self.value(param),
+            };
+            args.push(arg);
+        }
+        args.join(", ")
+    }
+
+    fn builtin(&mut self, name: &str, params: &[Box<Node>]) -> Expr {
+        let mut args: Vec<Expr> = params.iter().map(|param| self.expr(param)).collect();
+        let mut arg = |i: usize, prec: u8| bracket(std::mem::take(&mut args[i]), prec);
+        let (text, prec) = match name {
+            "LEFT" => (format!("Left({}, {})", arg(0, 0), arg(1, 0)), ATOM),
+            "RIGHT" => (format!("Right({}, {})", arg(0, 0), arg(1, 0)), ATOM),
+            "MID" => (
+                format!("Mid({}, {}, {})", arg(0, 0), arg(1, 0), arg(2, 0)),
+                ATOM,
+            ),
+            "LENGTH" => (format!("Len({})", arg(0, 0)), ATOM),
+            "TO_UPPER" => (format!("UCase({})", arg(0, 0)), ATOM),
+            "TO_LOWER" => (format!("LCase({})", arg(0, 0)), ATOM),
+            "ASC" => (format!("AscW({})", arg(0, 0)), ATOM),
+            "CHR" => (format!("ChrW({})", arg(0, 0)), ATOM),
+            "INT" => (format!("CInt(Fix({}))", arg(0, 0)), ATOM),
+            "RAND" => (
+                format!("Random.Shared.NextDouble() * {}", arg(0, MUL + 1)),
+                MUL,
+            ),
+            "DAY" => (format!("{}.Day", arg(0, ATOM)), ATOM),
+            "MONTH" => (format!("{}.Month", arg(0, ATOM)), ATOM),
+            "YEAR" => (format!("{}.Year", arg(0, ATOM)), ATOM),
+            // Sunday is 1
+            "DAYINDEX" => (format!("Weekday({})", arg(0, 0)), ATOM),
+            "SETDATE" => {
+                let (day, month, year) = (arg(0, 0), arg(1, 0), arg(2, 0));
+                (format!("New Date({}, {}, {})", year, month, day), UNARY)
+            }
+            "TODAY" => ("Date.Today".to_string(), ATOM),
+            "NUM_TO_STR" => ("Show".to_string(), 0),
+            "STR_TO_NUM" => ("StrToNum".to_string(), 0),
+            "IS_NUM" => ("IsNum".to_string(), 0),
+            "EOF" => ("EndOfFile".to_string(), 0),
+            _ => ("Nothing".to_string(), ATOM),
+        };
+        if prec > 0 {
+            return (text, prec);
+        }
+        // The rest are in the prelude
+        let part = PRELUDE.iter().find(|part| part.name == text).unwrap();
+        self.out.use_part(part.name);
+        let args: Vec<String> = params.iter().map(|param| self.value(param)).collect();
+        (format!("{}({})", text, args.join(", ")), ATOM)
+    }
+
+    // obj.prop, obj.arr(i) and obj.method() chains
+    fn composite(&mut self, children: &[Box<Node>]) -> Expr {
+        let first = &children[0];
+        let mut text = match first.deref() {
+            Node::Var { name, .. } if name == "super" => "MyBase".to_string(),
+            _ => bracket(self.expr(first), ATOM),
+        };
+        let mut t = self.program.type_of(first);
+        if let Some(VariableType::Pointer(to)) = t {
+            t = Some(self.program.checker.resolve(&to));
+        }
+        for child in children.iter().skip(1) {
+            let class = match &t {
+                Some(VariableType::Custom(name)) => Some(name.clone()),
+                _ => None,
+            };
+            match child.deref() {
+                Node::Var { name, .. } | Node::ArrayVar { name, .. } => {
+                    let field =
+                        class.and_then(|class| self.program.checker.get_field(&class, name));
+                    text = format!("{}.{}", text, self.names.get(name));
+                    t = field.map(|field| self.program.checker.resolve(&field.t));
+                    if let Node::ArrayVar { indices, .. } = child.deref() {
+                        text += &self.subscripts(indices, &t);
+                        t = match t {
+                            Some(VariableType::Array { t, .. }) => Some(*t),
+                            _ => None,
+                        };
+                    }
+                }
+                Node::FunctionCall { name, params, .. } => {
+                    let method =
+                        class.and_then(|class| self.program.checker.get_method(&class, name));
+                    t = method
+                        .as_ref()
+                        .and_then(|method| method.returns.as_ref())
+                        .map(|returns| self.program.checker.resolve(returns));
+                    let args = self.args(method, params);
+                    let method = match name.as_str() {
+                        "new" => "New".to_string(),
+                        _ => self.names.get(name),
+                    };
+                    text = format!("{}.{}({})", text, method, args);
+                }
+                _ => {}
+            }
+        }
+        (text, ATOM)
+    }
+}
+
+fn access(private: bool) -> &'static str {
+    match private {
+        true => "Private",
+        false => "Public",
+    }
+}
+
+// A VB.NET string literal, where a quote is written twice
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+fn char_literal(c: char) -> String {
+    match c {
+        c if c.is_control() => format!("ChrW({})", c as u32),
+        c => format!("{}c", quote(&c.to_string())),
+    }
+}
+
+// The pieces joined with &, which turns numbers and letters into text as well
+fn text(pieces: Vec<Piece>) -> String {
+    let mut parts: Vec<String> = vec![];
+    let mut literal: Option<String> = None;
+    let mut string = false;
+    for piece in pieces {
+        match piece {
+            Piece::Literal(text) => {
+                string = true;
+                literal.get_or_insert_with(String::new).push_str(&text);
+            }
+            Piece::Value(value, is_string) => {
+                string |= is_string;
+                if let Some(text) = literal.take() {
+                    parts.push(quote(&text));
+                }
+                parts.push(bracket(value, CONCAT + 1));
+            }
+        }
+    }
+    if let Some(text) = literal.take() {
+        parts.push(quote(&text));
+    }
+    match (parts.len(), string) {
+        (0, _) => "\"\"".to_string(),
+        (1, false) => format!("CStr({})", parts[0]),
+        _ => parts.join(" & "),
+    }
+}
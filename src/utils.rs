@@ -1,61 +1,246 @@
-use std::ops::Deref;
+use std::fmt::{Display, Formatter};
 
-use annotate_snippets::{Level, Renderer, Snippet};
+use annotate_snippets::{Level, Message, Renderer, Snippet};
 
 use crate::enums::{Position, Token};
 use crate::lexer::Lexer;
 use crate::tokens::TToken;
-use crate::SOURCE_FILE;
 
 pub const SUPPORT_MESSAGE: &str = "\nIf you believe this behaviour is not in line with Cambridge A-Lvls standard, please email jaxonmoh05@gmail.com";
 
+// Longest run of calls from one place the call stack of an error lists in full
+const MAX_REPEATED_FRAMES: usize = 4;
+
+// Program text kept around so errors can quote the offending lines
+#[derive(Debug, Clone, Default)]
+pub struct SourceFile {
+    pub name: String,
+    pub file: Vec<String>,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, text: &str) -> Self {
+        SourceFile {
+            name: name.to_string(),
+            file: text.lines().map(|line| line.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // Raised while turning source into tokens or nodes
+    Syntax,
+    // Raised while the program is executing
+    Runtime,
+    // Found by the checker before the program runs
+    Semantic,
+    // The program was stopped for using more than it was allowed
+    Limit(Limit),
+}
+
+// A resource the program can be limited in, each ending the run with a status of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    Time,
+    CallDepth,
+    Output,
+    ArrayElements,
+}
+
+impl Limit {
+    pub fn status(&self) -> i32 {
+        match self {
+            Limit::Steps => 3,
+            Limit::Time => 4,
+            Limit::CallDepth => 5,
+            Limit::Output => 6,
+            Limit::ArrayElements => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PseudoError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub pos: Position,
+    // Name of the program the error was raised in
+    pub source: String,
+    // The calls that were running, most recent first
+    pub backtrace: Vec<Frame>,
+}
+
+// A procedure, function or method call that had not returned yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    // Where it was called from
+    pub pos: Position,
+    // The values of its parameters, written the way they would be in a program
+    pub args: Vec<String>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}({}) called at line {}",
+            self.name,
+            self.args.join(", "),
+            self.pos.line_start
+        )
+    }
+}
+
+impl PseudoError {
+    pub fn new(kind: ErrorKind, message: &str, pos: &Position) -> Self {
+        PseudoError {
+            kind,
+            message: message.to_string(),
+            pos: *pos,
+            source: String::new(),
+            backtrace: vec![],
+        }
+    }
+
+    pub fn with_source(mut self, name: &str) -> Self {
+        self.source = name.to_string();
+        self
+    }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    // The exit status of a run stopped by this error
+    pub fn status(&self) -> i32 {
+        match self.kind {
+            ErrorKind::Limit(limit) => limit.status(),
+            _ => 1,
+        }
+    }
+
+    // Render the error as an annotated snippet of the offending source lines
+    pub fn render(&self, source: &SourceFile) -> String {
+        let pos = &self.pos;
+        let renderer = Renderer::styled();
+        // Values created by the interpreter carry no position, so only the message is shown
+        if pos.line_start == 0 || pos.line_end > source.file.len() {
+            let message: Message = Level::Error.title(&self.message);
+            return renderer.render(message).to_string() + &self.render_backtrace();
+        }
+
+        let mut lines = source.file[pos.line_start - 1].clone() + "  ";
+        let start = pos.pos_start;
+        let mut end = pos.pos_end;
+        if pos.line_start != pos.line_end {
+            let slice = &source.file[(pos.line_start - 1)..pos.line_end];
+            lines = slice.join("\n");
+            let mut len = slice[0].len() - pos.pos_start + 1;
+            if pos.line_end - pos.line_start > 1 {
+                for line in &slice[1..slice.len() - 1] {
+                    len += line.len() + 1;
+                }
+            }
+            len += pos.pos_end;
+            end = start + len;
+        }
+        // Tokens past the end of the last line, like EOF, point just after it
+        let end = end.min(lines.len());
+        let start = start.min(end);
+        let message = Level::Error.title(&self.message).snippet(
+            Snippet::source(&lines)
+                .line_start(pos.line_start)
+                .origin(&self.source)
+                .fold(true)
+                .annotation(Level::Error.span(start..end).label(&self.message)),
+        );
+        let rendered = renderer.render(message).to_string();
+        rendered + &self.render_backtrace()
+    }
+
+    // One line per call, with a run of calls made from the same place, as unbounded
+    // recursion makes, shown once with a count
+    fn render_backtrace(&self) -> String {
+        if self.backtrace.is_empty() {
+            return String::new();
+        }
+        let mut lines = vec!["\ncall stack, most recent call first:".to_string()];
+        let mut i = 0;
+        while i < self.backtrace.len() {
+            let frame = &self.backtrace[i];
+            let run = self.backtrace[i..]
+                .iter()
+                .take_while(|other| other.name == frame.name && other.pos == frame.pos)
+                .count();
+            // Deep recursion shows where it started and where it got to
+            if run > MAX_REPEATED_FRAMES {
+                lines.push(format!("  {}", frame));
+                lines.push(format!(
+                    "  ... {} more calls of {} from there",
+                    run - 2,
+                    frame.name
+                ));
+                lines.push(format!("  {}", self.backtrace[i + run - 1]));
+            } else {
+                for frame in &self.backtrace[i..i + run] {
+                    lines.push(format!("  {}", frame));
+                }
+            }
+            i += run;
+        }
+        lines.join("\n")
+    }
+}
+
+impl Display for PseudoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.source,
+            self.pos.line_start,
+            self.pos.pos_start + 1,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for PseudoError {}
+
 // Match the next token against a list of expected tokens
-pub fn expect_token(lexer: &mut Lexer, tokens: &[TToken], message: &str) -> Token {
+pub fn expect_token(
+    lexer: &mut Lexer,
+    tokens: &[TToken],
+    message: &str,
+) -> Result<Token, PseudoError> {
     if let Some(next) = lexer.next() {
         if next.t == TToken::EOF {
-            err(&format!("{} expected", message), &next.pos);
+            err(&format!("{} expected", message), &next.pos)?;
         }
         for token in tokens {
             if std::mem::discriminant(&next.t) == std::mem::discriminant(token) {
-                return next;
+                return Ok(next);
             }
         }
         err(
             &format!("{} expected. {:?} found", message, next.t),
             &next.pos,
-        );
+        )?;
     }
 
     unreachable!()
 }
 
-// Parser error function, contains position metadata
-pub fn err(message: &str, pos: &Position) -> ! {
-    let source = SOURCE_FILE.with(|file| file.borrow().deref().clone());
-    let mut lines = source.file[pos.line_start - 1].clone() + "  ";
-    let mut start = pos.pos_start;
-    let mut end = pos.pos_end;
-    if pos.line_start != pos.line_end {
-        let slice = &source.file[(pos.line_start - 1)..pos.line_end];
-        lines = slice.join("\n");
-        let mut len = slice[0].len() - pos.pos_start + 1;
-        if pos.line_end - pos.line_start > 1 {
-            for line in &slice[1..slice.len() - 1] {
-                len += line.len() + 1;
-            }
-        }
-        len += pos.pos_end;
-        end = start + len;
-    }
-    let message = Level::Error.title(message).snippet(
-        Snippet::source(&lines)
-            .line_start(pos.line_start)
-            .origin(&source.name)
-            .fold(true)
-            .annotation(Level::Error.span(start..end).label(message)),
-    );
+// Error function, contains position metadata
+pub fn err<T>(message: &str, pos: &Position) -> Result<T, PseudoError> {
+    Err(PseudoError::new(ErrorKind::Runtime, message, pos))
+}
 
-    let renderer = Renderer::styled();
-    anstream::println!("{}", renderer.render(message));
-    std::process::exit(0);
+// Error for a program stopped by one of its limits
+pub fn limit_err<T>(limit: Limit, message: &str, pos: &Position) -> Result<T, PseudoError> {
+    Err(PseudoError::new(ErrorKind::Limit(limit), message, pos))
 }
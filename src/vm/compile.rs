@@ -0,0 +1,999 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+
+use crate::enums::{Node, Position, VariableType};
+use crate::executor::hoist::{hoisted, is_definition};
+use crate::executor::run_builtins::builtin;
+use crate::tokens::TToken;
+use crate::utils::{ErrorKind, PseudoError};
+use crate::vm::value::Value;
+use crate::vm::CompileError;
+use crate::vm::{BinOp, EnumDef, Op, Program, RecordDef, Routine, Site, Slot, UnOp};
+
+// How a procedure or function is called, known before any of them are compiled
+struct Signature {
+    // Name of each parameter, whether it is BYREF and its type
+    params: Vec<(String, bool, u32)>,
+    returns: bool,
+    // A parameter whose name is already taken, and where it was declared
+    duplicate: Option<(usize, String, Position)>,
+    body: Vec<Box<Node>>,
+}
+
+// The variables of the routine being compiled
+struct Frame {
+    params: HashMap<u32, u32>,
+    locals: HashMap<u32, u32>,
+    returns: bool,
+}
+
+struct Compiler {
+    program: Program,
+    // Every variable name, numbered
+    names: HashMap<String, u32>,
+    // Names some routine keeps a local variable of
+    routine_names: HashSet<u32>,
+    // The first procedure or function given each name
+    routine_of: HashMap<String, u32>,
+    signatures: Vec<Signature>,
+    builtin_of: HashMap<String, u32>,
+    // How many routines the top level has defined so far
+    defined: u32,
+    frame: Option<Frame>,
+    // Jumps out of the top level statement being compiled, taken by RETURN
+    exits: Vec<usize>,
+}
+
+pub fn compile(nodes: &Vec<Box<Node>>) -> Result<Program, CompileError> {
+    let mut compiler = Compiler {
+        program: Program {
+            code: vec![],
+            sites: vec![],
+            constants: vec![],
+            types: vec![],
+            routines: vec![],
+            records: vec![],
+            enums: vec![],
+            defs: HashMap::new(),
+            globals: vec![],
+            global_count: 0,
+            builtins: vec![],
+        },
+        names: HashMap::new(),
+        routine_names: HashSet::new(),
+        routine_of: HashMap::new(),
+        signatures: vec![],
+        builtin_of: HashMap::new(),
+        defined: 0,
+        frame: None,
+        exits: vec![],
+    };
+    let mut stmts = vec![];
+    for node in nodes {
+        if let Node::Main { children } = node.deref() {
+            stmts.extend(children.iter().cloned());
+        }
+    }
+    compiler.scan_globals(&stmts, true)?;
+    for routine in 0..compiler.signatures.len() {
+        compiler.scan_locals(routine);
+    }
+    compiler.main(&stmts)?;
+    for routine in 0..compiler.signatures.len() {
+        compiler.routine(routine)?;
+    }
+    Ok(compiler.program)
+}
+
+fn unsupported<T>(what: &str, pos: Position) -> Result<T, CompileError> {
+    Err(CompileError::Unsupported {
+        what: what.to_string(),
+        pos,
+    })
+}
+
+fn invalid<T>(what: &str, pos: Position) -> Result<T, CompileError> {
+    let message = format!("The VM cannot compile this {}", what);
+    Err(CompileError::Invalid(PseudoError::new(
+        ErrorKind::Runtime,
+        &message,
+        &pos,
+    )))
+}
+
+// Names a routine declares as it runs, in the order they are declared
+fn declared_names(stmts: &[Box<Node>], names: &mut Vec<String>) {
+    for stmt in stmts {
+        match stmt.deref() {
+            Node::Declare { children, .. } => names.extend(children.iter().cloned()),
+            Node::Const { name, .. } => names.push(name.clone()),
+            Node::For { iter, body, .. } => {
+                if let Node::Var { name, .. } = iter.deref() {
+                    names.push(name.clone());
+                }
+                declared_names(body, names);
+            }
+            Node::If {
+                true_body,
+                false_body,
+                ..
+            } => {
+                declared_names(true_body, names);
+                declared_names(false_body, names);
+            }
+            Node::While { body, .. } | Node::Repeat { body, .. } => declared_names(body, names),
+            Node::Switch {
+                cases, otherwise, ..
+            } => {
+                for case in cases {
+                    if let Node::Case { children, .. } = case.deref() {
+                        declared_names(children, names);
+                    }
+                }
+                declared_names(otherwise, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn binary_op(op: &str) -> BinOp {
+    match op {
+        "+" => BinOp::Add,
+        "-" => BinOp::Sub,
+        "*" => BinOp::Mul,
+        "/" => BinOp::Div,
+        "%" => BinOp::Mod,
+        "//" => BinOp::IntDiv,
+        "<" => BinOp::Less,
+        ">" => BinOp::Greater,
+        "<=" => BinOp::LessEq,
+        ">=" => BinOp::GreaterEq,
+        "=" => BinOp::Equal,
+        "!=" => BinOp::NotEqual,
+        "&&" => BinOp::And,
+        "||" => BinOp::Or,
+        "&" => BinOp::Concat,
+        _ => unreachable!(),
+    }
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op) -> usize {
+        self.program.code.push(op);
+        self.program.code.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.program.code.len() as u32
+    }
+
+    // Point the jump at the given instruction to whatever is compiled next
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        self.program.code[at] = match self.program.code[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpUnless(_, site) => Op::JumpUnless(target, site),
+            Op::ForNext(slot, _, site) => Op::ForNext(slot, target, site),
+            Op::CaseValue(constant, _) => Op::CaseValue(constant, target),
+            Op::CaseRange(constant, _, site) => Op::CaseRange(constant, target, site),
+            _ => unreachable!(),
+        };
+    }
+
+    fn site(&mut self, pos: Position, text: &str, positions: Vec<Position>) -> u32 {
+        self.program.sites.push(Site {
+            pos,
+            text: text.to_string(),
+            positions,
+        });
+        (self.program.sites.len() - 1) as u32
+    }
+
+    fn raise(&mut self, message: &str, pos: Position) {
+        let site = self.site(pos, message, vec![]);
+        self.emit(Op::Raise(site));
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        self.program.constants.push(value);
+        (self.program.constants.len() - 1) as u32
+    }
+
+    fn var_type(&mut self, t: &VariableType, pos: Position) -> Result<u32, CompileError> {
+        match t {
+            VariableType::Pointer(_) => unsupported("pointer", pos)?,
+            VariableType::Array { t, .. } => {
+                self.var_type(t, pos)?;
+            }
+            _ => {}
+        }
+        self.program.types.push(t.clone());
+        Ok((self.program.types.len() - 1) as u32)
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.names.get(name) {
+            return *id;
+        }
+        let id = self.names.len() as u32;
+        self.names.insert(name.to_string(), id);
+        self.program.globals.push(None);
+        id
+    }
+
+    fn global(&mut self, name: &str) -> u32 {
+        let id = self.name(name) as usize;
+        if let Some(slot) = self.program.globals[id] {
+            return slot;
+        }
+        let slot = self.program.global_count as u32;
+        self.program.globals[id] = Some(slot);
+        self.program.global_count += 1;
+        slot
+    }
+
+    fn def(&mut self, name: &str) -> u32 {
+        let count = self.program.defs.len() as u32;
+        *self.program.defs.entry(name.to_string()).or_insert(count)
+    }
+
+    // Variables, definitions and routines of the program as a whole
+    fn scan_globals(&mut self, stmts: &[Box<Node>], top: bool) -> Result<(), CompileError> {
+        for stmt in stmts {
+            match stmt.deref() {
+                Node::Declare { children, .. } => {
+                    for name in children {
+                        self.global(name);
+                    }
+                }
+                Node::Const { name, .. } => {
+                    self.global(name);
+                }
+                Node::Enum {
+                    name,
+                    variants,
+                    pos,
+                } if top => {
+                    self.def(name);
+                    for variant in variants {
+                        if let Node::String { val, .. } = variant.deref() {
+                            self.global(val);
+                        }
+                    }
+                }
+                Node::Record { name, .. } if top => {
+                    if let Node::String { val, .. } = name.deref() {
+                        self.def(val);
+                    }
+                }
+                Node::Function {
+                    name,
+                    params,
+                    children,
+                    pos,
+                    ..
+                }
+                | Node::Procedure {
+                    name,
+                    params,
+                    children,
+                    pos,
+                    ..
+                } if top => {
+                    let returns = matches!(stmt.deref(), Node::Function { .. });
+                    self.signature(name, params, children, returns)?;
+                }
+                Node::Enum { pos, .. }
+                | Node::Record { pos, .. }
+                | Node::Function { pos, .. }
+                | Node::Procedure { pos, .. } => unsupported("nested definition", *pos)?,
+                Node::Class { pos, .. } => unsupported("class", *pos)?,
+                Node::PointerDef { pos, .. } => unsupported("pointer", *pos)?,
+                Node::For { iter, body, .. } => {
+                    if let Node::Var { name, .. } = iter.deref() {
+                        self.global(name);
+                    }
+                    self.scan_globals(body, false)?;
+                }
+                Node::If {
+                    true_body,
+                    false_body,
+                    ..
+                } => {
+                    self.scan_globals(true_body, false)?;
+                    self.scan_globals(false_body, false)?;
+                }
+                Node::While { body, .. } | Node::Repeat { body, .. } => {
+                    self.scan_globals(body, false)?
+                }
+                Node::Switch {
+                    cases, otherwise, ..
+                } => {
+                    for case in cases {
+                        if let Node::Case { children, .. } = case.deref() {
+                            self.scan_globals(children, false)?;
+                        }
+                    }
+                    self.scan_globals(otherwise, false)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn signature(
+        &mut self,
+        name: &Box<Node>,
+        params: &[Box<Node>],
+        body: &[Box<Node>],
+        returns: bool,
+    ) -> Result<(), CompileError> {
+        let Node::String { val: name, .. } = name.deref() else {
+            unreachable!()
+        };
+        let def = self.def(name);
+        let index = self.signatures.len() as u32;
+        self.routine_of.entry(name.clone()).or_insert(index);
+        let mut signature = Signature {
+            params: vec![],
+            returns,
+            duplicate: None,
+            body: body.to_vec(),
+        };
+        for (i, param) in params.iter().enumerate() {
+            if let Node::Declare {
+                t,
+                children,
+                byref,
+                pos,
+                ..
+            } = param.deref()
+            {
+                let param_name = children[0].clone();
+                let taken = signature
+                    .params
+                    .iter()
+                    .any(|(name, ..)| *name == param_name);
+                if taken && signature.duplicate.is_none() {
+                    signature.duplicate = Some((i, param_name.clone(), *pos));
+                }
+                let t = self.var_type(t, *pos)?;
+                signature.params.push((param_name, *byref, t));
+            }
+        }
+        self.program.routines.push(Routine {
+            name: name.clone(),
+            def,
+            entry: 0,
+            params: signature.params.len(),
+            locals: 0,
+            names: HashMap::new(),
+        });
+        self.signatures.push(signature);
+        Ok(())
+    }
+
+    // Give every parameter and variable a routine declares a slot of its own,
+    // the parameters first in the order they are passed
+    fn scan_locals(&mut self, routine: usize) {
+        let mut names: Vec<String> = self.signatures[routine]
+            .params
+            .iter()
+            .map(|(name, ..)| name.clone())
+            .collect();
+        let count = names.len();
+        declared_names(&self.signatures[routine].body, &mut names);
+        let mut slots = HashMap::new();
+        for (i, name) in names.iter().enumerate() {
+            let id = self.name(name);
+            self.routine_names.insert(id);
+            if !slots.contains_key(&id) {
+                let slot = if i < count { i } else { count + slots.len() };
+                slots.insert(id, slot as u32);
+            }
+        }
+        let locals = slots.values().map(|slot| *slot as usize + 1).max();
+        let info = &mut self.program.routines[routine];
+        info.locals = locals.unwrap_or(0).max(count);
+        info.names = slots;
+    }
+
+    fn main(&mut self, stmts: &[Box<Node>]) -> Result<(), CompileError> {
+        // Definitions are made before the first statement runs, as the tree-walker does
+        match hoisted(stmts) {
+            Ok(definitions) => {
+                for definition in definitions {
+                    self.stmt(definition, true)?;
+                }
+            }
+            Err(e) => self.raise(&e.message, e.pos),
+        }
+        for stmt in stmts {
+            if let Node::Return { pos, .. } = stmt.deref() {
+                self.raise("Cannot return outside of function or procedure", *pos);
+                continue;
+            }
+            if is_definition(stmt) {
+                continue;
+            }
+            self.stmt(stmt, true)?;
+            for exit in std::mem::take(&mut self.exits) {
+                self.patch(exit);
+            }
+        }
+        self.emit(Op::Halt);
+        Ok(())
+    }
+
+    fn routine(&mut self, routine: usize) -> Result<(), CompileError> {
+        let signature = &self.signatures[routine];
+        let returns = signature.returns;
+        let body = signature.body.clone();
+        let info = &self.program.routines[routine];
+        let params = info.params as u32;
+        let mut frame = Frame {
+            params: HashMap::new(),
+            locals: HashMap::new(),
+            returns,
+        };
+        for (name, slot) in &info.names {
+            if *slot < params {
+                frame.params.insert(*name, *slot);
+            } else {
+                frame.locals.insert(*name, *slot);
+            }
+        }
+        self.program.routines[routine].entry = self.here();
+        self.frame = Some(frame);
+        self.stmts(&body)?;
+        self.emit(if returns {
+            Op::MissingReturn
+        } else {
+            Op::EndProcedure
+        });
+        self.frame = None;
+        Ok(())
+    }
+
+    // Where a name is looked up from the code being compiled
+    fn lookup(&mut self, name: &str) -> Option<Slot> {
+        let id = self.name(name);
+        let global = self.program.globals[id as usize];
+        match &self.frame {
+            None => global.map(Slot::Global),
+            Some(frame) => {
+                if let Some(slot) = frame.params.get(&id) {
+                    Some(Slot::Local(*slot))
+                } else if let Some(slot) = frame.locals.get(&id) {
+                    Some(Slot::Scoped(*slot, id))
+                } else if self.routine_names.contains(&id) {
+                    Some(Slot::Outer(id))
+                } else {
+                    global.map(Slot::Global)
+                }
+            }
+        }
+    }
+
+    // The slot a DECLARE or CONSTANT creates, in the scope being compiled
+    fn declare_slot(&mut self, name: &str) -> Slot {
+        let id = self.name(name);
+        match &self.frame {
+            None => Slot::Global(self.global(name)),
+            Some(frame) => match frame.params.get(&id).or(frame.locals.get(&id)) {
+                Some(slot) => Slot::Local(*slot),
+                None => unreachable!(),
+            },
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Box<Node>]) -> Result<(), CompileError> {
+        for stmt in stmts {
+            self.stmt(stmt, false)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, node: &Box<Node>, top: bool) -> Result<(), CompileError> {
+        if Node::Null != *node.deref() {
+            let site = self.site(node.pos(), "", vec![]);
+            self.emit(Op::Step(site));
+        }
+        match node.deref() {
+            Node::Null => {}
+            Node::Declare {
+                t, children, pos, ..
+            } => {
+                let t = self.var_type(t, *pos)?;
+                for name in children {
+                    let slot = self.declare_slot(name);
+                    let site = self.site(*pos, name, vec![]);
+                    self.emit(Op::Declare(slot, t, site));
+                }
+            }
+            Node::Const { name, val, pos } => {
+                let slot = self.declare_slot(name);
+                let constant = self.constant(Value::from_node(val));
+                let site = self.site(*pos, name, vec![]);
+                self.emit(Op::DeclareConst(slot, constant, site));
+            }
+            Node::Enum {
+                name,
+                variants,
+                pos,
+            } if top => {
+                let mut def = EnumDef {
+                    def: self.def(name),
+                    name: name.clone(),
+                    pos: *pos,
+                    variants: vec![],
+                };
+                for variant in variants {
+                    if let Node::String { val, .. } = variant.deref() {
+                        def.variants.push((self.declare_slot(val), val.clone()));
+                    }
+                }
+                self.program.enums.push(def);
+                self.emit(Op::DefineEnum((self.program.enums.len() - 1) as u32));
+            }
+            Node::Record { name, children, .. } if top => {
+                let Node::String { val, pos } = name.deref() else {
+                    unreachable!()
+                };
+                let mut def = RecordDef {
+                    def: self.def(val),
+                    name: val.clone(),
+                    pos: *pos,
+                    fields: vec![],
+                };
+                for child in children {
+                    match child.deref() {
+                        Node::Null => {}
+                        Node::Declare {
+                            t, children, pos, ..
+                        } => {
+                            let t = self.var_type(t, *pos)?;
+                            for field in children {
+                                def.fields.push((field.clone(), t, *pos));
+                            }
+                        }
+                        _ => invalid("record field", child.pos())?,
+                    }
+                }
+                self.program.records.push(def);
+                self.emit(Op::DefineRecord((self.program.records.len() - 1) as u32));
+            }
+            Node::Function { name, .. } | Node::Procedure { name, .. } if top => {
+                let Node::String { val, pos } = name.deref() else {
+                    unreachable!()
+                };
+                // Routines are numbered in the order they were scanned
+                let routine = self.defined;
+                self.defined += 1;
+                let site = self.site(*pos, val, vec![]);
+                self.emit(Op::DefineRoutine(routine, site));
+            }
+            Node::Output { children, pos } => {
+                for child in children {
+                    self.expr(child)?;
+                    let site = self.site(child.pos(), "", vec![]);
+                    self.emit(Op::Print(site));
+                }
+                let site = self.site(*pos, "", vec![]);
+                self.emit(Op::Output(children.len() as u32, site));
+            }
+            Node::Input { child, pos } => {
+                match child.deref() {
+                    Node::Var { .. } | Node::ArrayVar { .. } | Node::Composite { .. } => {
+                        self.place(child)?;
+                        let site = self.site(*pos, "", vec![child.pos()]);
+                        self.emit(Op::Input(site));
+                    }
+                    Node::Reference { pos, .. } => unsupported("pointer", *pos)?,
+                    _ => self.raise("Input cannot be stored in this value", child.pos()),
+                };
+            }
+            Node::Assignment { lhs, rhs, pos } => {
+                if self.target(lhs)? {
+                    self.place(lhs)?;
+                    self.expr(rhs)?;
+                    let site = self.site(*pos, "", vec![]);
+                    self.emit(Op::Store(site));
+                }
+            }
+            Node::OpenFile {
+                filename,
+                mode,
+                pos,
+            } => {
+                let TToken::FileMode(mode) = mode else {
+                    unreachable!()
+                };
+                self.expr(filename)?;
+                let site = self.site(*pos, mode, vec![]);
+                self.emit(Op::OpenFile(site));
+            }
+            Node::CloseFile { filename, pos } => {
+                self.expr(filename)?;
+                let site = self.site(*pos, "", vec![]);
+                self.emit(Op::CloseFile(site));
+            }
+            Node::ReadFile { filename, var, pos } => {
+                self.expr(filename)?;
+                let site = self.site(*pos, "", vec![]);
+                self.emit(Op::ReadFile(site));
+                if self.target(var)? {
+                    self.place(var)?;
+                    let site = self.site(var.pos(), "", vec![]);
+                    self.emit(Op::Store(site));
+                }
+            }
+            Node::WriteFile {
+                filename,
+                expr,
+                pos,
+            } => {
+                self.expr(filename)?;
+                self.expr(expr)?;
+                let site = self.site(*pos, "", vec![]);
+                self.emit(Op::WriteFile(site));
+            }
+            Node::If {
+                cond,
+                true_body,
+                false_body,
+                ..
+            } => {
+                let skip = self.condition(cond)?;
+                self.stmts(true_body)?;
+                let end = self.emit(Op::Jump(0));
+                self.patch(skip);
+                self.stmts(false_body)?;
+                self.patch(end);
+            }
+            Node::While { cond, body, .. } => {
+                let start = self.here();
+                let exit = self.condition(cond)?;
+                let site = self.site(cond.pos(), "", vec![]);
+                self.emit(Op::Step(site));
+                self.stmts(body)?;
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+            }
+            Node::Repeat { cond, body, .. } => {
+                let start = self.here();
+                let site = self.site(cond.pos(), "", vec![]);
+                self.emit(Op::Step(site));
+                self.stmts(body)?;
+                self.expr(cond)?;
+                let site = self.site(cond.pos(), "", vec![]);
+                self.emit(Op::JumpUnless(start, site));
+            }
+            Node::For {
+                iter,
+                range,
+                step,
+                body,
+                ..
+            } => {
+                let (Node::Var { name, pos }, Node::Range { start, end, .. }) =
+                    (iter.deref(), range.deref())
+                else {
+                    unreachable!()
+                };
+                self.integer(start)?;
+                self.integer(end)?;
+                if let Node::Null = step.deref() {
+                    let one = self.constant(Value::Int(1));
+                    self.emit(Op::Constant(one));
+                } else {
+                    self.integer(step)?;
+                }
+                let slot = self.lookup(name).unwrap();
+                let site = self.site(*pos, name, vec![]);
+                self.emit(Op::ForSetup(slot, site));
+                let start = self.here();
+                let exit = self.emit(Op::ForNext(slot, 0, site));
+                self.emit(Op::Step(site));
+                self.stmts(body)?;
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+            }
+            Node::Switch {
+                cmp,
+                cases,
+                otherwise,
+                ..
+            } => {
+                self.expr(cmp)?;
+                let mut ends = vec![];
+                for case in cases {
+                    let Node::Case { expr, children, .. } = case.deref() else {
+                        unreachable!()
+                    };
+                    let next = match expr.deref() {
+                        Node::Range { start, end, .. } => {
+                            let constant = self.constant(Value::from_node(start));
+                            self.constant(Value::from_node(end));
+                            let site = self.site(cmp.pos(), "", vec![start.pos(), end.pos()]);
+                            self.emit(Op::CaseRange(constant, 0, site))
+                        }
+                        _ => {
+                            let constant = self.constant(Value::from_node(expr));
+                            self.emit(Op::CaseValue(constant, 0))
+                        }
+                    };
+                    self.stmts(children)?;
+                    ends.push(self.emit(Op::Jump(0)));
+                    self.patch(next);
+                }
+                self.stmts(otherwise)?;
+                for end in ends {
+                    self.patch(end);
+                }
+                self.emit(Op::Pop);
+            }
+            Node::Return { expr, .. } => {
+                self.expr(expr)?;
+                match &self.frame {
+                    Some(frame) if frame.returns => self.emit(Op::Return),
+                    Some(_) => self.emit(Op::ProcedureReturn),
+                    // Returning from the top level only ends the statement it is in
+                    None => {
+                        self.emit(Op::Clear);
+                        let exit = self.emit(Op::Jump(0));
+                        self.exits.push(exit);
+                        exit
+                    }
+                };
+            }
+            Node::Class { pos, .. } => unsupported("class", *pos)?,
+            Node::PointerDef { pos, .. } => unsupported("pointer", *pos)?,
+            Node::SeekFile { pos, .. }
+            | Node::GetRecord { pos, .. }
+            | Node::PutRecord { pos, .. } => unsupported("random file", *pos)?,
+            Node::Enum { pos, .. }
+            | Node::Record { pos, .. }
+            | Node::Function { pos, .. }
+            | Node::Procedure { pos, .. } => unsupported("nested definition", *pos)?,
+            _ => {
+                self.expr(node)?;
+                self.emit(Op::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    // Compile a condition, returning the jump taken when it is FALSE
+    fn condition(&mut self, cond: &Box<Node>) -> Result<usize, CompileError> {
+        self.expr(cond)?;
+        let site = self.site(cond.pos(), "", vec![]);
+        Ok(self.emit(Op::JumpUnless(0, site)))
+    }
+
+    fn integer(&mut self, node: &Box<Node>) -> Result<(), CompileError> {
+        self.expr(node)?;
+        let site = self.site(node.pos(), "", vec![]);
+        self.emit(Op::ExpectInt(site));
+        Ok(())
+    }
+
+    // Whether a value can be assigned to, raising the error if it cannot
+    fn target(&mut self, node: &Box<Node>) -> Result<bool, CompileError> {
+        match node.deref() {
+            Node::Var { .. } | Node::ArrayVar { .. } | Node::Composite { .. } => Ok(true),
+            Node::Dereference { pos, .. } => unsupported("pointer", *pos),
+            _ => {
+                self.raise("Cannot assign to this value", node.pos());
+                Ok(false)
+            }
+        }
+    }
+
+    fn expr(&mut self, node: &Box<Node>) -> Result<(), CompileError> {
+        match node.deref() {
+            Node::Int { .. }
+            | Node::Real { .. }
+            | Node::String { .. }
+            | Node::Char { .. }
+            | Node::Boolean { .. }
+            | Node::Date { .. }
+            | Node::Null => {
+                let constant = self.constant(Value::from_node(node));
+                self.emit(Op::Constant(constant));
+            }
+            Node::Var { name, pos } => match self.lookup(name) {
+                Some(slot) => {
+                    let site = self.site(*pos, name, vec![]);
+                    self.emit(Op::Load(slot, site));
+                }
+                None => self.raise(&format!("'{}' is not declared", name), *pos),
+            },
+            Node::ArrayVar { name, indices, pos } => {
+                let positions = self.indices(indices)?;
+                match self.lookup(name) {
+                    Some(slot) => {
+                        let site = self.site(*pos, name, positions);
+                        self.emit(Op::LoadElement(slot, site));
+                    }
+                    None => self.raise(&format!("'{}' is not declared", name), *pos),
+                }
+            }
+            Node::Binary { op, lhs, rhs, pos } => {
+                self.expr(rhs)?;
+                self.expr(lhs)?;
+                let site = self.site(*pos, op, vec![lhs.pos(), rhs.pos()]);
+                self.emit(Op::Binary(binary_op(op), site));
+            }
+            Node::Unary { op, expr, .. } => {
+                self.expr(expr)?;
+                let op = match op.as_str() {
+                    "!" => UnOp::Not,
+                    "_-" => UnOp::Negate,
+                    "_+" => UnOp::Plus,
+                    _ => unreachable!(),
+                };
+                let site = self.site(expr.pos(), "", vec![]);
+                self.emit(Op::Unary(op, site));
+            }
+            Node::FunctionCall { name, params, pos } => self.call(name, params, *pos)?,
+            Node::Composite { children, .. } => {
+                self.composite(children, false)?;
+                self.emit(Op::LoadPlace);
+            }
+            Node::CreateObject { pos, .. } => unsupported("class", *pos)?,
+            Node::Reference { pos, .. } | Node::Dereference { pos, .. } => {
+                unsupported("pointer", *pos)?
+            }
+            _ => invalid("expression", node.pos())?,
+        }
+        Ok(())
+    }
+
+    // Indices of an array, each of which must be an INTEGER
+    fn indices(&mut self, indices: &[Box<Node>]) -> Result<Vec<Position>, CompileError> {
+        for index in indices {
+            self.integer(index)?;
+        }
+        Ok(indices.iter().map(|index| index.pos()).collect())
+    }
+
+    fn call(
+        &mut self,
+        name: &str,
+        params: &[Box<Node>],
+        pos: Position,
+    ) -> Result<(), CompileError> {
+        if let Some((types, func)) = builtin(name) {
+            let index = match self.builtin_of.get(name) {
+                Some(index) => *index,
+                None => {
+                    self.program.builtins.push((types.clone(), func));
+                    let index = (self.program.builtins.len() - 1) as u32;
+                    self.builtin_of.insert(name.to_string(), index);
+                    index
+                }
+            };
+            if types.len() != params.len() {
+                self.raise("Invalid number of arguments", pos);
+                return Ok(());
+            }
+            for (i, param) in params.iter().enumerate() {
+                self.expr(param)?;
+                let site = self.site(param.pos(), "", vec![]);
+                self.emit(Op::BuiltinArg(index, i as u32, site));
+            }
+            let positions = params.iter().map(|param| param.pos()).collect();
+            let site = self.site(pos, name, positions);
+            self.emit(Op::CallBuiltin(index, site));
+            return Ok(());
+        }
+
+        let Some(def) = self.program.defs.get(name).copied() else {
+            self.raise(&format!("'{}' is not declared", name), pos);
+            return Ok(());
+        };
+        let site = self.site(pos, name, vec![]);
+        self.emit(Op::Callable(def, site));
+        let Some(routine) = self.routine_of.get(name).copied() else {
+            // Calling a type always fails
+            return Ok(());
+        };
+        let signature = &self.signatures[routine as usize];
+        if signature.params.len() != params.len() {
+            self.raise("Number of parameters doesnt match function definition", pos);
+            return Ok(());
+        }
+        let types: Vec<(bool, u32)> = signature
+            .params
+            .iter()
+            .map(|(_, byref, t)| (*byref, *t))
+            .collect();
+        let duplicate = signature.duplicate.clone();
+        for (i, (param, (byref, t))) in params.iter().zip(types).enumerate() {
+            if byref {
+                match param.deref() {
+                    Node::Var { .. } | Node::ArrayVar { .. } | Node::Composite { .. } => {
+                        self.place(param)?;
+                        let site = self.site(param.pos(), "", vec![]);
+                        self.emit(Op::ArgRef(t, site));
+                    }
+                    Node::Dereference { pos, .. } => unsupported("pointer", *pos)?,
+                    _ => {
+                        self.raise("Cannot pass this value byref", param.pos());
+                        return Ok(());
+                    }
+                }
+            } else {
+                self.expr(param)?;
+                let site = self.site(param.pos(), "", vec![]);
+                self.emit(Op::Arg(t, site));
+            }
+            if let Some((at, name, pos)) = &duplicate {
+                if *at == i {
+                    self.raise(&format!("'{}' is already initialized", name), *pos);
+                    return Ok(());
+                }
+            }
+        }
+        self.emit(Op::Call(routine, site));
+        Ok(())
+    }
+
+    // Leave where a variable, or part of one, is kept on the place stack
+    fn place(&mut self, node: &Box<Node>) -> Result<(), CompileError> {
+        match node.deref() {
+            Node::Var { name, pos } => self.place_var(name, *pos),
+            Node::ArrayVar { name, indices, pos } => {
+                let positions = self.indices(indices)?;
+                self.place_var(name, *pos);
+                let site = self.site(*pos, name, positions);
+                self.emit(Op::PlaceIndex(site));
+            }
+            Node::Composite { children, .. } => self.composite(children, true)?,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn place_var(&mut self, name: &str, pos: Position) {
+        match self.lookup(name) {
+            Some(slot) => {
+                let site = self.site(pos, name, vec![]);
+                self.emit(Op::Place(slot, site));
+            }
+            None => self.raise(&format!("'{}' is not declared", name), pos),
+        }
+    }
+
+    fn composite(&mut self, children: &[Box<Node>], write: bool) -> Result<(), CompileError> {
+        match children[0].deref() {
+            Node::FunctionCall { name, params, pos } if !write => {
+                self.call(name, params, *pos)?;
+                self.emit(Op::PlaceValue);
+            }
+            Node::FunctionCall { pos, .. } => unsupported("assignment to a call", *pos)?,
+            _ => self.place(&children[0])?,
+        }
+        for child in children.iter().skip(1) {
+            match child.deref() {
+                Node::Var { name, pos } => {
+                    let site = self.site(*pos, name, vec![]);
+                    self.emit(Op::PlaceField(site));
+                }
+                Node::ArrayVar { name, indices, pos } => {
+                    let site = self.site(*pos, name, vec![]);
+                    self.emit(Op::PlaceField(site));
+                    self.emit(Op::PlaceArray(site));
+                    let positions = self.indices(indices)?;
+                    let site = self.site(*pos, name, positions);
+                    self.emit(Op::PlaceIndex(site));
+                }
+                _ => unsupported("method call", child.pos())?,
+            }
+        }
+        Ok(())
+    }
+}
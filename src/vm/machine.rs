@@ -0,0 +1,1035 @@
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+
+use crate::enums::{Index, Position, VariableType};
+use crate::executor::run_expr::array_offset;
+use crate::executor::run_file::{close_file, open_file, read_line, write_file};
+use crate::executor::{self, Executor};
+use crate::utils::{self, err, PseudoError, SUPPORT_MESSAGE};
+use crate::vm::value::{EnumValue, RecordValue, Reference, Root, Step, Value};
+use crate::vm::{BinOp, Op, Program, Site, Slot, UnOp};
+
+struct Cell {
+    value: Value,
+    mutable: bool,
+}
+
+impl Cell {
+    fn undeclared() -> Cell {
+        Cell {
+            value: Value::Undeclared,
+            mutable: true,
+        }
+    }
+
+    fn declared(&self) -> bool {
+        !matches!(self.value, Value::Undeclared)
+    }
+}
+
+// A procedure or function that is running
+struct Frame {
+    routine: usize,
+    // Where its locals start
+    base: usize,
+    // How much of the stack belongs to its callers
+    stack: usize,
+    ret: usize,
+    // The call, where errors about returning point
+    site: u32,
+}
+
+// A variable, or the value of an expression, followed by the steps into it
+struct Place {
+    root: PlaceRoot,
+    start: usize,
+}
+
+enum PlaceRoot {
+    Var(Root),
+    Value(Value),
+}
+
+enum Def {
+    None,
+    Routine,
+    Record(Value),
+    Enum,
+}
+
+pub struct Machine<'a> {
+    program: &'a Program,
+    // Console and files, shared with the tree-walker's file statements and builtins
+    executor: &'a mut Executor,
+    stack: Vec<Value>,
+    globals: Vec<Cell>,
+    locals: Vec<Cell>,
+    frames: Vec<Frame>,
+    places: Vec<Place>,
+    steps: Vec<Step>,
+    defs: Vec<Def>,
+}
+
+fn number(value: &Value, pos: &Position) -> Result<(f64, bool), PseudoError> {
+    match value {
+        Value::Int(val) => Ok((*val as f64, false)),
+        Value::Real(val) => Ok((*val, true)),
+        _ => err(
+            "Arithmetic operation can only be performed on INTEGER or REAL",
+            pos,
+        ),
+    }
+}
+
+// A STRING of one character can be stored wherever a CHAR is expected, and a CHAR
+// wherever a STRING is
+fn promote(value: Value, t: &VariableType, pos: &Position) -> Result<Value, PseudoError> {
+    match (t, &value) {
+        (VariableType::Char, Value::Str(val)) => {
+            let mut chars = val.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Value::Char(c)),
+                _ => err(
+                    format!(
+                        "Cannot assign a STRING of {} characters to a CHAR",
+                        val.chars().count()
+                    )
+                    .as_str(),
+                    pos,
+                ),
+            }
+        }
+        (VariableType::String, Value::Char(c)) => Ok(Value::Str(Rc::from(c.to_string()))),
+        _ => Ok(value),
+    }
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(program: &'a Program, executor: &'a mut Executor) -> Self {
+        Machine {
+            program,
+            executor,
+            stack: Vec::with_capacity(64),
+            globals: (0..program.global_count)
+                .map(|_| Cell::undeclared())
+                .collect(),
+            locals: vec![],
+            frames: vec![],
+            places: vec![],
+            steps: vec![],
+            defs: program.defs.iter().map(|_| Def::None).collect(),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+
+    fn base(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.base)
+    }
+
+    // Subroutines see their own locals and the globals, never those of their callers
+    fn global(&self, name: u32) -> Option<Root> {
+        let slot = self.program.globals[name as usize]? as usize;
+        self.globals[slot].declared().then_some(Root::Global(slot))
+    }
+
+    fn resolve(&self, slot: Slot) -> Option<Root> {
+        match slot {
+            Slot::Global(g) => {
+                let g = g as usize;
+                self.globals[g].declared().then_some(Root::Global(g))
+            }
+            Slot::Local(l) => {
+                let i = self.base() + l as usize;
+                self.locals[i].declared().then_some(Root::Local(i))
+            }
+            Slot::Scoped(l, name) => {
+                let i = self.base() + l as usize;
+                if self.locals[i].declared() {
+                    Some(Root::Local(i))
+                } else {
+                    self.global(name)
+                }
+            }
+            Slot::Outer(name) => self.global(name),
+        }
+    }
+
+    fn find(&self, slot: Slot, site: &Site) -> Result<Root, PseudoError> {
+        if let Some(root) = self.resolve(slot) {
+            return Ok(root);
+        }
+        if let (Slot::Scoped(_, name) | Slot::Outer(name), Some(frame)) = (slot, self.frames.last())
+        {
+            // Point out a local of a calling routine that was meant to be passed in
+            let below = self.frames.len() - 1;
+            let owner = self.frames[..below].iter().rev().find(|caller| {
+                let routine = &self.program.routines[caller.routine];
+                routine
+                    .names
+                    .get(&name)
+                    .is_some_and(|slot| self.locals[caller.base + *slot as usize].declared())
+            });
+            if let Some(owner) = owner {
+                return err(
+                    format!(
+                        "'{}' is local to {}, pass it as a parameter to use it in {}",
+                        site.text,
+                        self.program.routines[owner.routine].name,
+                        self.program.routines[frame.routine].name
+                    )
+                    .as_str(),
+                    &site.pos,
+                );
+            }
+        }
+        err(
+            format!("'{}' is not declared", site.text).as_str(),
+            &site.pos,
+        )
+    }
+
+    fn cell(&self, root: Root) -> &Cell {
+        match root {
+            Root::Global(i) => &self.globals[i],
+            Root::Local(i) => &self.locals[i],
+        }
+    }
+
+    fn cell_mut(&mut self, root: Root) -> &mut Cell {
+        match root {
+            Root::Global(i) => &mut self.globals[i],
+            Root::Local(i) => &mut self.locals[i],
+        }
+    }
+
+    // A variable's value, read through the reference of a BYREF parameter
+    fn value(&self, root: Root) -> &Value {
+        match &self.cell(root).value {
+            Value::Ref(reference) => {
+                let mut value = &self.cell(reference.root).value;
+                for step in &reference.steps {
+                    value = value.child(*step);
+                }
+                value
+            }
+            value => value,
+        }
+    }
+
+    fn place_value<'p>(&'p self, place: &'p Place) -> &'p Value {
+        let mut value = match &place.root {
+            PlaceRoot::Var(root) => &self.cell(*root).value,
+            PlaceRoot::Value(value) => value,
+        };
+        for step in &self.steps[place.start..] {
+            value = value.child(*step);
+        }
+        value
+    }
+
+    fn place_value_mut<'p>(&'p mut self, place: &'p mut Place) -> &'p mut Value {
+        let mut value = match &mut place.root {
+            PlaceRoot::Var(Root::Global(i)) => &mut self.globals[*i].value,
+            PlaceRoot::Var(Root::Local(i)) => &mut self.locals[*i].value,
+            PlaceRoot::Value(value) => value,
+        };
+        for step in &self.steps[place.start..] {
+            value = value.child_mut(*step);
+        }
+        value
+    }
+
+    fn top_place(&self) -> &Value {
+        self.place_value(self.places.last().unwrap())
+    }
+
+    fn pop_place(&mut self) -> Place {
+        self.places.pop().unwrap()
+    }
+
+    fn end_place(&mut self, place: &Place) {
+        self.steps.truncate(place.start);
+    }
+
+    fn write(&mut self, mut place: Place, value: Value) {
+        *self.place_value_mut(&mut place) = value;
+        self.end_place(&place);
+    }
+
+    fn int_at(&self, back: usize) -> i64 {
+        match self.stack[self.stack.len() - back] {
+            Value::Int(val) => val,
+            _ => unreachable!(),
+        }
+    }
+
+    fn default(&self, t: &VariableType, pos: &Position) -> Result<Value, PseudoError> {
+        Ok(match t {
+            VariableType::Integer => Value::Int(0),
+            VariableType::Real => Value::Real(0.0),
+            VariableType::String => Value::Str(Rc::from("")),
+            VariableType::Char => Value::Char(' '),
+            VariableType::Boolean => Value::Bool(false),
+            VariableType::Date => Value::Date(NaiveDate::default()),
+            VariableType::Array { shape, t } => {
+                let capacity = self.executor.array_capacity(shape, pos)?;
+                let values = if capacity > 0 {
+                    vec![self.default(t, pos)?; capacity]
+                } else {
+                    vec![]
+                };
+                Value::Array(Rc::new(crate::vm::value::ArrayValue {
+                    t: *t.clone(),
+                    shape: shape.clone(),
+                    values,
+                }))
+            }
+            VariableType::Custom(name) => {
+                let def = self
+                    .program
+                    .defs
+                    .get(name)
+                    .map(|def| &self.defs[*def as usize]);
+                match def {
+                    Some(Def::Record(record)) => record.clone(),
+                    Some(Def::Enum) => Value::Nothing(Rc::new(t.clone())),
+                    _ => err(format!("'{}' is not declared", name).as_str(), pos)?,
+                }
+            }
+            VariableType::Pointer(_) => unreachable!(),
+        })
+    }
+
+    fn declare(
+        &mut self,
+        slot: Slot,
+        value: Value,
+        mutable: bool,
+        site: &Site,
+    ) -> Result<(), PseudoError> {
+        let root = match slot {
+            Slot::Global(g) => Root::Global(g as usize),
+            Slot::Local(l) => Root::Local(self.base() + l as usize),
+            _ => unreachable!(),
+        };
+        let cell = self.cell_mut(root);
+        if cell.declared() {
+            err(
+                format!("'{}' is already initialized", site.text).as_str(),
+                &site.pos,
+            )?
+        }
+        *cell = Cell { value, mutable };
+        Ok(())
+    }
+
+    pub fn run(&mut self) -> Result<(), PseudoError> {
+        let mut result = self.execute();
+        if let Err(e) = &mut result {
+            if e.backtrace.is_empty() {
+                e.backtrace = self.backtrace();
+            }
+        }
+        result
+    }
+
+    // The routines running, most recent first, as the tree-walker reports them
+    fn backtrace(&self) -> Vec<utils::Frame> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let info = &self.program.routines[frame.routine];
+                utils::Frame {
+                    name: info.name.clone(),
+                    pos: self.program.sites[frame.site as usize].pos,
+                    args: (frame.base..frame.base + info.params)
+                        .map(|i| self.value(Root::Local(i)).quoted())
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    fn execute(&mut self) -> Result<(), PseudoError> {
+        let program = self.program;
+        let code = &program.code;
+        let mut pc = 0;
+        loop {
+            let op = code[pc];
+            pc += 1;
+            match op {
+                Op::Constant(constant) => self
+                    .stack
+                    .push(program.constants[constant as usize].clone()),
+                Op::Pop => {
+                    self.stack.pop();
+                }
+                Op::Clear => {
+                    self.stack.clear();
+                    self.places.clear();
+                    self.steps.clear();
+                }
+                Op::Load(slot, site) => {
+                    let site = &program.sites[site as usize];
+                    let root = self.find(slot, site)?;
+                    let value = self.value(root).clone();
+                    self.stack.push(value);
+                }
+                Op::LoadElement(slot, site) => {
+                    let site = &program.sites[site as usize];
+                    let count = site.positions.len();
+                    let start = self.stack.len() - count;
+                    let root = self.find(slot, site)?;
+                    let element = match self.value(root) {
+                        Value::Array(array) => {
+                            let offset = offset(&self.stack[start..], &array.shape, site)?;
+                            array.values[offset].clone()
+                        }
+                        _ => err(format!("{} is not an array", site.text).as_str(), &site.pos)?,
+                    };
+                    self.stack.truncate(start);
+                    self.stack.push(element);
+                }
+                Op::Unary(op, site) => {
+                    let site = &program.sites[site as usize];
+                    let value = self.pop();
+                    let result = match op {
+                        UnOp::Not => match value {
+                            Value::Bool(val) => Value::Bool(!val),
+                            _ => err(
+                                "Logical operation ! can only be performed on BOOLEAN",
+                                &site.pos,
+                            )?,
+                        },
+                        UnOp::Negate | UnOp::Plus => {
+                            let (val, is_real) = number(&value, &site.pos)?;
+                            let val = if op == UnOp::Negate { -val } else { val };
+                            if is_real {
+                                Value::Real(val)
+                            } else {
+                                Value::Int(val.trunc() as i64)
+                            }
+                        }
+                    };
+                    self.stack.push(result);
+                }
+                Op::Binary(op, site) => {
+                    let site = &program.sites[site as usize];
+                    let lhs = self.pop();
+                    let rhs = self.pop();
+                    let result = binary(op, lhs, rhs, site)?;
+                    self.stack.push(result);
+                }
+                Op::ExpectInt(site) => {
+                    if !matches!(self.stack.last(), Some(Value::Int(_))) {
+                        err("Expected INTEGER", &program.sites[site as usize].pos)?
+                    }
+                }
+
+                Op::Place(slot, site) => {
+                    let site = &program.sites[site as usize];
+                    let root = self.find(slot, site)?;
+                    let cell = self.cell(root);
+                    if !cell.mutable {
+                        err(
+                            format!(
+                                "'{}' is a constant, it's value cannot be modified",
+                                site.text
+                            )
+                            .as_str(),
+                            &site.pos,
+                        )?
+                    }
+                    let reference = match &cell.value {
+                        Value::Ref(reference) => Some(reference.clone()),
+                        _ => None,
+                    };
+                    let start = self.steps.len();
+                    let root = match reference {
+                        Some(reference) => {
+                            self.steps.extend_from_slice(&reference.steps);
+                            reference.root
+                        }
+                        None => root,
+                    };
+                    self.places.push(Place {
+                        root: PlaceRoot::Var(root),
+                        start,
+                    });
+                }
+                Op::PlaceValue => {
+                    let value = self.pop();
+                    self.places.push(Place {
+                        root: PlaceRoot::Value(value),
+                        start: self.steps.len(),
+                    });
+                }
+                Op::PlaceField(site) => {
+                    let site = &program.sites[site as usize];
+                    let field = match self.top_place() {
+                        Value::Record(record) => {
+                            match record
+                                .fields
+                                .iter()
+                                .position(|(name, _)| *name == site.text)
+                            {
+                                Some(field) => field,
+                                None => err(
+                                    format!("Property '{}' not found", site.text).as_str(),
+                                    &site.pos,
+                                )?,
+                            }
+                        }
+                        _ => err("Value is not an object", &site.pos)?,
+                    };
+                    self.steps.push(Step::Field(field));
+                }
+                Op::PlaceArray(site) => {
+                    let site = &program.sites[site as usize];
+                    if !matches!(self.top_place(), Value::Array(_)) {
+                        err(format!("{} is not an array", site.text).as_str(), &site.pos)?
+                    }
+                }
+                Op::PlaceIndex(site) => {
+                    let site = &program.sites[site as usize];
+                    let count = site.positions.len();
+                    let start = self.stack.len() - count;
+                    let offset = match self.top_place() {
+                        Value::Array(array) => offset(&self.stack[start..], &array.shape, site)?,
+                        _ => err(format!("{} is not an array", site.text).as_str(), &site.pos)?,
+                    };
+                    self.stack.truncate(start);
+                    self.steps.push(Step::Index(offset));
+                }
+                Op::LoadPlace => {
+                    let place = self.pop_place();
+                    let value = self.place_value(&place).clone();
+                    self.end_place(&place);
+                    self.stack.push(value);
+                }
+                Op::Store(site) => {
+                    let site = &program.sites[site as usize];
+                    let value = self.pop();
+                    let place = self.pop_place();
+                    let current = self.place_value(&place);
+                    let value = match current {
+                        Value::Char(_) => promote(value, &VariableType::Char, &site.pos)?,
+                        Value::Str(_) => promote(value, &VariableType::String, &site.pos)?,
+                        _ => value,
+                    };
+                    if !value.same_type(current) {
+                        err(
+                            format!(
+                                "Cannot assign type {:?} to type {:?}",
+                                value.type_of().str(),
+                                current.type_of().str()
+                            )
+                            .as_str(),
+                            &site.pos,
+                        )?
+                    }
+                    self.write(place, value);
+                }
+
+                Op::Jump(target) => pc = target as usize,
+                Op::JumpUnless(target, site) => match self.pop() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => pc = target as usize,
+                    _ => err("Expected BOOLEAN", &program.sites[site as usize].pos)?,
+                },
+                Op::ForSetup(slot, _) => {
+                    if self.resolve(slot).is_none() {
+                        let start = self.int_at(3);
+                        let root = match slot {
+                            Slot::Global(g) => Root::Global(g as usize),
+                            Slot::Local(l) | Slot::Scoped(l, _) => {
+                                Root::Local(self.base() + l as usize)
+                            }
+                            Slot::Outer(_) => unreachable!(),
+                        };
+                        *self.cell_mut(root) = Cell {
+                            value: Value::Int(start),
+                            mutable: true,
+                        };
+                    }
+                }
+                Op::ForNext(slot, exit, site) => {
+                    let start = self.int_at(3);
+                    let end = self.int_at(2);
+                    let step = self.int_at(1);
+                    if start <= end {
+                        let site = &program.sites[site as usize];
+                        let root = self.find(slot, site)?;
+                        let cell = self.cell_mut(root);
+                        if !cell.mutable {
+                            err(
+                                format!(
+                                    "'{}' is a constant, it's value cannot be modified",
+                                    site.text
+                                )
+                                .as_str(),
+                                &site.pos,
+                            )?
+                        }
+                        // A BYREF counter counts in the variable it was given
+                        match &cell.value {
+                            Value::Ref(reference) => {
+                                let reference = reference.clone();
+                                let place = Place {
+                                    root: PlaceRoot::Var(reference.root),
+                                    start: self.steps.len(),
+                                };
+                                self.steps.extend_from_slice(&reference.steps);
+                                self.write(place, Value::Int(start));
+                            }
+                            _ => cell.value = Value::Int(start),
+                        }
+                        let len = self.stack.len();
+                        self.stack[len - 3] = Value::Int(start.wrapping_add(step));
+                    } else {
+                        let len = self.stack.len();
+                        self.stack.truncate(len - 3);
+                        pc = exit as usize;
+                    }
+                }
+                Op::CaseValue(constant, next) => {
+                    let cmp = self.stack.last().unwrap();
+                    if *cmp != program.constants[constant as usize] {
+                        pc = next as usize;
+                    }
+                }
+                Op::CaseRange(constant, next, site) => {
+                    let site = &program.sites[site as usize];
+                    let cmp = self.stack.last().unwrap();
+                    let start = &program.constants[constant as usize];
+                    let end = &program.constants[constant as usize + 1];
+                    // Both ends of a range are included
+                    let found = match (cmp, start, end) {
+                        (Value::Char(val), Value::Char(start), Value::Char(end)) => {
+                            (start..=end).contains(&val)
+                        }
+                        _ => {
+                            let (cmp, _) = number(cmp, &site.pos)?;
+                            let (start, _) = number(start, &site.positions[0])?;
+                            let (end, _) = number(end, &site.positions[1])?;
+                            (start..=end).contains(&cmp)
+                        }
+                    };
+                    if !found {
+                        pc = next as usize;
+                    }
+                }
+
+                Op::Declare(slot, t, site) => {
+                    let site = &program.sites[site as usize];
+                    let value = self.default(&program.types[t as usize], &site.pos)?;
+                    self.declare(slot, value, true, site)?;
+                }
+                Op::DeclareConst(slot, constant, site) => {
+                    let site = &program.sites[site as usize];
+                    let value = program.constants[constant as usize].clone();
+                    self.declare(slot, value, false, site)?;
+                }
+                Op::DefineRoutine(routine, site) => {
+                    let site = &program.sites[site as usize];
+                    let def = program.routines[routine as usize].def as usize;
+                    self.define(def, Def::Routine, &site.text, &site.pos)?;
+                }
+                Op::DefineRecord(record) => {
+                    let record = &program.records[record as usize];
+                    let mut fields: Vec<(String, Value)> = vec![];
+                    for (name, t, pos) in &record.fields {
+                        let value = self.default(&program.types[*t as usize], pos)?;
+                        match fields.iter_mut().find(|(field, _)| field == name) {
+                            Some(field) => field.1 = value,
+                            None => fields.push((name.clone(), value)),
+                        }
+                    }
+                    let value = Value::Record(Rc::new(RecordValue {
+                        name: record.name.clone(),
+                        fields,
+                    }));
+                    self.define(
+                        record.def as usize,
+                        Def::Record(value),
+                        &record.name,
+                        &record.pos,
+                    )?;
+                }
+                Op::DefineEnum(index) => {
+                    let def = &program.enums[index as usize];
+                    self.define(def.def as usize, Def::Enum, &def.name, &def.pos)?;
+                    for (slot, variant) in &def.variants {
+                        let value = Value::Enum(Rc::new(EnumValue {
+                            family: def.name.clone(),
+                            val: variant.clone(),
+                        }));
+                        let site = Site {
+                            pos: Position::invalid(),
+                            text: variant.clone(),
+                            positions: vec![],
+                        };
+                        self.declare(*slot, value, false, &site)?;
+                    }
+                }
+
+                Op::Callable(def, site) => {
+                    let site = &program.sites[site as usize];
+                    match self.defs[def as usize] {
+                        Def::Routine => {}
+                        Def::None => err(
+                            format!("'{}' is not declared", site.text).as_str(),
+                            &site.pos,
+                        )?,
+                        _ => err("Value is not a function, it cannot be called", &site.pos)?,
+                    }
+                }
+                Op::Arg(t, site) => {
+                    let site = &program.sites[site as usize];
+                    let t = &program.types[t as usize];
+                    let value = promote(self.pop(), t, &site.pos)?;
+                    if !value.is_type(t) {
+                        err(
+                            format!(
+                                "Cannot assign type {:?} to parameter of type {:?}",
+                                value.type_of().str(),
+                                t.str()
+                            )
+                            .as_str(),
+                            &site.pos,
+                        )?
+                    }
+                    self.stack.push(value);
+                }
+                Op::ArgRef(t, site) => {
+                    let site = &program.sites[site as usize];
+                    let t = &program.types[t as usize];
+                    let place = self.pop_place();
+                    let value = self.place_value(&place);
+                    if !value.is_type(t) {
+                        err(
+                            format!(
+                                "Cannot assign type {:?} to parameter of type {:?}",
+                                value.type_of().str(),
+                                t.str()
+                            )
+                            .as_str(),
+                            &site.pos,
+                        )?
+                    }
+                    let PlaceRoot::Var(root) = place.root else {
+                        unreachable!()
+                    };
+                    let reference = Reference {
+                        root,
+                        steps: self.steps[place.start..].to_vec(),
+                    };
+                    self.steps.truncate(place.start);
+                    self.stack.push(Value::Ref(Rc::new(reference)));
+                }
+                Op::Call(routine, site) => {
+                    self.executor
+                        .check_call_depth(self.frames.len(), &program.sites[site as usize].pos)?;
+                    let info = &program.routines[routine as usize];
+                    let base = self.locals.len();
+                    let args = self.stack.len() - info.params;
+                    for value in self.stack.drain(args..) {
+                        self.locals.push(Cell {
+                            value,
+                            mutable: true,
+                        });
+                    }
+                    for _ in info.params..info.locals {
+                        self.locals.push(Cell::undeclared());
+                    }
+                    self.frames.push(Frame {
+                        routine: routine as usize,
+                        base,
+                        stack: self.stack.len(),
+                        ret: pc,
+                        site,
+                    });
+                    pc = info.entry as usize;
+                }
+                Op::Return | Op::EndProcedure => {
+                    let value = match op {
+                        Op::Return => self.pop(),
+                        _ => Value::Null,
+                    };
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.stack);
+                    self.locals.truncate(frame.base);
+                    self.stack.push(value);
+                    pc = frame.ret;
+                }
+                Op::ProcedureReturn | Op::MissingReturn => {
+                    let frame = self.frames.last().unwrap();
+                    let message = match op {
+                        Op::ProcedureReturn => "Cannot return within procedure",
+                        _ => "Missing return statement",
+                    };
+                    err(message, &program.sites[frame.site as usize].pos)?
+                }
+                Op::BuiltinArg(builtin, param, site) => {
+                    let site = &program.sites[site as usize];
+                    let allowed = &program.builtins[builtin as usize].0[param as usize];
+                    // Parameters taking one type are shown by it, the last type otherwise
+                    let t = allowed.last().unwrap();
+                    let value = promote(self.pop(), t, &site.pos)?;
+                    if !allowed.iter().any(|allowed| value.is_type(allowed)) {
+                        err(
+                            format!(
+                                "Cannot assign type {:?} to parameter of type {:?}",
+                                value.type_of().str(),
+                                t.str()
+                            )
+                            .as_str(),
+                            &site.pos,
+                        )?
+                    }
+                    self.stack.push(value);
+                }
+                Op::CallBuiltin(builtin, site) => {
+                    let site = &program.sites[site as usize];
+                    let func = program.builtins[builtin as usize].1;
+                    let start = self.stack.len() - site.positions.len();
+                    let args: Vec<executor::Value> = self
+                        .stack
+                        .drain(start..)
+                        .map(|arg| arg.to_runtime())
+                        .collect();
+                    let result = func(&mut *self.executor, &args, &site.positions)?;
+                    self.stack.push(Value::from_runtime(&result));
+                }
+
+                Op::Print(site) => {
+                    let value = self.pop();
+                    match value.display() {
+                        Some(text) => self.stack.push(Value::Str(Rc::from(text))),
+                        None => err("Value cannot be printed", &program.sites[site as usize].pos)?,
+                    }
+                }
+                Op::Output(count, site) => {
+                    let start = self.stack.len() - count as usize;
+                    let mut line = String::new();
+                    for part in self.stack.drain(start..) {
+                        if let Value::Str(text) = part {
+                            line.push_str(&text);
+                        }
+                    }
+                    line.push('\n');
+                    self.executor
+                        .write_output(&line, &program.sites[site as usize].pos)?;
+                }
+                Op::Input(site) => {
+                    let site = &program.sites[site as usize];
+                    let place = self.pop_place();
+                    let line = match self.executor.io.read_line() {
+                        Ok(Some(line)) => line,
+                        Ok(None) => err("No more input to read", &site.pos)?,
+                        Err(_) => err("System error, failed to read input", &site.pos)?,
+                    };
+                    let t = self.place_value(&place).type_of();
+                    let value = match t {
+                        VariableType::Integer => match line.parse::<i64>() {
+                            Ok(val) => Value::Int(val),
+                            Err(_) => err("Input value is not INTEGER", &site.pos)?,
+                        },
+                        VariableType::String => Value::Str(Rc::from(line)),
+                        VariableType::Char => promote(Value::Str(Rc::from(line)), &t, &site.pos)?,
+                        VariableType::Real => match line.parse::<f64>() {
+                            Ok(val) => Value::Real(val),
+                            Err(_) => err("Input value is not REAL", &site.pos)?,
+                        },
+                        VariableType::Boolean => Value::Bool(line.to_uppercase() == "TRUE"),
+                        _ => err(
+                            format!("Input type {} is not allowed", t.str()).as_str(),
+                            &site.positions[0],
+                        )?,
+                    };
+                    self.write(place, value);
+                }
+                Op::OpenFile(site) => {
+                    let site = &program.sites[site as usize];
+                    let filename = self.pop().text();
+                    open_file(&mut *self.executor, filename, &site.text, &site.pos)?;
+                }
+                Op::CloseFile(site) => {
+                    let filename = self.pop().text();
+                    close_file(
+                        &mut *self.executor,
+                        filename,
+                        &program.sites[site as usize].pos,
+                    )?;
+                }
+                Op::ReadFile(site) => {
+                    let filename = self.pop().text();
+                    let line = read_line(
+                        &mut *self.executor,
+                        filename,
+                        &program.sites[site as usize].pos,
+                    )?;
+                    self.stack.push(Value::Str(Rc::from(line)));
+                }
+                Op::WriteFile(site) => {
+                    let data = self.pop().text();
+                    let filename = self.pop().text();
+                    write_file(
+                        &mut *self.executor,
+                        filename,
+                        data,
+                        &program.sites[site as usize].pos,
+                    )?;
+                }
+
+                Op::Raise(site) => {
+                    let site = &program.sites[site as usize];
+                    err(site.text.as_str(), &site.pos)?
+                }
+                Op::Step(site) => self.executor.step(&program.sites[site as usize].pos)?,
+                Op::Halt => return Ok(()),
+            }
+        }
+    }
+
+    fn define(
+        &mut self,
+        def: usize,
+        value: Def,
+        name: &str,
+        pos: &Position,
+    ) -> Result<(), PseudoError> {
+        if !matches!(self.defs[def], Def::None) {
+            err(format!("'{}' is already declared", name).as_str(), pos)?
+        }
+        self.defs[def] = value;
+        Ok(())
+    }
+}
+
+// Where an element is kept, given the indices on the stack. Most arrays have
+// one or two dimensions, so the indices rarely need a Vec of their own.
+fn offset(indices: &[Value], shape: &[Index], site: &Site) -> Result<usize, PseudoError> {
+    let int = |index: &Value| match index {
+        Value::Int(val) => *val,
+        _ => unreachable!(),
+    };
+    if indices.len() <= 4 {
+        let mut buf = [0; 4];
+        for (i, index) in indices.iter().enumerate() {
+            buf[i] = int(index);
+        }
+        array_offset(&buf[..indices.len()], shape, &site.positions)
+    } else {
+        let indices: Vec<i64> = indices.iter().map(int).collect();
+        array_offset(&indices, shape, &site.positions)
+    }
+}
+
+fn compare_error(lhs: &Value, rhs: &Value, pos: &Position) -> Result<Value, PseudoError> {
+    err(
+        format!(
+            "Cannot compare types {} AND {}",
+            lhs.type_of().str(),
+            rhs.type_of().str()
+        )
+        .as_str(),
+        pos,
+    )
+}
+
+fn binary(op: BinOp, lhs: Value, rhs: Value, site: &Site) -> Result<Value, PseudoError> {
+    let (lhs_pos, rhs_pos) = (&site.positions[0], &site.positions[1]);
+    Ok(match op {
+        BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod | BinOp::IntDiv => {
+            let (lhs_val, lhs_real) = number(&lhs, lhs_pos)?;
+            let (rhs_val, rhs_real) = number(&rhs, rhs_pos)?;
+            let res = match op {
+                BinOp::Add => lhs_val + rhs_val,
+                BinOp::Sub => lhs_val - rhs_val,
+                BinOp::Mul => lhs_val * rhs_val,
+                BinOp::Div => lhs_val / rhs_val,
+                BinOp::Mod => lhs_val % rhs_val,
+                _ => (lhs_val / rhs_val).floor(),
+            };
+            if lhs_real || rhs_real {
+                Value::Real(res)
+            } else {
+                Value::Int(res.trunc() as i64)
+            }
+        }
+        BinOp::Less | BinOp::Greater | BinOp::LessEq | BinOp::GreaterEq => {
+            // CHAR values are ordered by their character codes
+            let ordering = match (&lhs, &rhs) {
+                (Value::Char(lhs), Value::Char(rhs)) => lhs.partial_cmp(rhs),
+                (Value::Char(_), _) | (_, Value::Char(_)) => {
+                    return compare_error(&lhs, &rhs, &Position::range(*lhs_pos, *rhs_pos))
+                }
+                _ => {
+                    number(&lhs, lhs_pos)?;
+                    number(&rhs, rhs_pos)?;
+                    lhs.partial_cmp(&rhs)
+                }
+            };
+            use std::cmp::Ordering::*;
+            Value::Bool(match op {
+                BinOp::Less => ordering == Some(Less),
+                BinOp::Greater => ordering == Some(Greater),
+                BinOp::LessEq => matches!(ordering, Some(Less | Equal)),
+                _ => matches!(ordering, Some(Greater | Equal)),
+            })
+        }
+        BinOp::Equal | BinOp::NotEqual => {
+            // INTEGER and REAL values compare as numbers, CHAR and STRING as text
+            let numbers = lhs.as_number().is_some() && rhs.as_number().is_some();
+            let text = lhs.is_text() && rhs.is_text();
+            if !numbers && !text && !lhs.same_type(&rhs) {
+                return compare_error(&lhs, &rhs, &site.pos);
+            }
+            if matches!(lhs, Value::Array(_) | Value::Record(_)) {
+                err(
+                    format!(
+                        "Cannot compare type {}. {}",
+                        lhs.type_of().str(),
+                        SUPPORT_MESSAGE
+                    )
+                    .as_str(),
+                    &site.pos,
+                )?
+            }
+            Value::Bool((lhs == rhs) == (op == BinOp::Equal))
+        }
+        BinOp::And | BinOp::Or => {
+            let message = format!(
+                "Logical operation {} can only be performed on BOOLEAN",
+                site.text
+            );
+            let Value::Bool(rhs) = rhs else {
+                return err(message.as_str(), rhs_pos);
+            };
+            let Value::Bool(lhs) = lhs else {
+                return err(message.as_str(), lhs_pos);
+            };
+            Value::Bool(if op == BinOp::And {
+                lhs && rhs
+            } else {
+                lhs || rhs
+            })
+        }
+        BinOp::Concat => {
+            // A CHAR joins as a STRING of one character
+            if !rhs.is_text() {
+                err("'&' can only be performed on STRING or CHAR", rhs_pos)?
+            }
+            if !lhs.is_text() {
+                err("'&' can only be performed on STRING or CHAR", lhs_pos)?
+            }
+            Value::Str(Rc::from(format!("{}{}", lhs.text(), rhs.text())))
+        }
+    })
+}
@@ -0,0 +1,193 @@
+// A second way of running programs. The parsed nodes are compiled once into a flat
+// list of instructions with every variable resolved to a numbered slot, which a
+// stack machine then runs. Output and errors are the same as the tree-walker's.
+mod compile;
+mod machine;
+mod value;
+
+use std::collections::HashMap;
+
+use crate::enums::{Position, VariableType};
+use crate::executor::run_builtins::BuiltinFn;
+use crate::executor::Executor;
+use crate::utils::PseudoError;
+
+pub use crate::vm::compile::compile;
+use crate::vm::value::Value;
+
+// Why a program could not be compiled
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    // A construct the machine does not run, such as classes and pointers. Programs
+    // using one are left to the tree-walker.
+    Unsupported { what: String, pos: Position },
+    // A node the compiler has no case for, a mistake in the compiler itself
+    Invalid(PseudoError),
+}
+
+// Where a variable lives, worked out when the program is compiled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slot {
+    // A variable of the program as a whole
+    Global(u32),
+    // A parameter of the running procedure or function
+    Local(u32),
+    // A local once its DECLARE has run, the global of that name before then
+    Scoped(u32, u32),
+    // A global named like a local of another routine, which is pointed out if the
+    // global is missing while that routine is further down the call stack
+    Outer(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    IntDiv,
+    Less,
+    Greater,
+    LessEq,
+    GreaterEq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Concat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnOp {
+    Not,
+    Negate,
+    Plus,
+}
+
+// Each instruction refers to a Site by number, which is where in the program it
+// came from along with anything its error messages need
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    Pop,
+    // Drop everything a top level statement left on the stack
+    Clear,
+    Load(Slot, u32),
+    // Read one element, the indices are on the stack
+    LoadElement(Slot, u32),
+    Unary(UnOp, u32),
+    Binary(BinOp, u32),
+    // Fail unless the value on top of the stack is an INTEGER
+    ExpectInt(u32),
+
+    // Places are what assignments, INPUT and BYREF parameters write to
+    Place(Slot, u32),
+    // The value on top of the stack, so parts of it can be read
+    PlaceValue,
+    PlaceField(u32),
+    // Fail unless the place holds an array
+    PlaceArray(u32),
+    PlaceIndex(u32),
+    LoadPlace,
+    Store(u32),
+
+    Jump(u32),
+    JumpUnless(u32, u32),
+    // The start, end and step of a FOR loop are kept on the stack while it runs
+    ForSetup(Slot, u32),
+    ForNext(Slot, u32, u32),
+    // Jump to the next case unless it matches the value being compared
+    CaseValue(u32, u32),
+    CaseRange(u32, u32, u32),
+
+    Declare(Slot, u32, u32),
+    DeclareConst(Slot, u32, u32),
+    DefineRoutine(u32, u32),
+    DefineRecord(u32),
+    DefineEnum(u32),
+
+    // Fail unless a definition is a function or procedure that can be called
+    Callable(u32, u32),
+    Arg(u32, u32),
+    ArgRef(u32, u32),
+    Call(u32, u32),
+    Return,
+    EndProcedure,
+    ProcedureReturn,
+    MissingReturn,
+    BuiltinArg(u32, u32, u32),
+    CallBuiltin(u32, u32),
+
+    Print(u32),
+    Output(u32, u32),
+    Input(u32),
+    OpenFile(u32),
+    CloseFile(u32),
+    ReadFile(u32),
+    WriteFile(u32),
+
+    // Counts a statement or a loop going round, for the limits the program runs under
+    Step(u32),
+    Raise(u32),
+    Halt,
+}
+
+#[derive(Clone, Debug)]
+pub struct Site {
+    pub pos: Position,
+    // A name or message
+    pub text: String,
+    // Where the operands or arguments were written
+    pub positions: Vec<Position>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Routine {
+    pub name: String,
+    pub def: u32,
+    pub entry: u32,
+    pub params: usize,
+    pub locals: usize,
+    // Local slots by variable name, for pointing out a caller's local used by a routine it calls
+    pub names: HashMap<u32, u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordDef {
+    pub def: u32,
+    pub name: String,
+    pub pos: Position,
+    // Name, type and where it was declared
+    pub fields: Vec<(String, u32, Position)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EnumDef {
+    pub def: u32,
+    pub name: String,
+    pub pos: Position,
+    pub variants: Vec<(Slot, String)>,
+}
+
+pub struct Program {
+    pub(crate) code: Vec<Op>,
+    pub(crate) sites: Vec<Site>,
+    pub(crate) constants: Vec<Value>,
+    pub(crate) types: Vec<VariableType>,
+    pub(crate) routines: Vec<Routine>,
+    pub(crate) records: Vec<RecordDef>,
+    pub(crate) enums: Vec<EnumDef>,
+    // Names of the procedures, functions and types, numbered
+    pub(crate) defs: HashMap<String, u32>,
+    // The global slot of each variable name, numbered
+    pub(crate) globals: Vec<Option<u32>>,
+    pub(crate) global_count: usize,
+    pub(crate) builtins: Vec<(Vec<Vec<VariableType>>, BuiltinFn)>,
+}
+
+impl Program {
+    pub fn run(&self, executor: &mut Executor) -> Result<(), PseudoError> {
+        machine::Machine::new(self, executor).run()
+    }
+}
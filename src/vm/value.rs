@@ -0,0 +1,263 @@
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+
+use crate::enums::{Index, Node, VariableType};
+use crate::executor;
+
+// A value as the machine holds it. Arrays and records are shared between copies
+// until one of them is changed, so passing them around stays cheap.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i64),
+    Real(f64),
+    Bool(bool),
+    Char(char),
+    Str(Rc<str>),
+    Date(NaiveDate),
+    Enum(Rc<EnumValue>),
+    Array(Rc<ArrayValue>),
+    Record(Rc<RecordValue>),
+    // A variable of an enum type that has not been given a value yet
+    Nothing(Rc<VariableType>),
+    // What a procedure gives back
+    Null,
+    // A parameter passed BYREF, standing in for the variable it was given
+    Ref(Rc<Reference>),
+    // A variable whose DECLARE has not run yet
+    Undeclared,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumValue {
+    pub family: String,
+    pub val: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ArrayValue {
+    pub t: VariableType,
+    pub shape: Vec<Index>,
+    pub values: Vec<Value>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordValue {
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+// Where a variable is kept, a global or a local of some running routine
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Root {
+    Global(usize),
+    Local(usize),
+}
+
+// One step from a value to a part of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    Index(usize),
+    Field(usize),
+}
+
+#[derive(Clone, Debug)]
+pub struct Reference {
+    pub root: Root,
+    pub steps: Vec<Step>,
+}
+
+impl Value {
+    pub fn from_node(node: &Node) -> Value {
+        match node {
+            Node::Int { val, .. } => Value::Int(*val),
+            Node::Real { val, .. } => Value::Real(*val),
+            Node::Boolean { val, .. } => Value::Bool(*val),
+            Node::Char { val, .. } => Value::Char(*val),
+            Node::String { val, .. } => Value::Str(Rc::from(val.as_str())),
+            Node::Date { val, .. } => Value::Date(*val),
+            _ => Value::Null,
+        }
+    }
+
+    pub fn type_of(&self) -> VariableType {
+        match self {
+            Value::Int(_) => VariableType::Integer,
+            Value::Real(_) => VariableType::Real,
+            Value::Bool(_) => VariableType::Boolean,
+            Value::Char(_) => VariableType::Char,
+            Value::Str(_) => VariableType::String,
+            Value::Date(_) => VariableType::Date,
+            Value::Enum(value) => VariableType::Custom(value.family.clone()),
+            Value::Array(array) => VariableType::Array {
+                shape: array.shape.clone(),
+                t: Box::new(array.t.clone()),
+            },
+            Value::Record(record) => VariableType::Custom(record.name.clone()),
+            Value::Nothing(t) => t.as_ref().clone(),
+            Value::Null | Value::Ref(_) | Value::Undeclared => {
+                VariableType::Custom("NULL".to_string())
+            }
+        }
+    }
+
+    // Same as comparing type_of, without building the type for the common cases
+    pub fn is_type(&self, t: &VariableType) -> bool {
+        match (self, t) {
+            (Value::Int(_), VariableType::Integer)
+            | (Value::Real(_), VariableType::Real)
+            | (Value::Bool(_), VariableType::Boolean)
+            | (Value::Char(_), VariableType::Char)
+            | (Value::Str(_), VariableType::String)
+            | (Value::Date(_), VariableType::Date) => true,
+            (Value::Enum(value), VariableType::Custom(name)) => value.family == *name,
+            (Value::Record(record), VariableType::Custom(name)) => record.name == *name,
+            (Value::Array(array), VariableType::Array { shape, t }) => {
+                array.shape == *shape && array.t == **t
+            }
+            (Value::Nothing(own), _) => own.as_ref() == t,
+            _ => false,
+        }
+    }
+
+    pub fn same_type(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(_), Value::Int(_))
+            | (Value::Real(_), Value::Real(_))
+            | (Value::Bool(_), Value::Bool(_))
+            | (Value::Char(_), Value::Char(_))
+            | (Value::Str(_), Value::Str(_))
+            | (Value::Date(_), Value::Date(_)) => true,
+            _ => self.type_of() == other.type_of(),
+        }
+    }
+
+    // The value written out the way the tree-walker compares and passes it around
+    pub fn text(&self) -> String {
+        match self {
+            Value::Int(val) => val.to_string(),
+            Value::Real(val) => val.to_string(),
+            Value::Bool(val) => val.to_string(),
+            Value::Char(val) => val.to_string(),
+            Value::Str(val) => val.to_string(),
+            Value::Date(val) => val.to_string(),
+            Value::Enum(value) => value.val.clone(),
+            _ => String::new(),
+        }
+    }
+
+    // The value as OUTPUT prints it, if it can be printed at all
+    pub fn display(&self) -> Option<String> {
+        Some(match self {
+            Value::Int(val) => val.to_string(),
+            Value::Real(val) => val.to_string(),
+            Value::Str(val) => val.to_string(),
+            Value::Char(val) => val.to_string(),
+            Value::Bool(val) => val.to_string().to_uppercase(),
+            Value::Date(val) => val.format("%d-%m-%Y").to_string(),
+            Value::Null => "null".to_string(),
+            Value::Enum(value) => value.val.clone(),
+            _ => return None,
+        })
+    }
+
+    // The value as the call stack of an error shows an argument
+    pub fn quoted(&self) -> String {
+        match self {
+            Value::Str(val) => format!("{:?}", val),
+            Value::Char(val) => format!("'{}'", val),
+            Value::Nothing(_) => "null".to_string(),
+            _ => self.display().unwrap_or_else(|| self.type_of().str()),
+        }
+    }
+
+    // Builtins are shared with the tree-walker, so their arguments and results
+    // are given in its values
+    pub fn to_runtime(&self) -> executor::Value {
+        match self {
+            Value::Int(val) => executor::Value::Integer(*val),
+            Value::Real(val) => executor::Value::Real(*val),
+            Value::Bool(val) => executor::Value::Boolean(*val),
+            Value::Char(val) => executor::Value::Char(*val),
+            Value::Str(val) => executor::Value::String(val.clone()),
+            Value::Date(val) => executor::Value::Date(*val),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn from_runtime(value: &executor::Value) -> Value {
+        match value {
+            executor::Value::Integer(val) => Value::Int(*val),
+            executor::Value::Real(val) => Value::Real(*val),
+            executor::Value::Boolean(val) => Value::Bool(*val),
+            executor::Value::Char(val) => Value::Char(*val),
+            executor::Value::String(val) => Value::Str(val.clone()),
+            executor::Value::Date(val) => Value::Date(*val),
+            _ => unreachable!(),
+        }
+    }
+
+    // The number and whether it is REAL
+    pub fn as_number(&self) -> Option<(f64, bool)> {
+        match self {
+            Value::Int(val) => Some((*val as f64, false)),
+            Value::Real(val) => Some((*val, true)),
+            _ => None,
+        }
+    }
+
+    // Whether it is one of the values to_runtime gives the tree-walker's value of
+    pub fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            Value::Int(_)
+                | Value::Real(_)
+                | Value::Bool(_)
+                | Value::Char(_)
+                | Value::Str(_)
+                | Value::Date(_)
+        )
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, Value::Str(_) | Value::Char(_))
+    }
+
+    pub fn child(&self, step: Step) -> &Value {
+        match (self, step) {
+            (Value::Array(array), Step::Index(i)) => &array.values[i],
+            (Value::Record(record), Step::Field(i)) => &record.fields[i].1,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn child_mut(&mut self, step: Step) -> &mut Value {
+        match (self, step) {
+            (Value::Array(array), Step::Index(i)) => &mut Rc::make_mut(array).values[i],
+            (Value::Record(record), Step::Field(i)) => &mut Rc::make_mut(record).fields[i].1,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Equal the same way as the tree-walker's values, by comparing them as its values
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Enum(lhs), Value::Enum(rhs)) => lhs == rhs,
+            (Value::Null, Value::Null) | (Value::Nothing(_), Value::Nothing(_)) => true,
+            _ if self.is_scalar() && other.is_scalar() => self.to_runtime() == other.to_runtime(),
+            _ => false,
+        }
+    }
+}
+
+// Ordered the same way as the tree-walker's values
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<std::cmp::Ordering> {
+        match self.is_scalar() && other.is_scalar() {
+            true => self.to_runtime().partial_cmp(&other.to_runtime()),
+            false => None,
+        }
+    }
+}